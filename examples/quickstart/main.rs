@@ -130,7 +130,8 @@ async fn main() {
     println!("Loading tokens from Tycho... {url}", url = tycho_url.as_str());
     let all_tokens =
         load_all_tokens(tycho_url.as_str(), false, Some(tycho_api_key.as_str()), chain, None, None)
-            .await;
+            .await
+            .expect("Failed loading tokens");
     println!("Tokens loaded: {num}", num = all_tokens.len());
 
     let sell_token_address = Bytes::from_str(
@@ -0,0 +1,178 @@
+//! Records fixtures comparing native pool states to their VM adapters.
+//!
+//! Takes one snapshot of the given components from Tycho, quotes them with the VM adapter of
+//! their protocol and writes one fixture per component, containing the raw snapshot, its block,
+//! the pool tokens and the adapter's quotes. The native decoders' tests replay these fixtures
+//! and compare their quotes to the recorded ones.
+use std::{env, fs, path::PathBuf, str::FromStr};
+
+use clap::Parser;
+use num_bigint::BigUint;
+use serde::Serialize;
+use tracing_subscriber::EnvFilter;
+use tycho_simulation::{
+    evm::{
+        decoder::TychoStreamDecoder, engine_db::tycho_db::PreCachedDB,
+        protocol::vm::state::EVMPoolState,
+    },
+    tycho_client::{
+        feed::{component_tracker::ComponentFilter, synchronizer::ComponentWithState, BlockHeader},
+        stream::TychoStreamBuilder,
+    },
+    tycho_common::{models::Chain, Bytes},
+    utils::{get_default_url, load_all_tokens},
+};
+
+/// Fractions of the adapter's sell limit quoted for every token pair, in basis points.
+const AMOUNTS_BPS: [u32; 4] = [1, 10, 100, 1_000];
+
+#[derive(Parser)]
+struct Cli {
+    /// The VM protocol system of the components, e.g. `vm:curve`
+    #[arg(long)]
+    protocol: String,
+    /// Ids of the components to record
+    #[arg(long, value_delimiter = ',', required = true)]
+    components: Vec<String>,
+    /// Directory the fixtures are written to, e.g. `tests/assets/curve`
+    #[arg(long)]
+    out: PathBuf,
+    #[arg(long, default_value = "ethereum")]
+    chain: String,
+}
+
+#[derive(Serialize)]
+struct FixtureToken {
+    address: Bytes,
+    decimals: u32,
+}
+
+#[derive(Serialize)]
+struct FixtureQuote {
+    token_in: Bytes,
+    token_out: Bytes,
+    amount_in: String,
+    amount_out: String,
+}
+
+#[derive(Serialize)]
+struct Fixture {
+    snapshot: ComponentWithState,
+    block: BlockHeader,
+    tokens: Vec<FixtureToken>,
+    quotes: Vec<FixtureQuote>,
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_target(false)
+        .init();
+
+    let cli = Cli::parse();
+    let chain = Chain::from_str(&cli.chain)
+        .unwrap_or_else(|_| panic!("Unknown chain {chain}", chain = cli.chain));
+    let tycho_url = env::var("TYCHO_URL").unwrap_or_else(|_| {
+        get_default_url(&chain)
+            .unwrap_or_else(|| panic!("Unknown URL for chain {chain}", chain = cli.chain))
+    });
+    let tycho_api_key: String =
+        env::var("TYCHO_API_KEY").unwrap_or_else(|_| "sampletoken".to_string());
+
+    let all_tokens =
+        load_all_tokens(tycho_url.as_str(), false, Some(tycho_api_key.as_str()), chain, None, None)
+            .await
+            .expect("Failed loading tokens");
+
+    let (_, mut rx) = TychoStreamBuilder::new(&tycho_url, chain.into())
+        .exchange(&cli.protocol, ComponentFilter::Ids(cli.components.clone()))
+        .auth_key(Some(tycho_api_key.clone()))
+        .build()
+        .await
+        .expect("Failed building the Tycho stream");
+    let msg = rx
+        .recv()
+        .await
+        .expect("The Tycho stream ended before the first snapshot");
+
+    let state_msg = msg.state_msgs[&cli.protocol].clone();
+    let mut decoder = TychoStreamDecoder::new();
+    decoder.register_decoder::<EVMPoolState<PreCachedDB>>(&cli.protocol);
+    decoder
+        .set_tokens(all_tokens.clone())
+        .await;
+    let update = decoder
+        .decode(msg)
+        .await
+        .expect("Failed decoding the snapshot");
+
+    fs::create_dir_all(&cli.out).expect("Failed creating the output directory");
+    for id in &cli.components {
+        let snapshot = state_msg
+            .snapshots
+            .states
+            .get(id)
+            .unwrap_or_else(|| panic!("No snapshot of {id}"))
+            .clone();
+        let state = update
+            .states
+            .get(id)
+            .unwrap_or_else(|| panic!("Failed decoding {id} with the VM adapter"));
+        let tokens: Vec<_> = snapshot
+            .component
+            .tokens
+            .iter()
+            .map(|address| all_tokens[address].clone())
+            .collect();
+
+        let mut quotes = Vec::new();
+        for token_in in &tokens {
+            for token_out in &tokens {
+                if token_in == token_out {
+                    continue;
+                }
+                let Ok((max_in, _)) =
+                    state.get_limits(token_in.address.clone(), token_out.address.clone())
+                else {
+                    continue;
+                };
+                for bps in AMOUNTS_BPS {
+                    let amount_in = &max_in * BigUint::from(bps) / BigUint::from(10_000u32);
+                    if amount_in == BigUint::ZERO {
+                        continue;
+                    }
+                    match state.get_amount_out(amount_in.clone(), token_in, token_out) {
+                        Ok(result) => quotes.push(FixtureQuote {
+                            token_in: token_in.address.clone(),
+                            token_out: token_out.address.clone(),
+                            amount_in: amount_in.to_string(),
+                            amount_out: result.amount.to_string(),
+                        }),
+                        Err(err) => println!(
+                            "Skipping {amount_in} {} -> {} of {id}: {err}",
+                            token_in.symbol, token_out.symbol
+                        ),
+                    }
+                }
+            }
+        }
+
+        let fixture = Fixture {
+            snapshot,
+            block: state_msg.header.clone(),
+            tokens: tokens
+                .iter()
+                .map(|token| FixtureToken {
+                    address: token.address.clone(),
+                    decimals: token.decimals,
+                })
+                .collect(),
+            quotes,
+        };
+        let path = cli.out.join(format!("{id}.json"));
+        fs::write(&path, serde_json::to_string_pretty(&fixture).unwrap())
+            .unwrap_or_else(|err| panic!("Failed writing {path:?}: {err}"));
+        println!("Recorded {} quotes of {id} to {path:?}", fixture.quotes.len());
+    }
+}
@@ -87,7 +87,8 @@ async fn main() {
     println!("Loading tokens from Tycho... {url}", url = tycho_url.as_str());
     let all_tokens =
         load_all_tokens(tycho_url.as_str(), false, Some(tycho_api_key.as_str()), chain, None, None)
-            .await;
+            .await
+            .expect("Failed loading tokens");
     println!("Tokens loaded: {num}", num = all_tokens.len());
 
     let sell_token_address = Bytes::from_str(
@@ -133,7 +134,9 @@ async fn main() {
     let (tx, mut rx) = mpsc::channel::<Update>(100);
 
     let rfq_stream_builder = RFQStreamBuilder::new()
-        .add_client::<BebopState>("bebop", Box::new(bebop_client))
+        .add_client_with::<BebopState, _>("bebop", Box::new(bebop_client.clone()), move |state| {
+            state.with_client(bebop_client.clone())
+        })
         .set_tokens(all_tokens.clone())
         .await;
 
@@ -3,7 +3,7 @@ use std::{
     future::Future,
     pin::Pin,
     str::FromStr,
-    sync::Arc,
+    sync::{Arc, Mutex, MutexGuard},
 };
 
 use alloy::primitives::{Address, U256};
@@ -32,14 +32,17 @@ use crate::{
     evm::{
         engine_db::{update_engine, SHARED_TYCHO_DB},
         protocol::{
+            set_block,
             utils::bytes_to_address,
             vm::{constants::ERC20_PROXY_BYTECODE, erc20_token::IMPLEMENTATION_SLOT},
         },
+        token_registry::TokenSource,
         tycho_models::{AccountUpdate, ResponseAccount},
     },
     protocol::{
         errors::InvalidSnapshotError,
-        models::{ProtocolComponent, TryFromWithBlock, Update},
+        models::{DecodeStats, ProtocolComponent, TryFromWithBlock, Update},
+        token_policy::TokenPolicy,
     },
 };
 
@@ -52,12 +55,97 @@ pub enum StreamDecodeError {
 #[derive(Default)]
 struct DecoderState {
     tokens: HashMap<Bytes, Token>,
+    // Transfer taxes in basis points, overriding the tax of the known tokens
+    transfer_taxes: HashMap<Bytes, u64>,
+    // Tax indexed by Tycho of the tokens whose tax is currently overridden
+    indexed_taxes: HashMap<Bytes, u64>,
     states: HashMap<String, Box<dyn ProtocolSim>>,
     components: HashMap<String, ProtocolComponent>,
     // maps contract address to the pools they affect
     contracts_map: HashMap<Bytes, HashSet<String>>,
     // Maps original token address to their new proxy token address
     proxy_token_addresses: HashMap<Address, Address>,
+    // Snapshots skipped because of unknown tokens, retried once their tokens are known
+    pending_components: HashMap<String, PendingComponent>,
+}
+
+struct PendingComponent {
+    protocol: String,
+    snapshot: ComponentWithState,
+    account_balances: AccountBalances,
+    // Deltas received since the snapshot, replayed once the component is decoded
+    deltas: Vec<(ProtocolStateDelta, Balances)>,
+}
+
+impl DecoderState {
+    /// Adds tokens as indexed by Tycho and applies the transfer taxes to them.
+    fn extend_tokens(&mut self, tokens: HashMap<Bytes, Token>) {
+        for address in tokens.keys() {
+            self.indexed_taxes.remove(address);
+        }
+        self.tokens.extend(tokens);
+        self.apply_transfer_taxes();
+    }
+
+    /// Overrides the tax of the known tokens with the transfer taxes, restoring the indexed tax
+    /// of tokens without an override, and updates the tokens of the tracked components.
+    fn apply_transfer_taxes(&mut self) {
+        self.indexed_taxes
+            .retain(|address, indexed_tax| {
+                if self
+                    .transfer_taxes
+                    .contains_key(address)
+                {
+                    return true;
+                }
+                if let Some(token) = self.tokens.get_mut(address) {
+                    token.tax = *indexed_tax;
+                }
+                false
+            });
+        for (address, tax) in &self.transfer_taxes {
+            if let Some(token) = self.tokens.get_mut(address) {
+                self.indexed_taxes
+                    .entry(address.clone())
+                    .or_insert(token.tax);
+                token.tax = *tax;
+            }
+        }
+        for component in self.components.values_mut() {
+            for token in component.tokens.iter_mut() {
+                if let Some(known) = self.tokens.get(&token.address) {
+                    token.tax = known.tax;
+                }
+            }
+        }
+    }
+}
+
+/// A component that could not be decoded, with the last error it failed with.
+///
+/// Quarantined components are not tracked by the decoder. A component leaves the quarantine once
+/// a new snapshot of it decodes successfully.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuarantinedComponent {
+    pub protocol: String,
+    pub error: String,
+    /// Block number or timestamp of the message the component failed to decode in
+    pub block: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DecodeFailure {
+    Snapshot,
+    Transition,
+    TokenSkip,
+    MissingDecoder,
+}
+
+#[derive(Default)]
+struct DecodeDiagnostics {
+    // Cumulative failure counts per protocol
+    stats: HashMap<String, DecodeStats>,
+    quarantine: HashMap<String, QuarantinedComponent>,
 }
 
 type DecodeFut =
@@ -66,7 +154,8 @@ type AccountBalances = HashMap<Bytes, HashMap<Bytes, Bytes>>;
 type RegistryFn<H> = dyn Fn(ComponentWithState, H, AccountBalances, Arc<RwLock<DecoderState>>) -> DecodeFut
     + Send
     + Sync;
-type FilterFn = fn(&ComponentWithState) -> bool;
+type FilterFn = dyn Fn(&ComponentWithState) -> bool + Send + Sync;
+type StateFilterFn = dyn Fn(&ProtocolComponent, &dyn ProtocolSim) -> bool + Send + Sync;
 
 /// A decoder to process raw messages.
 ///
@@ -86,9 +175,16 @@ where
 {
     state: Arc<RwLock<DecoderState>>,
     skip_state_decode_failures: bool,
-    min_token_quality: u32,
+    token_policy: TokenPolicy,
     registry: HashMap<String, Box<RegistryFn<H>>>,
-    inclusion_filters: HashMap<String, FilterFn>,
+    inclusion_filters: HashMap<String, Box<FilterFn>>,
+    // Filters added on top of the registered filter, e.g. from a `FilterConfig`
+    additional_filters: HashMap<String, Vec<Box<FilterFn>>>,
+    state_filters: HashMap<String, Box<StateFilterFn>>,
+    token_source: Option<Box<dyn TokenSource>>,
+    // Updated while the async state guards are held, so it uses a sync lock
+    diagnostics: Mutex<DecodeDiagnostics>,
+    include_decode_stats: bool,
 }
 
 impl<H> Default for TychoStreamDecoder<H>
@@ -108,25 +204,87 @@ where
         Self {
             state: Arc::new(RwLock::new(DecoderState::default())),
             skip_state_decode_failures: false,
-            min_token_quality: 51,
+            token_policy: TokenPolicy::default(),
             registry: HashMap::new(),
             inclusion_filters: HashMap::new(),
+            additional_filters: HashMap::new(),
+            state_filters: HashMap::new(),
+            token_source: None,
+            diagnostics: Mutex::new(DecodeDiagnostics::default()),
+            include_decode_stats: false,
         }
     }
 
     /// Sets the currently known tokens which will be considered during decoding.
     ///
     /// Protocol components containing tokens which are not included in this initial list, or
-    /// added when applying deltas, will not be decoded.
-    pub async fn set_tokens(&self, tokens: HashMap<Bytes, Token>) {
+    /// added when applying deltas, will not be decoded. Tokens rejected by the token policy are
+    /// ignored.
+    pub async fn set_tokens(&self, mut tokens: HashMap<Bytes, Token>) {
+        tokens.retain(|_, token| self.token_policy.admits(token));
         let mut guard = self.state.write().await;
         guard.tokens = tokens;
+        guard.indexed_taxes.clear();
+        guard.apply_transfer_taxes();
+    }
+
+    /// Sets the policy deciding which tokens are known to the decoder.
+    ///
+    /// The policy applies to the tokens set with [`Self::set_tokens`], the new tokens of deltas
+    /// and the tokens fetched from the token source. Known tokens the policy rejects are removed.
+    pub async fn set_token_policy(&mut self, policy: TokenPolicy) {
+        self.state
+            .write()
+            .await
+            .tokens
+            .retain(|_, token| policy.admits(token));
+        self.token_policy = policy;
+    }
+
+    /// Sets the transfer taxes of fee-on-transfer tokens, in basis points.
+    ///
+    /// The taxes override the `tax` of the known tokens, of tokens added later and of the tokens
+    /// of the tracked components, which native states apply to the amounts transferred in and out
+    /// of a swap. Tokens without an entry keep, or get back, the tax indexed by Tycho.
+    pub async fn set_transfer_taxes(&self, transfer_taxes: HashMap<Bytes, u64>) {
+        let mut guard = self.state.write().await;
+        guard.transfer_taxes = transfer_taxes;
+        guard.apply_transfer_taxes();
+    }
+
+    /// Sets the source used to resolve the tokens of new components that are not known yet.
+    ///
+    /// Without a source, components with unknown tokens are skipped until their tokens are added
+    /// by a later message. Fetched tokens are cached with the known tokens.
+    pub fn set_token_source<S>(&mut self, source: S)
+    where
+        S: TokenSource + 'static,
+    {
+        self.token_source = Some(Box::new(source));
     }
 
     pub fn skip_state_decode_failures(&mut self, skip: bool) {
         self.skip_state_decode_failures = skip;
     }
 
+    /// Attaches the decode failures of each message to the `decode_stats` of its `Update`.
+    pub fn include_decode_stats(&mut self, include: bool) {
+        self.include_decode_stats = include;
+    }
+
+    /// Returns the decode failures per protocol since the decoder was created.
+    pub fn decode_stats(&self) -> HashMap<String, DecodeStats> {
+        self.diagnostics().stats.clone()
+    }
+
+    /// Returns the components that failed to decode, keyed by component id.
+    ///
+    /// Only failures skipped with [`Self::skip_state_decode_failures`], or the last fatal one,
+    /// are recorded. Components skipped because of unknown tokens are not quarantined.
+    pub fn quarantined_components(&self) -> HashMap<String, QuarantinedComponent> {
+        self.diagnostics().quarantine.clone()
+    }
+
     /// Registers a decoder for a given exchange.
     ///
     /// This method maps an exchange identifier to a specific protocol simulation type.
@@ -145,16 +303,34 @@ where
             + Send
             + 'static,
     {
+        self.register_decoder_with::<T, _>(exchange, |state| state);
+    }
+
+    /// Registers a decoder for a given exchange, passing every decoded state through `configure`.
+    ///
+    /// Snapshots only carry the state of a component, so `configure` is the place to attach
+    /// anything else the state needs, e.g. the configured client an RFQ state requests binding
+    /// quotes with. See [`Self::register_decoder`].
+    pub fn register_decoder_with<T, F>(&mut self, exchange: &str, configure: F)
+    where
+        T: ProtocolSim
+            + TryFromWithBlock<ComponentWithState, H, Error = InvalidSnapshotError>
+            + Send
+            + 'static,
+        F: Fn(T) -> T + Send + Sync + 'static,
+    {
+        let configure = Arc::new(configure);
         let decoder = Box::new(
             move |component: ComponentWithState,
                   header: H,
                   account_balances: AccountBalances,
                   state: Arc<RwLock<DecoderState>>| {
+                let configure = configure.clone();
                 Box::pin(async move {
                     let guard = state.read().await;
                     T::try_from_with_header(component, header, &account_balances, &guard.tokens)
                         .await
-                        .map(|c| Box::new(c) as Box<dyn ProtocolSim>)
+                        .map(|c| Box::new(configure(c)) as Box<dyn ProtocolSim>)
                 }) as DecodeFut
             },
         );
@@ -177,9 +353,85 @@ where
     /// For example, you might use a filter to exclude pools that are not fully supported in the
     /// protocol, or to ignore pools with certain attributes that are irrelevant to your
     /// application.
-    pub fn register_filter(&mut self, exchange: &str, predicate: FilterFn) {
+    ///
+    /// The filter replaces any filter previously registered for the exchange. The predicate can
+    /// be a closure capturing configuration, e.g. a
+    /// [`ComponentFilterConfig`](crate::evm::protocol::filter_config::ComponentFilterConfig).
+    pub fn register_filter<F>(&mut self, exchange: &str, predicate: F)
+    where
+        F: Fn(&ComponentWithState) -> bool + Send + Sync + 'static,
+    {
         self.inclusion_filters
-            .insert(exchange.to_string(), predicate);
+            .insert(exchange.to_string(), Box::new(predicate));
+    }
+
+    /// Adds a client-side filter for a given exchange, in addition to the filter registered with
+    /// [`Self::register_filter`].
+    ///
+    /// Added filters are kept separately, so registering the exchange's filter before or after
+    /// adding them makes no difference. Components are only included if they pass all filters.
+    pub fn add_filter<F>(&mut self, exchange: &str, predicate: F)
+    where
+        F: Fn(&ComponentWithState) -> bool + Send + Sync + 'static,
+    {
+        self.additional_filters
+            .entry(exchange.to_string())
+            .or_default()
+            .push(Box::new(predicate));
+    }
+
+    /// Registers a filter re-evaluated on every new or updated state of a given exchange.
+    ///
+    /// Unlike the filters of [`Self::register_filter`], which only run on snapshots, this filter
+    /// runs whenever a component's state is decoded or changes. Components whose state no longer
+    /// passes the filter stop being tracked and are emitted in the update's `removed_pairs`. They
+    /// are only tracked again if Tycho sends a new snapshot of them. The filter replaces any state
+    /// filter previously registered for the exchange.
+    pub fn register_state_filter<F>(&mut self, exchange: &str, predicate: F)
+    where
+        F: Fn(&ProtocolComponent, &dyn ProtocolSim) -> bool + Send + Sync + 'static,
+    {
+        self.state_filters
+            .insert(exchange.to_string(), Box::new(predicate));
+    }
+
+    fn diagnostics(&self) -> MutexGuard<'_, DecodeDiagnostics> {
+        self.diagnostics
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Counts a decode failure in the stats of this message and the cumulative stats.
+    fn record_failure(
+        &self,
+        block_stats: &mut HashMap<String, DecodeStats>,
+        protocol: &str,
+        failure: DecodeFailure,
+    ) {
+        let mut diagnostics = self.diagnostics();
+        for stats in [
+            block_stats
+                .entry(protocol.to_string())
+                .or_default(),
+            diagnostics
+                .stats
+                .entry(protocol.to_string())
+                .or_default(),
+        ] {
+            match failure {
+                DecodeFailure::Snapshot => stats.snapshot_failures += 1,
+                DecodeFailure::Transition => stats.transition_failures += 1,
+                DecodeFailure::TokenSkip => stats.token_skips += 1,
+                DecodeFailure::MissingDecoder => stats.missing_decoder += 1,
+            }
+        }
+    }
+
+    fn quarantine(&self, protocol: &str, id: &str, error: String, block: u64) {
+        self.diagnostics().quarantine.insert(
+            id.to_string(),
+            QuarantinedComponent { protocol: protocol.to_string(), error, block },
+        );
     }
 
     /// Decodes a `FeedMessage` into a `BlockUpdate` containing the updated states of protocol
@@ -190,6 +442,10 @@ where
         let mut new_pairs = HashMap::new();
         let mut removed_pairs = HashMap::new();
         let mut contracts_map = HashMap::new();
+        // components that stopped passing their state filter
+        let mut untracked_components = HashSet::new();
+        // decode failures of this message
+        let mut decode_stats = HashMap::new();
 
         let header = msg
             .state_msgs
@@ -198,8 +454,18 @@ where
             .ok_or_else(|| StreamDecodeError::Fatal("Missing block!".into()))?
             .header
             .clone();
+        let block = header.block_number_or_timestamp();
 
         for (protocol, protocol_msg) in msg.state_msgs.iter() {
+            let state_filter = self
+                .state_filters
+                .get(protocol.as_str());
+            let previously_updated: HashSet<String> = if state_filter.is_some() {
+                updated_states.keys().cloned().collect()
+            } else {
+                HashSet::new()
+            };
+
             // Add any new tokens
             if let Some(deltas) = protocol_msg.deltas.as_ref() {
                 let mut state_guard = self.state.write().await;
@@ -207,10 +473,7 @@ where
                 let new_tokens = deltas
                     .new_tokens
                     .iter()
-                    .filter(|(addr, t)| {
-                        t.quality >= self.min_token_quality &&
-                            !state_guard.tokens.contains_key(*addr)
-                    })
+                    .filter(|(addr, _)| !state_guard.tokens.contains_key(*addr))
                     .filter_map(|(addr, t)| {
                         t.clone()
                             .try_into()
@@ -221,11 +484,12 @@ where
                             })
                             .ok()
                     })
+                    .filter(|(_, token)| self.token_policy.admits(token))
                     .collect::<HashMap<Bytes, Token>>();
 
                 if !new_tokens.is_empty() {
                     debug!(n = new_tokens.len(), "NewTokens");
-                    state_guard.tokens.extend(new_tokens);
+                    state_guard.extend_tokens(new_tokens);
                 }
             }
 
@@ -268,6 +532,12 @@ where
                     })
                     .collect();
 
+                for id in protocol_msg.removed_components.keys() {
+                    state_guard
+                        .pending_components
+                        .remove(id);
+                }
+
                 // Remove components from state and add to removed_pairs
                 for (id, component) in removed_components {
                     state_guard.components.remove(&id);
@@ -366,27 +636,102 @@ where
                 })
                 .collect::<AccountBalances>();
 
+            // Skip any unsupported pools
+            let mut snapshots: Vec<(String, ComponentWithState, AccountBalances)> = protocol_msg
+                .snapshots
+                .get_states()
+                .clone()
+                .into_iter()
+                .filter(|(_, snapshot)| {
+                    self.inclusion_filters
+                        .get(protocol.as_str())
+                        .is_none_or(|predicate| predicate(snapshot)) &&
+                        self.additional_filters
+                            .get(protocol.as_str())
+                            .is_none_or(|predicates| {
+                                predicates
+                                    .iter()
+                                    .all(|predicate| predicate(snapshot))
+                            })
+                })
+                .map(|(id, snapshot)| (id, snapshot, account_balances.clone()))
+                .collect();
+
+            // Resolve the unknown tokens of the new components
+            if let Some(source) = &self.token_source {
+                let unknown_tokens: Vec<Bytes> = {
+                    let state_guard = self.state.read().await;
+                    snapshots
+                        .iter()
+                        .flat_map(|(_, snapshot, _)| snapshot.component.tokens.iter())
+                        .filter(|token| !state_guard.tokens.contains_key(*token))
+                        .cloned()
+                        .collect::<HashSet<_>>()
+                        .into_iter()
+                        .collect()
+                };
+                if !unknown_tokens.is_empty() {
+                    match source
+                        .fetch_tokens(&unknown_tokens)
+                        .await
+                        .map(|mut tokens| {
+                            tokens.retain(|_, token| self.token_policy.admits(token));
+                            tokens
+                        }) {
+                        Ok(tokens) if !tokens.is_empty() => {
+                            debug!(n = tokens.len(), "FetchedTokens");
+                            let mut state_guard = self.state.write().await;
+                            state_guard.extend_tokens(tokens);
+                        }
+                        Ok(_) => {}
+                        Err(e) => warn!(error = %e, "Failed to fetch unknown tokens"),
+                    }
+                }
+            }
+
+            // Retry the components skipped earlier whose tokens are now known
+            let mut replays = HashMap::new();
+            {
+                let mut state_guard = self.state.write().await;
+                for (id, _, _) in snapshots.iter() {
+                    state_guard
+                        .pending_components
+                        .remove(id);
+                }
+                let ready: Vec<String> = state_guard
+                    .pending_components
+                    .iter()
+                    .filter(|(_, pending)| {
+                        &pending.protocol == protocol &&
+                            pending
+                                .snapshot
+                                .component
+                                .tokens
+                                .iter()
+                                .all(|token| state_guard.tokens.contains_key(token))
+                    })
+                    .map(|(id, _)| id.clone())
+                    .collect();
+                for id in ready {
+                    if let Some(pending) = state_guard
+                        .pending_components
+                        .remove(&id)
+                    {
+                        debug!(pool = id, "Retrying component with newly known tokens");
+                        replays.insert(id.clone(), pending.deltas);
+                        snapshots.push((id, pending.snapshot, pending.account_balances));
+                    }
+                }
+            }
+
             let mut new_components = HashMap::new();
             let mut count_token_skips = 0;
+            let mut skipped_components = HashMap::new();
             let mut components_to_store = HashMap::new();
             {
                 let state_guard = self.state.read().await;
                 // PROCESS SNAPSHOTS
-                'outer: for (id, snapshot) in protocol_msg
-                    .snapshots
-                    .get_states()
-                    .clone()
-                {
-                    // Skip any unsupported pools
-                    if let Some(predicate) = self
-                        .inclusion_filters
-                        .get(protocol.as_str())
-                    {
-                        if !predicate(&snapshot) {
-                            continue;
-                        }
-                    }
-
+                'outer: for (id, snapshot, snapshot_balances) in snapshots {
                     // Construct component from snapshot
                     let mut component_tokens = Vec::new();
                     let mut new_tokens_accounts = HashMap::new();
@@ -426,7 +771,30 @@ where
                             }
                             None => {
                                 count_token_skips += 1;
+                                self.record_failure(
+                                    &mut decode_stats,
+                                    protocol,
+                                    DecodeFailure::TokenSkip,
+                                );
                                 debug!("Token not found {}, ignoring pool {:x?}", token, id);
+                                let account_balances = snapshot_balances
+                                    .into_iter()
+                                    .filter(|(addr, _)| {
+                                        snapshot
+                                            .component
+                                            .contract_ids
+                                            .contains(addr)
+                                    })
+                                    .collect();
+                                skipped_components.insert(
+                                    id,
+                                    PendingComponent {
+                                        protocol: protocol.clone(),
+                                        snapshot,
+                                        account_balances,
+                                        deltas: Vec::new(),
+                                    },
+                                );
                                 continue 'outer;
                             }
                         }
@@ -472,15 +840,48 @@ where
                         match state_decode_f(
                             snapshot,
                             header.clone(),
-                            account_balances.clone(),
+                            snapshot_balances,
                             self.state.clone(),
                         )
                         .await
                         {
-                            Ok(state) => {
+                            Ok(mut state) => {
+                                // Catch up with the deltas received while the component waited
+                                // for its tokens
+                                for (delta, balances) in replays.remove(&id).unwrap_or_default() {
+                                    if let Err(e) = state.delta_transition(
+                                        delta,
+                                        &state_guard.tokens,
+                                        &balances,
+                                    ) {
+                                        self.record_failure(
+                                            &mut decode_stats,
+                                            protocol,
+                                            DecodeFailure::Transition,
+                                        );
+                                        self.quarantine(protocol, &id, format!("{e:?}"), block);
+                                        if self.skip_state_decode_failures {
+                                            warn!(pool = id, error = ?e, "Failed to replay delta");
+                                            continue 'outer;
+                                        }
+                                        error!(pool = id, error = ?e, "DeltaTransitionError");
+                                        return Err(StreamDecodeError::Fatal(format!(
+                                            "TransitionFailure: {e:?}"
+                                        )));
+                                    }
+                                }
+                                self.diagnostics()
+                                    .quarantine
+                                    .remove(&id);
                                 new_components.insert(id.clone(), state);
                             }
                             Err(e) => {
+                                self.record_failure(
+                                    &mut decode_stats,
+                                    protocol,
+                                    DecodeFailure::Snapshot,
+                                );
+                                self.quarantine(protocol, &id, e.to_string(), block);
                                 if self.skip_state_decode_failures {
                                     warn!(pool = id, error = %e, "StateDecodingFailure");
                                     continue 'outer;
@@ -490,10 +891,22 @@ where
                                 }
                             }
                         }
-                    } else if self.skip_state_decode_failures {
-                        warn!(pool = id, "MissingDecoderRegistration");
-                        continue 'outer;
                     } else {
+                        self.record_failure(
+                            &mut decode_stats,
+                            protocol,
+                            DecodeFailure::MissingDecoder,
+                        );
+                        self.quarantine(
+                            protocol,
+                            &id,
+                            format!("Missing decoder registration for: {protocol}"),
+                            block,
+                        );
+                        if self.skip_state_decode_failures {
+                            warn!(pool = id, "MissingDecoderRegistration");
+                            continue 'outer;
+                        }
                         error!(pool = id, "MissingDecoderRegistration");
                         return Err(StreamDecodeError::Fatal(format!(
                             "Missing decoder registration for: {id}"
@@ -503,13 +916,16 @@ where
             }
 
             // Batch insert components into state
-            if !components_to_store.is_empty() {
+            if !components_to_store.is_empty() || !skipped_components.is_empty() {
                 let mut state_guard = self.state.write().await;
                 for (id, component) in components_to_store {
                     state_guard
                         .components
                         .insert(id, component);
                 }
+                state_guard
+                    .pending_components
+                    .extend(skipped_components);
             }
 
             if !protocol_msg.snapshots.states.is_empty() {
@@ -643,8 +1059,54 @@ where
                         .collect(),
                 };
 
+                // Buffer the deltas of components waiting for their tokens
+                let (pending_updates, state_updates): (Vec<_>, Vec<_>) = deltas
+                    .state_updates
+                    .into_iter()
+                    .partition(|(id, _)| {
+                        state_guard
+                            .pending_components
+                            .contains_key(id)
+                    });
+                let mut buffered_deltas: HashMap<String, ProtocolStateDelta> =
+                    pending_updates.into_iter().collect();
+                for id in all_balances.component_balances.keys() {
+                    if state_guard
+                        .pending_components
+                        .contains_key(id)
+                    {
+                        buffered_deltas
+                            .entry(id.clone())
+                            .or_default();
+                    }
+                }
+                let buffered_deltas: Vec<(String, ProtocolStateDelta, Balances)> = buffered_deltas
+                    .into_iter()
+                    .map(|(id, delta)| {
+                        pools_to_update.remove(&id);
+                        let contracts = &state_guard.pending_components[&id]
+                            .snapshot
+                            .component
+                            .contract_ids;
+                        let balances = Balances {
+                            component_balances: all_balances
+                                .component_balances
+                                .get(&id)
+                                .map(|balances| HashMap::from([(id.clone(), balances.clone())]))
+                                .unwrap_or_default(),
+                            account_balances: all_balances
+                                .account_balances
+                                .iter()
+                                .filter(|(account, _)| contracts.contains(account))
+                                .map(|(account, balances)| (account.clone(), balances.clone()))
+                                .collect(),
+                        };
+                        (id, delta, balances)
+                    })
+                    .collect();
+
                 // update states with protocol state deltas (attribute changes etc.)
-                for (id, update) in deltas.state_updates {
+                for (id, update) in state_updates {
                     match Self::apply_update(
                         &id,
                         update,
@@ -656,6 +1118,12 @@ where
                             pools_to_update.remove(&id);
                         }
                         Err(e) => {
+                            self.record_failure(
+                                &mut decode_stats,
+                                protocol,
+                                DecodeFailure::Transition,
+                            );
+                            self.quarantine(protocol, &id, e.to_string(), block);
                             if self.skip_state_decode_failures {
                                 warn!(pool = id, error = %e, "Failed to apply state update, marking component as removed");
                                 // Remove from updated_states if it was there
@@ -689,6 +1157,12 @@ where
                     ) {
                         Ok(_) => {}
                         Err(e) => {
+                            self.record_failure(
+                                &mut decode_stats,
+                                protocol,
+                                DecodeFailure::Transition,
+                            );
+                            self.quarantine(protocol, &pool, e.to_string(), block);
                             if self.skip_state_decode_failures {
                                 warn!(pool = pool, error = %e, "Failed to apply contract/balance update, marking component as removed");
                                 // Remove from updated_states if it was there
@@ -709,7 +1183,54 @@ where
                         }
                     }
                 }
+                drop(state_guard);
+
+                if !buffered_deltas.is_empty() {
+                    let mut state_guard = self.state.write().await;
+                    for (id, delta, balances) in buffered_deltas {
+                        if let Some(pending) = state_guard
+                            .pending_components
+                            .get_mut(&id)
+                        {
+                            pending.deltas.push((delta, balances));
+                        }
+                    }
+                }
             };
+
+            // Stop tracking components whose new state doesn't pass the state filter
+            if let Some(predicate) = state_filter {
+                let state_guard = self.state.read().await;
+                let rejected: Vec<String> = updated_states
+                    .iter()
+                    .filter(|(id, _)| !previously_updated.contains(*id))
+                    .filter(|(id, state)| {
+                        new_pairs
+                            .get(*id)
+                            .or_else(|| state_guard.components.get(*id))
+                            .is_some_and(|component| !predicate(component, state.as_ref()))
+                    })
+                    .map(|(id, _)| id.clone())
+                    .collect();
+                for id in rejected {
+                    debug!(pool = id, "Component state filtered out");
+                    updated_states.remove(&id);
+                    // New components are not emitted at all
+                    if new_pairs.remove(&id).is_none() {
+                        if let Some(component) = state_guard.components.get(&id) {
+                            removed_pairs.insert(id.clone(), component.clone());
+                        }
+                    }
+                    untracked_components.insert(id);
+                }
+            }
+        }
+
+        // Deltas don't carry their block, so move the states keeping one to the current block
+        if let Some(block_header) = header.clone().block() {
+            for state in updated_states.values_mut() {
+                set_block(state.as_any_mut(), &block_header);
+            }
         }
 
         // Persist the newly added/updated states
@@ -729,6 +1250,10 @@ where
         for (id, _) in removed_pairs.iter() {
             state_guard.components.remove(id);
         }
+        for id in untracked_components.iter() {
+            state_guard.components.remove(id);
+            state_guard.states.remove(id);
+        }
 
         for (key, values) in contracts_map {
             state_guard
@@ -737,11 +1262,25 @@ where
                 .or_insert_with(HashSet::new)
                 .extend(values);
         }
+        // Contract changes no longer update untracked components
+        if !untracked_components.is_empty() {
+            state_guard
+                .contracts_map
+                .retain(|_, ids| {
+                    ids.retain(|id| !untracked_components.contains(id));
+                    !ids.is_empty()
+                });
+        }
+
+        if !self.include_decode_stats {
+            decode_stats.clear();
+        }
 
         // Send the tick with all updated states
-        Ok(Update::new(header.block_number_or_timestamp(), updated_states, new_pairs)
+        Ok(Update::new(block, updated_states, new_pairs)
             .set_removed_pairs(removed_pairs)
-            .set_sync_states(msg.sync_states))
+            .set_sync_states(msg.sync_states)
+            .set_decode_stats(decode_stats))
     }
 
     fn apply_update(
@@ -960,6 +1499,165 @@ mod tests {
         assert_eq!(res2.sync_states.len(), 1);
     }
 
+    #[tokio::test]
+    async fn test_decode_with_transfer_taxes() {
+        let decoder = setup_decoder(true).await;
+        let usdt = Bytes::from("0xdac17f958d2ee523a2206206994597c13d831ec7").lpad(20, 0);
+        decoder
+            .set_transfer_taxes(HashMap::from([(usdt.clone(), 200)]))
+            .await;
+
+        let res = decoder
+            .decode(load_test_msg("uniswap_v2_snapshot"))
+            .await
+            .expect("decode failure");
+
+        let component = res
+            .new_pairs
+            .values()
+            .next()
+            .expect("Missing new pair");
+        for token in &component.tokens {
+            let expected_tax = if token.address == usdt { 200 } else { 100 };
+            assert_eq!(token.tax, expected_tax);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update_transfer_taxes() {
+        async fn usdt_taxes(decoder: &TychoStreamDecoder<BlockHeader>, usdt: &Bytes) -> (u64, u64) {
+            let guard = decoder.state.read().await;
+            let component_tax = guard
+                .components
+                .values()
+                .flat_map(|component| component.tokens.iter())
+                .find(|token| token.address == *usdt)
+                .expect("Missing USDT in the tracked component")
+                .tax;
+            (guard.tokens[usdt].tax, component_tax)
+        }
+
+        let decoder = setup_decoder(true).await;
+        let usdt = Bytes::from("0xdac17f958d2ee523a2206206994597c13d831ec7").lpad(20, 0);
+        decoder
+            .decode(load_test_msg("uniswap_v2_snapshot"))
+            .await
+            .expect("decode failure");
+        assert_eq!(usdt_taxes(&decoder, &usdt).await, (100, 100));
+
+        decoder
+            .set_transfer_taxes(HashMap::from([(usdt.clone(), 200)]))
+            .await;
+        assert_eq!(usdt_taxes(&decoder, &usdt).await, (200, 200));
+
+        decoder
+            .set_transfer_taxes(HashMap::from([(usdt.clone(), 300)]))
+            .await;
+        assert_eq!(usdt_taxes(&decoder, &usdt).await, (300, 300));
+
+        // Removing the override restores the indexed tax
+        decoder
+            .set_transfer_taxes(HashMap::new())
+            .await;
+        assert_eq!(usdt_taxes(&decoder, &usdt).await, (100, 100));
+    }
+
+    #[tokio::test]
+    async fn test_decode_with_state_filter() {
+        let mut decoder = setup_decoder(true).await;
+        let include = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let include_clone = include.clone();
+        decoder.register_state_filter("uniswap_v2", move |_, _| {
+            include_clone.load(std::sync::atomic::Ordering::Relaxed)
+        });
+
+        let res1 = decoder
+            .decode(load_test_msg("uniswap_v2_snapshot"))
+            .await
+            .expect("decode failure");
+        assert_eq!(res1.states.len(), 1);
+        assert_eq!(res1.new_pairs.len(), 1);
+
+        include.store(false, std::sync::atomic::Ordering::Relaxed);
+        let res2 = decoder
+            .decode(load_test_msg("uniswap_v2_delta"))
+            .await
+            .expect("decode failure");
+        assert!(res2.states.is_empty());
+        assert_eq!(
+            res2.removed_pairs
+                .keys()
+                .collect::<Vec<_>>(),
+            res1.new_pairs
+                .keys()
+                .collect::<Vec<_>>()
+        );
+        assert!(decoder
+            .state
+            .read()
+            .await
+            .states
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_untracked_component_removed_from_contracts_map() {
+        let mut decoder = setup_decoder(true).await;
+        decoder.register_state_filter("uniswap_v2", |_, _| false);
+        let contract = Bytes::from("0xba12222222228d8ba445958a75a0704d566bf2c8").lpad(20, 0);
+        let other_contract = Bytes::from("0xba100000625a3754423978a60c9317c58a424e3d").lpad(20, 0);
+        let pool_id = "0x0d4a11d5eeaac28ec3f61d100daf4d40471f1852".to_string();
+        {
+            let mut state_guard = decoder.state.write().await;
+            state_guard
+                .contracts_map
+                .insert(contract.clone(), HashSet::from([pool_id.clone()]));
+            state_guard.contracts_map.insert(
+                other_contract.clone(),
+                HashSet::from([pool_id.clone(), "other".to_string()]),
+            );
+        }
+
+        let res = decoder
+            .decode(load_test_msg("uniswap_v2_snapshot"))
+            .await
+            .expect("decode failure");
+        assert!(res.states.is_empty());
+
+        let state_guard = decoder.state.read().await;
+        assert!(!state_guard
+            .contracts_map
+            .contains_key(&contract));
+        assert_eq!(
+            state_guard.contracts_map[&other_contract],
+            HashSet::from(["other".to_string()])
+        );
+    }
+
+    #[rstest]
+    #[case::added_first(true)]
+    #[case::registered_first(false)]
+    #[tokio::test]
+    async fn test_decode_with_added_filter(#[case] add_first: bool) {
+        let mut decoder = setup_decoder(true).await;
+        if add_first {
+            decoder.add_filter("uniswap_v2", |_| false);
+            decoder.register_filter("uniswap_v2", |_| true);
+        } else {
+            decoder.register_filter("uniswap_v2", |_| true);
+            decoder.add_filter("uniswap_v2", |_| false);
+        }
+
+        let res = decoder
+            .decode(load_test_msg("uniswap_v2_snapshot"))
+            .await
+            .expect("decode failure");
+
+        // The added filter rejects the component regardless of the order
+        assert!(res.states.is_empty());
+        assert!(res.new_pairs.is_empty());
+    }
+
     #[tokio::test]
     async fn test_decode_component_missing_token() {
         let decoder = setup_decoder(false).await;
@@ -984,6 +1682,158 @@ mod tests {
         assert_eq!(res1.states.len(), 0);
     }
 
+    struct StaticTokenSource(HashMap<Bytes, Token>);
+
+    impl TokenSource for StaticTokenSource {
+        fn fetch_tokens<'a>(
+            &'a self,
+            addresses: &'a [Bytes],
+        ) -> crate::evm::token_registry::TokenFut<'a> {
+            Box::pin(async move {
+                Ok(addresses
+                    .iter()
+                    .filter_map(|addr| {
+                        self.0
+                            .get(addr)
+                            .map(|token| (addr.clone(), token.clone()))
+                    })
+                    .collect())
+            })
+        }
+    }
+
+    fn test_token(addr: &Bytes) -> (Bytes, Token) {
+        let addr_str = format!("{addr:x}");
+        (addr.clone(), Token::new(addr, &addr_str, 18, 100, &[Some(100_000)], Chain::Ethereum, 100))
+    }
+
+    #[tokio::test]
+    async fn test_decode_with_token_source() {
+        let mut decoder = setup_decoder(false).await;
+        let weth = Bytes::from("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").lpad(20, 0);
+        let usdt = Bytes::from("0xdac17f958d2ee523a2206206994597c13d831ec7").lpad(20, 0);
+        decoder
+            .set_tokens(HashMap::from([test_token(&weth)]))
+            .await;
+        decoder.set_token_source(StaticTokenSource(HashMap::from([test_token(&usdt)])));
+
+        let res = decoder
+            .decode(load_test_msg("uniswap_v2_snapshot"))
+            .await
+            .expect("decode failure");
+
+        assert_eq!(res.states.len(), 1);
+        assert!(decoder
+            .state
+            .read()
+            .await
+            .tokens
+            .contains_key(&usdt));
+    }
+
+    #[tokio::test]
+    async fn test_decode_with_token_policy() {
+        let mut decoder = setup_decoder(true).await;
+        let usdt = Bytes::from("0xdac17f958d2ee523a2206206994597c13d831ec7").lpad(20, 0);
+        decoder
+            .set_token_policy(TokenPolicy::default().deny([usdt.clone()]))
+            .await;
+        decoder.set_token_source(StaticTokenSource(HashMap::from([test_token(&usdt)])));
+
+        let res = decoder
+            .decode(load_test_msg("uniswap_v2_snapshot"))
+            .await
+            .expect("decode failure");
+
+        assert!(res.states.is_empty());
+        assert!(!decoder
+            .state
+            .read()
+            .await
+            .tokens
+            .contains_key(&usdt));
+    }
+
+    #[tokio::test]
+    async fn test_decode_retries_component_with_new_tokens() {
+        let decoder = setup_decoder(false).await;
+        let weth = Bytes::from("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").lpad(20, 0);
+        let usdt = Bytes::from("0xdac17f958d2ee523a2206206994597c13d831ec7").lpad(20, 0);
+        decoder
+            .set_tokens(HashMap::from([test_token(&weth)]))
+            .await;
+
+        let res1 = decoder
+            .decode(load_test_msg("uniswap_v2_snapshot"))
+            .await
+            .expect("decode failure");
+        assert!(res1.states.is_empty());
+
+        decoder
+            .set_tokens(HashMap::from([test_token(&weth), test_token(&usdt)]))
+            .await;
+        let res2 = decoder
+            .decode(load_test_msg("uniswap_v2_delta"))
+            .await
+            .expect("decode failure");
+
+        assert_eq!(res2.states.len(), 1);
+        assert_eq!(res2.new_pairs.len(), 1);
+        assert!(decoder
+            .state
+            .read()
+            .await
+            .pending_components
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_decode_replays_deltas_of_retried_component() {
+        let decoder = setup_decoder(false).await;
+        let weth = Bytes::from("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").lpad(20, 0);
+        let usdt = Bytes::from("0xdac17f958d2ee523a2206206994597c13d831ec7").lpad(20, 0);
+        let pool_id = "0x0d4a11d5eeaac28ec3f61d100daf4d40471f1852";
+        decoder
+            .set_tokens(HashMap::from([test_token(&weth)]))
+            .await;
+
+        // The component is skipped, then receives a delta while waiting for its tokens
+        decoder
+            .decode(load_test_msg("uniswap_v2_snapshot"))
+            .await
+            .expect("decode failure");
+        let res = decoder
+            .decode(load_test_msg("uniswap_v2_delta"))
+            .await
+            .expect("decode failure");
+        assert!(res.states.is_empty());
+
+        decoder
+            .set_tokens(HashMap::from([test_token(&weth), test_token(&usdt)]))
+            .await;
+        let mut msg = load_test_msg("uniswap_v2_delta");
+        let deltas = msg
+            .state_msgs
+            .get_mut("uniswap_v2")
+            .unwrap()
+            .deltas
+            .as_mut()
+            .unwrap();
+        deltas.state_updates.clear();
+        deltas.component_balances.clear();
+        let res = decoder
+            .decode(msg)
+            .await
+            .expect("decode failure");
+
+        let state = res.states[pool_id]
+            .as_any()
+            .downcast_ref::<UniswapV2State>()
+            .unwrap();
+        assert_eq!(state.reserve0, U256::from_str("0x02a17f13e7674e01a281").unwrap());
+        assert_eq!(state.reserve1, U256::from_str("0x288c879fc6e0").unwrap());
+    }
+
     #[rstest]
     #[case(true)]
     #[case(false)]
@@ -1041,6 +1891,30 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_decode_stats_and_quarantine() {
+        let mut decoder = setup_decoder(true).await;
+        decoder.skip_state_decode_failures(true);
+        decoder.include_decode_stats(true);
+
+        let msg = load_test_msg("uniswap_v2_snapshot_broken_state");
+        let res = decoder
+            .decode(msg)
+            .await
+            .expect("decode failure");
+
+        let expected = DecodeStats { snapshot_failures: 1, ..Default::default() };
+        assert_eq!(res.decode_stats.get("uniswap_v2"), Some(&expected));
+        assert_eq!(decoder.decode_stats().get("uniswap_v2"), Some(&expected));
+        let quarantine = decoder.quarantined_components();
+        let quarantined = quarantine
+            .get("0x0d4a11d5eeaac28ec3f61d100daf4d40471f1852")
+            .expect("component should be quarantined");
+        assert_eq!(quarantined.protocol, "uniswap_v2");
+        assert_eq!(quarantined.error, "Missing attributes reserve0");
+        assert_eq!(quarantined.block, 21284145);
+    }
+
     #[tokio::test]
     async fn test_decode_updates_state_on_contract_change() {
         let decoder = setup_decoder(true).await;
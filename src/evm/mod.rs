@@ -7,6 +7,7 @@ pub mod engine_db;
 pub mod protocol;
 pub mod simulation;
 pub mod stream;
+pub mod token_registry;
 pub mod traces;
 pub mod tycho_models;
 
@@ -0,0 +1,103 @@
+//! Port of Balancer V2's `FixedPoint` library, operating on 18 decimal fixed point numbers.
+//!
+//! See https://github.com/balancer/balancer-v2-monorepo/blob/master/pkg/solidity-utils/contracts/math/FixedPoint.sol
+use alloy::primitives::U256;
+use tycho_common::simulation::errors::SimulationError;
+
+use super::log_exp_math;
+use crate::evm::protocol::safe_math::{safe_add_u256, safe_div_u256, safe_mul_u256, safe_sub_u256};
+
+pub(crate) const ONE: U256 = U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0]);
+const TWO: U256 = U256::from_limbs([2_000_000_000_000_000_000, 0, 0, 0]);
+const FOUR: U256 = U256::from_limbs([4_000_000_000_000_000_000, 0, 0, 0]);
+/// Relative error bound of `LogExpMath.pow`, 1e-14
+const MAX_POW_RELATIVE_ERROR: U256 = U256::from_limbs([10_000, 0, 0, 0]);
+
+pub(crate) fn mul_down(a: U256, b: U256) -> Result<U256, SimulationError> {
+    safe_div_u256(safe_mul_u256(a, b)?, ONE)
+}
+
+pub(crate) fn mul_up(a: U256, b: U256) -> Result<U256, SimulationError> {
+    let product = safe_mul_u256(a, b)?;
+    if product.is_zero() {
+        return Ok(U256::ZERO);
+    }
+    Ok((product - U256::from(1)) / ONE + U256::from(1))
+}
+
+pub(crate) fn div_down(a: U256, b: U256) -> Result<U256, SimulationError> {
+    safe_div_u256(safe_mul_u256(a, ONE)?, b)
+}
+
+pub(crate) fn div_up(a: U256, b: U256) -> Result<U256, SimulationError> {
+    if b.is_zero() {
+        return Err(SimulationError::FatalError("Division by zero".to_string()));
+    }
+    if a.is_zero() {
+        return Ok(U256::ZERO);
+    }
+    Ok((safe_mul_u256(a, ONE)? - U256::from(1)) / b + U256::from(1))
+}
+
+/// Returns `1 - x`, or zero if `x` is larger than one.
+pub(crate) fn complement(x: U256) -> U256 {
+    if x < ONE {
+        ONE - x
+    } else {
+        U256::ZERO
+    }
+}
+
+/// Returns `x^y`, rounded up to an upper bound of the exact result.
+pub(crate) fn pow_up(x: U256, y: U256) -> Result<U256, SimulationError> {
+    if y == ONE {
+        Ok(x)
+    } else if y == TWO {
+        mul_up(x, x)
+    } else if y == FOUR {
+        let square = mul_up(x, x)?;
+        mul_up(square, square)
+    } else {
+        let raw = log_exp_math::pow(x, y)?;
+        let max_error = safe_add_u256(mul_up(raw, MAX_POW_RELATIVE_ERROR)?, U256::from(1))?;
+        safe_add_u256(raw, max_error)
+    }
+}
+
+/// Subtracts `b` from `a`, erroring with `message` on underflow.
+pub(crate) fn sub(a: U256, b: U256, message: &str) -> Result<U256, SimulationError> {
+    safe_sub_u256(a, b).map_err(|_| SimulationError::FatalError(message.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_rounding() {
+        let a = U256::from(3);
+        let b = U256::from_str("333333333333333333").unwrap();
+
+        assert_eq!(mul_down(a, b).unwrap(), U256::ZERO);
+        assert_eq!(mul_up(a, b).unwrap(), U256::from(1));
+        assert_eq!(div_down(U256::from(1), U256::from(3)).unwrap(), b);
+        assert_eq!(div_up(U256::from(1), U256::from(3)).unwrap(), b + U256::from(1));
+        assert_eq!(div_up(U256::ZERO, U256::from(3)).unwrap(), U256::ZERO);
+    }
+
+    #[test]
+    fn test_pow_up() {
+        let x = U256::from_str("2000000000000000000").unwrap();
+
+        // Special cases are exact
+        assert_eq!(pow_up(x, ONE).unwrap(), x);
+        assert_eq!(pow_up(x, TWO).unwrap(), U256::from_str("4000000000000000000").unwrap());
+        // sqrt(2) = 1.414213562373095048..., pow is rounded up by its max error
+        assert_eq!(
+            pow_up(x, ONE / U256::from(2)).unwrap(),
+            U256::from_str("1414213562373109191").unwrap()
+        );
+    }
+}
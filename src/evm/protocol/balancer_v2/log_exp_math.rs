@@ -0,0 +1,239 @@
+//! Port of Balancer V2's `LogExpMath` library, computing exponentials and logarithms of 18 decimal
+//! fixed point numbers.
+//!
+//! Intermediate values use 20 and 36 decimals to reduce rounding errors. All divisions truncate
+//! towards zero like in Solidity, so results match the contracts exactly.
+//!
+//! See https://github.com/balancer/balancer-v2-monorepo/blob/master/pkg/solidity-utils/contracts/math/LogExpMath.sol
+use alloy::primitives::{uint, I256, U256};
+use tycho_common::simulation::errors::SimulationError;
+
+use crate::evm::protocol::safe_math::safe_mul_i256;
+
+const fn int(value: U256) -> I256 {
+    I256::from_raw(value)
+}
+
+const ONE_18: I256 = int(uint!(1000000000000000000_U256));
+const ONE_20: I256 = int(uint!(100000000000000000000_U256));
+const ONE_36: I256 = int(uint!(1000000000000000000000000000000000000_U256));
+
+const MAX_NATURAL_EXPONENT: I256 = int(uint!(130000000000000000000_U256));
+// -41e18
+const MIN_NATURAL_EXPONENT: I256 =
+    int(uint!(0xfffffffffffffffffffffffffffffffffffffffffffffffdc702bd3a30fc0000_U256));
+
+// Bounds for ln_36's argument, (1e18 - 1e17, 1e18 + 1e17)
+const LN_36_LOWER_BOUND: I256 = int(uint!(900000000000000000_U256));
+const LN_36_UPPER_BOUND: I256 = int(uint!(1100000000000000000_U256));
+
+// 2^254 / ONE_20, bound for the exponent so that ln(x) * y can't overflow
+const MILD_EXPONENT_BOUND: U256 =
+    uint!(289480223093290488558927462521719769633174961664101410098_U256);
+
+// 18 decimal constants, a_n = e^(x_n) without decimals
+const X0: I256 = int(uint!(128000000000000000000_U256));
+const A0: I256 = int(uint!(38877084059945950922200000000000000000000000000000000000_U256));
+const X1: I256 = int(uint!(64000000000000000000_U256));
+const A1: I256 = int(uint!(6235149080811616882910000000_U256));
+
+// 20 decimal constants, a_n = e^(x_n)
+const X2: I256 = int(uint!(3200000000000000000000_U256));
+const A2: I256 = int(uint!(7896296018268069516100000000000000_U256));
+const X3: I256 = int(uint!(1600000000000000000000_U256));
+const A3: I256 = int(uint!(888611052050787263676000000_U256));
+const X4: I256 = int(uint!(800000000000000000000_U256));
+const A4: I256 = int(uint!(298095798704172827474000_U256));
+const X5: I256 = int(uint!(400000000000000000000_U256));
+const A5: I256 = int(uint!(5459815003314423907810_U256));
+const X6: I256 = int(uint!(200000000000000000000_U256));
+const A6: I256 = int(uint!(738905609893065022723_U256));
+const X7: I256 = int(uint!(100000000000000000000_U256));
+const A7: I256 = int(uint!(271828182845904523536_U256));
+const X8: I256 = int(uint!(50000000000000000000_U256));
+const A8: I256 = int(uint!(164872127070012814685_U256));
+const X9: I256 = int(uint!(25000000000000000000_U256));
+const A9: I256 = int(uint!(128402541668774148407_U256));
+const X10: I256 = int(uint!(12500000000000000000_U256));
+const A10: I256 = int(uint!(113314845306682631683_U256));
+const X11: I256 = int(uint!(6250000000000000000_U256));
+const A11: I256 = int(uint!(106449445891785942956_U256));
+
+/// Terms used to reduce the argument of `exp` (`X2` to `X9`) and `ln` (`X2` to `X11`).
+const REDUCTION_TERMS: [(I256, I256); 10] = [
+    (X2, A2),
+    (X3, A3),
+    (X4, A4),
+    (X5, A5),
+    (X6, A6),
+    (X7, A7),
+    (X8, A8),
+    (X9, A9),
+    (X10, A10),
+    (X11, A11),
+];
+
+fn i256(value: u64) -> I256 {
+    I256::from_raw(U256::from(value))
+}
+
+/// Returns `x^y`, for 18 decimal fixed point `x` and `y`.
+pub(super) fn pow(x: U256, y: U256) -> Result<U256, SimulationError> {
+    if y.is_zero() {
+        return Ok(ONE_18.into_raw());
+    }
+    if x.is_zero() {
+        return Ok(U256::ZERO);
+    }
+    if x.bit(255) {
+        return Err(SimulationError::FatalError("Pow base out of bounds".to_string()));
+    }
+    if y >= MILD_EXPONENT_BOUND {
+        return Err(SimulationError::FatalError("Pow exponent out of bounds".to_string()));
+    }
+    let (x, y) = (int(x), int(y));
+
+    let logx_times_y = if LN_36_LOWER_BOUND < x && x < LN_36_UPPER_BOUND {
+        let ln_36_x = ln_36(x);
+        // ln_36_x has 36 decimals, split it to avoid overflows when multiplying by y
+        (ln_36_x / ONE_18) * y + ((ln_36_x % ONE_18) * y) / ONE_18
+    } else {
+        safe_mul_i256(ln(x), y)?
+    };
+    let logx_times_y = logx_times_y / ONE_18;
+
+    if logx_times_y < MIN_NATURAL_EXPONENT || logx_times_y > MAX_NATURAL_EXPONENT {
+        return Err(SimulationError::FatalError("Pow result out of bounds".to_string()));
+    }
+    Ok(exp(logx_times_y).into_raw())
+}
+
+/// Returns `e^x` for an 18 decimal fixed point `x` between the natural exponent bounds.
+fn exp(x: I256) -> I256 {
+    if x.is_negative() {
+        // e^(-x) = 1 / e^x
+        return (ONE_18 * ONE_18) / exp(-x);
+    }
+
+    let mut x = x;
+    let first_an = if x >= X0 {
+        x -= X0;
+        A0
+    } else if x >= X1 {
+        x -= X1;
+        A1
+    } else {
+        I256::ONE
+    };
+
+    // Switch to 20 decimals for higher precision
+    x *= i256(100);
+
+    let mut product = ONE_20;
+    for (x_n, a_n) in REDUCTION_TERMS.iter().take(8) {
+        if x >= *x_n {
+            x -= *x_n;
+            product = (product * *a_n) / ONE_20;
+        }
+    }
+
+    // Taylor series for the remaining x, which is now smaller than X9
+    let mut series_sum = ONE_20 + x;
+    let mut term = x;
+    for n in 2..=12 {
+        term = ((term * x) / ONE_20) / i256(n);
+        series_sum += term;
+    }
+
+    (((product * series_sum) / ONE_20) * first_an) / i256(100)
+}
+
+/// Returns the natural logarithm of an 18 decimal fixed point `a`.
+fn ln(a: I256) -> I256 {
+    if a < ONE_18 {
+        // ln(a) = -ln(1 / a)
+        return -ln((ONE_18 * ONE_18) / a);
+    }
+
+    let mut a = a;
+    let mut sum = I256::ZERO;
+    if a >= A0 * ONE_18 {
+        a /= A0;
+        sum += X0;
+    }
+    if a >= A1 * ONE_18 {
+        a /= A1;
+        sum += X1;
+    }
+
+    // Switch to 20 decimals for higher precision
+    sum *= i256(100);
+    a *= i256(100);
+
+    for (x_n, a_n) in REDUCTION_TERMS.iter() {
+        if a >= *a_n {
+            a = (a * ONE_20) / *a_n;
+            sum += *x_n;
+        }
+    }
+
+    // ln(a) = 2 * atanh(z) for z = (a - 1) / (a + 1), computed with its Taylor series
+    let z = ((a - ONE_20) * ONE_20) / (a + ONE_20);
+    let z_squared = (z * z) / ONE_20;
+    let mut num = z;
+    let mut series_sum = num;
+    for n in [3, 5, 7, 9, 11] {
+        num = (num * z_squared) / ONE_20;
+        series_sum += num / i256(n);
+    }
+
+    (sum + series_sum * i256(2)) / i256(100)
+}
+
+/// Returns the natural logarithm of an 18 decimal fixed point `x` close to one, with 36 decimals.
+fn ln_36(x: I256) -> I256 {
+    let x = x * ONE_18;
+
+    let z = ((x - ONE_36) * ONE_36) / (x + ONE_36);
+    let z_squared = (z * z) / ONE_36;
+    let mut num = z;
+    let mut series_sum = num;
+    for n in [3, 5, 7, 9, 11, 13, 15] {
+        num = (num * z_squared) / ONE_36;
+        series_sum += num / i256(n);
+    }
+
+    series_sum * i256(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::sqrt("2000000000000000000", "500000000000000000", "1414213562373095047")]
+    #[case::close_to_one("1001000000000000000", "3000000000000000000", "1003003000999999999")]
+    #[case::base_below_one("500000000000000000", "250000000000000000", "840896415253714543")]
+    #[case::fractional("1234567890000000000", "700000000000000000", "1158938749851596416")]
+    #[case::large_base(
+        "10000000000000000000000",
+        "3000000000000000000",
+        "999999999999999999746590469972"
+    )]
+    fn test_pow(#[case] x: &str, #[case] y: &str, #[case] expected: &str) {
+        let result = pow(U256::from_str(x).unwrap(), U256::from_str(y).unwrap()).unwrap();
+
+        assert_eq!(result, U256::from_str(expected).unwrap());
+    }
+
+    #[test]
+    fn test_pow_edge_cases() {
+        assert_eq!(pow(U256::from(5), U256::ZERO).unwrap(), ONE_18.into_raw());
+        assert_eq!(pow(U256::ZERO, U256::from(5)).unwrap(), U256::ZERO);
+        assert!(pow(U256::from(2), MILD_EXPONENT_BOUND).is_err());
+    }
+}
@@ -0,0 +1,16 @@
+//! Balancer V2 weighted and stable pools
+//!
+//! The fixed point, weighted and stable math are shared with the Balancer V3 pools.
+//!
+//! `BalancerV2State` is not yet validated against the `vm:balancer_v2` adapter:
+//! `test_balancer_v2_matches_vm_adapter` stays ignored until fixtures are recorded with the
+//! `record_adapter_fixtures` example. Until then, keep decoding `vm:balancer_v2` components into
+//! `EVMPoolState` instead of registering this state in their place.
+pub(crate) mod fixed_point;
+mod log_exp_math;
+pub(crate) mod stable_math;
+pub mod state;
+mod tycho_decoder;
+pub(crate) mod weighted_math;
+
+pub(crate) use tycho_decoder::is_supported_pool_type;
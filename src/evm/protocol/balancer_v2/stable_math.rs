@@ -0,0 +1,230 @@
+//! Swap math of Balancer V2 stable pools.
+//!
+//! All amounts and balances are upscaled to 18 decimals. The amplification parameter is scaled by
+//! `AMP_PRECISION`. See https://github.com/balancer/balancer-v2-monorepo/blob/master/pkg/pool-stable/contracts/StableMath.sol
+use alloy::primitives::U256;
+use tycho_common::simulation::errors::SimulationError;
+
+use crate::evm::protocol::safe_math::{safe_add_u256, safe_div_u256, safe_mul_u256, safe_sub_u256};
+
+pub(crate) const AMP_PRECISION: U256 = U256::from_limbs([1_000, 0, 0, 0]);
+const MAX_ITERATIONS: usize = 255;
+
+fn div_up(a: U256, b: U256) -> Result<U256, SimulationError> {
+    if b.is_zero() {
+        return Err(SimulationError::FatalError("Division by zero".to_string()));
+    }
+    if a.is_zero() {
+        return Ok(U256::ZERO);
+    }
+    Ok((a - U256::from(1)) / b + U256::from(1))
+}
+
+fn converged(current: U256, previous: U256) -> bool {
+    if current > previous {
+        current - previous <= U256::from(1)
+    } else {
+        previous - current <= U256::from(1)
+    }
+}
+
+/// Computes the invariant `D` of the pool with Newton's method.
+///
+/// `D` solves `A * n^n * S + D = A * D * n^n + D^(n+1) / (n^n * P)`, with `S` the sum and `P` the
+/// product of the balances.
+pub(crate) fn calculate_invariant(amp: U256, balances: &[U256]) -> Result<U256, SimulationError> {
+    let num_tokens = U256::from(balances.len());
+    let mut sum = U256::ZERO;
+    for balance in balances {
+        sum = safe_add_u256(sum, *balance)?;
+    }
+    if sum.is_zero() {
+        return Ok(U256::ZERO);
+    }
+
+    let amp_times_total = safe_mul_u256(amp, num_tokens)?;
+    let mut invariant = sum;
+    for _ in 0..MAX_ITERATIONS {
+        let mut d_p = invariant;
+        for balance in balances {
+            // (d_p * invariant) / (balance * n)
+            d_p = safe_div_u256(
+                safe_mul_u256(d_p, invariant)?,
+                safe_mul_u256(*balance, num_tokens)?,
+            )?;
+        }
+
+        let previous_invariant = invariant;
+        // ((ampTimesTotal * sum) / AMP_PRECISION + D_P * n) * invariant
+        let numerator = safe_mul_u256(
+            safe_add_u256(
+                safe_mul_u256(amp_times_total, sum)? / AMP_PRECISION,
+                safe_mul_u256(d_p, num_tokens)?,
+            )?,
+            invariant,
+        )?;
+        // ((ampTimesTotal - AMP_PRECISION) * invariant) / AMP_PRECISION + (n + 1) * D_P
+        let denominator = safe_add_u256(
+            safe_mul_u256(safe_sub_u256(amp_times_total, AMP_PRECISION)?, invariant)? /
+                AMP_PRECISION,
+            safe_mul_u256(num_tokens + U256::from(1), d_p)?,
+        )?;
+        invariant = safe_div_u256(numerator, denominator)?;
+
+        if converged(invariant, previous_invariant) {
+            return Ok(invariant);
+        }
+    }
+    Err(SimulationError::FatalError("Stable invariant didn't converge".to_string()))
+}
+
+/// Returns the balance of the token at `token_index` that keeps the invariant, given all other
+/// balances. The balance of the token itself is ignored.
+fn get_token_balance_given_invariant_and_all_other_balances(
+    amp: U256,
+    balances: &[U256],
+    invariant: U256,
+    token_index: usize,
+) -> Result<U256, SimulationError> {
+    let num_tokens = U256::from(balances.len());
+    let amp_times_total = safe_mul_u256(amp, num_tokens)?;
+    let mut sum = balances[0];
+    let mut p_d = safe_mul_u256(balances[0], num_tokens)?;
+    for balance in &balances[1..] {
+        p_d = safe_div_u256(safe_mul_u256(safe_mul_u256(p_d, *balance)?, num_tokens)?, invariant)?;
+        sum = safe_add_u256(sum, *balance)?;
+    }
+    sum = safe_sub_u256(sum, balances[token_index])?;
+
+    let inv2 = safe_mul_u256(invariant, invariant)?;
+    // c = inv2 / (ampTimesTotal * P_D) * AMP_PRECISION * balance
+    let c = safe_mul_u256(
+        safe_mul_u256(div_up(inv2, safe_mul_u256(amp_times_total, p_d)?)?, AMP_PRECISION)?,
+        balances[token_index],
+    )?;
+    // b = sum + invariant / ampTimesTotal * AMP_PRECISION
+    let b = safe_add_u256(
+        sum,
+        safe_mul_u256(safe_div_u256(invariant, amp_times_total)?, AMP_PRECISION)?,
+    )?;
+
+    let mut token_balance = div_up(safe_add_u256(inv2, c)?, safe_add_u256(invariant, b)?)?;
+    for _ in 0..MAX_ITERATIONS {
+        let previous_token_balance = token_balance;
+        // (token_balance^2 + c) / (2 * token_balance + b - invariant)
+        token_balance = div_up(
+            safe_add_u256(safe_mul_u256(token_balance, token_balance)?, c)?,
+            safe_sub_u256(
+                safe_add_u256(safe_mul_u256(token_balance, U256::from(2))?, b)?,
+                invariant,
+            )?,
+        )?;
+
+        if converged(token_balance, previous_token_balance) {
+            return Ok(token_balance);
+        }
+    }
+    Err(SimulationError::FatalError("Stable balance didn't converge".to_string()))
+}
+
+/// Returns the amount out for an exact amount in, rounded down.
+pub(crate) fn calc_out_given_in(
+    amp: U256,
+    balances: &[U256],
+    token_index_in: usize,
+    token_index_out: usize,
+    amount_in: U256,
+    invariant: U256,
+) -> Result<U256, SimulationError> {
+    let mut balances = balances.to_vec();
+    balances[token_index_in] = safe_add_u256(balances[token_index_in], amount_in)?;
+    let final_balance_out = get_token_balance_given_invariant_and_all_other_balances(
+        amp,
+        &balances,
+        invariant,
+        token_index_out,
+    )?;
+    // Round down by subtracting one
+    safe_sub_u256(safe_sub_u256(balances[token_index_out], final_balance_out)?, U256::from(1))
+}
+
+/// Returns the amount in for an exact amount out, rounded up.
+pub(crate) fn calc_in_given_out(
+    amp: U256,
+    balances: &[U256],
+    token_index_in: usize,
+    token_index_out: usize,
+    amount_out: U256,
+    invariant: U256,
+) -> Result<U256, SimulationError> {
+    let mut balances = balances.to_vec();
+    balances[token_index_out] = safe_sub_u256(balances[token_index_out], amount_out)?;
+    let final_balance_in = get_token_balance_given_invariant_and_all_other_balances(
+        amp,
+        &balances,
+        invariant,
+        token_index_in,
+    )?;
+    // Round up by adding one
+    safe_add_u256(safe_sub_u256(final_balance_in, balances[token_index_in])?, U256::from(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn balances() -> Vec<U256> {
+        vec![
+            U256::from_str("1000000000000000000000000").unwrap(),
+            U256::from_str("1200000000000000000000000").unwrap(),
+            U256::from_str("900000000000000000000000").unwrap(),
+        ]
+    }
+
+    fn amp() -> U256 {
+        U256::from(200) * AMP_PRECISION
+    }
+
+    #[test]
+    fn test_calculate_invariant() {
+        let invariant = calculate_invariant(amp(), &balances()).unwrap();
+
+        assert_eq!(invariant, U256::from_str("3099888769460454695322188").unwrap());
+    }
+
+    #[test]
+    fn test_calc_out_given_in() {
+        let invariant = calculate_invariant(amp(), &balances()).unwrap();
+
+        let amount_out = calc_out_given_in(
+            amp(),
+            &balances(),
+            0,
+            1,
+            U256::from_str("1000000000000000000000").unwrap(),
+            invariant,
+        )
+        .unwrap();
+
+        assert_eq!(amount_out, U256::from_str("1000871241064995192514").unwrap());
+    }
+
+    #[test]
+    fn test_calc_in_given_out() {
+        let invariant = calculate_invariant(amp(), &balances()).unwrap();
+
+        let amount_in = calc_in_given_out(
+            amp(),
+            &balances(),
+            0,
+            1,
+            U256::from_str("1000871241064995192514").unwrap(),
+            invariant,
+        )
+        .unwrap();
+
+        assert_eq!(amount_in, U256::from_str("1000000000000000000060").unwrap());
+    }
+}
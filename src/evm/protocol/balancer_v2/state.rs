@@ -0,0 +1,600 @@
+use std::{any::Any, collections::HashMap};
+
+use alloy::primitives::U256;
+use num_bigint::{BigUint, ToBigUint};
+use tycho_common::{
+    dto::ProtocolStateDelta,
+    models::token::Token,
+    simulation::{
+        errors::{SimulationError, TransitionError},
+        protocol_sim::{Balances, GetAmountOutResult, ProtocolSim},
+    },
+    Bytes,
+};
+
+use super::{
+    fixed_point::{complement, div_down, div_up, mul_down, mul_up, sub, ONE},
+    stable_math::{self, AMP_PRECISION},
+    weighted_math::{self, MAX_IN_RATIO},
+};
+use crate::{
+    evm::protocol::{
+        safe_math::{safe_add_u256, safe_mul_u256, safe_sub_u256},
+        u256_num::{biguint_to_u256, u256_to_biguint, u256_to_f64},
+        utils::decode_u256_list,
+    },
+    protocol::{fee::SwapFee, transfer_tax::amount_after_tax},
+};
+
+const WEIGHTED_SWAP_GAS: u64 = 85_000;
+const STABLE_SWAP_GAS: u64 = 120_000;
+/// Soft limit for stable pool swaps: the amount that takes out 90% of the balance out
+const STABLE_MAX_OUT_RATIO: U256 = U256::from_limbs([900_000_000_000_000_000, 0, 0, 0]);
+
+/// The invariant of a Balancer V2 pool, with its pool type specific parameters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BalancerV2PoolType {
+    /// Weighted pool, with the normalized weight of each token scaled by 1e18
+    Weighted { normalized_weights: Vec<U256> },
+    /// Stable or composable stable pool, with the amplification parameter scaled by 1e3 (as
+    /// returned by `getAmplificationParameter`)
+    Stable { amp: U256 },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BalancerV2State {
+    /// The pool's identifier
+    id: String,
+    /// The pool's swappable tokens, excluding the BPT of composable stable pools
+    tokens: Vec<Bytes>,
+    /// Balance of each token
+    balances: Vec<U256>,
+    /// Multiplier bringing the amounts of each token to 18 decimals
+    decimal_scaling: Vec<U256>,
+    /// Rate of each token, scaled by 1e18
+    rates: Vec<U256>,
+    /// Swap fee percentage, scaled by 1e18
+    swap_fee: U256,
+    pool_type: BalancerV2PoolType,
+}
+
+impl BalancerV2State {
+    /// Creates a new `BalancerV2State`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The pool id.
+    /// * `tokens` - Addresses of the pool's swappable tokens, in pool order.
+    /// * `balances` - Balance of each token.
+    /// * `decimals` - Decimals of each token, at most 18.
+    /// * `rates` - Rate of each token scaled by 1e18, 1e18 for tokens without rate provider.
+    /// * `swap_fee` - Swap fee percentage scaled by 1e18.
+    /// * `pool_type` - The pool type and its parameters.
+    pub fn new(
+        id: String,
+        tokens: Vec<Bytes>,
+        balances: Vec<U256>,
+        decimals: &[u32],
+        rates: Vec<U256>,
+        swap_fee: U256,
+        pool_type: BalancerV2PoolType,
+    ) -> Self {
+        let decimal_scaling = decimals
+            .iter()
+            .map(|decimals| U256::from(10).pow(U256::from(18u32.saturating_sub(*decimals))))
+            .collect();
+        Self { id, tokens, balances, decimal_scaling, rates, swap_fee, pool_type }
+    }
+
+    fn token_index(&self, token: &Bytes) -> Result<usize, SimulationError> {
+        self.tokens
+            .iter()
+            .position(|pool_token| pool_token == token)
+            .ok_or_else(|| {
+                SimulationError::InvalidInput(format!("Token {token} is not in the pool"), None)
+            })
+    }
+
+    fn token_indices(
+        &self,
+        token_in: &Bytes,
+        token_out: &Bytes,
+    ) -> Result<(usize, usize), SimulationError> {
+        let (index_in, index_out) = (self.token_index(token_in)?, self.token_index(token_out)?);
+        if index_in == index_out {
+            return Err(SimulationError::InvalidInput(
+                "Token in and token out must differ".to_string(),
+                None,
+            ));
+        }
+        Ok((index_in, index_out))
+    }
+
+    /// Returns the 18 decimal fixed point factor converting amounts of the token at `index` to 18
+    /// decimals, including its rate.
+    fn scaling_factor(&self, index: usize) -> Result<U256, SimulationError> {
+        safe_mul_u256(self.decimal_scaling[index], self.rates[index])
+    }
+
+    fn upscale(&self, amount: U256, index: usize) -> Result<U256, SimulationError> {
+        mul_down(amount, self.scaling_factor(index)?)
+    }
+
+    fn downscale_down(&self, amount: U256, index: usize) -> Result<U256, SimulationError> {
+        div_down(amount, self.scaling_factor(index)?)
+    }
+
+    fn downscale_up(&self, amount: U256, index: usize) -> Result<U256, SimulationError> {
+        div_up(amount, self.scaling_factor(index)?)
+    }
+
+    fn upscaled_balances(&self) -> Result<Vec<U256>, SimulationError> {
+        self.balances
+            .iter()
+            .enumerate()
+            .map(|(index, balance)| self.upscale(*balance, index))
+            .collect()
+    }
+
+    /// Returns the upscaled amount out for an upscaled amount in, after fees.
+    fn calc_out_given_in(
+        &self,
+        balances: &[U256],
+        index_in: usize,
+        index_out: usize,
+        amount_in: U256,
+    ) -> Result<U256, SimulationError> {
+        match &self.pool_type {
+            BalancerV2PoolType::Weighted { normalized_weights } => {
+                weighted_math::calc_out_given_in(
+                    balances[index_in],
+                    normalized_weights[index_in],
+                    balances[index_out],
+                    normalized_weights[index_out],
+                    amount_in,
+                )
+            }
+            BalancerV2PoolType::Stable { amp } => {
+                let invariant = stable_math::calculate_invariant(*amp, balances)?;
+                stable_math::calc_out_given_in(
+                    *amp, balances, index_in, index_out, amount_in, invariant,
+                )
+            }
+        }
+    }
+
+    fn swap_gas(&self) -> u64 {
+        match self.pool_type {
+            BalancerV2PoolType::Weighted { .. } => WEIGHTED_SWAP_GAS,
+            BalancerV2PoolType::Stable { .. } => STABLE_SWAP_GAS,
+        }
+    }
+
+    /// Returns the amount of the token at `index_out` received for one unit of the token at
+    /// `index_in`, in upscaled units and without fees.
+    fn upscaled_spot_price(
+        &self,
+        balances: &[U256],
+        index_in: usize,
+        index_out: usize,
+    ) -> Result<f64, SimulationError> {
+        match &self.pool_type {
+            BalancerV2PoolType::Weighted { normalized_weights } => {
+                // (balanceOut / weightOut) / (balanceIn / weightIn)
+                Ok(
+                    (u256_to_f64(balances[index_out]) / u256_to_f64(normalized_weights[index_out])) /
+                        (u256_to_f64(balances[index_in]) /
+                            u256_to_f64(normalized_weights[index_in])),
+                )
+            }
+            BalancerV2PoolType::Stable { amp } => {
+                // Ratio of the partial derivatives of the invariant equation, which are
+                // A * n + D^(n+1) / (n^n * P * x_i) with Balancer's amplification parameter
+                let invariant = u256_to_f64(stable_math::calculate_invariant(*amp, balances)?);
+                let num_tokens = balances.len() as f64;
+                let amp_times_total = u256_to_f64(*amp) / u256_to_f64(AMP_PRECISION) * num_tokens;
+                let d_p = balances
+                    .iter()
+                    .fold(invariant, |d_p, balance| {
+                        d_p * invariant / (u256_to_f64(*balance) * num_tokens)
+                    });
+                let derivative =
+                    |index: usize| amp_times_total + d_p / u256_to_f64(balances[index]);
+                Ok(derivative(index_in) / derivative(index_out))
+            }
+        }
+    }
+}
+
+impl ProtocolSim for BalancerV2State {
+    fn fee(&self) -> f64 {
+        u256_to_f64(self.swap_fee) / u256_to_f64(ONE)
+    }
+
+    fn spot_price(&self, base: &Token, quote: &Token) -> Result<f64, SimulationError> {
+        let (index_base, index_quote) = self.token_indices(&base.address, &quote.address)?;
+        let balances = self.upscaled_balances()?;
+        if balances[index_base].is_zero() || balances[index_quote].is_zero() {
+            return Err(SimulationError::RecoverableError("No liquidity".to_string()));
+        }
+        // Upscaled amounts include the token rates, convert the price back to token amounts
+        let rates_correction =
+            u256_to_f64(self.rates[index_base]) / u256_to_f64(self.rates[index_quote]);
+        Ok(self.upscaled_spot_price(&balances, index_base, index_quote)? * rates_correction)
+    }
+
+    fn get_amount_out(
+        &self,
+        amount_in: BigUint,
+        token_in: &Token,
+        token_out: &Token,
+    ) -> Result<GetAmountOutResult, SimulationError> {
+        let (index_in, index_out) = self.token_indices(&token_in.address, &token_out.address)?;
+        let amount_in = biguint_to_u256(&amount_after_tax(&amount_in, token_in));
+        if amount_in.is_zero() {
+            return Err(SimulationError::InvalidInput("Amount in cannot be zero".to_string(), None));
+        }
+
+        let fee_amount = mul_up(amount_in, self.swap_fee)?;
+        let amount_in_after_fee = safe_sub_u256(amount_in, fee_amount)?;
+        let balances = self.upscaled_balances()?;
+        let amount_out = self.calc_out_given_in(
+            &balances,
+            index_in,
+            index_out,
+            self.upscale(amount_in_after_fee, index_in)?,
+        )?;
+        let amount_out = self.downscale_down(amount_out, index_out)?;
+
+        let mut new_state = self.clone();
+        new_state.balances[index_in] = safe_add_u256(self.balances[index_in], amount_in)?;
+        new_state.balances[index_out] =
+            sub(self.balances[index_out], amount_out, "Amount out exceeds balance")?;
+
+        Ok(GetAmountOutResult::new(
+            amount_after_tax(&u256_to_biguint(amount_out), token_out),
+            self.swap_gas()
+                .to_biguint()
+                .expect("Expected an unsigned integer as gas value"),
+            Box::new(new_state),
+        ))
+    }
+
+    fn get_limits(
+        &self,
+        sell_token: Bytes,
+        buy_token: Bytes,
+    ) -> Result<(BigUint, BigUint), SimulationError> {
+        let (index_in, index_out) = self.token_indices(&sell_token, &buy_token)?;
+        let balances = self.upscaled_balances()?;
+        if balances[index_in].is_zero() || balances[index_out].is_zero() {
+            return Ok((BigUint::ZERO, BigUint::ZERO));
+        }
+
+        let (max_in, max_out) = match &self.pool_type {
+            // Weighted pools have a hard limit on the amount in
+            BalancerV2PoolType::Weighted { .. } => {
+                let max_in = mul_down(balances[index_in], MAX_IN_RATIO)?;
+                let max_out = self.calc_out_given_in(&balances, index_in, index_out, max_in)?;
+                (self.downscale_down(max_in, index_in)?, self.downscale_down(max_out, index_out)?)
+            }
+            BalancerV2PoolType::Stable { amp } => {
+                let max_out = mul_down(balances[index_out], STABLE_MAX_OUT_RATIO)?;
+                let invariant = stable_math::calculate_invariant(*amp, &balances)?;
+                let max_in = stable_math::calc_in_given_out(
+                    *amp, &balances, index_in, index_out, max_out, invariant,
+                )?;
+                (self.downscale_up(max_in, index_in)?, self.downscale_down(max_out, index_out)?)
+            }
+        };
+        // The fee is taken from the amount in before swapping
+        let max_in = div_down(max_in, complement(self.swap_fee))?;
+
+        Ok((u256_to_biguint(max_in), u256_to_biguint(max_out)))
+    }
+
+    fn delta_transition(
+        &mut self,
+        delta: ProtocolStateDelta,
+        _tokens: &HashMap<Bytes, Token>,
+        balances: &Balances,
+    ) -> Result<(), TransitionError<String>> {
+        if let Some(component_balances) = balances
+            .component_balances
+            .get(&self.id)
+        {
+            for (token, balance) in component_balances {
+                // Skips the BPT of composable stable pools
+                if let Ok(index) = self.token_index(token) {
+                    self.balances[index] = U256::from_be_slice(balance);
+                }
+            }
+        }
+
+        if let Some(fee) = delta.updated_attributes.get("fee") {
+            self.swap_fee = U256::from_be_slice(fee);
+        }
+        if let Some(rates) = delta
+            .updated_attributes
+            .get("token_rates")
+        {
+            let rates = decode_u256_list(rates)
+                .map_err(|err| TransitionError::DecodeError(err.to_string()))?;
+            if rates.len() != self.tokens.len() {
+                return Err(TransitionError::DecodeError(format!(
+                    "Expected {} token rates, got {}",
+                    self.tokens.len(),
+                    rates.len()
+                )));
+            }
+            self.rates = rates;
+        }
+        if let BalancerV2PoolType::Stable { amp } = &mut self.pool_type {
+            if let Some(new_amp) = delta.updated_attributes.get("amp") {
+                *amp = U256::from_be_slice(new_amp);
+            }
+        }
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn ProtocolSim> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn eq(&self, other: &dyn ProtocolSim) -> bool {
+        if let Some(other_state) = other.as_any().downcast_ref::<Self>() {
+            self == other_state
+        } else {
+            false
+        }
+    }
+}
+
+impl SwapFee for BalancerV2State {
+    fn swap_fee(
+        &self,
+        _token_in: &Token,
+        _token_out: &Token,
+        _amount_in: Option<&BigUint>,
+    ) -> Result<f64, SimulationError> {
+        Ok(self.fee())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use approx::assert_relative_eq;
+    use rstest::rstest;
+    use tycho_common::models::Chain;
+
+    use super::*;
+
+    fn token(address: &str, decimals: u32) -> Token {
+        Token::new(
+            &Bytes::from_str(address).unwrap(),
+            "T",
+            decimals,
+            0,
+            &[Some(10_000)],
+            Chain::Ethereum,
+            100,
+        )
+    }
+
+    fn weth() -> Token {
+        token("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2", 18)
+    }
+
+    fn bal() -> Token {
+        token("0xba100000625a3754423978a60c9317c58a424e3d", 18)
+    }
+
+    fn usdc() -> Token {
+        token("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", 6)
+    }
+
+    fn dai() -> Token {
+        token("0x6b175474e89094c44da98b954eedeac495271d0f", 18)
+    }
+
+    fn usdt() -> Token {
+        token("0xdac17f958d2ee523a2206206994597c13d831ec7", 6)
+    }
+
+    fn u256(value: &str) -> U256 {
+        U256::from_str(value).unwrap()
+    }
+
+    /// 80 BAL / 20 WETH pool with a 1% fee
+    fn weighted_state() -> BalancerV2State {
+        BalancerV2State::new(
+            "weighted".to_string(),
+            vec![bal().address, weth().address],
+            vec![u256("8000000000000000000000"), u256("200000000000000000000")],
+            &[18, 18],
+            vec![ONE, ONE],
+            u256("10000000000000000"),
+            BalancerV2PoolType::Weighted {
+                normalized_weights: vec![u256("800000000000000000"), u256("200000000000000000")],
+            },
+        )
+    }
+
+    /// DAI / USDC / USDT pool with A = 200 and a 0.01% fee
+    fn stable_state() -> BalancerV2State {
+        BalancerV2State::new(
+            "stable".to_string(),
+            vec![dai().address, usdc().address, usdt().address],
+            vec![u256("1000000000000000000000000"), u256("1200000000000"), u256("900000000000")],
+            &[18, 6, 6],
+            vec![ONE, ONE, ONE],
+            u256("100000000000000"),
+            BalancerV2PoolType::Stable { amp: U256::from(200) * AMP_PRECISION },
+        )
+    }
+
+    #[rstest]
+    #[case::weighted_bal_weth(
+        weighted_state(),
+        bal(),
+        weth(),
+        "10000000000000000000",
+        "986944751576945200"
+    )]
+    #[case::weighted_weth_bal(
+        weighted_state(),
+        weth(),
+        bal(),
+        "1000000000000000000",
+        "9869485126435008000"
+    )]
+    #[case::stable_dai_usdc(stable_state(), dai(), usdc(), "1000000000000000000000", "1000771154")]
+    #[case::stable_usdc_usdt(stable_state(), usdc(), usdt(), "1000000000", "998437408")]
+    fn test_get_amount_out(
+        #[case] state: BalancerV2State,
+        #[case] token_in: Token,
+        #[case] token_out: Token,
+        #[case] amount_in: &str,
+        #[case] expected: &str,
+    ) {
+        let result = state
+            .get_amount_out(BigUint::from_str(amount_in).unwrap(), &token_in, &token_out)
+            .unwrap();
+
+        assert_eq!(result.amount, BigUint::from_str(expected).unwrap());
+        let new_state = result
+            .new_state
+            .as_any()
+            .downcast_ref::<BalancerV2State>()
+            .unwrap();
+        let index_in = state
+            .token_index(&token_in.address)
+            .unwrap();
+        let index_out = state
+            .token_index(&token_out.address)
+            .unwrap();
+        assert_eq!(new_state.balances[index_in], state.balances[index_in] + u256(amount_in));
+        assert_eq!(new_state.balances[index_out], state.balances[index_out] - u256(expected));
+    }
+
+    #[test]
+    fn test_get_amount_out_exceeds_max_in_ratio() {
+        let result = weighted_state().get_amount_out(
+            BigUint::from_str("3000000000000000000000").unwrap(),
+            &bal(),
+            &weth(),
+        );
+
+        assert!(matches!(result, Err(SimulationError::InvalidInput(_, None))));
+    }
+
+    #[test]
+    fn test_get_amount_out_unknown_token() {
+        let result = weighted_state().get_amount_out(BigUint::from(1_000u32), &usdc(), &weth());
+
+        assert!(matches!(result, Err(SimulationError::InvalidInput(_, None))));
+    }
+
+    #[test]
+    fn test_spot_price() {
+        // (200 / 0.2) / (8000 / 0.8) = 0.1 WETH per BAL
+        assert_relative_eq!(
+            weighted_state()
+                .spot_price(&bal(), &weth())
+                .unwrap(),
+            0.1,
+            max_relative = 1e-12
+        );
+        // The marginal price of a balanced stable pool is one
+        let balanced = BalancerV2State::new(
+            "stable".to_string(),
+            vec![dai().address, usdc().address],
+            vec![u256("1000000000000000000000000"), u256("1000000000000")],
+            &[18, 6],
+            vec![ONE, ONE],
+            U256::ZERO,
+            BalancerV2PoolType::Stable { amp: U256::from(200) * AMP_PRECISION },
+        );
+        assert_relative_eq!(
+            balanced
+                .spot_price(&dai(), &usdc())
+                .unwrap(),
+            1.0,
+            max_relative = 1e-12
+        );
+        // USDT is scarcer than USDC, so it's worth more
+        let price = stable_state()
+            .spot_price(&usdt(), &usdc())
+            .unwrap();
+        assert!(price > 1.0 && price < 1.01);
+    }
+
+    #[test]
+    fn test_get_limits() {
+        let state = weighted_state();
+
+        let (max_in, max_out) = state
+            .get_limits(bal().address, weth().address)
+            .unwrap();
+
+        // 30% of the balance, grossed up by the fee
+        assert_eq!(max_in, BigUint::from_str("2424242424242424242424").unwrap());
+        assert!(state
+            .get_amount_out(max_in, &bal(), &weth())
+            .is_ok());
+        assert!(max_out < BigUint::from_str("200000000000000000000").unwrap());
+
+        let state = stable_state();
+        let (max_in, max_out) = state
+            .get_limits(usdc().address, usdt().address)
+            .unwrap();
+
+        // 90% of the USDT balance, reached up to rounding
+        assert_eq!(max_out, BigUint::from(810_000_000_000u64));
+        let result = state
+            .get_amount_out(max_in, &usdc(), &usdt())
+            .unwrap();
+        assert_eq!(result.amount, BigUint::from(809_999_999_999u64));
+    }
+
+    #[test]
+    fn test_delta_transition() {
+        let mut state = stable_state();
+        let delta = ProtocolStateDelta {
+            component_id: "stable".to_string(),
+            updated_attributes: HashMap::from([
+                (
+                    "fee".to_string(),
+                    Bytes::from(U256::from(400_000_000_000_000u64).to_be_bytes_vec()),
+                ),
+                ("amp".to_string(), Bytes::from(U256::from(100_000).to_be_bytes_vec())),
+            ]),
+            deleted_attributes: Default::default(),
+        };
+        let balances = Balances {
+            component_balances: HashMap::from([(
+                "stable".to_string(),
+                HashMap::from([(usdc().address, Bytes::from(U256::from(5u64).to_be_bytes_vec()))]),
+            )]),
+            account_balances: HashMap::new(),
+        };
+
+        state
+            .delta_transition(delta, &HashMap::new(), &balances)
+            .unwrap();
+
+        assert_eq!(state.swap_fee, U256::from(400_000_000_000_000u64));
+        assert_eq!(state.pool_type, BalancerV2PoolType::Stable { amp: U256::from(100_000) });
+        assert_eq!(state.balances[1], U256::from(5));
+    }
+}
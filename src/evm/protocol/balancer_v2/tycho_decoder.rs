@@ -0,0 +1,461 @@
+use std::{collections::HashMap, str::FromStr};
+
+use alloy::primitives::U256;
+use tycho_client::feed::{synchronizer::ComponentWithState, BlockHeader};
+use tycho_common::{models::token::Token, Bytes};
+
+use super::{
+    fixed_point::ONE,
+    state::{BalancerV2PoolType, BalancerV2State},
+};
+use crate::{
+    evm::protocol::{
+        utils::{attribute, decode_u256_list, required_attribute},
+        vm::utils::json_deserialize_address_list,
+    },
+    protocol::{errors::InvalidSnapshotError, models::TryFromWithBlock},
+};
+
+/// Returns whether pools of the given Balancer V2 factory can be simulated natively, i.e. whether
+/// they are weighted or (meta/composable) stable pools.
+pub(crate) fn is_supported_pool_type(pool_type: &str) -> bool {
+    pool_type.starts_with("WeightedPool") || pool_type.contains("StablePool")
+}
+
+impl TryFromWithBlock<ComponentWithState, BlockHeader> for BalancerV2State {
+    type Error = InvalidSnapshotError;
+
+    /// Decodes a `ComponentWithState` of the `vm:balancer_v2` protocol system into a
+    /// `BalancerV2State`.
+    ///
+    /// Uses the following attributes, state attributes taking precedence over static ones:
+    /// - `pool_type`: the factory name, weighted and (meta/composable) stable pools are supported
+    /// - `fee`: the swap fee percentage, scaled by 1e18
+    /// - `normalized_weights`: JSON list of the token weights, for weighted pools
+    /// - `amp`: the amplification parameter scaled by 1e3, for stable pools
+    /// - `token_rates`: optional JSON list of the token rates scaled by 1e18, required if the pool
+    ///   has non-zero `rate_providers`
+    ///
+    /// The BPT of composable stable pools, i.e. the pool's own address, is not swappable and is
+    /// excluded from the tokens.
+    async fn try_from_with_header(
+        snapshot: ComponentWithState,
+        _block: BlockHeader,
+        _account_balances: &HashMap<Bytes, HashMap<Bytes, Bytes>>,
+        all_tokens: &HashMap<Bytes, Token>,
+    ) -> Result<Self, Self::Error> {
+        let id = snapshot.component.id.clone();
+        let pool_type = String::from_utf8(required_attribute(&snapshot, "pool_type")?.to_vec())
+            .map_err(|_| InvalidSnapshotError::ValueError("Invalid pool_type".to_string()))?;
+        if !is_supported_pool_type(&pool_type) {
+            return Err(InvalidSnapshotError::ValueError(format!(
+                "Unsupported Balancer V2 pool type {pool_type}"
+            )));
+        }
+
+        // The pool id starts with the pool address, which is also the BPT
+        let pool_address = id
+            .get(..42)
+            .and_then(|address| Bytes::from_str(address).ok())
+            .ok_or_else(|| InvalidSnapshotError::ValueError(format!("Invalid pool id {id}")))?;
+        let tokens: Vec<Bytes> = snapshot
+            .component
+            .tokens
+            .iter()
+            .filter(|token| **token != pool_address)
+            .cloned()
+            .collect();
+
+        let mut balances = Vec::with_capacity(tokens.len());
+        let mut decimals = Vec::with_capacity(tokens.len());
+        for token in &tokens {
+            balances.push(
+                snapshot
+                    .state
+                    .balances
+                    .get(token)
+                    .map(|balance| U256::from_be_slice(balance))
+                    .unwrap_or_default(),
+            );
+            let token_decimals = all_tokens
+                .get(token)
+                .ok_or_else(|| {
+                    InvalidSnapshotError::ValueError(format!("Token {token} not found"))
+                })?
+                .decimals;
+            if token_decimals > 18 {
+                return Err(InvalidSnapshotError::ValueError(format!(
+                    "Token {token} has more than 18 decimals"
+                )));
+            }
+            decimals.push(token_decimals);
+        }
+
+        let rates = match attribute(&snapshot, "token_rates") {
+            Some(rates) => decode_u256_list(rates)
+                .map_err(|err| InvalidSnapshotError::ValueError(err.to_string()))?,
+            None => {
+                let has_rate_providers = match attribute(&snapshot, "rate_providers") {
+                    Some(providers) => json_deserialize_address_list(providers)
+                        .map_err(|err| InvalidSnapshotError::ValueError(err.to_string()))?
+                        .iter()
+                        .any(|provider| provider.iter().any(|byte| *byte != 0)),
+                    None => false,
+                };
+                if has_rate_providers {
+                    return Err(InvalidSnapshotError::MissingAttribute("token_rates".to_string()));
+                }
+                vec![ONE; tokens.len()]
+            }
+        };
+        if rates.len() != tokens.len() {
+            return Err(InvalidSnapshotError::ValueError(format!(
+                "Expected {} token rates, got {}",
+                tokens.len(),
+                rates.len()
+            )));
+        }
+
+        let swap_fee = U256::from_be_slice(required_attribute(&snapshot, "fee")?);
+
+        let pool_type = if pool_type.starts_with("WeightedPool") {
+            let normalized_weights =
+                decode_u256_list(required_attribute(&snapshot, "normalized_weights")?)
+                    .map_err(|err| InvalidSnapshotError::ValueError(err.to_string()))?;
+            if normalized_weights.len() != tokens.len() {
+                return Err(InvalidSnapshotError::ValueError(format!(
+                    "Expected {} weights, got {}",
+                    tokens.len(),
+                    normalized_weights.len()
+                )));
+            }
+            BalancerV2PoolType::Weighted { normalized_weights }
+        } else {
+            BalancerV2PoolType::Stable {
+                amp: U256::from_be_slice(required_attribute(&snapshot, "amp")?),
+            }
+        };
+
+        Ok(BalancerV2State::new(id, tokens, balances, &decimals, rates, swap_fee, pool_type))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, fs, path::Path};
+
+    use num_bigint::BigUint;
+    use rstest::rstest;
+    use serde::Deserialize;
+    use tycho_common::{
+        dto::{ProtocolComponent, ResponseProtocolState},
+        models::Chain,
+        simulation::protocol_sim::ProtocolSim,
+    };
+
+    use super::*;
+
+    const POOL_ID: &str = "0x96646936b91d6b9d7d0c47c496afbf3d6ec7b6f8000200000000000000000019";
+    const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+    const WETH: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+
+    fn header() -> BlockHeader {
+        BlockHeader {
+            number: 1,
+            hash: Bytes::from(vec![0; 32]),
+            parent_hash: Bytes::from(vec![0; 32]),
+            revert: false,
+            timestamp: 1,
+        }
+    }
+
+    fn tokens() -> HashMap<Bytes, Token> {
+        [(USDC, 6), (WETH, 18)]
+            .into_iter()
+            .map(|(address, decimals)| {
+                let address = Bytes::from_str(address).unwrap();
+                let token =
+                    Token::new(&address, "T", decimals, 0, &[Some(10_000)], Chain::Ethereum, 100);
+                (address, token)
+            })
+            .collect()
+    }
+
+    fn weighted_snapshot() -> ComponentWithState {
+        let static_attributes = HashMap::from([
+            ("pool_type".to_string(), Bytes::from("WeightedPoolFactory".as_bytes())),
+            (
+                "normalized_weights".to_string(),
+                Bytes::from(r#"["0x06f05b59d3b20000","0x06f05b59d3b20000"]"#.as_bytes()),
+            ),
+            (
+                "fee".to_string(),
+                Bytes::from(U256::from(3_000_000_000_000_000u64).to_be_bytes_vec()),
+            ),
+        ]);
+        ComponentWithState {
+            state: ResponseProtocolState {
+                component_id: POOL_ID.to_string(),
+                attributes: HashMap::new(),
+                balances: HashMap::from([
+                    (
+                        Bytes::from_str(USDC).unwrap(),
+                        Bytes::from(
+                            1_000_000_000_000u64
+                                .to_be_bytes()
+                                .to_vec(),
+                        ),
+                    ),
+                    (
+                        Bytes::from_str(WETH).unwrap(),
+                        Bytes::from(
+                            U256::from(10)
+                                .pow(U256::from(20))
+                                .to_be_bytes_vec(),
+                        ),
+                    ),
+                ]),
+            },
+            component: ProtocolComponent {
+                id: POOL_ID.to_string(),
+                protocol_system: "vm:balancer_v2".to_string(),
+                tokens: vec![Bytes::from_str(USDC).unwrap(), Bytes::from_str(WETH).unwrap()],
+                static_attributes,
+                ..Default::default()
+            },
+            component_tvl: None,
+            entrypoints: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_balancer_v2_try_from_weighted() {
+        let result = BalancerV2State::try_from_with_header(
+            weighted_snapshot(),
+            header(),
+            &HashMap::new(),
+            &tokens(),
+        )
+        .await
+        .unwrap();
+
+        let half = U256::from(500_000_000_000_000_000u64);
+        let expected = BalancerV2State::new(
+            POOL_ID.to_string(),
+            vec![Bytes::from_str(USDC).unwrap(), Bytes::from_str(WETH).unwrap()],
+            vec![U256::from(1_000_000_000_000u64), U256::from(10).pow(U256::from(20))],
+            &[6, 18],
+            vec![ONE, ONE],
+            U256::from(3_000_000_000_000_000u64),
+            BalancerV2PoolType::Weighted { normalized_weights: vec![half, half] },
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[tokio::test]
+    async fn test_balancer_v2_try_from_composable_stable() {
+        let mut snapshot = weighted_snapshot();
+        let bpt = Bytes::from_str(&POOL_ID[..42]).unwrap();
+        snapshot
+            .component
+            .tokens
+            .push(bpt.clone());
+        snapshot
+            .state
+            .balances
+            .insert(bpt, Bytes::from(U256::MAX.to_be_bytes_vec()));
+        snapshot
+            .component
+            .static_attributes
+            .insert("pool_type".to_string(), Bytes::from("ComposableStablePoolFactory".as_bytes()));
+        snapshot
+            .state
+            .attributes
+            .insert("amp".to_string(), Bytes::from(U256::from(200_000).to_be_bytes_vec()));
+
+        let result =
+            BalancerV2State::try_from_with_header(snapshot, header(), &HashMap::new(), &tokens())
+                .await
+                .unwrap();
+
+        let expected = BalancerV2State::new(
+            POOL_ID.to_string(),
+            vec![Bytes::from_str(USDC).unwrap(), Bytes::from_str(WETH).unwrap()],
+            vec![U256::from(1_000_000_000_000u64), U256::from(10).pow(U256::from(20))],
+            &[6, 18],
+            vec![ONE, ONE],
+            U256::from(3_000_000_000_000_000u64),
+            BalancerV2PoolType::Stable { amp: U256::from(200_000) },
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[tokio::test]
+    #[rstest]
+    #[case::missing_pool_type("pool_type")]
+    #[case::missing_fee("fee")]
+    #[case::missing_weights("normalized_weights")]
+    async fn test_balancer_v2_try_from_missing_attribute(#[case] missing_attribute: &str) {
+        let mut snapshot = weighted_snapshot();
+        snapshot
+            .component
+            .static_attributes
+            .remove(missing_attribute);
+
+        let result =
+            BalancerV2State::try_from_with_header(snapshot, header(), &HashMap::new(), &tokens())
+                .await;
+
+        assert!(matches!(
+            result.unwrap_err(),
+            InvalidSnapshotError::MissingAttribute(attribute) if attribute == missing_attribute
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_balancer_v2_try_from_unsupported_pool_type() {
+        let mut snapshot = weighted_snapshot();
+        snapshot
+            .component
+            .static_attributes
+            .insert("pool_type".to_string(), Bytes::from("ERC4626LinearPoolFactory".as_bytes()));
+
+        let result =
+            BalancerV2State::try_from_with_header(snapshot, header(), &HashMap::new(), &tokens())
+                .await;
+
+        assert!(matches!(result.unwrap_err(), InvalidSnapshotError::ValueError(_)));
+    }
+
+    #[tokio::test]
+    async fn test_balancer_v2_try_from_rate_providers_without_rates() {
+        let mut snapshot = weighted_snapshot();
+        snapshot
+            .component
+            .static_attributes
+            .insert(
+                "rate_providers".to_string(),
+                Bytes::from(
+                    format!(r#"["0x0000000000000000000000000000000000000000","{USDC}"]"#)
+                        .as_bytes(),
+                ),
+            );
+
+        let result =
+            BalancerV2State::try_from_with_header(snapshot, header(), &HashMap::new(), &tokens())
+                .await;
+
+        assert!(matches!(
+            result.unwrap_err(),
+            InvalidSnapshotError::MissingAttribute(attribute) if attribute == "token_rates"
+        ));
+    }
+
+    #[derive(Deserialize)]
+    struct FixtureToken {
+        address: Bytes,
+        decimals: u32,
+    }
+
+    #[derive(Deserialize)]
+    struct FixtureQuote {
+        token_in: Bytes,
+        token_out: Bytes,
+        amount_in: String,
+        /// The amount out returned by the VM adapter
+        amount_out: String,
+    }
+
+    /// A snapshot of a Balancer V2 pool with quotes recorded with `BalancerV2SwapAdapter` at the
+    /// same block.
+    #[derive(Deserialize)]
+    struct Fixture {
+        snapshot: ComponentWithState,
+        tokens: Vec<FixtureToken>,
+        quotes: Vec<FixtureQuote>,
+    }
+
+    /// Compares the native states to the VM adapter on the fixtures in `tests/assets/balancer_v2`,
+    /// which have to cover both weighted and composable stable pools.
+    ///
+    /// Swaps of the BPT are not supported natively, so quotes of the BPT are skipped.
+    #[tokio::test]
+    #[ignore] // Requires fixtures recorded with the `record_adapter_fixtures` example
+    async fn test_balancer_v2_matches_vm_adapter() {
+        let fixtures_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/assets/balancer_v2");
+        let mut checked = 0;
+        let mut pool_types = HashSet::new();
+        for entry in fs::read_dir(fixtures_dir).expect("Failed to read the fixtures directory") {
+            let path = entry.unwrap().path();
+            if path
+                .extension()
+                .is_none_or(|extension| extension != "json")
+            {
+                continue;
+            }
+            let fixture: Fixture =
+                serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+            let all_tokens: HashMap<Bytes, Token> = fixture
+                .tokens
+                .iter()
+                .map(|fixture_token| {
+                    let token = Token::new(
+                        &fixture_token.address,
+                        "T",
+                        fixture_token.decimals,
+                        0,
+                        &[Some(10_000)],
+                        Chain::Ethereum,
+                        100,
+                    );
+                    (fixture_token.address.clone(), token)
+                })
+                .collect();
+            let pool_type = String::from_utf8(
+                attribute(&fixture.snapshot, "pool_type")
+                    .unwrap()
+                    .to_vec(),
+            )
+            .unwrap();
+            pool_types.insert(if pool_type.starts_with("WeightedPool") {
+                "weighted"
+            } else {
+                "stable"
+            });
+            let pool_address = Bytes::from_str(&fixture.snapshot.component.id[..42]).unwrap();
+
+            let state = BalancerV2State::try_from_with_header(
+                fixture.snapshot,
+                header(),
+                &HashMap::new(),
+                &all_tokens,
+            )
+            .await
+            .unwrap_or_else(|err| panic!("Failed to decode {path:?}: {err:?}"));
+
+            for quote in fixture.quotes {
+                if quote.token_in == pool_address || quote.token_out == pool_address {
+                    continue;
+                }
+                let result = state
+                    .get_amount_out(
+                        BigUint::from_str(&quote.amount_in).unwrap(),
+                        &all_tokens[&quote.token_in],
+                        &all_tokens[&quote.token_out],
+                    )
+                    .unwrap();
+                assert_eq!(
+                    result.amount,
+                    BigUint::from_str(&quote.amount_out).unwrap(),
+                    "{path:?}: {} {} -> {}",
+                    quote.amount_in,
+                    quote.token_in,
+                    quote.token_out
+                );
+                checked += 1;
+            }
+        }
+        assert!(checked > 0, "No fixtures found");
+        assert_eq!(pool_types.len(), 2, "The fixtures don't cover weighted and stable pools");
+    }
+}
@@ -0,0 +1,115 @@
+//! Swap math of Balancer V2 weighted pools.
+//!
+//! All amounts and balances are upscaled to 18 decimals and weights are normalized, i.e. they sum
+//! up to one. See https://github.com/balancer/balancer-v2-monorepo/blob/master/pkg/pool-weighted/contracts/WeightedMath.sol
+use alloy::primitives::U256;
+use tycho_common::simulation::errors::SimulationError;
+
+use super::fixed_point::{complement, div_down, div_up, mul_down, mul_up, pow_up, sub, ONE};
+use crate::evm::protocol::safe_math::safe_add_u256;
+
+/// Swaps can't take in more than 30% of the balance in
+pub(crate) const MAX_IN_RATIO: U256 = U256::from_limbs([300_000_000_000_000_000, 0, 0, 0]);
+/// Swaps can't take out more than 30% of the balance out
+pub(crate) const MAX_OUT_RATIO: U256 = U256::from_limbs([300_000_000_000_000_000, 0, 0, 0]);
+
+/// Returns the amount out for an exact amount in, rounded down.
+pub(crate) fn calc_out_given_in(
+    balance_in: U256,
+    weight_in: U256,
+    balance_out: U256,
+    weight_out: U256,
+    amount_in: U256,
+) -> Result<U256, SimulationError> {
+    // amountOut = balanceOut * (1 - (balanceIn / (balanceIn + amountIn))^(weightIn / weightOut))
+    if amount_in > mul_down(balance_in, MAX_IN_RATIO)? {
+        return Err(SimulationError::InvalidInput(
+            "Amount in exceeds the max in ratio".to_string(),
+            None,
+        ));
+    }
+
+    let denominator = safe_add_u256(balance_in, amount_in)?;
+    let base = div_up(balance_in, denominator)?;
+    let exponent = div_down(weight_in, weight_out)?;
+    let power = pow_up(base, exponent)?;
+
+    mul_down(balance_out, complement(power))
+}
+
+/// Returns the amount in for an exact amount out, rounded up.
+pub(crate) fn calc_in_given_out(
+    balance_in: U256,
+    weight_in: U256,
+    balance_out: U256,
+    weight_out: U256,
+    amount_out: U256,
+) -> Result<U256, SimulationError> {
+    // amountIn = balanceIn * ((balanceOut / (balanceOut - amountOut))^(weightOut / weightIn) - 1)
+    if amount_out > mul_down(balance_out, MAX_OUT_RATIO)? {
+        return Err(SimulationError::InvalidInput(
+            "Amount out exceeds the max out ratio".to_string(),
+            None,
+        ));
+    }
+
+    let base = div_up(balance_out, sub(balance_out, amount_out, "Amount out exceeds balance")?)?;
+    let exponent = div_up(weight_out, weight_in)?;
+    let power = pow_up(base, exponent)?;
+
+    mul_up(balance_in, sub(power, ONE, "Pow result below one")?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn u256(value: &str) -> U256 {
+        U256::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_calc_out_given_in() {
+        // 80/20 pool
+        let result = calc_out_given_in(
+            u256("1000000000000000000000"),
+            u256("800000000000000000"),
+            u256("2000000000000000000000"),
+            u256("200000000000000000"),
+            u256("10000000000000000000"),
+        )
+        .unwrap();
+
+        assert_eq!(result, u256("78039311034367422000"));
+    }
+
+    #[test]
+    fn test_calc_in_given_out() {
+        // 80/20 pool
+        let result = calc_in_given_out(
+            u256("1000000000000000000000"),
+            u256("800000000000000000"),
+            u256("2000000000000000000000"),
+            u256("200000000000000000"),
+            u256("78039311034367422000"),
+        )
+        .unwrap();
+
+        assert_eq!(result, u256("10000000000010099000"));
+    }
+
+    #[test]
+    fn test_max_in_ratio() {
+        let result = calc_out_given_in(
+            u256("1000000000000000000000"),
+            u256("500000000000000000"),
+            u256("1000000000000000000000"),
+            u256("500000000000000000"),
+            u256("300000000000000000001"),
+        );
+
+        assert!(matches!(result, Err(SimulationError::InvalidInput(_, None))));
+    }
+}
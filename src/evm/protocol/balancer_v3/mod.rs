@@ -0,0 +1,4 @@
+//! Balancer V3 weighted and stable pools, including boosted pools swapping through the Vault's
+//! ERC4626 buffers
+pub mod state;
+mod tycho_decoder;
@@ -0,0 +1,1083 @@
+use std::{any::Any, collections::HashMap};
+
+use alloy::primitives::U256;
+use num_bigint::{BigUint, ToBigUint};
+use tycho_common::{
+    dto::ProtocolStateDelta,
+    models::token::Token,
+    simulation::{
+        errors::{SimulationError, TransitionError},
+        protocol_sim::{Balances, GetAmountOutResult, ProtocolSim},
+    },
+    Bytes,
+};
+
+use crate::{
+    evm::protocol::{
+        balancer_v2::{
+            fixed_point::{complement, div_down, div_up, mul_down, mul_up, sub, ONE},
+            stable_math::{self, AMP_PRECISION},
+            weighted_math::{self, MAX_IN_RATIO},
+        },
+        safe_math::{safe_add_u256, safe_mul_u256, safe_sub_u256},
+        u256_num::{biguint_to_u256, u256_to_biguint, u256_to_f64},
+        utils::decode_u256_list,
+    },
+    protocol::{fee::SwapFee, transfer_tax::amount_after_tax},
+};
+
+const WEIGHTED_SWAP_GAS: u64 = 100_000;
+const STABLE_SWAP_GAS: u64 = 135_000;
+/// Gas of wrapping or unwrapping through an ERC4626 buffer
+const BUFFER_GAS: u64 = 60_000;
+/// Soft limit for stable pool swaps: the amount that takes out 90% of the balance out
+const STABLE_MAX_OUT_RATIO: U256 = U256::from_limbs([900_000_000_000_000_000, 0, 0, 0]);
+
+/// The invariant of a Balancer V3 pool, with its pool type specific parameters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BalancerV3PoolType {
+    /// Weighted pool, with the normalized weight of each token scaled by 1e18
+    Weighted { normalized_weights: Vec<U256> },
+    /// Stable pool, with the amplification parameter scaled by 1e3
+    Stable { amp: U256 },
+}
+
+/// Attributes with the liquidity of the buffers, as JSON lists with an entry per pool token: the
+/// underlying and wrapped balances of the buffer, and the `maxDeposit` and `maxWithdraw` of the
+/// ERC4626 vault.
+pub(crate) const BUFFER_LIQUIDITY_ATTRIBUTES: [&str; 4] = [
+    "buffer_underlying_balances",
+    "buffer_wrapped_balances",
+    "erc4626_max_deposits",
+    "erc4626_max_withdrawals",
+];
+
+/// Liquidity available to wrap and unwrap an ERC4626 pool token.
+///
+/// The Vault serves wraps and unwraps from the balances of its buffer if they suffice, and
+/// otherwise deposits into or withdraws from the ERC4626 vault.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BufferLiquidity {
+    /// Underlying tokens held by the buffer
+    pub underlying_balance: U256,
+    /// ERC4626 shares held by the buffer
+    pub wrapped_balance: U256,
+    /// Underlying tokens the ERC4626 vault accepts, as `maxDeposit`
+    pub max_deposit: U256,
+    /// Underlying tokens that can be withdrawn from the ERC4626 vault, as `maxWithdraw`
+    pub max_withdraw: U256,
+}
+
+impl BufferLiquidity {
+    /// Returns the liquidity at the given index of `BUFFER_LIQUIDITY_ATTRIBUTES`.
+    pub(crate) fn attribute_mut(&mut self, attribute: usize) -> &mut U256 {
+        match attribute {
+            0 => &mut self.underlying_balance,
+            1 => &mut self.wrapped_balance,
+            2 => &mut self.max_deposit,
+            _ => &mut self.max_withdraw,
+        }
+    }
+}
+
+/// The Vault's ERC4626 buffer of a pool token, wrapping and unwrapping its underlying token.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Erc4626Buffer {
+    /// The underlying token
+    pub underlying_token: Bytes,
+    /// Decimals of the underlying token, at most 18
+    pub underlying_decimals: u32,
+    /// Liquidity of the buffer. Swaps through the buffer are not bounded if it is unknown.
+    pub liquidity: Option<BufferLiquidity>,
+}
+
+impl Erc4626Buffer {
+    pub fn new(underlying_token: Bytes, underlying_decimals: u32) -> Self {
+        Self { underlying_token, underlying_decimals, liquidity: None }
+    }
+
+    pub fn with_liquidity(mut self, liquidity: BufferLiquidity) -> Self {
+        self.liquidity = Some(liquidity);
+        self
+    }
+
+    /// Multiplier bringing amounts of the underlying token to 18 decimals
+    fn underlying_scaling(&self) -> U256 {
+        U256::from(10).pow(U256::from(18u32.saturating_sub(self.underlying_decimals)))
+    }
+
+    /// Takes wrapping `amount` underlying tokens into `shares` out of the liquidity.
+    fn record_wrap(&mut self, amount: U256, shares: U256) -> Result<(), SimulationError> {
+        let Some(liquidity) = &mut self.liquidity else {
+            return Ok(());
+        };
+        if shares <= liquidity.wrapped_balance {
+            liquidity.wrapped_balance -= shares;
+            liquidity.underlying_balance = safe_add_u256(liquidity.underlying_balance, amount)?;
+        } else if amount <= liquidity.max_deposit {
+            liquidity.max_deposit -= amount;
+        } else {
+            return Err(SimulationError::InvalidInput(
+                format!("Wrapping {amount} exceeds the liquidity of the ERC4626 buffer"),
+                None,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Takes unwrapping `shares` into `amount` underlying tokens out of the liquidity.
+    fn record_unwrap(&mut self, shares: U256, amount: U256) -> Result<(), SimulationError> {
+        let Some(liquidity) = &mut self.liquidity else {
+            return Ok(());
+        };
+        if amount <= liquidity.underlying_balance {
+            liquidity.underlying_balance -= amount;
+            liquidity.wrapped_balance = safe_add_u256(liquidity.wrapped_balance, shares)?;
+        } else if amount <= liquidity.max_withdraw {
+            liquidity.max_withdraw -= amount;
+        } else {
+            return Err(SimulationError::InvalidInput(
+                format!("Unwrapping {amount} exceeds the liquidity of the ERC4626 buffer"),
+                None,
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A token of a swap, either a pool token or the underlying token of an ERC4626 pool token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct SwapToken {
+    index: usize,
+    /// Whether the token is the underlying token, wrapped or unwrapped through the Vault's buffer
+    underlying: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BalancerV3State {
+    /// The pool's address
+    id: String,
+    /// The pool's tokens
+    tokens: Vec<Bytes>,
+    /// Buffer of each pool token that is an ERC4626 vault with a Vault buffer
+    buffers: Vec<Option<Erc4626Buffer>>,
+    /// Raw balance of each token
+    balances: Vec<U256>,
+    /// Multiplier bringing the amounts of each token to 18 decimals
+    decimal_scaling: Vec<U256>,
+    /// Rate of each token, scaled by 1e18. For ERC4626 tokens this is the value of a share in
+    /// underlying tokens, both in 18 decimals
+    rates: Vec<U256>,
+    /// Swap fee percentage, scaled by 1e18
+    swap_fee: U256,
+    pool_type: BalancerV3PoolType,
+}
+
+impl BalancerV3State {
+    /// Creates a new `BalancerV3State`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The pool address.
+    /// * `tokens` - Addresses of the pool's tokens, in pool order.
+    /// * `balances` - Raw balance of each token.
+    /// * `decimals` - Decimals of each token, at most 18.
+    /// * `rates` - Rate of each token scaled by 1e18, 1e18 for tokens without rate provider.
+    /// * `swap_fee` - Swap fee percentage scaled by 1e18.
+    /// * `pool_type` - The pool type and its parameters.
+    pub fn new(
+        id: String,
+        tokens: Vec<Bytes>,
+        balances: Vec<U256>,
+        decimals: &[u32],
+        rates: Vec<U256>,
+        swap_fee: U256,
+        pool_type: BalancerV3PoolType,
+    ) -> Self {
+        let decimal_scaling = decimals
+            .iter()
+            .map(|decimals| U256::from(10).pow(U256::from(18u32.saturating_sub(*decimals))))
+            .collect();
+        let buffers = vec![None; tokens.len()];
+        Self { id, tokens, buffers, balances, decimal_scaling, rates, swap_fee, pool_type }
+    }
+
+    /// Sets the buffer of each ERC4626 pool token, `None` for other tokens.
+    ///
+    /// Swaps from or to an underlying token go through the Vault's ERC4626 buffer, converting
+    /// between underlying and pool token at the pool token's rate, and are bounded by the buffer's
+    /// liquidity.
+    pub fn with_buffers(mut self, buffers: Vec<Option<Erc4626Buffer>>) -> Self {
+        self.buffers = buffers;
+        self
+    }
+
+    fn buffer(&self, index: usize) -> Result<&Erc4626Buffer, SimulationError> {
+        self.buffers[index]
+            .as_ref()
+            .ok_or_else(|| SimulationError::FatalError(format!("Token {index} has no buffer")))
+    }
+
+    fn buffer_mut(&mut self, index: usize) -> Result<&mut Erc4626Buffer, SimulationError> {
+        self.buffers[index]
+            .as_mut()
+            .ok_or_else(|| SimulationError::FatalError(format!("Token {index} has no buffer")))
+    }
+
+    fn swap_token(&self, token: &Bytes) -> Result<SwapToken, SimulationError> {
+        if let Some(index) = self
+            .tokens
+            .iter()
+            .position(|pool_token| pool_token == token)
+        {
+            return Ok(SwapToken { index, underlying: false });
+        }
+        self.buffers
+            .iter()
+            .position(|buffer| {
+                buffer
+                    .as_ref()
+                    .is_some_and(|buffer| &buffer.underlying_token == token)
+            })
+            .map(|index| SwapToken { index, underlying: true })
+            .ok_or_else(|| {
+                SimulationError::InvalidInput(format!("Token {token} is not in the pool"), None)
+            })
+    }
+
+    fn swap_tokens(
+        &self,
+        token_in: &Bytes,
+        token_out: &Bytes,
+    ) -> Result<(SwapToken, SwapToken), SimulationError> {
+        let (swap_in, swap_out) = (self.swap_token(token_in)?, self.swap_token(token_out)?);
+        if swap_in.index == swap_out.index {
+            return Err(SimulationError::InvalidInput(
+                "Token in and token out must differ".to_string(),
+                None,
+            ));
+        }
+        Ok((swap_in, swap_out))
+    }
+
+    /// Converts an amount of underlying tokens to ERC4626 shares, as `previewDeposit` does.
+    ///
+    /// The rate relates both tokens in 18 decimals, so the amounts are scaled by the decimals of
+    /// the underlying token and of the shares.
+    fn wrap(&self, amount: U256, index: usize) -> Result<U256, SimulationError> {
+        let amount_scaled18 = safe_mul_u256(amount, self.buffer(index)?.underlying_scaling())?;
+        Ok(div_down(amount_scaled18, self.rates[index])? / self.decimal_scaling[index])
+    }
+
+    /// Converts an amount of ERC4626 shares to underlying tokens, as `previewRedeem` does.
+    fn unwrap(&self, amount: U256, index: usize) -> Result<U256, SimulationError> {
+        Ok(self.to_scaled18(amount, index)? / self.buffer(index)?.underlying_scaling())
+    }
+
+    /// Returns the most underlying tokens the buffer of the token at `index` can wrap, `None` if
+    /// its liquidity is unknown.
+    fn max_wrap(&self, index: usize) -> Result<Option<U256>, SimulationError> {
+        let Some(liquidity) = &self.buffer(index)?.liquidity else {
+            return Ok(None);
+        };
+        Ok(Some(
+            self.unwrap(liquidity.wrapped_balance, index)?
+                .max(liquidity.max_deposit),
+        ))
+    }
+
+    /// Returns the most underlying tokens the buffer of the token at `index` can unwrap to, `None`
+    /// if its liquidity is unknown.
+    fn max_unwrap(&self, index: usize) -> Result<Option<U256>, SimulationError> {
+        Ok(self
+            .buffer(index)?
+            .liquidity
+            .as_ref()
+            .map(|liquidity| {
+                liquidity
+                    .underlying_balance
+                    .max(liquidity.max_withdraw)
+            }))
+    }
+
+    /// Returns the 18 decimal fixed point factor converting amounts of the token at `index` to 18
+    /// decimals, including its rate.
+    fn scaling_factor(&self, index: usize) -> Result<U256, SimulationError> {
+        safe_mul_u256(self.decimal_scaling[index], self.rates[index])
+    }
+
+    /// Converts a raw amount to a live 18 decimal amount, as `toScaled18ApplyRateRoundDown`.
+    fn to_scaled18(&self, amount: U256, index: usize) -> Result<U256, SimulationError> {
+        mul_down(safe_mul_u256(amount, self.decimal_scaling[index])?, self.rates[index])
+    }
+
+    /// Converts a live 18 decimal amount to a raw amount, as `toRawUndoRateRoundDown`.
+    fn to_raw_down(&self, amount: U256, index: usize) -> Result<U256, SimulationError> {
+        div_down(amount, self.scaling_factor(index)?)
+    }
+
+    fn to_raw_up(&self, amount: U256, index: usize) -> Result<U256, SimulationError> {
+        div_up(amount, self.scaling_factor(index)?)
+    }
+
+    fn live_balances(&self) -> Result<Vec<U256>, SimulationError> {
+        self.balances
+            .iter()
+            .enumerate()
+            .map(|(index, balance)| self.to_scaled18(*balance, index))
+            .collect()
+    }
+
+    /// Returns the live amount out for a live amount in, after fees.
+    fn compute_out_given_in(
+        &self,
+        balances: &[U256],
+        index_in: usize,
+        index_out: usize,
+        amount_in: U256,
+    ) -> Result<U256, SimulationError> {
+        match &self.pool_type {
+            BalancerV3PoolType::Weighted { normalized_weights } => {
+                weighted_math::calc_out_given_in(
+                    balances[index_in],
+                    normalized_weights[index_in],
+                    balances[index_out],
+                    normalized_weights[index_out],
+                    amount_in,
+                )
+            }
+            BalancerV3PoolType::Stable { amp } => {
+                let invariant = stable_math::calculate_invariant(*amp, balances)?;
+                stable_math::calc_out_given_in(
+                    *amp, balances, index_in, index_out, amount_in, invariant,
+                )
+            }
+        }
+    }
+
+    /// Returns the raw amount of the token at `index_out` the pool swaps a raw amount in for.
+    fn pool_amount_out(
+        &self,
+        amount_in: U256,
+        index_in: usize,
+        index_out: usize,
+    ) -> Result<U256, SimulationError> {
+        // The Vault takes the fee from the live amount in before calling the pool
+        let amount_in_scaled18 = self.to_scaled18(amount_in, index_in)?;
+        let fee_amount = mul_up(amount_in_scaled18, self.swap_fee)?;
+        let balances = self.live_balances()?;
+        let amount_out_scaled18 = self.compute_out_given_in(
+            &balances,
+            index_in,
+            index_out,
+            safe_sub_u256(amount_in_scaled18, fee_amount)?,
+        )?;
+        self.to_raw_down(amount_out_scaled18, index_out)
+    }
+
+    /// Returns the largest raw amount in, up to `max_in`, the pool swaps for at most `max_out`.
+    fn pool_amount_in_for_max_out(
+        &self,
+        max_in: U256,
+        max_out: U256,
+        index_in: usize,
+        index_out: usize,
+    ) -> Result<U256, SimulationError> {
+        // Bisecting, as the weighted math only inverts swaps up to the max out ratio
+        let (mut low, mut high) = (U256::ZERO, max_in);
+        while low < high {
+            let mid = high - (high - low) / U256::from(2);
+            if self.pool_amount_out(mid, index_in, index_out)? <= max_out {
+                low = mid;
+            } else {
+                high = mid - U256::from(1);
+            }
+        }
+        Ok(low)
+    }
+
+    fn swap_gas(&self, swap_in: SwapToken, swap_out: SwapToken) -> u64 {
+        let pool_gas = match self.pool_type {
+            BalancerV3PoolType::Weighted { .. } => WEIGHTED_SWAP_GAS,
+            BalancerV3PoolType::Stable { .. } => STABLE_SWAP_GAS,
+        };
+        let buffers = u64::from(swap_in.underlying) + u64::from(swap_out.underlying);
+        pool_gas + BUFFER_GAS * buffers
+    }
+
+    /// Returns the amount of the token at `index_out` received for one unit of the token at
+    /// `index_in`, in live units and without fees.
+    fn live_spot_price(
+        &self,
+        balances: &[U256],
+        index_in: usize,
+        index_out: usize,
+    ) -> Result<f64, SimulationError> {
+        match &self.pool_type {
+            BalancerV3PoolType::Weighted { normalized_weights } => {
+                // (balanceOut / weightOut) / (balanceIn / weightIn)
+                Ok(
+                    (u256_to_f64(balances[index_out]) / u256_to_f64(normalized_weights[index_out])) /
+                        (u256_to_f64(balances[index_in]) /
+                            u256_to_f64(normalized_weights[index_in])),
+                )
+            }
+            BalancerV3PoolType::Stable { amp } => {
+                // Ratio of the partial derivatives of the invariant equation, see
+                // `BalancerV2State`
+                let invariant = u256_to_f64(stable_math::calculate_invariant(*amp, balances)?);
+                let num_tokens = balances.len() as f64;
+                let amp_times_total = u256_to_f64(*amp) / u256_to_f64(AMP_PRECISION) * num_tokens;
+                let d_p = balances
+                    .iter()
+                    .fold(invariant, |d_p, balance| {
+                        d_p * invariant / (u256_to_f64(*balance) * num_tokens)
+                    });
+                let derivative =
+                    |index: usize| amp_times_total + d_p / u256_to_f64(balances[index]);
+                Ok(derivative(index_in) / derivative(index_out))
+            }
+        }
+    }
+
+    /// Returns the value of one token in live units, i.e. the rate of pool tokens and one for
+    /// underlying tokens.
+    fn unit_value(&self, token: SwapToken) -> f64 {
+        if token.underlying {
+            1.0
+        } else {
+            u256_to_f64(self.rates[token.index]) / u256_to_f64(ONE)
+        }
+    }
+}
+
+impl ProtocolSim for BalancerV3State {
+    fn fee(&self) -> f64 {
+        u256_to_f64(self.swap_fee) / u256_to_f64(ONE)
+    }
+
+    fn spot_price(&self, base: &Token, quote: &Token) -> Result<f64, SimulationError> {
+        let (swap_base, swap_quote) = self.swap_tokens(&base.address, &quote.address)?;
+        let balances = self.live_balances()?;
+        if balances[swap_base.index].is_zero() || balances[swap_quote.index].is_zero() {
+            return Err(SimulationError::RecoverableError("No liquidity".to_string()));
+        }
+        Ok(self.live_spot_price(&balances, swap_base.index, swap_quote.index)? *
+            self.unit_value(swap_base) /
+            self.unit_value(swap_quote))
+    }
+
+    fn get_amount_out(
+        &self,
+        amount_in: BigUint,
+        token_in: &Token,
+        token_out: &Token,
+    ) -> Result<GetAmountOutResult, SimulationError> {
+        let (swap_in, swap_out) = self.swap_tokens(&token_in.address, &token_out.address)?;
+        let (index_in, index_out) = (swap_in.index, swap_out.index);
+        let mut amount_in = biguint_to_u256(&amount_after_tax(&amount_in, token_in));
+        if amount_in.is_zero() {
+            return Err(SimulationError::InvalidInput("Amount in cannot be zero".to_string(), None));
+        }
+        let mut new_state = self.clone();
+        if swap_in.underlying {
+            let shares = self.wrap(amount_in, index_in)?;
+            new_state
+                .buffer_mut(index_in)?
+                .record_wrap(amount_in, shares)?;
+            amount_in = shares;
+        }
+
+        let amount_out = self.pool_amount_out(amount_in, index_in, index_out)?;
+        new_state.balances[index_in] = safe_add_u256(self.balances[index_in], amount_in)?;
+        new_state.balances[index_out] =
+            sub(self.balances[index_out], amount_out, "Amount out exceeds balance")?;
+
+        let amount_out = if swap_out.underlying {
+            let underlying = self.unwrap(amount_out, index_out)?;
+            new_state
+                .buffer_mut(index_out)?
+                .record_unwrap(amount_out, underlying)?;
+            underlying
+        } else {
+            amount_out
+        };
+        Ok(GetAmountOutResult::new(
+            amount_after_tax(&u256_to_biguint(amount_out), token_out),
+            self.swap_gas(swap_in, swap_out)
+                .to_biguint()
+                .expect("Expected an unsigned integer as gas value"),
+            Box::new(new_state),
+        ))
+    }
+
+    fn get_limits(
+        &self,
+        sell_token: Bytes,
+        buy_token: Bytes,
+    ) -> Result<(BigUint, BigUint), SimulationError> {
+        let (swap_in, swap_out) = self.swap_tokens(&sell_token, &buy_token)?;
+        let (index_in, index_out) = (swap_in.index, swap_out.index);
+        let balances = self.live_balances()?;
+        if balances[index_in].is_zero() || balances[index_out].is_zero() {
+            return Ok((BigUint::ZERO, BigUint::ZERO));
+        }
+
+        let (max_in, mut max_out) = match &self.pool_type {
+            // Weighted pools have a hard limit on the amount in
+            BalancerV3PoolType::Weighted { .. } => {
+                let max_in = mul_down(balances[index_in], MAX_IN_RATIO)?;
+                let max_out = self.compute_out_given_in(&balances, index_in, index_out, max_in)?;
+                (self.to_raw_down(max_in, index_in)?, self.to_raw_down(max_out, index_out)?)
+            }
+            BalancerV3PoolType::Stable { amp } => {
+                let max_out = mul_down(balances[index_out], STABLE_MAX_OUT_RATIO)?;
+                let invariant = stable_math::calculate_invariant(*amp, &balances)?;
+                let max_in = stable_math::calc_in_given_out(
+                    *amp, &balances, index_in, index_out, max_out, invariant,
+                )?;
+                (self.to_raw_up(max_in, index_in)?, self.to_raw_down(max_out, index_out)?)
+            }
+        };
+        // The fee is taken from the amount in before swapping
+        let mut max_in = div_down(max_in, complement(self.swap_fee))?;
+
+        // Swaps through buffers are also bounded by the liquidity of the buffers
+        if swap_in.underlying {
+            if let Some(max_wrap) = self.max_wrap(index_in)? {
+                let max_shares = self.wrap(max_wrap, index_in)?;
+                if max_shares < max_in {
+                    max_in = max_shares;
+                    max_out = max_out.min(self.pool_amount_out(max_in, index_in, index_out)?);
+                }
+            }
+        }
+        if swap_out.underlying {
+            if let Some(max_unwrap) = self.max_unwrap(index_out)? {
+                let max_shares = self.wrap(max_unwrap, index_out)?;
+                if max_shares < max_out {
+                    max_out = max_shares;
+                    max_in =
+                        self.pool_amount_in_for_max_out(max_in, max_out, index_in, index_out)?;
+                }
+            }
+        }
+
+        let max_in = if swap_in.underlying { self.unwrap(max_in, index_in)? } else { max_in };
+        let max_out = if swap_out.underlying { self.unwrap(max_out, index_out)? } else { max_out };
+        Ok((u256_to_biguint(max_in), u256_to_biguint(max_out)))
+    }
+
+    fn delta_transition(
+        &mut self,
+        delta: ProtocolStateDelta,
+        _tokens: &HashMap<Bytes, Token>,
+        balances: &Balances,
+    ) -> Result<(), TransitionError<String>> {
+        if let Some(component_balances) = balances
+            .component_balances
+            .get(&self.id)
+        {
+            for (token, balance) in component_balances {
+                if let Some(index) = self
+                    .tokens
+                    .iter()
+                    .position(|pool_token| pool_token == token)
+                {
+                    self.balances[index] = U256::from_be_slice(balance);
+                }
+            }
+        }
+
+        if let Some(fee) = delta.updated_attributes.get("fee") {
+            self.swap_fee = U256::from_be_slice(fee);
+        }
+        if let Some(rates) = delta
+            .updated_attributes
+            .get("token_rates")
+        {
+            let rates = decode_u256_list(rates)
+                .map_err(|err| TransitionError::DecodeError(err.to_string()))?;
+            if rates.len() != self.tokens.len() {
+                return Err(TransitionError::DecodeError(format!(
+                    "Expected {} token rates, got {}",
+                    self.tokens.len(),
+                    rates.len()
+                )));
+            }
+            self.rates = rates;
+        }
+        for (attribute, name) in BUFFER_LIQUIDITY_ATTRIBUTES
+            .iter()
+            .enumerate()
+        {
+            let Some(values) = delta.updated_attributes.get(*name) else {
+                continue;
+            };
+            let values = decode_u256_list(values)
+                .map_err(|err| TransitionError::DecodeError(err.to_string()))?;
+            if values.len() != self.tokens.len() {
+                return Err(TransitionError::DecodeError(format!(
+                    "Expected {} {name}, got {}",
+                    self.tokens.len(),
+                    values.len()
+                )));
+            }
+            // Buffers whose liquidity is unknown stay unbounded until the next snapshot
+            for (buffer, value) in self.buffers.iter_mut().zip(values) {
+                if let Some(liquidity) = buffer
+                    .as_mut()
+                    .and_then(|buffer| buffer.liquidity.as_mut())
+                {
+                    *liquidity.attribute_mut(attribute) = value;
+                }
+            }
+        }
+        if let BalancerV3PoolType::Stable { amp } = &mut self.pool_type {
+            if let Some(new_amp) = delta.updated_attributes.get("amp") {
+                *amp = U256::from_be_slice(new_amp);
+            }
+        }
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn ProtocolSim> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn eq(&self, other: &dyn ProtocolSim) -> bool {
+        if let Some(other_state) = other.as_any().downcast_ref::<Self>() {
+            self == other_state
+        } else {
+            false
+        }
+    }
+}
+
+impl SwapFee for BalancerV3State {
+    fn swap_fee(
+        &self,
+        _token_in: &Token,
+        _token_out: &Token,
+        _amount_in: Option<&BigUint>,
+    ) -> Result<f64, SimulationError> {
+        Ok(self.fee())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use approx::assert_relative_eq;
+    use rstest::rstest;
+    use tycho_common::models::Chain;
+
+    use super::*;
+
+    fn token(address: &str, decimals: u32) -> Token {
+        Token::new(
+            &Bytes::from_str(address).unwrap(),
+            "T",
+            decimals,
+            0,
+            &[Some(10_000)],
+            Chain::Ethereum,
+            100,
+        )
+    }
+
+    fn weth() -> Token {
+        token("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2", 18)
+    }
+
+    fn bal() -> Token {
+        token("0xba100000625a3754423978a60c9317c58a424e3d", 18)
+    }
+
+    fn usdc() -> Token {
+        token("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", 6)
+    }
+
+    fn usdt() -> Token {
+        token("0xdac17f958d2ee523a2206206994597c13d831ec7", 6)
+    }
+
+    fn wa_usdc() -> Token {
+        token("0xd4fa2d31b7968e448877f69a96de69f5de8cd23e", 6)
+    }
+
+    fn wa_usdt() -> Token {
+        token("0x7bc3485026ac48b6cf9baf0a377477fff5703af8", 6)
+    }
+
+    fn u256(value: &str) -> U256 {
+        U256::from_str(value).unwrap()
+    }
+
+    /// 80 BAL / 20 WETH pool with a 1% fee
+    fn weighted_state() -> BalancerV3State {
+        BalancerV3State::new(
+            "weighted".to_string(),
+            vec![bal().address, weth().address],
+            vec![u256("8000000000000000000000"), u256("200000000000000000000")],
+            &[18, 18],
+            vec![ONE, ONE],
+            u256("10000000000000000"),
+            BalancerV3PoolType::Weighted {
+                normalized_weights: vec![u256("800000000000000000"), u256("200000000000000000")],
+            },
+        )
+    }
+
+    /// Boosted waUSDC / waUSDT pool with A = 200 and a 0.01% fee, the ERC4626 tokens being worth
+    /// 1.1 USDC and 1.05 USDT
+    fn boosted_state() -> BalancerV3State {
+        BalancerV3State::new(
+            "boosted".to_string(),
+            vec![wa_usdc().address, wa_usdt().address],
+            vec![u256("1000000000000"), u256("1100000000000")],
+            &[6, 6],
+            vec![u256("1100000000000000000"), u256("1050000000000000000")],
+            u256("100000000000000"),
+            BalancerV3PoolType::Stable { amp: U256::from(200) * AMP_PRECISION },
+        )
+        .with_buffers(vec![
+            Some(Erc4626Buffer::new(usdc().address, 6)),
+            Some(Erc4626Buffer::new(usdt().address, 6)),
+        ])
+    }
+
+    fn wa_usdc_18() -> Token {
+        token("0x1111111111111111111111111111111111111111", 18)
+    }
+
+    /// `boosted_state` with an 18 decimals ERC4626 token over USDC instead of waUSDC
+    fn boosted_state_18_decimals() -> BalancerV3State {
+        BalancerV3State::new(
+            "boosted".to_string(),
+            vec![wa_usdc_18().address, wa_usdt().address],
+            vec![u256("1000000000000000000000000"), u256("1100000000000")],
+            &[18, 6],
+            vec![u256("1100000000000000000"), u256("1050000000000000000")],
+            u256("100000000000000"),
+            BalancerV3PoolType::Stable { amp: U256::from(200) * AMP_PRECISION },
+        )
+        .with_buffers(vec![
+            Some(Erc4626Buffer::new(usdc().address, 6)),
+            Some(Erc4626Buffer::new(usdt().address, 6)),
+        ])
+    }
+
+    fn liquidity(
+        underlying_balance: u64,
+        wrapped_balance: u64,
+        max_deposit: u64,
+        max_withdraw: u64,
+    ) -> BufferLiquidity {
+        BufferLiquidity {
+            underlying_balance: U256::from(underlying_balance),
+            wrapped_balance: U256::from(wrapped_balance),
+            max_deposit: U256::from(max_deposit),
+            max_withdraw: U256::from(max_withdraw),
+        }
+    }
+
+    /// `boosted_state` with the given liquidity of the waUSDC and waUSDT buffers
+    fn boosted_state_with_liquidity(
+        usdc_liquidity: BufferLiquidity,
+        usdt_liquidity: BufferLiquidity,
+    ) -> BalancerV3State {
+        boosted_state().with_buffers(vec![
+            Some(Erc4626Buffer::new(usdc().address, 6).with_liquidity(usdc_liquidity)),
+            Some(Erc4626Buffer::new(usdt().address, 6).with_liquidity(usdt_liquidity)),
+        ])
+    }
+
+    #[rstest]
+    #[case::weighted(weighted_state(), bal(), weth(), "10000000000000000000", "986944751576945200")]
+    #[case::boosted_wrapped(boosted_state(), wa_usdc(), wa_usdt(), "1000000000", "1047763733")]
+    #[case::boosted_underlying(boosted_state(), usdc(), usdt(), "1000000000", "1000138551")]
+    #[case::boosted_mixed(boosted_state(), usdc(), wa_usdt(), "1000000000", "952512906")]
+    fn test_get_amount_out(
+        #[case] state: BalancerV3State,
+        #[case] token_in: Token,
+        #[case] token_out: Token,
+        #[case] amount_in: &str,
+        #[case] expected: &str,
+    ) {
+        let result = state
+            .get_amount_out(BigUint::from_str(amount_in).unwrap(), &token_in, &token_out)
+            .unwrap();
+
+        assert_eq!(result.amount, BigUint::from_str(expected).unwrap());
+    }
+
+    #[test]
+    fn test_get_amount_out_through_buffers() {
+        let state = boosted_state();
+
+        let result = state
+            .get_amount_out(BigUint::from(1_000_000_000u64), &usdc(), &usdt())
+            .unwrap();
+
+        // The pool balances move by the wrapped amounts
+        let new_state = result
+            .new_state
+            .as_any()
+            .downcast_ref::<BalancerV3State>()
+            .unwrap();
+        assert_eq!(new_state.balances, vec![u256("1000909090909"), u256("1099047487094")]);
+        assert_eq!(result.gas, BigUint::from(STABLE_SWAP_GAS + 2 * BUFFER_GAS));
+    }
+
+    #[test]
+    fn test_get_amount_out_wrapper_with_other_decimals() {
+        let state = boosted_state_18_decimals();
+
+        let result = state
+            .get_amount_out(BigUint::from(1_000_000_000u64), &usdc(), &usdt())
+            .unwrap();
+
+        // 1000 USDC wrap into 1000 / 1.1 shares of 18 decimals
+        let wrapped = state
+            .get_amount_out(
+                BigUint::from_str("909090909090909090909").unwrap(),
+                &wa_usdc_18(),
+                &usdt(),
+            )
+            .unwrap();
+        assert_eq!(result.amount, wrapped.amount);
+        // The same as with waUSDC, up to the precision of the wrapped amount
+        let expected = BigUint::from(1_000_138_551u64);
+        assert!(
+            result
+                .amount
+                .clone()
+                .max(expected.clone()) -
+                result.amount.min(expected) <=
+                1u32.into()
+        );
+
+        // Unwrapping scales to the decimals of the underlying token too
+        let result = state
+            .get_amount_out(BigUint::from(1_000_000_000u64), &usdt(), &usdc())
+            .unwrap();
+        let shares = state
+            .get_amount_out(BigUint::from(1_000_000_000u64), &usdt(), &wa_usdc_18())
+            .unwrap();
+        assert_eq!(
+            result.amount,
+            (shares.amount * BigUint::from(11u32) / BigUint::from(10u32)) /
+                BigUint::from(1_000_000_000_000u64)
+        );
+    }
+
+    #[test]
+    fn test_get_amount_out_bounded_by_buffer_liquidity() {
+        // The USDC buffer can only wrap by depositing up to 500 USDC into waUSDC
+        let state = boosted_state_with_liquidity(
+            liquidity(0, 0, 500_000_000, 0),
+            liquidity(0, 0, 0, u64::MAX),
+        );
+        let result = state.get_amount_out(BigUint::from(1_000_000_000u64), &usdc(), &usdt());
+        assert!(matches!(result, Err(SimulationError::InvalidInput(_, None))));
+
+        let result = state
+            .get_amount_out(BigUint::from(400_000_000u64), &usdc(), &usdt())
+            .unwrap();
+        let new_state = result
+            .new_state
+            .as_any()
+            .downcast_ref::<BalancerV3State>()
+            .unwrap();
+        let usdc_liquidity = new_state.buffers[0]
+            .as_ref()
+            .unwrap()
+            .liquidity
+            .clone()
+            .unwrap();
+        assert_eq!(usdc_liquidity.max_deposit, U256::from(100_000_000u64));
+        let result = new_state.get_amount_out(BigUint::from(400_000_000u64), &usdc(), &usdt());
+        assert!(matches!(result, Err(SimulationError::InvalidInput(_, None))));
+
+        // The USDT buffer holds 2000 USDT, and waUSDT can't be redeemed
+        let state = boosted_state_with_liquidity(
+            liquidity(0, 0, u64::MAX, 0),
+            liquidity(2_000_000_000, 0, 0, 0),
+        );
+        let result = state
+            .get_amount_out(BigUint::from(1_000_000_000u64), &usdc(), &usdt())
+            .unwrap();
+        let new_state = result
+            .new_state
+            .as_any()
+            .downcast_ref::<BalancerV3State>()
+            .unwrap();
+        let usdt_liquidity = new_state.buffers[1]
+            .as_ref()
+            .unwrap()
+            .liquidity
+            .clone()
+            .unwrap();
+        assert_eq!(
+            usdt_liquidity.underlying_balance,
+            U256::from(2_000_000_000u64) - biguint_to_u256(&result.amount)
+        );
+        let result = state.get_amount_out(BigUint::from(3_000_000_000u64), &usdc(), &usdt());
+        assert!(matches!(result, Err(SimulationError::InvalidInput(_, None))));
+    }
+
+    #[test]
+    fn test_get_limits_bounded_by_buffer_liquidity() {
+        // The USDC buffer holds 5000 USDC worth of waUSDC
+        let state = boosted_state_with_liquidity(
+            liquidity(0, 4_545_454_545, 0, 0),
+            liquidity(0, 0, 0, u64::MAX),
+        );
+        let (max_in, max_out) = state
+            .get_limits(usdc().address, usdt().address)
+            .unwrap();
+        assert!(max_in <= BigUint::from(5_000_000_000u64));
+        assert!(max_in > BigUint::from(4_999_000_000u64));
+        let result = state
+            .get_amount_out(max_in, &usdc(), &usdt())
+            .unwrap();
+        assert!(result.amount <= max_out);
+
+        // 10000 USDT can be withdrawn from waUSDT
+        let state = boosted_state_with_liquidity(
+            liquidity(0, 0, u64::MAX, 0),
+            liquidity(0, 0, 0, 10_000_000_000),
+        );
+        let (max_in, max_out) = state
+            .get_limits(usdc().address, usdt().address)
+            .unwrap();
+        assert!(max_out <= BigUint::from(10_000_000_000u64));
+        let result = state
+            .get_amount_out(max_in, &usdc(), &usdt())
+            .unwrap();
+        assert!(result.amount <= max_out);
+        assert!(result.amount > BigUint::from(9_999_000_000u64));
+    }
+
+    #[test]
+    fn test_get_amount_out_unknown_token() {
+        let result = boosted_state().get_amount_out(BigUint::from(1u64), &weth(), &usdt());
+
+        assert!(matches!(result, Err(SimulationError::InvalidInput(_, None))));
+    }
+
+    #[test]
+    fn test_get_amount_out_underlying_and_its_wrapper() {
+        let result = boosted_state().get_amount_out(BigUint::from(1u64), &usdc(), &wa_usdc());
+
+        assert!(matches!(result, Err(SimulationError::InvalidInput(_, None))));
+    }
+
+    #[test]
+    fn test_spot_price() {
+        let state = boosted_state();
+
+        let wrapped = state
+            .spot_price(&wa_usdc(), &wa_usdt())
+            .unwrap();
+        let underlying = state
+            .spot_price(&usdc(), &usdt())
+            .unwrap();
+
+        // waUSDC is worth 1.1 / 1.05 waUSDT, and the pool is nearly balanced in USD
+        assert_relative_eq!(wrapped, 1.1 / 1.05, epsilon = 1e-3);
+        assert_relative_eq!(underlying, wrapped * 1.05 / 1.1, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_get_limits() {
+        let state = boosted_state();
+
+        let (max_in, max_out) = state
+            .get_limits(usdc().address, usdt().address)
+            .unwrap();
+
+        // 90% of the waUSDT balance, unwrapped
+        assert_eq!(max_out, BigUint::from(1_039_500_000_000u64));
+        let result = state
+            .get_amount_out(max_in, &usdc(), &usdt())
+            .unwrap();
+        // Reached up to the rounding of the buffers
+        assert_eq!(result.amount, BigUint::from(1_039_499_999_998u64));
+    }
+
+    #[test]
+    fn test_delta_transition() {
+        let mut state = boosted_state();
+        let delta = ProtocolStateDelta {
+            component_id: "boosted".to_string(),
+            updated_attributes: HashMap::from([
+                (
+                    "fee".to_string(),
+                    Bytes::from(U256::from(400_000_000_000_000u64).to_be_bytes_vec()),
+                ),
+                (
+                    "token_rates".to_string(),
+                    Bytes::from(r#"["0x10a741a462780000","0x0f43fc2c04ee0000"]"#.as_bytes()),
+                ),
+            ]),
+            deleted_attributes: Default::default(),
+        };
+        let balances = Balances {
+            component_balances: HashMap::from([(
+                "boosted".to_string(),
+                HashMap::from([(
+                    wa_usdt().address,
+                    Bytes::from(U256::from(5u64).to_be_bytes_vec()),
+                )]),
+            )]),
+            account_balances: HashMap::new(),
+        };
+
+        state
+            .delta_transition(delta, &HashMap::new(), &balances)
+            .unwrap();
+
+        assert_eq!(state.swap_fee, U256::from(400_000_000_000_000u64));
+        assert_eq!(state.rates, vec![u256("1200000000000000000"), u256("1100000000000000000")]);
+        assert_eq!(state.balances[1], U256::from(5));
+    }
+
+    #[test]
+    fn test_delta_transition_buffer_liquidity() {
+        let mut state = boosted_state_with_liquidity(liquidity(1, 2, 3, 4), liquidity(5, 6, 7, 8));
+        let delta = ProtocolStateDelta {
+            component_id: "boosted".to_string(),
+            updated_attributes: HashMap::from([(
+                "buffer_underlying_balances".to_string(),
+                Bytes::from(r#"["0x0a","0x0b"]"#.as_bytes()),
+            )]),
+            deleted_attributes: Default::default(),
+        };
+
+        state
+            .delta_transition(delta, &HashMap::new(), &Balances::default())
+            .unwrap();
+
+        let buffer_liquidity = |index: usize| {
+            state.buffers[index]
+                .as_ref()
+                .unwrap()
+                .liquidity
+                .clone()
+                .unwrap()
+        };
+        assert_eq!(buffer_liquidity(0), liquidity(10, 2, 3, 4));
+        assert_eq!(buffer_liquidity(1), liquidity(11, 6, 7, 8));
+    }
+}
@@ -0,0 +1,572 @@
+use std::collections::HashMap;
+
+use alloy::primitives::U256;
+use tycho_client::feed::{synchronizer::ComponentWithState, BlockHeader};
+use tycho_common::{models::token::Token, Bytes};
+
+use super::state::{
+    BalancerV3PoolType, BalancerV3State, BufferLiquidity, Erc4626Buffer,
+    BUFFER_LIQUIDITY_ATTRIBUTES,
+};
+use crate::{
+    evm::protocol::{
+        balancer_v2::{fixed_point::ONE, is_supported_pool_type},
+        utils::{attribute, decode_u256_list, required_attribute},
+        vm::utils::json_deserialize_address_list,
+    },
+    protocol::{errors::InvalidSnapshotError, models::TryFromWithBlock},
+};
+
+fn is_zero_address(address: &Bytes) -> bool {
+    address.iter().all(|byte| *byte == 0)
+}
+
+impl TryFromWithBlock<ComponentWithState, BlockHeader> for BalancerV3State {
+    type Error = InvalidSnapshotError;
+
+    /// Decodes a `ComponentWithState` of the `vm:balancer_v3` protocol system into a
+    /// `BalancerV3State`.
+    ///
+    /// Uses the following attributes, state attributes taking precedence over static ones:
+    /// - `pool_type`: the factory name, weighted and stable pools are supported
+    /// - `fee`: the swap fee percentage, scaled by 1e18
+    /// - `normalized_weights`: JSON list of the token weights, for weighted pools
+    /// - `amp`: the amplification parameter scaled by 1e3, for stable pools
+    /// - `token_rates`: optional JSON list of the token rates scaled by 1e18, required if the pool
+    ///   has non-zero `rate_providers` or is flagged `erc4626`
+    /// - `underlying_tokens`: optional JSON list of the underlying token of each ERC4626 pool
+    ///   token, the zero address for other tokens
+    /// - `buffer_underlying_balances`, `buffer_wrapped_balances`, `erc4626_max_deposits` and
+    ///   `erc4626_max_withdrawals`: optional JSON lists of the liquidity of the buffer of each
+    ///   ERC4626 pool token, all or none of them. Swaps through buffers are not bounded without
+    ///   them.
+    async fn try_from_with_header(
+        snapshot: ComponentWithState,
+        _block: BlockHeader,
+        _account_balances: &HashMap<Bytes, HashMap<Bytes, Bytes>>,
+        all_tokens: &HashMap<Bytes, Token>,
+    ) -> Result<Self, Self::Error> {
+        let id = snapshot.component.id.clone();
+        let pool_type = String::from_utf8(required_attribute(&snapshot, "pool_type")?.to_vec())
+            .map_err(|_| InvalidSnapshotError::ValueError("Invalid pool_type".to_string()))?;
+        if !is_supported_pool_type(&pool_type) {
+            return Err(InvalidSnapshotError::ValueError(format!(
+                "Unsupported Balancer V3 pool type {pool_type}"
+            )));
+        }
+
+        let tokens = snapshot.component.tokens.clone();
+        let decimals_of = |token: &Bytes| {
+            all_tokens
+                .get(token)
+                .map(|token| token.decimals)
+                .ok_or_else(|| InvalidSnapshotError::ValueError(format!("Token {token} not found")))
+        };
+        let mut balances = Vec::with_capacity(tokens.len());
+        let mut decimals = Vec::with_capacity(tokens.len());
+        for token in &tokens {
+            balances.push(
+                snapshot
+                    .state
+                    .balances
+                    .get(token)
+                    .map(|balance| U256::from_be_slice(balance))
+                    .unwrap_or_default(),
+            );
+            let token_decimals = decimals_of(token)?;
+            if token_decimals > 18 {
+                return Err(InvalidSnapshotError::ValueError(format!(
+                    "Token {token} has more than 18 decimals"
+                )));
+            }
+            decimals.push(token_decimals);
+        }
+
+        let underlying_tokens = match attribute(&snapshot, "underlying_tokens") {
+            Some(underlying_tokens) => json_deserialize_address_list(underlying_tokens)
+                .map_err(|err| InvalidSnapshotError::ValueError(err.to_string()))?
+                .into_iter()
+                .map(|underlying| (!is_zero_address(&underlying)).then_some(underlying))
+                .collect(),
+            None => vec![None; tokens.len()],
+        };
+        if underlying_tokens.len() != tokens.len() {
+            return Err(InvalidSnapshotError::ValueError(format!(
+                "Expected {} underlying tokens, got {}",
+                tokens.len(),
+                underlying_tokens.len()
+            )));
+        }
+        let liquidity = decode_buffer_liquidity(&snapshot, tokens.len())?;
+        let buffers = underlying_tokens
+            .iter()
+            .enumerate()
+            .map(|(index, underlying)| {
+                let Some(underlying) = underlying else {
+                    return Ok(None);
+                };
+                let underlying_decimals = decimals_of(underlying)?;
+                if underlying_decimals > 18 {
+                    return Err(InvalidSnapshotError::ValueError(format!(
+                        "Token {underlying} has more than 18 decimals"
+                    )));
+                }
+                let buffer = Erc4626Buffer::new(underlying.clone(), underlying_decimals);
+                Ok(Some(match &liquidity {
+                    Some(liquidity) => buffer.with_liquidity(liquidity[index].clone()),
+                    None => buffer,
+                }))
+            })
+            .collect::<Result<Vec<_>, InvalidSnapshotError>>()?;
+
+        let rates = match attribute(&snapshot, "token_rates") {
+            Some(rates) => decode_u256_list(rates)
+                .map_err(|err| InvalidSnapshotError::ValueError(err.to_string()))?,
+            None => {
+                let has_rate_providers = match attribute(&snapshot, "rate_providers") {
+                    Some(providers) => json_deserialize_address_list(providers)
+                        .map_err(|err| InvalidSnapshotError::ValueError(err.to_string()))?
+                        .iter()
+                        .any(|provider| !is_zero_address(provider)),
+                    None => false,
+                };
+                if has_rate_providers ||
+                    underlying_tokens
+                        .iter()
+                        .any(Option::is_some)
+                {
+                    return Err(InvalidSnapshotError::MissingAttribute("token_rates".to_string()));
+                }
+                vec![ONE; tokens.len()]
+            }
+        };
+        if rates.len() != tokens.len() {
+            return Err(InvalidSnapshotError::ValueError(format!(
+                "Expected {} token rates, got {}",
+                tokens.len(),
+                rates.len()
+            )));
+        }
+
+        let swap_fee = U256::from_be_slice(required_attribute(&snapshot, "fee")?);
+
+        let pool_type = if pool_type.starts_with("WeightedPool") {
+            let normalized_weights =
+                decode_u256_list(required_attribute(&snapshot, "normalized_weights")?)
+                    .map_err(|err| InvalidSnapshotError::ValueError(err.to_string()))?;
+            if normalized_weights.len() != tokens.len() {
+                return Err(InvalidSnapshotError::ValueError(format!(
+                    "Expected {} weights, got {}",
+                    tokens.len(),
+                    normalized_weights.len()
+                )));
+            }
+            BalancerV3PoolType::Weighted { normalized_weights }
+        } else {
+            BalancerV3PoolType::Stable {
+                amp: U256::from_be_slice(required_attribute(&snapshot, "amp")?),
+            }
+        };
+
+        Ok(BalancerV3State::new(id, tokens, balances, &decimals, rates, swap_fee, pool_type)
+            .with_buffers(buffers))
+    }
+}
+
+/// Decodes the liquidity of the buffer of each token, if the snapshot has the liquidity
+/// attributes.
+fn decode_buffer_liquidity(
+    snapshot: &ComponentWithState,
+    num_tokens: usize,
+) -> Result<Option<Vec<BufferLiquidity>>, InvalidSnapshotError> {
+    if BUFFER_LIQUIDITY_ATTRIBUTES
+        .iter()
+        .all(|name| attribute(snapshot, name).is_none())
+    {
+        return Ok(None);
+    }
+    let mut liquidity = vec![
+        BufferLiquidity {
+            underlying_balance: U256::ZERO,
+            wrapped_balance: U256::ZERO,
+            max_deposit: U256::ZERO,
+            max_withdraw: U256::ZERO,
+        };
+        num_tokens
+    ];
+    for (index, name) in BUFFER_LIQUIDITY_ATTRIBUTES
+        .iter()
+        .enumerate()
+    {
+        let values = decode_u256_list(required_attribute(snapshot, name)?)
+            .map_err(|err| InvalidSnapshotError::ValueError(err.to_string()))?;
+        if values.len() != num_tokens {
+            return Err(InvalidSnapshotError::ValueError(format!(
+                "Expected {num_tokens} {name}, got {}",
+                values.len()
+            )));
+        }
+        for (token_liquidity, value) in liquidity.iter_mut().zip(values) {
+            *token_liquidity.attribute_mut(index) = value;
+        }
+    }
+    Ok(Some(liquidity))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, str::FromStr};
+
+    use num_bigint::BigUint;
+    use rstest::rstest;
+    use serde::Deserialize;
+    use tycho_common::{
+        dto::{ProtocolComponent, ResponseProtocolState},
+        models::Chain,
+        simulation::protocol_sim::ProtocolSim,
+    };
+
+    use super::*;
+
+    const POOL_ID: &str = "0x85b2b559bc2d21104c4defdd6efca8a20343361d";
+    const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+    const USDT: &str = "0xdac17f958d2ee523a2206206994597c13d831ec7";
+    const WA_USDC: &str = "0xd4fa2d31b7968e448877f69a96de69f5de8cd23e";
+    const WA_USDT: &str = "0x7bc3485026ac48b6cf9baf0a377477fff5703af8";
+
+    fn header() -> BlockHeader {
+        BlockHeader {
+            number: 1,
+            hash: Bytes::from(vec![0; 32]),
+            parent_hash: Bytes::from(vec![0; 32]),
+            revert: false,
+            timestamp: 1,
+        }
+    }
+
+    fn address(address: &str) -> Bytes {
+        Bytes::from_str(address).unwrap()
+    }
+
+    fn tokens() -> HashMap<Bytes, Token> {
+        [USDC, USDT, WA_USDC, WA_USDT]
+            .into_iter()
+            .map(|token| {
+                let token =
+                    Token::new(&address(token), "T", 6, 0, &[Some(10_000)], Chain::Ethereum, 100);
+                (token.address.clone(), token)
+            })
+            .collect()
+    }
+
+    fn u256_bytes(value: u128) -> Bytes {
+        Bytes::from(U256::from(value).to_be_bytes_vec())
+    }
+
+    /// Boosted stable pool of waUSDC and waUSDT
+    fn boosted_snapshot() -> ComponentWithState {
+        let static_attributes = HashMap::from([
+            ("pool_type".to_string(), Bytes::from("StablePoolFactory".as_bytes())),
+            ("erc4626".to_string(), Bytes::from(vec![1u8])),
+            (
+                "underlying_tokens".to_string(),
+                Bytes::from(format!(r#"["{USDC}","{USDT}"]"#).as_bytes()),
+            ),
+        ]);
+        ComponentWithState {
+            state: ResponseProtocolState {
+                component_id: POOL_ID.to_string(),
+                attributes: HashMap::from([
+                    ("fee".to_string(), u256_bytes(100_000_000_000_000)),
+                    ("amp".to_string(), u256_bytes(200_000)),
+                    (
+                        "token_rates".to_string(),
+                        Bytes::from(r#"["0x0f43fc2c04ee0000","0x0e92596fd6290000"]"#.as_bytes()),
+                    ),
+                ]),
+                balances: HashMap::from([
+                    (address(WA_USDC), u256_bytes(1_000_000_000_000)),
+                    (address(WA_USDT), u256_bytes(1_100_000_000_000)),
+                ]),
+            },
+            component: ProtocolComponent {
+                id: POOL_ID.to_string(),
+                protocol_system: "vm:balancer_v3".to_string(),
+                tokens: vec![address(WA_USDC), address(WA_USDT)],
+                static_attributes,
+                ..Default::default()
+            },
+            component_tvl: None,
+            entrypoints: Vec::new(),
+        }
+    }
+
+    async fn decode(snapshot: ComponentWithState) -> Result<BalancerV3State, InvalidSnapshotError> {
+        BalancerV3State::try_from_with_header(snapshot, header(), &HashMap::new(), &tokens()).await
+    }
+
+    #[tokio::test]
+    async fn test_balancer_v3_try_from_boosted() {
+        let result = decode(boosted_snapshot())
+            .await
+            .unwrap();
+
+        let expected = BalancerV3State::new(
+            POOL_ID.to_string(),
+            vec![address(WA_USDC), address(WA_USDT)],
+            vec![U256::from(1_000_000_000_000u64), U256::from(1_100_000_000_000u64)],
+            &[6, 6],
+            vec![
+                U256::from(1_100_000_000_000_000_000u64),
+                U256::from(1_050_000_000_000_000_000u64),
+            ],
+            U256::from(100_000_000_000_000u64),
+            BalancerV3PoolType::Stable { amp: U256::from(200_000) },
+        )
+        .with_buffers(vec![
+            Some(Erc4626Buffer::new(address(USDC), 6)),
+            Some(Erc4626Buffer::new(address(USDT), 6)),
+        ]);
+        assert_eq!(result, expected);
+    }
+
+    #[tokio::test]
+    async fn test_balancer_v3_try_from_buffer_liquidity() {
+        let mut snapshot = boosted_snapshot();
+        for (index, name) in BUFFER_LIQUIDITY_ATTRIBUTES
+            .iter()
+            .enumerate()
+        {
+            snapshot.state.attributes.insert(
+                name.to_string(),
+                Bytes::from(format!(r#"["{:#x}","{:#x}"]"#, index + 1, index + 5).as_bytes()),
+            );
+        }
+
+        let result = decode(snapshot.clone()).await.unwrap();
+
+        let expected = decode(boosted_snapshot())
+            .await
+            .unwrap()
+            .with_buffers(vec![
+                Some(Erc4626Buffer::new(address(USDC), 6).with_liquidity(BufferLiquidity {
+                    underlying_balance: U256::from(1),
+                    wrapped_balance: U256::from(2),
+                    max_deposit: U256::from(3),
+                    max_withdraw: U256::from(4),
+                })),
+                Some(Erc4626Buffer::new(address(USDT), 6).with_liquidity(BufferLiquidity {
+                    underlying_balance: U256::from(5),
+                    wrapped_balance: U256::from(6),
+                    max_deposit: U256::from(7),
+                    max_withdraw: U256::from(8),
+                })),
+            ]);
+        assert_eq!(result, expected);
+
+        // The liquidity attributes are required together
+        snapshot
+            .state
+            .attributes
+            .remove("erc4626_max_deposits");
+        assert!(matches!(
+            decode(snapshot).await.unwrap_err(),
+            InvalidSnapshotError::MissingAttribute(attribute) if attribute == "erc4626_max_deposits"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_balancer_v3_try_from_wrapper_with_other_decimals() {
+        let mut all_tokens = tokens();
+        all_tokens
+            .get_mut(&address(WA_USDC))
+            .unwrap()
+            .decimals = 18;
+
+        let mut snapshot = boosted_snapshot();
+        snapshot
+            .state
+            .balances
+            .insert(address(WA_USDC), u256_bytes(1_000_000_000_000_000_000_000_000));
+
+        let result =
+            BalancerV3State::try_from_with_header(snapshot, header(), &HashMap::new(), &all_tokens)
+                .await
+                .unwrap();
+
+        // The buffer keeps the decimals of USDC
+        let wa_usdc = all_tokens[&address(WA_USDC)].clone();
+        let usdc = all_tokens[&address(USDC)].clone();
+        let shares = result
+            .get_amount_out(1_000_000u64.into(), &usdc, &all_tokens[&address(WA_USDT)])
+            .unwrap();
+        let wrapped = result
+            .get_amount_out(
+                BigUint::from(909_090_909_090_909_090u64),
+                &wa_usdc,
+                &all_tokens[&address(WA_USDT)],
+            )
+            .unwrap();
+        assert_eq!(shares.amount, wrapped.amount);
+    }
+
+    #[tokio::test]
+    async fn test_balancer_v3_try_from_partially_boosted() {
+        let mut snapshot = boosted_snapshot();
+        snapshot
+            .component
+            .static_attributes
+            .insert(
+                "underlying_tokens".to_string(),
+                Bytes::from(
+                    format!(r#"["{USDC}","0x0000000000000000000000000000000000000000"]"#)
+                        .as_bytes(),
+                ),
+            );
+
+        let result = decode(snapshot).await.unwrap();
+
+        let usdt = tokens()[&address(USDT)].clone();
+        let wa_usdt = tokens()[&address(WA_USDT)].clone();
+        let usdc = tokens()[&address(USDC)].clone();
+        // USDT isn't the underlying token of waUSDT anymore, so only USDC can be swapped through
+        // a buffer
+        assert!(result
+            .get_amount_out(1_000_000u64.into(), &usdc, &wa_usdt)
+            .is_ok());
+        assert!(result
+            .get_amount_out(1_000_000u64.into(), &usdc, &usdt)
+            .is_err());
+    }
+
+    #[tokio::test]
+    #[rstest]
+    #[case::missing_pool_type("pool_type")]
+    #[case::missing_fee("fee")]
+    #[case::missing_amp("amp")]
+    #[case::missing_token_rates("token_rates")]
+    async fn test_balancer_v3_try_from_missing_attribute(#[case] missing_attribute: &str) {
+        let mut snapshot = boosted_snapshot();
+        snapshot
+            .state
+            .attributes
+            .remove(missing_attribute);
+        snapshot
+            .component
+            .static_attributes
+            .remove(missing_attribute);
+
+        let result = decode(snapshot).await;
+
+        assert!(matches!(
+            result.unwrap_err(),
+            InvalidSnapshotError::MissingAttribute(attribute) if attribute == missing_attribute
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_balancer_v3_try_from_unsupported_pool_type() {
+        let mut snapshot = boosted_snapshot();
+        snapshot
+            .component
+            .static_attributes
+            .insert("pool_type".to_string(), Bytes::from("Gyro2CLPPoolFactory".as_bytes()));
+
+        let result = decode(snapshot).await;
+
+        assert!(matches!(result.unwrap_err(), InvalidSnapshotError::ValueError(_)));
+    }
+
+    #[derive(Deserialize)]
+    struct FixtureToken {
+        address: Bytes,
+        decimals: u32,
+    }
+
+    #[derive(Deserialize)]
+    struct FixtureQuote {
+        token_in: Bytes,
+        token_out: Bytes,
+        amount_in: String,
+        /// The amount out returned by the VM adapter
+        amount_out: String,
+    }
+
+    /// A snapshot of a Balancer V3 pool with quotes recorded with `BalancerV3SwapAdapter` at the
+    /// same block.
+    #[derive(Deserialize)]
+    struct Fixture {
+        snapshot: ComponentWithState,
+        tokens: Vec<FixtureToken>,
+        quotes: Vec<FixtureQuote>,
+    }
+
+    /// Compares the native states to the VM adapter on the fixtures in `tests/assets/balancer_v3`,
+    /// which have to cover weighted pools and boosted pools, including ERC4626 tokens whose
+    /// decimals differ from their underlying token's.
+    #[tokio::test]
+    #[ignore] // Requires fixtures recorded with the `record_adapter_fixtures` example
+    async fn test_balancer_v3_matches_vm_adapter() {
+        let fixtures_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/assets/balancer_v3");
+        let mut checked = 0;
+        let mut boosted = false;
+        for entry in fs::read_dir(fixtures_dir).expect("Failed to read the fixtures directory") {
+            let path = entry.unwrap().path();
+            if path
+                .extension()
+                .is_none_or(|extension| extension != "json")
+            {
+                continue;
+            }
+            let fixture: Fixture =
+                serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+            let all_tokens: HashMap<Bytes, Token> = fixture
+                .tokens
+                .iter()
+                .map(|fixture_token| {
+                    let token = Token::new(
+                        &fixture_token.address,
+                        "T",
+                        fixture_token.decimals,
+                        0,
+                        &[Some(10_000)],
+                        Chain::Ethereum,
+                        100,
+                    );
+                    (fixture_token.address.clone(), token)
+                })
+                .collect();
+            boosted |= attribute(&fixture.snapshot, "underlying_tokens").is_some();
+
+            let state = BalancerV3State::try_from_with_header(
+                fixture.snapshot,
+                header(),
+                &HashMap::new(),
+                &all_tokens,
+            )
+            .await
+            .unwrap_or_else(|err| panic!("Failed to decode {path:?}: {err:?}"));
+
+            for quote in fixture.quotes {
+                let result = state
+                    .get_amount_out(
+                        BigUint::from_str(&quote.amount_in).unwrap(),
+                        &all_tokens[&quote.token_in],
+                        &all_tokens[&quote.token_out],
+                    )
+                    .unwrap();
+                assert_eq!(
+                    result.amount,
+                    BigUint::from_str(&quote.amount_out).unwrap(),
+                    "{path:?}: {} {} -> {}",
+                    quote.amount_in,
+                    quote.token_in,
+                    quote.token_out
+                );
+                checked += 1;
+            }
+        }
+        assert!(checked > 0, "No fixtures found");
+        assert!(boosted, "The fixtures don't cover boosted pools");
+    }
+}
@@ -1,2 +1,4 @@
 pub(crate) mod protocol;
 mod reserve_price;
+pub mod state;
+mod tycho_decoder;
@@ -12,7 +12,7 @@ use tycho_common::{
 use super::reserve_price::spot_price_from_reserves;
 use crate::{
     evm::protocol::{
-        safe_math::{safe_add_u256, safe_div_u256, safe_mul_u256},
+        safe_math::{safe_add_u256, safe_div_u256, safe_mul_u256, safe_sub_u256},
         u256_num::u256_to_biguint,
     },
     protocol::errors::InvalidSnapshotError,
@@ -62,6 +62,18 @@ pub fn cpmm_get_amount_out(
     reserve0: U256,
     reserve1: U256,
     fee_bps: u32,
+) -> Result<U256, SimulationError> {
+    cpmm_get_amount_out_with_fee(amount_in, zero2one, reserve0, reserve1, fee_bps, 10000)
+}
+
+/// Same as `cpmm_get_amount_out` with a fee expressed as `fee / fee_denominator` of the amount in.
+pub fn cpmm_get_amount_out_with_fee(
+    amount_in: U256,
+    zero2one: bool,
+    reserve0: U256,
+    reserve1: U256,
+    fee: u32,
+    fee_denominator: u32,
 ) -> Result<U256, SimulationError> {
     if amount_in == U256::from(0u64) {
         return Err(SimulationError::InvalidInput("Amount in cannot be zero".to_string(), None));
@@ -73,11 +85,13 @@ pub fn cpmm_get_amount_out(
         return Err(SimulationError::RecoverableError("No liquidity".to_string()));
     }
 
-    let fee_multiplier = U256::from(10000 - fee_bps);
+    let fee_multiplier = safe_sub_u256(U256::from(fee_denominator), U256::from(fee))?;
     let amount_in_with_fee = safe_mul_u256(amount_in, fee_multiplier)?;
     let numerator = safe_mul_u256(amount_in_with_fee, reserve_buy)?;
-    let denominator =
-        safe_add_u256(safe_mul_u256(reserve_sell, U256::from(10000))?, amount_in_with_fee)?;
+    let denominator = safe_add_u256(
+        safe_mul_u256(reserve_sell, U256::from(fee_denominator))?,
+        amount_in_with_fee,
+    )?;
 
     safe_div_u256(numerator, denominator)
 }
@@ -0,0 +1,388 @@
+use std::{any::Any, collections::HashMap};
+
+use alloy::primitives::U256;
+use num_bigint::{BigUint, ToBigUint};
+use tycho_common::{
+    dto::ProtocolStateDelta,
+    models::token::Token,
+    simulation::{
+        errors::{SimulationError, TransitionError},
+        protocol_sim::{Balances, GetAmountOutResult, ProtocolSim},
+    },
+    Bytes,
+};
+
+use super::protocol::{
+    cpmm_delta_transition, cpmm_get_amount_out_with_fee, cpmm_get_limits, cpmm_spot_price,
+};
+use crate::{
+    evm::protocol::{
+        safe_math::{safe_add_u256, safe_sub_u256},
+        u256_num::{biguint_to_u256, u256_to_biguint},
+    },
+    protocol::{fee::SwapFee, transfer_tax::amount_after_tax},
+};
+
+/// State of a Uniswap V2 style constant product pool with a configurable fee.
+///
+/// Covers forks that only differ from Uniswap V2 in their fee, e.g. Sushiswap V2 or Pancakeswap
+/// V2, as well as pools charging a different fee per swap direction like Camelot V2 volatile
+/// pairs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CpmmState {
+    pub reserve0: U256,
+    pub reserve1: U256,
+    /// Fee numerator charged when selling token 0.
+    pub fee0: u32,
+    /// Fee numerator charged when selling token 1.
+    pub fee1: u32,
+    pub fee_denominator: u32,
+}
+
+impl CpmmState {
+    /// Creates a new instance of `CpmmState` charging the same fee in both directions.
+    ///
+    /// # Arguments
+    ///
+    /// * `reserve0` - Reserve of token 0.
+    /// * `reserve1` - Reserve of token 1.
+    /// * `fee` - Fee numerator, e.g. 30 for a 0.3% fee with a denominator of 10000.
+    /// * `fee_denominator` - Denominator of the fee.
+    pub fn new(reserve0: U256, reserve1: U256, fee: u32, fee_denominator: u32) -> Self {
+        Self::with_directional_fees(reserve0, reserve1, fee, fee, fee_denominator)
+    }
+
+    /// Creates a new instance of `CpmmState` charging `fee0` when selling token 0 and `fee1` when
+    /// selling token 1.
+    pub fn with_directional_fees(
+        reserve0: U256,
+        reserve1: U256,
+        fee0: u32,
+        fee1: u32,
+        fee_denominator: u32,
+    ) -> Self {
+        CpmmState { reserve0, reserve1, fee0, fee1, fee_denominator }
+    }
+
+    fn direction_fee(&self, zero2one: bool) -> u32 {
+        if zero2one {
+            self.fee0
+        } else {
+            self.fee1
+        }
+    }
+
+    fn fee_fraction(&self, fee: u32) -> f64 {
+        fee as f64 / self.fee_denominator as f64
+    }
+}
+
+impl ProtocolSim for CpmmState {
+    /// Returns the highest of the two directional fees.
+    fn fee(&self) -> f64 {
+        self.fee_fraction(self.fee0.max(self.fee1))
+    }
+
+    fn spot_price(&self, base: &Token, quote: &Token) -> Result<f64, SimulationError> {
+        cpmm_spot_price(base, quote, self.reserve0, self.reserve1)
+    }
+
+    fn get_amount_out(
+        &self,
+        amount_in: BigUint,
+        token_in: &Token,
+        token_out: &Token,
+    ) -> Result<GetAmountOutResult, SimulationError> {
+        let amount_in = biguint_to_u256(&amount_after_tax(&amount_in, token_in));
+        let zero2one = token_in.address < token_out.address;
+        let amount_out = cpmm_get_amount_out_with_fee(
+            amount_in,
+            zero2one,
+            self.reserve0,
+            self.reserve1,
+            self.direction_fee(zero2one),
+            self.fee_denominator,
+        )?;
+        let mut new_state = self.clone();
+        if zero2one {
+            new_state.reserve0 = safe_add_u256(self.reserve0, amount_in)?;
+            new_state.reserve1 = safe_sub_u256(self.reserve1, amount_out)?;
+        } else {
+            new_state.reserve0 = safe_sub_u256(self.reserve0, amount_out)?;
+            new_state.reserve1 = safe_add_u256(self.reserve1, amount_in)?;
+        };
+        Ok(GetAmountOutResult::new(
+            amount_after_tax(&u256_to_biguint(amount_out), token_out),
+            120_000
+                .to_biguint()
+                .expect("Expected an unsigned integer as gas value"),
+            Box::new(new_state),
+        ))
+    }
+
+    fn get_limits(
+        &self,
+        sell_token: Bytes,
+        buy_token: Bytes,
+    ) -> Result<(BigUint, BigUint), SimulationError> {
+        cpmm_get_limits(sell_token, buy_token, self.reserve0, self.reserve1)
+    }
+
+    /// Updates the fees if any of the `fee`, `token0_fee` or `token1_fee` attributes changed.
+    /// Reserves are required in every delta which updates one of them.
+    fn delta_transition(
+        &mut self,
+        delta: ProtocolStateDelta,
+        _tokens: &HashMap<Bytes, Token>,
+        _balances: &Balances,
+    ) -> Result<(), TransitionError<String>> {
+        let decode_fee = |name: &str| -> Result<Option<u32>, TransitionError<String>> {
+            delta
+                .updated_attributes
+                .get(name)
+                .map(|value| {
+                    u32::try_from(U256::from_be_slice(value))
+                        .map_err(|_| TransitionError::DecodeError(format!("Invalid {name}")))
+                })
+                .transpose()
+        };
+        if let Some(fee) = decode_fee("fee")? {
+            self.fee0 = fee;
+            self.fee1 = fee;
+        }
+        if let Some(fee0) = decode_fee("token0_fee")? {
+            self.fee0 = fee0;
+        }
+        if let Some(fee1) = decode_fee("token1_fee")? {
+            self.fee1 = fee1;
+        }
+
+        let updated = &delta.updated_attributes;
+        if updated.contains_key("reserve0") || updated.contains_key("reserve1") {
+            cpmm_delta_transition(delta, &mut self.reserve0, &mut self.reserve1)?;
+        }
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn ProtocolSim> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn eq(&self, other: &dyn ProtocolSim) -> bool {
+        if let Some(other_state) = other.as_any().downcast_ref::<Self>() {
+            self == other_state
+        } else {
+            false
+        }
+    }
+}
+
+impl SwapFee for CpmmState {
+    fn swap_fee(
+        &self,
+        token_in: &Token,
+        token_out: &Token,
+        _amount_in: Option<&BigUint>,
+    ) -> Result<f64, SimulationError> {
+        let zero2one = token_in.address < token_out.address;
+        Ok(self.fee_fraction(self.direction_fee(zero2one)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        str::FromStr,
+    };
+
+    use approx::assert_ulps_eq;
+    use num_bigint::BigUint;
+    use tycho_common::{
+        dto::ProtocolStateDelta,
+        hex_bytes::Bytes,
+        models::{token::Token, Chain},
+        simulation::{
+            errors::TransitionError,
+            protocol_sim::{Balances, ProtocolSim},
+        },
+    };
+
+    use super::*;
+    use crate::evm::protocol::uniswap_v2::state::UniswapV2State;
+
+    fn token(address: &str, symbol: &str, decimals: u32) -> Token {
+        Token::new(
+            &Bytes::from_str(address).unwrap(),
+            symbol,
+            decimals,
+            0,
+            &[Some(10_000)],
+            Chain::Ethereum,
+            100,
+        )
+    }
+
+    fn tokens() -> (Token, Token) {
+        (
+            token("0x0000000000000000000000000000000000000000", "T0", 18),
+            token("0x0000000000000000000000000000000000000001", "T1", 18),
+        )
+    }
+
+    #[test]
+    fn test_get_amount_out_matches_uniswap_v2() {
+        let (t0, t1) = tokens();
+        let reserve0 = U256::from_str("114293490733").unwrap();
+        let reserve1 = U256::from_str("69592908201923870949").unwrap();
+        let state = CpmmState::new(reserve0, reserve1, 30, 10_000);
+        let uniswap_v2 = UniswapV2State::new(reserve0, reserve1);
+        let amount_in = BigUint::from_str("13088600769481610").unwrap();
+
+        let res = state
+            .get_amount_out(amount_in.clone(), &t1, &t0)
+            .unwrap();
+        let exp = uniswap_v2
+            .get_amount_out(amount_in, &t1, &t0)
+            .unwrap();
+
+        assert_eq!(res.amount, exp.amount);
+        let new_state = res
+            .new_state
+            .as_any()
+            .downcast_ref::<CpmmState>()
+            .unwrap();
+        assert_eq!(new_state.reserve0, reserve0 - biguint_to_u256(&exp.amount));
+        assert_eq!(new_state.fee0, 30);
+        // Assert that the old state is unchanged
+        assert_eq!(state.reserve0, reserve0);
+        assert_eq!(state.reserve1, reserve1);
+    }
+
+    #[test]
+    fn test_get_amount_out_directional_fees() {
+        let (t0, t1) = tokens();
+        // Camelot style pool charging 0.3% when selling token 0 and 0.1% when selling token 1
+        let state = CpmmState::with_directional_fees(
+            U256::from(1_000_000u64),
+            U256::from(1_000_000u64),
+            300,
+            100,
+            100_000,
+        );
+        let amount_in = BigUint::from(10_000u64);
+
+        let zero2one = state
+            .get_amount_out(amount_in.clone(), &t0, &t1)
+            .unwrap();
+        let one2zero = state
+            .get_amount_out(amount_in, &t1, &t0)
+            .unwrap();
+
+        // 9970 * 1e6 / (1e6 + 9970) and 9990 * 1e6 / (1e6 + 9990)
+        assert_eq!(zero2one.amount, BigUint::from(9871u64));
+        assert_eq!(one2zero.amount, BigUint::from(9891u64));
+    }
+
+    #[test]
+    fn test_get_amount_out_invalid_fee() {
+        let (t0, t1) = tokens();
+        let state = CpmmState::new(U256::from(1000u64), U256::from(1000u64), 10_001, 10_000);
+
+        let res = state.get_amount_out(BigUint::from(10u64), &t0, &t1);
+
+        assert!(matches!(res, Err(SimulationError::FatalError(_))));
+    }
+
+    #[test]
+    fn test_fee() {
+        let (t0, t1) = tokens();
+        let state = CpmmState::with_directional_fees(
+            U256::from(1000u64),
+            U256::from(1000u64),
+            300,
+            100,
+            100_000,
+        );
+
+        assert_ulps_eq!(state.fee(), 0.003);
+        assert_ulps_eq!(state.swap_fee(&t0, &t1, None).unwrap(), 0.003);
+        assert_ulps_eq!(state.swap_fee(&t1, &t0, None).unwrap(), 0.001);
+    }
+
+    #[test]
+    fn test_delta_transition() {
+        let mut state = CpmmState::new(U256::from(1000u64), U256::from(1000u64), 30, 10_000);
+        let attributes: HashMap<String, Bytes> = vec![
+            ("reserve0".to_string(), Bytes::from(1500_u64.to_be_bytes().to_vec())),
+            ("reserve1".to_string(), Bytes::from(2000_u64.to_be_bytes().to_vec())),
+            ("token1_fee".to_string(), Bytes::from(10_u32.to_be_bytes().to_vec())),
+        ]
+        .into_iter()
+        .collect();
+        let delta = ProtocolStateDelta {
+            component_id: "State1".to_owned(),
+            updated_attributes: attributes,
+            deleted_attributes: HashSet::new(),
+        };
+
+        state
+            .delta_transition(delta, &HashMap::new(), &Balances::default())
+            .unwrap();
+
+        assert_eq!(
+            state,
+            CpmmState::with_directional_fees(
+                U256::from(1500u64),
+                U256::from(2000u64),
+                30,
+                10,
+                10_000
+            )
+        );
+    }
+
+    #[test]
+    fn test_delta_transition_fee_only() {
+        let mut state = CpmmState::new(U256::from(1000u64), U256::from(1000u64), 30, 10_000);
+        let delta = ProtocolStateDelta {
+            component_id: "State1".to_owned(),
+            updated_attributes: HashMap::from([(
+                "fee".to_string(),
+                Bytes::from(25_u32.to_be_bytes().to_vec()),
+            )]),
+            deleted_attributes: HashSet::new(),
+        };
+
+        state
+            .delta_transition(delta, &HashMap::new(), &Balances::default())
+            .unwrap();
+
+        assert_eq!(state, CpmmState::new(U256::from(1000u64), U256::from(1000u64), 25, 10_000));
+    }
+
+    #[test]
+    fn test_delta_transition_missing_attribute() {
+        let mut state = CpmmState::new(U256::from(1000u64), U256::from(1000u64), 30, 10_000);
+        let delta = ProtocolStateDelta {
+            component_id: "State1".to_owned(),
+            updated_attributes: HashMap::from([(
+                "reserve0".to_string(),
+                Bytes::from(1500_u64.to_be_bytes().to_vec()),
+            )]),
+            deleted_attributes: HashSet::new(),
+        };
+
+        let res = state.delta_transition(delta, &HashMap::new(), &Balances::default());
+
+        assert!(matches!(res, Err(TransitionError::MissingAttribute(ref x)) if x == "reserve1"));
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+
+use alloy::primitives::U256;
+use tycho_client::feed::{synchronizer::ComponentWithState, BlockHeader};
+use tycho_common::{models::token::Token, Bytes};
+
+use super::{protocol::cpmm_try_from_with_header, state::CpmmState};
+use crate::{
+    evm::protocol::utils::attribute,
+    protocol::{errors::InvalidSnapshotError, models::TryFromWithBlock},
+};
+
+const DEFAULT_FEE_DENOMINATOR: u32 = 10_000;
+
+/// Fees, in basis points, of known forks which don't index a `fee` attribute.
+const FORK_FEES: [(&str, u32); 3] =
+    [("uniswap_v2", 30), ("sushiswap_v2", 30), ("pancakeswap_v2", 25)];
+
+fn fork_fee(protocol_system: &str) -> Option<u32> {
+    FORK_FEES
+        .iter()
+        .find(|(system, _)| *system == protocol_system)
+        .map(|(_, fee)| *fee)
+}
+
+fn u32_attribute(
+    snapshot: &ComponentWithState,
+    name: &str,
+) -> Result<Option<u32>, InvalidSnapshotError> {
+    attribute(snapshot, name)
+        .map(|value| {
+            u32::try_from(U256::from_be_slice(value))
+                .map_err(|_| InvalidSnapshotError::ValueError(format!("Invalid {name}")))
+        })
+        .transpose()
+}
+
+impl TryFromWithBlock<ComponentWithState, BlockHeader> for CpmmState {
+    type Error = InvalidSnapshotError;
+
+    /// Decodes a `ComponentWithState` of any Uniswap V2 style protocol system into a `CpmmState`.
+    ///
+    /// Uses the `reserve0` and `reserve1` state attributes and the following fee attributes, state
+    /// attributes taking precedence over static ones:
+    /// - `fee`: the fee numerator charged in both directions
+    /// - `token0_fee`, `token1_fee`: directional fee numerators, overriding `fee`
+    /// - `fee_denominator`: defaults to 10000, i.e. fees in basis points
+    ///
+    /// Components of `uniswap_v2`, `sushiswap_v2` and `pancakeswap_v2` without fee attributes use
+    /// the fork's fee.
+    async fn try_from_with_header(
+        snapshot: ComponentWithState,
+        _block: BlockHeader,
+        _account_balances: &HashMap<Bytes, HashMap<Bytes, Bytes>>,
+        _all_tokens: &HashMap<Bytes, Token>,
+    ) -> Result<Self, Self::Error> {
+        let fee_denominator =
+            u32_attribute(&snapshot, "fee_denominator")?.unwrap_or(DEFAULT_FEE_DENOMINATOR);
+        let fee = match u32_attribute(&snapshot, "fee")? {
+            Some(fee) => Some(fee),
+            None => fork_fee(&snapshot.component.protocol_system),
+        };
+        let fee0 = u32_attribute(&snapshot, "token0_fee")?
+            .or(fee)
+            .ok_or_else(|| InvalidSnapshotError::MissingAttribute("fee".to_string()))?;
+        let fee1 = u32_attribute(&snapshot, "token1_fee")?
+            .or(fee)
+            .ok_or_else(|| InvalidSnapshotError::MissingAttribute("fee".to_string()))?;
+        if fee_denominator == 0 || fee0 >= fee_denominator || fee1 >= fee_denominator {
+            return Err(InvalidSnapshotError::ValueError(format!(
+                "Fees {fee0} and {fee1} must be below the fee denominator {fee_denominator}"
+            )));
+        }
+
+        let (reserve0, reserve1) = cpmm_try_from_with_header(snapshot)?;
+        Ok(Self::with_directional_fees(reserve0, reserve1, fee0, fee1, fee_denominator))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use alloy::primitives::U256;
+    use rstest::rstest;
+    use tycho_client::feed::{synchronizer::ComponentWithState, BlockHeader};
+    use tycho_common::{
+        dto::{ProtocolComponent, ResponseProtocolState},
+        Bytes,
+    };
+
+    use super::super::state::CpmmState;
+    use crate::protocol::{errors::InvalidSnapshotError, models::TryFromWithBlock};
+
+    fn header() -> BlockHeader {
+        BlockHeader {
+            number: 1,
+            hash: Bytes::from(vec![0; 32]),
+            parent_hash: Bytes::from(vec![0; 32]),
+            revert: false,
+            timestamp: 1,
+        }
+    }
+
+    fn snapshot(protocol_system: &str, static_attributes: &[(&str, u32)]) -> ComponentWithState {
+        ComponentWithState {
+            state: ResponseProtocolState {
+                component_id: "State1".to_owned(),
+                attributes: HashMap::from([
+                    ("reserve0".to_string(), Bytes::from(1000_u64.to_be_bytes().to_vec())),
+                    ("reserve1".to_string(), Bytes::from(2000_u64.to_be_bytes().to_vec())),
+                ]),
+                balances: HashMap::new(),
+            },
+            component: ProtocolComponent {
+                protocol_system: protocol_system.to_string(),
+                static_attributes: static_attributes
+                    .iter()
+                    .map(|(name, value)| {
+                        (name.to_string(), Bytes::from(value.to_be_bytes().to_vec()))
+                    })
+                    .collect(),
+                ..Default::default()
+            },
+            component_tvl: None,
+            entrypoints: Vec::new(),
+        }
+    }
+
+    async fn decode(snapshot: ComponentWithState) -> Result<CpmmState, InvalidSnapshotError> {
+        CpmmState::try_from_with_header(snapshot, header(), &HashMap::new(), &HashMap::new()).await
+    }
+
+    #[tokio::test]
+    #[rstest]
+    #[case::sushiswap_default("sushiswap_v2", &[], 30, 30, 10_000)]
+    #[case::pancakeswap_default("pancakeswap_v2", &[], 25, 25, 10_000)]
+    #[case::fee_attribute("uniswap_v2", &[("fee", 20)], 20, 20, 10_000)]
+    #[case::fee_denominator("some_v2_fork", &[("fee", 2), ("fee_denominator", 1000)], 2, 2, 1000)]
+    #[case::directional(
+        "camelot_v2",
+        &[("token0_fee", 300), ("token1_fee", 100), ("fee_denominator", 100_000)],
+        300,
+        100,
+        100_000
+    )]
+    async fn test_cpmm_try_from(
+        #[case] protocol_system: &str,
+        #[case] static_attributes: &[(&str, u32)],
+        #[case] fee0: u32,
+        #[case] fee1: u32,
+        #[case] fee_denominator: u32,
+    ) {
+        let result = decode(snapshot(protocol_system, static_attributes))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result,
+            CpmmState::with_directional_fees(
+                U256::from(1000u64),
+                U256::from(2000u64),
+                fee0,
+                fee1,
+                fee_denominator
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cpmm_try_from_missing_fee() {
+        let result = decode(snapshot("some_v2_fork", &[])).await;
+
+        assert!(matches!(
+            result.unwrap_err(),
+            InvalidSnapshotError::MissingAttribute(ref x) if x == "fee"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cpmm_try_from_fee_above_denominator() {
+        let result =
+            decode(snapshot("some_v2_fork", &[("fee", 1000), ("fee_denominator", 1000)])).await;
+
+        assert!(matches!(result.unwrap_err(), InvalidSnapshotError::ValueError(_)));
+    }
+
+    #[tokio::test]
+    async fn test_cpmm_try_from_missing_reserve() {
+        let mut snapshot = snapshot("uniswap_v2", &[]);
+        snapshot
+            .state
+            .attributes
+            .remove("reserve1");
+
+        let result = decode(snapshot).await;
+
+        assert!(matches!(
+            result.unwrap_err(),
+            InvalidSnapshotError::MissingAttribute(ref x) if x == "reserve1"
+        ));
+    }
+}
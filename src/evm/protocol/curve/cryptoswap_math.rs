@@ -0,0 +1,347 @@
+//! Swap math of Curve CryptoSwap pools with two (twocrypto) or three (tricrypto) coins.
+//!
+//! Balances are converted to `xp`, i.e. scaled to 18 decimals and multiplied by the price scale,
+//! before being passed to these functions. `ann` is `A * N^N * A_MULTIPLIER` and `gamma` is
+//! scaled by 1e18. Mirrors the Newton's method implementations of the two-coin swap and the
+//! three-coin math contracts, see https://github.com/curvefi/curve-crypto-contract/tree/master/contracts
+use alloy::primitives::U256;
+use tycho_common::simulation::errors::SimulationError;
+
+use crate::evm::protocol::safe_math::{safe_add_u256, safe_div_u256, safe_mul_u256, safe_sub_u256};
+
+const A_MULTIPLIER: U256 = U256::from_limbs([10_000, 0, 0, 0]);
+const MAX_ITERATIONS: usize = 255;
+
+fn e18() -> U256 {
+    U256::from(1_000_000_000_000_000_000u64)
+}
+
+fn abs_diff(a: U256, b: U256) -> U256 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+fn check_coins(x: &[U256]) -> Result<(), SimulationError> {
+    if !(2..=3).contains(&x.len()) {
+        return Err(SimulationError::FatalError(format!(
+            "CryptoSwap pools with {} coins are not supported",
+            x.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that each `x / d` is within the bounds enforced by the contracts, (1e16 - 1, 1e20 + 1).
+fn check_fraction(x: U256, d: U256) -> Result<(), SimulationError> {
+    let fraction = safe_div_u256(safe_mul_u256(x, e18())?, d)?;
+    if fraction < U256::from(10_000_000_000_000_000u64) ||
+        fraction > U256::from(100_000_000_000_000_000_000u128)
+    {
+        return Err(SimulationError::InvalidInput(
+            "CryptoSwap balances are out of the safe range".to_string(),
+            None,
+        ));
+    }
+    Ok(())
+}
+
+fn sorted_descending(x: &[U256]) -> Vec<U256> {
+    let mut x = x.to_vec();
+    x.sort_unstable_by(|a, b| b.cmp(a));
+    x
+}
+
+/// Returns the geometric mean of `x`, which must be sorted in descending order.
+fn geometric_mean(x: &[U256]) -> Result<U256, SimulationError> {
+    let n_coins = U256::from(x.len());
+    let mut d = x[0];
+    for _ in 0..MAX_ITERATIONS {
+        let d_prev = d;
+        d = if x.len() == 2 {
+            // (D + x0 * x1 / D) / N
+            safe_add_u256(d, safe_div_u256(safe_mul_u256(x[0], x[1])?, d)?)? / n_coins
+        } else {
+            let mut tmp = e18();
+            for x_i in x {
+                tmp = safe_div_u256(safe_mul_u256(tmp, *x_i)?, d)?;
+            }
+            // D * ((N - 1) * 1e18 + tmp) / (N * 1e18)
+            safe_div_u256(
+                safe_mul_u256(d, safe_add_u256((n_coins - U256::from(1)) * e18(), tmp)?)?,
+                n_coins * e18(),
+            )?
+        };
+        let diff = abs_diff(d, d_prev);
+        if diff <= U256::from(1) || safe_mul_u256(diff, e18())? < d {
+            return Ok(d);
+        }
+    }
+    Err(SimulationError::FatalError("CryptoSwap geometric mean didn't converge".to_string()))
+}
+
+/// Returns `|gamma + 1e18 - k0| + 1`.
+fn g1k0(gamma: U256, k0: U256) -> Result<U256, SimulationError> {
+    let g1k0 = safe_add_u256(gamma, e18())?;
+    Ok(abs_diff(g1k0, k0) + U256::from(1))
+}
+
+/// Returns `1e18 * D / gamma * g1k0 / gamma * g1k0 * A_MULTIPLIER / ann`.
+fn mul1(ann: U256, gamma: U256, d: U256, g1k0: U256) -> Result<U256, SimulationError> {
+    let mut mul1 = safe_div_u256(safe_mul_u256(e18(), d)?, gamma)?;
+    mul1 = safe_div_u256(safe_mul_u256(mul1, g1k0)?, gamma)?;
+    mul1 = safe_mul_u256(safe_mul_u256(mul1, g1k0)?, A_MULTIPLIER)?;
+    safe_div_u256(mul1, ann)
+}
+
+/// Computes the invariant `D` of the pool with Newton's method.
+pub(super) fn newton_d(ann: U256, gamma: U256, x: &[U256]) -> Result<U256, SimulationError> {
+    check_coins(x)?;
+    let n_coins = U256::from(x.len());
+    let x = sorted_descending(x);
+    if x.iter().any(|x_i| x_i.is_zero()) {
+        return Err(SimulationError::FatalError("CryptoSwap pool has an empty balance".to_string()));
+    }
+
+    let mut d = safe_mul_u256(n_coins, geometric_mean(&x)?)?;
+    let mut sum = U256::ZERO;
+    for x_i in &x {
+        sum = safe_add_u256(sum, *x_i)?;
+    }
+
+    for _ in 0..MAX_ITERATIONS {
+        let d_prev = d;
+
+        let k0 = if x.len() == 2 {
+            // (1e18 * N^2) * x0 / D * x1 / D
+            let k0 = safe_div_u256(safe_mul_u256(e18() * n_coins * n_coins, x[0])?, d)?;
+            safe_div_u256(safe_mul_u256(k0, x[1])?, d)?
+        } else {
+            let mut k0 = e18();
+            for x_i in &x {
+                k0 = safe_div_u256(safe_mul_u256(safe_mul_u256(k0, *x_i)?, n_coins)?, d)?;
+            }
+            k0
+        };
+        if k0.is_zero() {
+            return Err(SimulationError::FatalError("CryptoSwap D is out of range".to_string()));
+        }
+        let g1k0 = g1k0(gamma, k0)?;
+        let mul1 = mul1(ann, gamma, d, g1k0)?;
+        // 2 * N * K0 / g1k0
+        let mul2 = safe_div_u256(safe_mul_u256(e18() * U256::from(2) * n_coins, k0)?, g1k0)?;
+
+        // (S + S * mul2 / 1e18) + mul1 * N / K0 - mul2 * D / 1e18
+        let neg_fprime = safe_sub_u256(
+            safe_add_u256(
+                safe_add_u256(sum, safe_mul_u256(sum, mul2)? / e18())?,
+                safe_div_u256(safe_mul_u256(mul1, n_coins)?, k0)?,
+            )?,
+            safe_mul_u256(mul2, d)? / e18(),
+        )?;
+
+        // D -= f / fprime
+        let d_plus = safe_div_u256(safe_mul_u256(d, safe_add_u256(neg_fprime, sum)?)?, neg_fprime)?;
+        let mut d_minus = safe_div_u256(safe_mul_u256(d, d)?, neg_fprime)?;
+        let correction = safe_mul_u256(d, safe_div_u256(mul1, neg_fprime)?)? / e18();
+        if e18() > k0 {
+            d_minus =
+                safe_add_u256(d_minus, safe_div_u256(safe_mul_u256(correction, e18() - k0)?, k0)?)?;
+        } else {
+            d_minus =
+                safe_sub_u256(d_minus, safe_div_u256(safe_mul_u256(correction, k0 - e18())?, k0)?)?;
+        }
+        d = if d_plus > d_minus { d_plus - d_minus } else { (d_minus - d_plus) / U256::from(2) };
+
+        let diff = abs_diff(d, d_prev);
+        if safe_mul_u256(diff, U256::from(100_000_000_000_000u64))? <
+            d.max(U256::from(10_000_000_000_000_000u64))
+        {
+            for x_i in &x {
+                check_fraction(*x_i, d)?;
+            }
+            return Ok(d);
+        }
+    }
+    Err(SimulationError::FatalError("CryptoSwap D didn't converge".to_string()))
+}
+
+/// Returns the `xp` of coin `i` that keeps the invariant `d`, given the `xp` of the other coins.
+/// The current `xp` of coin `i` is ignored.
+pub(super) fn newton_y(
+    ann: U256,
+    gamma: U256,
+    x: &[U256],
+    d: U256,
+    i: usize,
+) -> Result<U256, SimulationError> {
+    check_coins(x)?;
+    let n_coins = U256::from(x.len());
+
+    let (mut y, k0_i, sum_i, convergence_limit) = if x.len() == 2 {
+        let x_j = x[1 - i];
+        // D^2 / (x_j * N^2)
+        let y = safe_div_u256(safe_mul_u256(d, d)?, safe_mul_u256(x_j, n_coins * n_coins)?)?;
+        let k0_i = safe_div_u256(safe_mul_u256(e18() * n_coins, x_j)?, d)?;
+        (y, k0_i, x_j, x_j.max(d) / U256::from(100_000_000_000_000u64))
+    } else {
+        let mut others = x.to_vec();
+        others[i] = U256::ZERO;
+        let others = sorted_descending(&others);
+
+        let mut y = d / n_coins;
+        let mut sum_i = U256::ZERO;
+        // Small balances first
+        for x_k in others[..x.len() - 1].iter().rev() {
+            y = safe_div_u256(safe_mul_u256(y, d)?, safe_mul_u256(*x_k, n_coins)?)?;
+            sum_i = safe_add_u256(sum_i, *x_k)?;
+        }
+        // Large balances first
+        let mut k0_i = e18();
+        for x_k in &others[..x.len() - 1] {
+            k0_i = safe_div_u256(safe_mul_u256(safe_mul_u256(k0_i, *x_k)?, n_coins)?, d)?;
+        }
+        (y, k0_i, sum_i, others[0].max(d) / U256::from(100_000_000_000_000u64))
+    };
+    let convergence_limit = convergence_limit.max(U256::from(100));
+
+    for _ in 0..MAX_ITERATIONS {
+        let y_prev = y;
+
+        let k0 = safe_div_u256(safe_mul_u256(safe_mul_u256(k0_i, y)?, n_coins)?, d)?;
+        if k0.is_zero() {
+            return Err(SimulationError::FatalError("CryptoSwap y is out of range".to_string()));
+        }
+        let sum = safe_add_u256(sum_i, y)?;
+        let g1k0 = g1k0(gamma, k0)?;
+        let mul1 = mul1(ann, gamma, d, g1k0)?;
+        // 1e18 + 2e18 * K0 / g1k0
+        let mul2 =
+            safe_add_u256(e18(), safe_div_u256(safe_mul_u256(e18() * U256::from(2), k0)?, g1k0)?)?;
+
+        let mut yfprime = safe_add_u256(
+            safe_add_u256(safe_mul_u256(e18(), y)?, safe_mul_u256(sum, mul2)?)?,
+            mul1,
+        )?;
+        let dyfprime = safe_mul_u256(d, mul2)?;
+        if yfprime < dyfprime {
+            y = y_prev / U256::from(2);
+            continue;
+        }
+        yfprime -= dyfprime;
+        let fprime = safe_div_u256(yfprime, y)?;
+
+        // y -= f / fprime
+        let mut y_minus = safe_div_u256(mul1, fprime)?;
+        let y_plus = safe_add_u256(
+            safe_div_u256(safe_add_u256(yfprime, safe_mul_u256(e18(), d)?)?, fprime)?,
+            safe_div_u256(safe_mul_u256(y_minus, e18())?, k0)?,
+        )?;
+        y_minus = safe_add_u256(y_minus, safe_div_u256(safe_mul_u256(e18(), sum)?, fprime)?)?;
+        y = if y_plus < y_minus { y_prev / U256::from(2) } else { y_plus - y_minus };
+
+        let diff = abs_diff(y, y_prev);
+        if diff < convergence_limit.max(y / U256::from(100_000_000_000_000u64)) {
+            check_fraction(y, d)?;
+            return Ok(y);
+        }
+    }
+    Err(SimulationError::FatalError("CryptoSwap y didn't converge".to_string()))
+}
+
+/// Returns the fee scaled by 1e10 for the given `xp`, between `mid_fee` for balanced pools and
+/// `out_fee` for imbalanced ones.
+pub(super) fn fee(
+    xp: &[U256],
+    mid_fee: U256,
+    out_fee: U256,
+    fee_gamma: U256,
+) -> Result<U256, SimulationError> {
+    check_coins(xp)?;
+    let n_coins = U256::from(xp.len());
+    let mut sum = U256::ZERO;
+    for x in xp {
+        sum = safe_add_u256(sum, *x)?;
+    }
+
+    // Reduction coefficient, 1e18 for balanced pools
+    let mut k = if xp.len() == 2 {
+        let k = safe_div_u256(safe_mul_u256(e18() * U256::from(4), xp[0])?, sum)?;
+        safe_div_u256(safe_mul_u256(k, xp[1])?, sum)?
+    } else {
+        let mut k = e18();
+        for x in xp {
+            k = safe_div_u256(safe_mul_u256(safe_mul_u256(k, n_coins)?, *x)?, sum)?;
+        }
+        k
+    };
+    if !fee_gamma.is_zero() {
+        k = safe_div_u256(
+            safe_mul_u256(fee_gamma, e18())?,
+            safe_sub_u256(safe_add_u256(fee_gamma, e18())?, k)?,
+        )?;
+    }
+
+    // (mid_fee * f + out_fee * (1e18 - f)) / 1e18
+    Ok(safe_add_u256(
+        safe_mul_u256(mid_fee, k)?,
+        safe_mul_u256(out_fee, safe_sub_u256(e18(), k)?)?,
+    )? / e18())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn u256(value: &str) -> U256 {
+        U256::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_geometric_mean() {
+        assert_eq!(geometric_mean(&[e18() * U256::from(4), e18()]).unwrap(), e18() * U256::from(2));
+        assert_eq!(
+            geometric_mean(&[e18() * U256::from(3), e18() * U256::from(2), e18()]).unwrap(),
+            u256("1817120592832139658")
+        );
+    }
+
+    #[test]
+    fn test_newton_d_balanced() {
+        let xp = [u256("2000000000000000000000000"), u256("2000000000000000000000000")];
+
+        let d = newton_d(U256::from(400_000), U256::from(145_000_000_000_000u64), &xp).unwrap();
+
+        assert_eq!(d, u256("4000000000000000000000000"));
+    }
+
+    #[test]
+    fn test_newton_d_three_coins() {
+        let xp = [
+            u256("10000000000000000000000000"),
+            u256("9999999999900000000000000"),
+            u256("10000000000000000000000000"),
+        ];
+
+        let d = newton_d(U256::from(1_707_629), U256::from(11_809_167_828_997u64), &xp).unwrap();
+
+        assert_eq!(d, u256("29999999999899999999654702"));
+    }
+
+    #[test]
+    fn test_fee() {
+        let mid_fee = U256::from(26_000_000);
+        let out_fee = U256::from(45_000_000);
+        let fee_gamma = U256::from(230_000_000_000_000u64);
+        let balanced = [e18(), e18()];
+
+        assert_eq!(fee(&balanced, mid_fee, out_fee, fee_gamma).unwrap(), mid_fee);
+        assert!(
+            fee(&[e18(), e18() * U256::from(3)], mid_fee, out_fee, fee_gamma).unwrap() > mid_fee
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Swap math of Curve StableSwap pools.
+//!
+//! Balances are converted to `xp`, i.e. scaled by the coin rates to 18 decimals, before being
+//! passed to these functions. The amplification coefficient is scaled by `A_PRECISION`. Mirrors
+//! the factory and NG implementations, see https://github.com/curvefi/stableswap-ng/blob/main/contracts/main/CurveStableSwapNG.vy
+use alloy::primitives::U256;
+use tycho_common::simulation::errors::SimulationError;
+
+use crate::evm::protocol::safe_math::{safe_add_u256, safe_div_u256, safe_mul_u256, safe_sub_u256};
+
+pub(super) const A_PRECISION: U256 = U256::from_limbs([100, 0, 0, 0]);
+pub(super) const FEE_DENOMINATOR: U256 = U256::from_limbs([10_000_000_000, 0, 0, 0]);
+pub(super) const PRECISION: U256 = U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0]);
+const MAX_ITERATIONS: usize = 255;
+
+fn converged(current: U256, previous: U256) -> bool {
+    if current > previous {
+        current - previous <= U256::from(1)
+    } else {
+        previous - current <= U256::from(1)
+    }
+}
+
+/// Computes the invariant `D` of the pool with Newton's method.
+pub(super) fn get_d(xp: &[U256], amp: U256) -> Result<U256, SimulationError> {
+    let n_coins = U256::from(xp.len());
+    let mut sum = U256::ZERO;
+    for x in xp {
+        sum = safe_add_u256(sum, *x)?;
+    }
+    if sum.is_zero() {
+        return Ok(U256::ZERO);
+    }
+
+    let ann = safe_mul_u256(amp, n_coins)?;
+    let mut d = sum;
+    for _ in 0..MAX_ITERATIONS {
+        let mut d_p = d;
+        for x in xp {
+            d_p = safe_div_u256(safe_mul_u256(d_p, d)?, safe_mul_u256(*x, n_coins)?)?;
+        }
+        let d_prev = d;
+        // (Ann * S / A_PRECISION + D_P * N) * D
+        let numerator = safe_mul_u256(
+            safe_add_u256(safe_mul_u256(ann, sum)? / A_PRECISION, safe_mul_u256(d_p, n_coins)?)?,
+            d,
+        )?;
+        // (Ann - A_PRECISION) * D / A_PRECISION + (N + 1) * D_P
+        let denominator = safe_add_u256(
+            safe_mul_u256(safe_sub_u256(ann, A_PRECISION)?, d)? / A_PRECISION,
+            safe_mul_u256(n_coins + U256::from(1), d_p)?,
+        )?;
+        d = safe_div_u256(numerator, denominator)?;
+
+        if converged(d, d_prev) {
+            return Ok(d);
+        }
+    }
+    Err(SimulationError::FatalError("StableSwap D didn't converge".to_string()))
+}
+
+/// Returns the `xp` of coin `j` that keeps the invariant `d` when coin `i` has the `xp` `x`.
+pub(super) fn get_y(
+    i: usize,
+    j: usize,
+    x: U256,
+    xp: &[U256],
+    amp: U256,
+    d: U256,
+) -> Result<U256, SimulationError> {
+    if i == j || i >= xp.len() || j >= xp.len() {
+        return Err(SimulationError::InvalidInput(
+            format!("Invalid coin indices {i} and {j}"),
+            None,
+        ));
+    }
+    let n_coins = U256::from(xp.len());
+    let ann = safe_mul_u256(amp, n_coins)?;
+
+    let mut c = d;
+    let mut sum = U256::ZERO;
+    for (k, xp_k) in xp.iter().enumerate() {
+        let x_k = if k == i {
+            x
+        } else if k != j {
+            *xp_k
+        } else {
+            continue;
+        };
+        sum = safe_add_u256(sum, x_k)?;
+        c = safe_div_u256(safe_mul_u256(c, d)?, safe_mul_u256(x_k, n_coins)?)?;
+    }
+    // c = c * D * A_PRECISION / (Ann * N)
+    c = safe_div_u256(
+        safe_mul_u256(safe_mul_u256(c, d)?, A_PRECISION)?,
+        safe_mul_u256(ann, n_coins)?,
+    )?;
+    // b = S + D * A_PRECISION / Ann
+    let b = safe_add_u256(sum, safe_div_u256(safe_mul_u256(d, A_PRECISION)?, ann)?)?;
+
+    let mut y = d;
+    for _ in 0..MAX_ITERATIONS {
+        let y_prev = y;
+        // (y^2 + c) / (2 * y + b - D)
+        y = safe_div_u256(
+            safe_add_u256(safe_mul_u256(y, y)?, c)?,
+            safe_sub_u256(safe_add_u256(safe_mul_u256(y, U256::from(2))?, b)?, d)?,
+        )?;
+
+        if converged(y, y_prev) {
+            return Ok(y);
+        }
+    }
+    Err(SimulationError::FatalError("StableSwap y didn't converge".to_string()))
+}
+
+/// Returns the fee of a swap between two coins with the given average `xp`.
+///
+/// NG pools with an off-peg fee multiplier above `FEE_DENOMINATOR` charge higher fees when the
+/// coins are imbalanced. Other pools always charge the base fee.
+pub(super) fn dynamic_fee(
+    xp_i: U256,
+    xp_j: U256,
+    fee: U256,
+    offpeg_fee_multiplier: U256,
+) -> Result<U256, SimulationError> {
+    if offpeg_fee_multiplier <= FEE_DENOMINATOR {
+        return Ok(fee);
+    }
+    let xps2 = safe_mul_u256(safe_add_u256(xp_i, xp_j)?, safe_add_u256(xp_i, xp_j)?)?;
+    // multiplier * fee / ((multiplier - 1e10) * 4 * xp_i * xp_j / xps2 + 1e10)
+    let imbalance = safe_div_u256(
+        safe_mul_u256(
+            safe_mul_u256(
+                safe_mul_u256(offpeg_fee_multiplier - FEE_DENOMINATOR, U256::from(4))?,
+                xp_i,
+            )?,
+            xp_j,
+        )?,
+        xps2,
+    )?;
+    safe_div_u256(
+        safe_mul_u256(offpeg_fee_multiplier, fee)?,
+        safe_add_u256(imbalance, FEE_DENOMINATOR)?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn u256(value: &str) -> U256 {
+        U256::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_get_d() {
+        let xp = [
+            u256("1000000000000000000000000"),
+            u256("1200000000000000000000000"),
+            u256("900000000000000000000000"),
+        ];
+
+        let d = get_d(&xp, U256::from(2000) * A_PRECISION).unwrap();
+
+        assert_eq!(d, u256("3099988825478648946602064"));
+    }
+
+    #[test]
+    fn test_get_y_symmetric() {
+        // Swapping 100 into a 1000/1100 pool mirrors its balances
+        let xp = [u256("1000000000000000000000"), u256("1100000000000000000000")];
+        let amp = U256::from(1500) * A_PRECISION;
+        let d = get_d(&xp, amp).unwrap();
+
+        let y = get_y(0, 1, xp[1], &xp, amp, d).unwrap();
+
+        assert_eq!(xp[1] - y - U256::from(1), u256("100000000000000000000"));
+    }
+
+    #[test]
+    fn test_dynamic_fee() {
+        let fee = U256::from(4_000_000);
+        let multiplier = U256::from(20_000_000_000u64);
+        let balanced = U256::from(1_000_000);
+
+        assert_eq!(dynamic_fee(balanced, balanced, fee, U256::ZERO).unwrap(), fee);
+        assert_eq!(dynamic_fee(balanced, balanced, fee, multiplier).unwrap(), fee);
+        // 1:3 imbalance: 2 * fee / (1 * 4 * 3 / 16 + 1)
+        assert_eq!(
+            dynamic_fee(balanced, balanced * U256::from(3), fee, multiplier).unwrap(),
+            U256::from(4_571_428)
+        );
+    }
+}
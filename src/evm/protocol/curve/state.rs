@@ -0,0 +1,811 @@
+use std::{any::Any, collections::HashMap};
+
+use alloy::primitives::U256;
+use num_bigint::{BigUint, ToBigUint};
+use tycho_common::{
+    dto::ProtocolStateDelta,
+    models::token::Token,
+    simulation::{
+        errors::{SimulationError, TransitionError},
+        protocol_sim::{Balances, GetAmountOutResult, ProtocolSim},
+    },
+    Bytes,
+};
+
+use super::{
+    cryptoswap_math,
+    stableswap_math::{self, FEE_DENOMINATOR, PRECISION},
+};
+use crate::{
+    evm::protocol::{
+        safe_math::{safe_add_u256, safe_div_u256, safe_mul_u256, safe_sub_u256},
+        u256_num::{biguint_to_u256, u256_to_biguint, u256_to_f64},
+        utils::decode_u256_list,
+    },
+    protocol::{fee::SwapFee, transfer_tax::amount_after_tax},
+};
+
+const STABLESWAP_GAS: u64 = 130_000;
+const CRYPTOSWAP_GAS: u64 = 250_000;
+/// Soft limit for swaps: the amount that takes out 90% of the balance out
+const MAX_OUT_NUMERATOR: u64 = 9;
+const MAX_OUT_DENOMINATOR: u64 = 10;
+/// Fraction of the balance in used to quote spot prices
+const SPOT_PRICE_DENOMINATOR: u64 = 1_000_000;
+
+/// The invariant of a Curve pool, with its pool type specific parameters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CurvePool {
+    /// StableSwap pool, plain or meta. The coins of meta pools are the meta coin and the base
+    /// pool's LP token, whose rate is the base pool's virtual price.
+    StableSwap {
+        /// Amplification coefficient scaled by `A_PRECISION` (100), as returned by `A_precise`
+        amp: U256,
+        /// Swap fee scaled by 1e10
+        fee: U256,
+        /// Off-peg fee multiplier of NG pools scaled by 1e10, zero for pools without dynamic fees
+        offpeg_fee_multiplier: U256,
+        /// Rate of each coin scaled by 1e18, including the scaling of the coin to 18 decimals
+        rates: Vec<U256>,
+    },
+    /// CryptoSwap pool with two or three coins
+    CryptoSwap {
+        /// `A * N^N * A_MULTIPLIER`, as returned by `A`
+        ann: U256,
+        /// Gamma scaled by 1e18
+        gamma: U256,
+        /// The invariant
+        d: U256,
+        /// Price of each coin after the first in terms of the first, scaled by 1e18
+        price_scale: Vec<U256>,
+        /// Fee of balanced pools scaled by 1e10
+        mid_fee: U256,
+        /// Fee of imbalanced pools scaled by 1e10
+        out_fee: U256,
+        /// Speed of the transition from `mid_fee` to `out_fee`, scaled by 1e18
+        fee_gamma: U256,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CurveState {
+    /// The pool's identifier
+    id: String,
+    /// The pool's coins, in pool order
+    tokens: Vec<Bytes>,
+    /// Balance of each coin
+    balances: Vec<U256>,
+    /// Multiplier bringing the amounts of each coin to 18 decimals
+    precisions: Vec<U256>,
+    pool: CurvePool,
+}
+
+impl CurveState {
+    /// Creates a new `CurveState`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The pool id.
+    /// * `tokens` - Addresses of the pool's coins, in pool order.
+    /// * `balances` - Balance of each coin, as stored by the pool.
+    /// * `decimals` - Decimals of each coin, at most 18.
+    /// * `pool` - The pool type and its parameters.
+    pub fn new(
+        id: String,
+        tokens: Vec<Bytes>,
+        balances: Vec<U256>,
+        decimals: &[u32],
+        pool: CurvePool,
+    ) -> Self {
+        let precisions = decimals
+            .iter()
+            .map(|decimals| U256::from(10).pow(U256::from(18u32.saturating_sub(*decimals))))
+            .collect();
+        Self { id, tokens, balances, precisions, pool }
+    }
+
+    fn token_index(&self, token: &Bytes) -> Result<usize, SimulationError> {
+        self.tokens
+            .iter()
+            .position(|pool_token| pool_token == token)
+            .ok_or_else(|| {
+                SimulationError::InvalidInput(format!("Token {token} is not in the pool"), None)
+            })
+    }
+
+    fn token_indices(
+        &self,
+        token_in: &Bytes,
+        token_out: &Bytes,
+    ) -> Result<(usize, usize), SimulationError> {
+        let (index_in, index_out) = (self.token_index(token_in)?, self.token_index(token_out)?);
+        if index_in == index_out {
+            return Err(SimulationError::InvalidInput(
+                "Token in and token out must differ".to_string(),
+                None,
+            ));
+        }
+        Ok((index_in, index_out))
+    }
+
+    fn stableswap_xp(rates: &[U256], balances: &[U256]) -> Result<Vec<U256>, SimulationError> {
+        rates
+            .iter()
+            .zip(balances)
+            .map(|(rate, balance)| Ok(safe_mul_u256(*rate, *balance)? / PRECISION))
+            .collect()
+    }
+
+    fn cryptoswap_xp(
+        &self,
+        price_scale: &[U256],
+        balances: &[U256],
+    ) -> Result<Vec<U256>, SimulationError> {
+        let mut xp = Vec::with_capacity(balances.len());
+        xp.push(safe_mul_u256(balances[0], self.precisions[0])?);
+        for (index, price) in price_scale.iter().enumerate() {
+            let balance = safe_mul_u256(balances[index + 1], self.precisions[index + 1])?;
+            xp.push(safe_mul_u256(balance, *price)? / PRECISION);
+        }
+        Ok(xp)
+    }
+
+    /// Returns the amount out for `amount_in` and the fee deducted from it, mirroring the pools'
+    /// `get_dy`.
+    fn get_dy(
+        &self,
+        index_in: usize,
+        index_out: usize,
+        amount_in: U256,
+    ) -> Result<(U256, U256), SimulationError> {
+        match &self.pool {
+            CurvePool::StableSwap { amp, fee, offpeg_fee_multiplier, rates } => {
+                let xp = Self::stableswap_xp(rates, &self.balances)?;
+                let d = stableswap_math::get_d(&xp, *amp)?;
+                let x = safe_add_u256(
+                    xp[index_in],
+                    safe_mul_u256(amount_in, rates[index_in])? / PRECISION,
+                )?;
+                let y = stableswap_math::get_y(index_in, index_out, x, &xp, *amp, d)?;
+                let dy = safe_sub_u256(safe_sub_u256(xp[index_out], y)?, U256::from(1))?;
+
+                let fee = stableswap_math::dynamic_fee(
+                    safe_add_u256(xp[index_in], x)? / U256::from(2),
+                    safe_add_u256(xp[index_out], y)? / U256::from(2),
+                    *fee,
+                    *offpeg_fee_multiplier,
+                )?;
+                let dy_fee = safe_mul_u256(fee, dy)? / FEE_DENOMINATOR;
+                let amount_out = safe_div_u256(
+                    safe_mul_u256(safe_sub_u256(dy, dy_fee)?, PRECISION)?,
+                    rates[index_out],
+                )?;
+                let fee_amount =
+                    safe_div_u256(safe_mul_u256(dy, PRECISION)?, rates[index_out])? - amount_out;
+                Ok((amount_out, fee_amount))
+            }
+            CurvePool::CryptoSwap { ann, gamma, d, price_scale, mid_fee, out_fee, fee_gamma } => {
+                let mut balances = self.balances.clone();
+                balances[index_in] = safe_add_u256(balances[index_in], amount_in)?;
+                let mut xp = self.cryptoswap_xp(price_scale, &balances)?;
+                let y = cryptoswap_math::newton_y(*ann, *gamma, &xp, *d, index_out)?;
+                let mut dy = safe_sub_u256(safe_sub_u256(xp[index_out], y)?, U256::from(1))?;
+                xp[index_out] = y;
+                if index_out > 0 {
+                    dy = safe_div_u256(safe_mul_u256(dy, PRECISION)?, price_scale[index_out - 1])?;
+                }
+                dy /= self.precisions[index_out];
+
+                let fee = cryptoswap_math::fee(&xp, *mid_fee, *out_fee, *fee_gamma)?;
+                let fee_amount = safe_mul_u256(fee, dy)? / FEE_DENOMINATOR;
+                Ok((dy - fee_amount, fee_amount))
+            }
+        }
+    }
+
+    /// Returns the state after swapping `amount_in` for `amount_out`. Fees stay in the pool.
+    fn swapped(
+        &self,
+        index_in: usize,
+        index_out: usize,
+        amount_in: U256,
+        amount_out: U256,
+    ) -> Result<Self, SimulationError> {
+        let mut new_state = self.clone();
+        new_state.balances[index_in] = safe_add_u256(self.balances[index_in], amount_in)?;
+        new_state.balances[index_out] = safe_sub_u256(self.balances[index_out], amount_out)?;
+        if let CurvePool::CryptoSwap { ann, gamma, d, price_scale, .. } = &mut new_state.pool {
+            // The pool recomputes D after each swap. Price scale adjustments only arrive with the
+            // next delta.
+            let xp = self.cryptoswap_xp(price_scale, &new_state.balances)?;
+            *d = cryptoswap_math::newton_d(*ann, *gamma, &xp)?;
+        }
+        Ok(new_state)
+    }
+
+    /// Returns the amount in that takes `amount_out` out of the pool, ignoring fees.
+    fn amount_in_before_fees(
+        &self,
+        index_in: usize,
+        index_out: usize,
+        amount_out: U256,
+    ) -> Result<U256, SimulationError> {
+        match &self.pool {
+            CurvePool::StableSwap { amp, rates, .. } => {
+                let xp = Self::stableswap_xp(rates, &self.balances)?;
+                let d = stableswap_math::get_d(&xp, *amp)?;
+                let y = safe_sub_u256(
+                    xp[index_out],
+                    safe_mul_u256(amount_out, rates[index_out])? / PRECISION,
+                )?;
+                let x = stableswap_math::get_y(index_out, index_in, y, &xp, *amp, d)?;
+                safe_div_u256(
+                    safe_mul_u256(safe_sub_u256(x, xp[index_in])?, PRECISION)?,
+                    rates[index_in],
+                )
+            }
+            CurvePool::CryptoSwap { ann, gamma, d, price_scale, .. } => {
+                let mut balances = self.balances.clone();
+                balances[index_out] = safe_sub_u256(balances[index_out], amount_out)?;
+                let xp = self.cryptoswap_xp(price_scale, &balances)?;
+                let mut x = cryptoswap_math::newton_y(*ann, *gamma, &xp, *d, index_in)?;
+                if index_in > 0 {
+                    x = safe_div_u256(safe_mul_u256(x, PRECISION)?, price_scale[index_in - 1])?;
+                }
+                safe_sub_u256(x / self.precisions[index_in], self.balances[index_in])
+            }
+        }
+    }
+
+    fn swap_gas(&self) -> u64 {
+        match self.pool {
+            CurvePool::StableSwap { .. } => STABLESWAP_GAS,
+            CurvePool::CryptoSwap { .. } => CRYPTOSWAP_GAS,
+        }
+    }
+
+    /// Returns the current fee scaled by 1e10, before any dynamic adjustment to the swap.
+    fn current_fee(&self) -> Result<U256, SimulationError> {
+        match &self.pool {
+            CurvePool::StableSwap { fee, .. } => Ok(*fee),
+            CurvePool::CryptoSwap { price_scale, mid_fee, out_fee, fee_gamma, .. } => {
+                let xp = self.cryptoswap_xp(price_scale, &self.balances)?;
+                cryptoswap_math::fee(&xp, *mid_fee, *out_fee, *fee_gamma)
+            }
+        }
+    }
+}
+
+impl ProtocolSim for CurveState {
+    fn fee(&self) -> f64 {
+        self.current_fee()
+            .map(|fee| u256_to_f64(fee) / u256_to_f64(FEE_DENOMINATOR))
+            .unwrap_or(f64::NAN)
+    }
+
+    fn spot_price(&self, base: &Token, quote: &Token) -> Result<f64, SimulationError> {
+        let (index_base, index_quote) = self.token_indices(&base.address, &quote.address)?;
+        if self.balances[index_base].is_zero() || self.balances[index_quote].is_zero() {
+            return Err(SimulationError::RecoverableError("No liquidity".to_string()));
+        }
+        // Quotes a small fraction of the balance, the invariants have no closed form derivative
+        let amount_in =
+            (self.balances[index_base] / U256::from(SPOT_PRICE_DENOMINATOR)).max(U256::from(1));
+        let (amount_out, fee_amount) = self.get_dy(index_base, index_quote, amount_in)?;
+        let amount_out = u256_to_f64(amount_out + fee_amount) / 10f64.powi(quote.decimals as i32);
+        Ok(amount_out / (u256_to_f64(amount_in) / 10f64.powi(base.decimals as i32)))
+    }
+
+    fn get_amount_out(
+        &self,
+        amount_in: BigUint,
+        token_in: &Token,
+        token_out: &Token,
+    ) -> Result<GetAmountOutResult, SimulationError> {
+        let (index_in, index_out) = self.token_indices(&token_in.address, &token_out.address)?;
+        let amount_in = biguint_to_u256(&amount_after_tax(&amount_in, token_in));
+        if amount_in.is_zero() {
+            return Err(SimulationError::InvalidInput("Amount in cannot be zero".to_string(), None));
+        }
+
+        let (amount_out, _) = self.get_dy(index_in, index_out, amount_in)?;
+        let new_state = self.swapped(index_in, index_out, amount_in, amount_out)?;
+
+        Ok(GetAmountOutResult::new(
+            amount_after_tax(&u256_to_biguint(amount_out), token_out),
+            self.swap_gas()
+                .to_biguint()
+                .expect("Expected an unsigned integer as gas value"),
+            Box::new(new_state),
+        ))
+    }
+
+    fn get_limits(
+        &self,
+        sell_token: Bytes,
+        buy_token: Bytes,
+    ) -> Result<(BigUint, BigUint), SimulationError> {
+        let (index_in, index_out) = self.token_indices(&sell_token, &buy_token)?;
+        if self.balances[index_in].is_zero() || self.balances[index_out].is_zero() {
+            return Ok((BigUint::ZERO, BigUint::ZERO));
+        }
+
+        let max_out = safe_mul_u256(self.balances[index_out], U256::from(MAX_OUT_NUMERATOR))? /
+            U256::from(MAX_OUT_DENOMINATOR);
+        // Ignoring fees underestimates the amount in, so swapping it stays below max_out
+        let max_in = self.amount_in_before_fees(index_in, index_out, max_out)?;
+
+        Ok((u256_to_biguint(max_in), u256_to_biguint(max_out)))
+    }
+
+    fn delta_transition(
+        &mut self,
+        delta: ProtocolStateDelta,
+        _tokens: &HashMap<Bytes, Token>,
+        balances: &Balances,
+    ) -> Result<(), TransitionError<String>> {
+        let attributes = &delta.updated_attributes;
+        let decode_list = |name: &str| -> Result<Option<Vec<U256>>, TransitionError<String>> {
+            attributes
+                .get(name)
+                .map(|value| {
+                    decode_u256_list(value)
+                        .map_err(|err| TransitionError::DecodeError(err.to_string()))
+                })
+                .transpose()
+        };
+        let update = |target: &mut U256, name: &str| {
+            if let Some(value) = attributes.get(name) {
+                *target = U256::from_be_slice(value);
+            }
+        };
+
+        if let Some(pool_balances) = decode_list("balances")? {
+            if pool_balances.len() != self.tokens.len() {
+                return Err(TransitionError::DecodeError(format!(
+                    "Expected {} balances, got {}",
+                    self.tokens.len(),
+                    pool_balances.len()
+                )));
+            }
+            self.balances = pool_balances;
+        } else if let Some(component_balances) = balances
+            .component_balances
+            .get(&self.id)
+        {
+            for (token, balance) in component_balances {
+                if let Ok(index) = self.token_index(token) {
+                    self.balances[index] = U256::from_be_slice(balance);
+                }
+            }
+        }
+
+        match &mut self.pool {
+            CurvePool::StableSwap { amp, fee, offpeg_fee_multiplier, rates } => {
+                update(amp, "A");
+                update(fee, "fee");
+                update(offpeg_fee_multiplier, "offpeg_fee_multiplier");
+                if let Some(new_rates) = decode_list("rates")? {
+                    if new_rates.len() != rates.len() {
+                        return Err(TransitionError::DecodeError(format!(
+                            "Expected {} rates, got {}",
+                            rates.len(),
+                            new_rates.len()
+                        )));
+                    }
+                    *rates = new_rates;
+                }
+                if let Some(last_rate) = rates.last_mut() {
+                    update(last_rate, "base_virtual_price");
+                }
+            }
+            CurvePool::CryptoSwap { ann, gamma, d, price_scale, mid_fee, out_fee, fee_gamma } => {
+                update(ann, "A");
+                update(gamma, "gamma");
+                update(d, "D");
+                update(mid_fee, "mid_fee");
+                update(out_fee, "out_fee");
+                update(fee_gamma, "fee_gamma");
+                if let Some(new_price_scale) = decode_list("price_scale")? {
+                    if new_price_scale.len() != price_scale.len() {
+                        return Err(TransitionError::DecodeError(format!(
+                            "Expected {} prices, got {}",
+                            price_scale.len(),
+                            new_price_scale.len()
+                        )));
+                    }
+                    *price_scale = new_price_scale;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn ProtocolSim> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn eq(&self, other: &dyn ProtocolSim) -> bool {
+        if let Some(other_state) = other.as_any().downcast_ref::<Self>() {
+            self == other_state
+        } else {
+            false
+        }
+    }
+}
+
+impl SwapFee for CurveState {
+    /// Returns the fee charged on the swap. NG StableSwap pools with an off-peg multiplier and
+    /// CryptoSwap pools charge fees that depend on the pool balances after the swap.
+    fn swap_fee(
+        &self,
+        token_in: &Token,
+        token_out: &Token,
+        amount_in: Option<&BigUint>,
+    ) -> Result<f64, SimulationError> {
+        let Some(amount_in) = amount_in else {
+            return Ok(self.fee());
+        };
+        let (index_in, index_out) = self.token_indices(&token_in.address, &token_out.address)?;
+        let (amount_out, fee_amount) =
+            self.get_dy(index_in, index_out, biguint_to_u256(amount_in))?;
+        let amount_before_fee = amount_out + fee_amount;
+        if amount_before_fee.is_zero() {
+            return Ok(self.fee());
+        }
+        Ok(u256_to_f64(fee_amount) / u256_to_f64(amount_before_fee))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use approx::assert_relative_eq;
+    use rstest::rstest;
+    use tycho_common::models::Chain;
+
+    use super::*;
+
+    fn e18(value: u64) -> U256 {
+        U256::from(value) * PRECISION
+    }
+
+    fn token(address: &str, decimals: u32) -> Token {
+        Token::new(
+            &Bytes::from_str(address).unwrap(),
+            "T",
+            decimals,
+            0,
+            &[Some(10_000)],
+            Chain::Ethereum,
+            100,
+        )
+    }
+
+    fn dai() -> Token {
+        token("0x6b175474e89094c44da98b954eedeac495271d0f", 18)
+    }
+
+    fn usdc() -> Token {
+        token("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", 6)
+    }
+
+    fn usdt() -> Token {
+        token("0xdac17f958d2ee523a2206206994597c13d831ec7", 6)
+    }
+
+    fn wbtc() -> Token {
+        token("0x2260fac5e5542a773aa44fbcfedf7c193bc2c599", 8)
+    }
+
+    fn weth() -> Token {
+        token("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2", 18)
+    }
+
+    fn frax() -> Token {
+        token("0x853d955acef822db058eb8505911ed77f175b99e", 18)
+    }
+
+    fn crv_3crv() -> Token {
+        token("0x6c3f90f043a72fa612cbac8115ee7e52bde6e490", 18)
+    }
+
+    fn state(tokens: &[Token], balances: Vec<U256>, pool: CurvePool) -> CurveState {
+        let decimals: Vec<u32> = tokens
+            .iter()
+            .map(|token| token.decimals)
+            .collect();
+        CurveState::new(
+            "0xpool".to_string(),
+            tokens
+                .iter()
+                .map(|token| token.address.clone())
+                .collect(),
+            balances,
+            &decimals,
+            pool,
+        )
+    }
+
+    /// 3pool-like DAI/USDC/USDT pool with A = 2000 and a 0.01% fee
+    fn three_pool() -> CurveState {
+        // USDC and USDT have 6 decimals
+        let usd_rate = PRECISION * U256::from(10).pow(U256::from(12));
+        state(
+            &[dai(), usdc(), usdt()],
+            vec![e18(1_000_000), U256::from(1_200_000_000_000u64), U256::from(900_000_000_000u64)],
+            CurvePool::StableSwap {
+                amp: U256::from(200_000),
+                fee: U256::from(1_000_000),
+                offpeg_fee_multiplier: U256::ZERO,
+                rates: vec![PRECISION, usd_rate, usd_rate],
+            },
+        )
+    }
+
+    /// Imbalanced NG pool with A = 150, a 0.04% fee and a 2x off-peg fee multiplier
+    fn ng_pool(offpeg_fee_multiplier: U256) -> CurveState {
+        state(
+            &[dai(), frax()],
+            vec![e18(1_000), e18(3_000)],
+            CurvePool::StableSwap {
+                amp: U256::from(15_000),
+                fee: U256::from(4_000_000),
+                offpeg_fee_multiplier,
+                rates: vec![PRECISION, PRECISION],
+            },
+        )
+    }
+
+    /// FRAX/3CRV meta pool with a base virtual price of 1.03
+    fn meta_pool() -> CurveState {
+        state(
+            &[frax(), crv_3crv()],
+            vec![e18(1_000_000), e18(950_000)],
+            CurvePool::StableSwap {
+                amp: U256::from(100_000),
+                fee: U256::from(4_000_000),
+                offpeg_fee_multiplier: U256::ZERO,
+                rates: vec![PRECISION, U256::from(1_030_000_000_000_000_000u64)],
+            },
+        )
+    }
+
+    /// Balanced USDC/WETH twocrypto pool at 2000 USDC per WETH
+    fn twocrypto_pool() -> CurveState {
+        state(
+            &[usdc(), weth()],
+            vec![U256::from(2_000_000_000_000u64), e18(1_000)],
+            CurvePool::CryptoSwap {
+                ann: U256::from(400_000),
+                gamma: U256::from(145_000_000_000_000u64),
+                d: e18(4_000_000),
+                price_scale: vec![e18(2_000)],
+                mid_fee: U256::from(26_000_000),
+                out_fee: U256::from(45_000_000),
+                fee_gamma: U256::from(230_000_000_000_000u64),
+            },
+        )
+    }
+
+    /// USDT/WBTC/WETH tricrypto pool at 30000 USDT per WBTC and 2000 USDT per WETH
+    fn tricrypto_pool() -> CurveState {
+        state(
+            &[usdt(), wbtc(), weth()],
+            vec![U256::from(10_000_000_000_000u64), U256::from(33_333_333_333u64), e18(5_000)],
+            CurvePool::CryptoSwap {
+                ann: U256::from(1_707_629),
+                gamma: U256::from(11_809_167_828_997u64),
+                d: U256::from_str("29999999999899999999654702").unwrap(),
+                price_scale: vec![e18(30_000), e18(2_000)],
+                mid_fee: U256::from(3_000_000),
+                out_fee: U256::from(30_000_000),
+                fee_gamma: U256::from(500_000_000_000_000u64),
+            },
+        )
+    }
+
+    #[rstest]
+    #[case::stable_dai_usdc(three_pool(), dai(), usdc(), "1000000000000000000000", "999987471")]
+    #[case::stable_usdt_dai(three_pool(), usdt(), dai(), "1000000000", "999958019481818587825")]
+    #[case::ng_dynamic_fee(
+        ng_pool(U256::from(20_000_000_000u64)),
+        dai(),
+        frax(),
+        "100000000000000000000",
+        "101003784131457874563"
+    )]
+    #[case::ng_base_fee(
+        ng_pool(U256::ZERO),
+        dai(),
+        frax(),
+        "100000000000000000000",
+        "101008922116608552547"
+    )]
+    #[case::meta(
+        meta_pool(),
+        frax(),
+        crv_3crv(),
+        "1000000000000000000000",
+        "970463380290893174119"
+    )]
+    #[case::twocrypto_usdc_weth(
+        twocrypto_pool(),
+        usdc(),
+        weth(),
+        "10000000000",
+        "4984494050964054153"
+    )]
+    #[case::twocrypto_weth_usdc(
+        twocrypto_pool(),
+        weth(),
+        usdc(),
+        "5000000000000000000",
+        "9968988102"
+    )]
+    #[case::tricrypto_usdt_wbtc(tricrypto_pool(), usdt(), wbtc(), "30000000000", "99950343")]
+    #[case::tricrypto_weth_usdt(
+        tricrypto_pool(),
+        weth(),
+        usdt(),
+        "10000000000000000000",
+        "19992368932"
+    )]
+    fn test_get_amount_out(
+        #[case] pool: CurveState,
+        #[case] token_in: Token,
+        #[case] token_out: Token,
+        #[case] amount_in: &str,
+        #[case] expected: &str,
+    ) {
+        let amount_in = BigUint::from_str(amount_in).unwrap();
+
+        let result = pool
+            .get_amount_out(amount_in.clone(), &token_in, &token_out)
+            .unwrap();
+
+        assert_eq!(result.amount, BigUint::from_str(expected).unwrap());
+        let new_state = result
+            .new_state
+            .as_any()
+            .downcast_ref::<CurveState>()
+            .unwrap();
+        let (index_in, index_out) = pool
+            .token_indices(&token_in.address, &token_out.address)
+            .unwrap();
+        assert_eq!(
+            new_state.balances[index_in],
+            pool.balances[index_in] + biguint_to_u256(&amount_in)
+        );
+        assert_eq!(
+            new_state.balances[index_out],
+            pool.balances[index_out] - biguint_to_u256(&result.amount)
+        );
+    }
+
+    #[test]
+    fn test_cryptoswap_new_state_recomputes_d() {
+        let pool = twocrypto_pool();
+
+        let result = pool
+            .get_amount_out(BigUint::from(10_000_000_000u64), &usdc(), &weth())
+            .unwrap();
+
+        let new_state = result
+            .new_state
+            .as_any()
+            .downcast_ref::<CurveState>()
+            .unwrap();
+        let CurvePool::CryptoSwap { d, .. } = new_state.pool else { panic!("Expected CryptoSwap") };
+        // Fees stay in the pool and increase the invariant
+        assert!(d > e18(4_000_000));
+    }
+
+    #[rstest]
+    #[case::stable(three_pool(), dai(), usdc(), 1.0)]
+    #[case::twocrypto(twocrypto_pool(), weth(), usdc(), 2000.0)]
+    #[case::tricrypto(tricrypto_pool(), wbtc(), weth(), 15.0)]
+    fn test_spot_price(
+        #[case] pool: CurveState,
+        #[case] base: Token,
+        #[case] quote: Token,
+        #[case] expected: f64,
+    ) {
+        let price = pool.spot_price(&base, &quote).unwrap();
+
+        assert_relative_eq!(price, expected, epsilon = expected * 1e-3);
+    }
+
+    #[rstest]
+    #[case::stable(three_pool(), usdc(), usdt())]
+    #[case::meta(meta_pool(), crv_3crv(), frax())]
+    #[case::tricrypto(tricrypto_pool(), weth(), wbtc())]
+    fn test_get_limits(#[case] pool: CurveState, #[case] sell: Token, #[case] buy: Token) {
+        let (max_in, max_out) = pool
+            .get_limits(sell.address.clone(), buy.address.clone())
+            .unwrap();
+
+        let index_out = pool.token_index(&buy.address).unwrap();
+        assert_eq!(
+            max_out,
+            u256_to_biguint(pool.balances[index_out] * U256::from(9) / U256::from(10))
+        );
+        let amount_out = pool
+            .get_amount_out(max_in, &sell, &buy)
+            .unwrap()
+            .amount;
+        assert!(amount_out <= max_out);
+        assert!(amount_out > max_out.clone() * 99u32 / 100u32);
+    }
+
+    #[test]
+    fn test_fee() {
+        assert_eq!(three_pool().fee(), 0.0001);
+        // Balanced CryptoSwap pools charge the mid fee
+        assert_relative_eq!(twocrypto_pool().fee(), 0.0026);
+    }
+
+    #[test]
+    fn test_swap_fee_dynamic() {
+        let pool = ng_pool(U256::from(20_000_000_000u64));
+        let amount_in = BigUint::from(100u32) * BigUint::from(10u32).pow(18);
+
+        let fee = pool
+            .swap_fee(&dai(), &frax(), Some(&amount_in))
+            .unwrap();
+
+        // Above the base fee of the imbalanced pool
+        assert!(fee > 0.0004);
+        assert_eq!(
+            pool.swap_fee(&dai(), &frax(), None)
+                .unwrap(),
+            0.0004
+        );
+    }
+
+    #[test]
+    fn test_delta_transition() {
+        let mut pool = meta_pool();
+        let delta = ProtocolStateDelta {
+            component_id: "0xpool".to_string(),
+            updated_attributes: HashMap::from([
+                ("A".to_string(), Bytes::from(U256::from(200_000).to_be_bytes_vec())),
+                (
+                    "base_virtual_price".to_string(),
+                    Bytes::from(U256::from(1_040_000_000_000_000_000u64).to_be_bytes_vec()),
+                ),
+            ]),
+            deleted_attributes: Default::default(),
+        };
+        let balances = Balances {
+            component_balances: HashMap::from([(
+                "0xpool".to_string(),
+                HashMap::from([(frax().address, Bytes::from(e18(5).to_be_bytes_vec()))]),
+            )]),
+            account_balances: HashMap::new(),
+        };
+
+        pool.delta_transition(delta, &HashMap::new(), &balances)
+            .unwrap();
+
+        assert_eq!(pool.balances, vec![e18(5), e18(950_000)]);
+        assert_eq!(
+            pool.pool,
+            CurvePool::StableSwap {
+                amp: U256::from(200_000),
+                fee: U256::from(4_000_000),
+                offpeg_fee_multiplier: U256::ZERO,
+                rates: vec![PRECISION, U256::from(1_040_000_000_000_000_000u64)],
+            }
+        );
+    }
+}
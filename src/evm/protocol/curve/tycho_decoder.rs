@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+
+use alloy::primitives::U256;
+use tycho_client::feed::{synchronizer::ComponentWithState, BlockHeader};
+use tycho_common::{models::token::Token, Bytes};
+
+use super::{
+    stableswap_math::PRECISION,
+    state::{CurvePool, CurveState},
+};
+use crate::{
+    evm::protocol::utils::{attribute, decode_u256_list, required_attribute},
+    protocol::{errors::InvalidSnapshotError, models::TryFromWithBlock},
+};
+
+fn required_u256(snapshot: &ComponentWithState, name: &str) -> Result<U256, InvalidSnapshotError> {
+    required_attribute(snapshot, name).map(|value| U256::from_be_slice(value))
+}
+
+fn u256_list(
+    snapshot: &ComponentWithState,
+    name: &str,
+    expected_len: usize,
+) -> Result<Option<Vec<U256>>, InvalidSnapshotError> {
+    let Some(value) = attribute(snapshot, name) else {
+        return Ok(None);
+    };
+    let list =
+        decode_u256_list(value).map_err(|err| InvalidSnapshotError::ValueError(err.to_string()))?;
+    if list.len() != expected_len {
+        return Err(InvalidSnapshotError::ValueError(format!(
+            "Expected {expected_len} values for {name}, got {}",
+            list.len()
+        )));
+    }
+    Ok(Some(list))
+}
+
+impl TryFromWithBlock<ComponentWithState, BlockHeader> for CurveState {
+    type Error = InvalidSnapshotError;
+
+    /// Decodes a `ComponentWithState` of a Curve pool into a `CurveState`.
+    ///
+    /// Pools with a `gamma` attribute are CryptoSwap pools, all others StableSwap pools. State
+    /// attributes take precedence over static ones.
+    ///
+    /// StableSwap attributes:
+    /// - `A`: the amplification coefficient scaled by 100
+    /// - `fee`: the swap fee scaled by 1e10
+    /// - `offpeg_fee_multiplier`: optional, for NG pools with dynamic fees
+    /// - `rates`: optional JSON list of the stored rates, defaults to the decimal scaling
+    /// - `base_virtual_price`: optional, the rate of a meta pool's base LP token
+    ///
+    /// CryptoSwap attributes: `A` (`A * N^N * 10000`), `gamma`, `D`, `mid_fee`, `out_fee`,
+    /// `fee_gamma` and `price_scale`, a JSON list with the price of each coin after the first.
+    ///
+    /// The pool's balances are read from the optional `balances` JSON list attribute, which
+    /// excludes admin fees, and default to the component balances.
+    async fn try_from_with_header(
+        snapshot: ComponentWithState,
+        _block: BlockHeader,
+        _account_balances: &HashMap<Bytes, HashMap<Bytes, Bytes>>,
+        all_tokens: &HashMap<Bytes, Token>,
+    ) -> Result<Self, Self::Error> {
+        let id = snapshot.component.id.clone();
+        let tokens = snapshot.component.tokens.clone();
+        let n_coins = tokens.len();
+        if n_coins < 2 {
+            return Err(InvalidSnapshotError::ValueError(format!(
+                "Curve pool {id} has {n_coins} coins"
+            )));
+        }
+
+        let mut decimals = Vec::with_capacity(n_coins);
+        for token in &tokens {
+            let token_decimals = all_tokens
+                .get(token)
+                .ok_or_else(|| {
+                    InvalidSnapshotError::ValueError(format!("Token {token} not found"))
+                })?
+                .decimals;
+            if token_decimals > 18 {
+                return Err(InvalidSnapshotError::ValueError(format!(
+                    "Token {token} has more than 18 decimals"
+                )));
+            }
+            decimals.push(token_decimals);
+        }
+
+        let balances = match u256_list(&snapshot, "balances", n_coins)? {
+            Some(balances) => balances,
+            None => tokens
+                .iter()
+                .map(|token| {
+                    snapshot
+                        .state
+                        .balances
+                        .get(token)
+                        .map(|balance| U256::from_be_slice(balance))
+                        .unwrap_or_default()
+                })
+                .collect(),
+        };
+
+        let pool = if attribute(&snapshot, "gamma").is_some() {
+            if n_coins > 3 {
+                return Err(InvalidSnapshotError::ValueError(format!(
+                    "CryptoSwap pool {id} has {n_coins} coins"
+                )));
+            }
+            CurvePool::CryptoSwap {
+                ann: required_u256(&snapshot, "A")?,
+                gamma: required_u256(&snapshot, "gamma")?,
+                d: required_u256(&snapshot, "D")?,
+                price_scale: u256_list(&snapshot, "price_scale", n_coins - 1)?.ok_or_else(
+                    || InvalidSnapshotError::MissingAttribute("price_scale".to_string()),
+                )?,
+                mid_fee: required_u256(&snapshot, "mid_fee")?,
+                out_fee: required_u256(&snapshot, "out_fee")?,
+                fee_gamma: required_u256(&snapshot, "fee_gamma")?,
+            }
+        } else {
+            let mut rates = match u256_list(&snapshot, "rates", n_coins)? {
+                Some(rates) => rates,
+                None => decimals
+                    .iter()
+                    .map(|decimals| PRECISION * U256::from(10).pow(U256::from(18 - decimals)))
+                    .collect(),
+            };
+            if let Some(base_virtual_price) = attribute(&snapshot, "base_virtual_price") {
+                rates[n_coins - 1] = U256::from_be_slice(base_virtual_price);
+            }
+            CurvePool::StableSwap {
+                amp: required_u256(&snapshot, "A")?,
+                fee: required_u256(&snapshot, "fee")?,
+                offpeg_fee_multiplier: attribute(&snapshot, "offpeg_fee_multiplier")
+                    .map(|value| U256::from_be_slice(value))
+                    .unwrap_or_default(),
+                rates,
+            }
+        };
+
+        Ok(CurveState::new(id, tokens, balances, &decimals, pool))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, fs, path::Path, str::FromStr};
+
+    use num_bigint::BigUint;
+    use rstest::rstest;
+    use serde::Deserialize;
+    use tycho_common::{
+        dto::{ProtocolComponent, ResponseProtocolState},
+        models::Chain,
+        simulation::protocol_sim::ProtocolSim,
+    };
+
+    use super::*;
+
+    const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+    const WETH: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+
+    fn header() -> BlockHeader {
+        BlockHeader {
+            number: 1,
+            hash: Bytes::from(vec![0; 32]),
+            parent_hash: Bytes::from(vec![0; 32]),
+            revert: false,
+            timestamp: 1,
+        }
+    }
+
+    fn token(address: &Bytes, decimals: u32) -> Token {
+        Token::new(address, "T", decimals, 0, &[Some(10_000)], Chain::Ethereum, 100)
+    }
+
+    fn tokens() -> HashMap<Bytes, Token> {
+        [(USDC, 6), (WETH, 18)]
+            .into_iter()
+            .map(|(address, decimals)| {
+                let address = Bytes::from_str(address).unwrap();
+                (address.clone(), token(&address, decimals))
+            })
+            .collect()
+    }
+
+    fn u256_bytes(value: u128) -> Bytes {
+        Bytes::from(U256::from(value).to_be_bytes_vec())
+    }
+
+    fn snapshot(attributes: HashMap<String, Bytes>) -> ComponentWithState {
+        ComponentWithState {
+            state: ResponseProtocolState {
+                component_id: "0xpool".to_string(),
+                attributes,
+                balances: HashMap::from([
+                    (Bytes::from_str(USDC).unwrap(), u256_bytes(2_000_000_000_000)),
+                    (Bytes::from_str(WETH).unwrap(), u256_bytes(1_000_000_000_000_000_000_000)),
+                ]),
+            },
+            component: ProtocolComponent {
+                id: "0xpool".to_string(),
+                protocol_system: "vm:curve".to_string(),
+                tokens: vec![Bytes::from_str(USDC).unwrap(), Bytes::from_str(WETH).unwrap()],
+                ..Default::default()
+            },
+            component_tvl: None,
+            entrypoints: Vec::new(),
+        }
+    }
+
+    fn stableswap_attributes() -> HashMap<String, Bytes> {
+        HashMap::from([
+            ("A".to_string(), u256_bytes(20_000)),
+            ("fee".to_string(), u256_bytes(4_000_000)),
+        ])
+    }
+
+    fn cryptoswap_attributes() -> HashMap<String, Bytes> {
+        HashMap::from([
+            ("A".to_string(), u256_bytes(400_000)),
+            ("gamma".to_string(), u256_bytes(145_000_000_000_000)),
+            ("D".to_string(), u256_bytes(4_000_000_000_000_000_000_000_000)),
+            ("price_scale".to_string(), Bytes::from(r#"["0x6c6b935b8bbd400000"]"#.as_bytes())),
+            ("mid_fee".to_string(), u256_bytes(26_000_000)),
+            ("out_fee".to_string(), u256_bytes(45_000_000)),
+            ("fee_gamma".to_string(), u256_bytes(230_000_000_000_000)),
+        ])
+    }
+
+    fn expected_tokens() -> Vec<Bytes> {
+        vec![Bytes::from_str(USDC).unwrap(), Bytes::from_str(WETH).unwrap()]
+    }
+
+    fn expected_balances() -> Vec<U256> {
+        vec![U256::from(2_000_000_000_000u64), U256::from(1_000_000_000_000_000_000_000u128)]
+    }
+
+    #[tokio::test]
+    async fn test_curve_try_from_stableswap() {
+        let result = CurveState::try_from_with_header(
+            snapshot(stableswap_attributes()),
+            header(),
+            &HashMap::new(),
+            &tokens(),
+        )
+        .await
+        .unwrap();
+
+        let expected = CurveState::new(
+            "0xpool".to_string(),
+            expected_tokens(),
+            expected_balances(),
+            &[6, 18],
+            CurvePool::StableSwap {
+                amp: U256::from(20_000),
+                fee: U256::from(4_000_000),
+                offpeg_fee_multiplier: U256::ZERO,
+                rates: vec![PRECISION * U256::from(1_000_000_000_000u64), PRECISION],
+            },
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[tokio::test]
+    async fn test_curve_try_from_cryptoswap() {
+        let result = CurveState::try_from_with_header(
+            snapshot(cryptoswap_attributes()),
+            header(),
+            &HashMap::new(),
+            &tokens(),
+        )
+        .await
+        .unwrap();
+
+        let expected = CurveState::new(
+            "0xpool".to_string(),
+            expected_tokens(),
+            expected_balances(),
+            &[6, 18],
+            CurvePool::CryptoSwap {
+                ann: U256::from(400_000),
+                gamma: U256::from(145_000_000_000_000u64),
+                d: U256::from(4_000_000_000_000_000_000_000_000u128),
+                price_scale: vec![PRECISION * U256::from(2_000)],
+                mid_fee: U256::from(26_000_000),
+                out_fee: U256::from(45_000_000),
+                fee_gamma: U256::from(230_000_000_000_000u64),
+            },
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[tokio::test]
+    #[rstest]
+    #[case::missing_a("A")]
+    #[case::missing_d("D")]
+    #[case::missing_price_scale("price_scale")]
+    #[case::missing_fee_gamma("fee_gamma")]
+    async fn test_curve_try_from_missing_attribute(#[case] missing_attribute: &str) {
+        let mut attributes = cryptoswap_attributes();
+        attributes.remove(missing_attribute);
+
+        let result = CurveState::try_from_with_header(
+            snapshot(attributes),
+            header(),
+            &HashMap::new(),
+            &tokens(),
+        )
+        .await;
+
+        assert!(matches!(
+            result.unwrap_err(),
+            InvalidSnapshotError::MissingAttribute(attribute) if attribute == missing_attribute
+        ));
+    }
+
+    #[derive(Deserialize)]
+    struct FixtureToken {
+        address: Bytes,
+        decimals: u32,
+    }
+
+    #[derive(Deserialize)]
+    struct FixtureQuote {
+        token_in: Bytes,
+        token_out: Bytes,
+        amount_in: String,
+        /// The amount out returned by the VM adapter
+        amount_out: String,
+    }
+
+    /// A snapshot of a Curve pool with quotes recorded with `CurveSwapAdapter` at the same block.
+    #[derive(Deserialize)]
+    struct Fixture {
+        snapshot: ComponentWithState,
+        tokens: Vec<FixtureToken>,
+        quotes: Vec<FixtureQuote>,
+    }
+
+    /// Compares the native states to the VM adapter on the fixtures in `tests/assets/curve`,
+    /// which have to cover plain, meta, twocrypto and tricrypto pools.
+    #[tokio::test]
+    #[ignore] // Requires fixtures recorded with the `record_adapter_fixtures` example
+    async fn test_curve_matches_vm_adapter() {
+        let fixtures_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/assets/curve");
+        let mut checked = 0;
+        let mut pool_kinds = HashSet::new();
+        for entry in fs::read_dir(fixtures_dir).expect("Failed to read the fixtures directory") {
+            let path = entry.unwrap().path();
+            if path
+                .extension()
+                .is_none_or(|extension| extension != "json")
+            {
+                continue;
+            }
+            let fixture: Fixture =
+                serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+            let all_tokens: HashMap<Bytes, Token> = fixture
+                .tokens
+                .iter()
+                .map(|fixture_token| {
+                    (
+                        fixture_token.address.clone(),
+                        token(&fixture_token.address, fixture_token.decimals),
+                    )
+                })
+                .collect();
+            pool_kinds.insert(
+                match (
+                    attribute(&fixture.snapshot, "gamma").is_some(),
+                    attribute(&fixture.snapshot, "base_virtual_price").is_some(),
+                ) {
+                    (true, _) if fixture.snapshot.component.tokens.len() == 3 => "tricrypto",
+                    (true, _) => "twocrypto",
+                    (false, true) => "meta",
+                    (false, false) => "plain",
+                },
+            );
+
+            let state = CurveState::try_from_with_header(
+                fixture.snapshot,
+                header(),
+                &HashMap::new(),
+                &all_tokens,
+            )
+            .await
+            .unwrap_or_else(|err| panic!("Failed to decode {path:?}: {err:?}"));
+
+            for quote in fixture.quotes {
+                let result = state
+                    .get_amount_out(
+                        BigUint::from_str(&quote.amount_in).unwrap(),
+                        &all_tokens[&quote.token_in],
+                        &all_tokens[&quote.token_out],
+                    )
+                    .unwrap();
+                assert_eq!(
+                    result.amount,
+                    BigUint::from_str(&quote.amount_out).unwrap(),
+                    "{path:?}: {} {} -> {}",
+                    quote.amount_in,
+                    quote.token_in,
+                    quote.token_out
+                );
+                checked += 1;
+            }
+        }
+        assert!(checked > 0, "No fixtures found");
+        for kind in ["plain", "meta", "twocrypto", "tricrypto"] {
+            assert!(pool_kinds.contains(kind), "No fixtures of {kind} pools");
+        }
+    }
+}
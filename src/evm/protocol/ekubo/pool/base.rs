@@ -18,7 +18,7 @@ use tycho_common::{
     Bytes,
 };
 
-use super::{EkuboPool, EkuboPoolQuote};
+use super::{EkuboPool, EkuboPoolLimit, EkuboPoolQuote};
 use crate::{
     evm::protocol::ekubo::attributes::ticks_from_attributes, protocol::errors::InvalidSnapshotError,
 };
@@ -122,7 +122,7 @@ impl EkuboPool for BasePool {
         })
     }
 
-    fn get_limit(&self, token_in: U256) -> Result<i128, SimulationError> {
+    fn get_limit(&self, token_in: U256) -> Result<EkuboPoolLimit, SimulationError> {
         get_limit(token_in, self.sqrt_ratio(), &self.imp, self.state, (), identity)
     }
 
@@ -211,7 +211,7 @@ pub(super) fn get_limit<P, S, M, R>(
     state: S,
     meta: M,
     resources_fn: impl FnOnce(R) -> BasePoolResources,
-) -> Result<i128, SimulationError>
+) -> Result<EkuboPoolLimit, SimulationError>
 where
     P: Pool<State = S, Meta = M, Resources = R>,
 {
@@ -249,11 +249,17 @@ where
         .map_err(|err| SimulationError::RecoverableError(format!("quoting error: {err:?}")))?;
 
     let resources = resources_fn(quote.execution_resources);
-
-    Ok(quote.consumed_amount.saturating_sub(
-        WEI_UNDERESTIMATION_FACTOR *
-            (i128::from(resources.initialized_ticks_crossed) +
-                i128::from(resources.tick_spacings_crossed) / 256 +
-                1),
-    ))
+    let underestimation = WEI_UNDERESTIMATION_FACTOR *
+        (i128::from(resources.initialized_ticks_crossed) +
+            i128::from(resources.tick_spacings_crossed) / 256 +
+            1);
+
+    Ok(EkuboPoolLimit {
+        max_amount_in: quote
+            .consumed_amount
+            .saturating_sub(underestimation),
+        max_amount_out: quote
+            .calculated_amount
+            .saturating_sub(underestimation.unsigned_abs()),
+    })
 }
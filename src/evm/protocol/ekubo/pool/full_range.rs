@@ -13,7 +13,7 @@ use tycho_common::{
     Bytes,
 };
 
-use super::{EkuboPool, EkuboPoolQuote};
+use super::{EkuboPool, EkuboPoolLimit, EkuboPoolQuote};
 use crate::protocol::errors::InvalidSnapshotError;
 
 #[derive(Debug, Clone, Eq)]
@@ -81,8 +81,8 @@ impl EkuboPool for FullRangePool {
         })
     }
 
-    fn get_limit(&self, token_in: U256) -> Result<i128, SimulationError> {
-        Ok(self
+    fn get_limit(&self, token_in: U256) -> Result<EkuboPoolLimit, SimulationError> {
+        let quote = self
             .imp
             .quote(QuoteParams {
                 token_amount: TokenAmount { amount: i128::MAX, token: token_in },
@@ -90,8 +90,12 @@ impl EkuboPool for FullRangePool {
                 override_state: Some(self.state),
                 meta: (),
             })
-            .map_err(|err| SimulationError::RecoverableError(format!("quoting error: {err:?}")))?
-            .consumed_amount)
+            .map_err(|err| SimulationError::RecoverableError(format!("quoting error: {err:?}")))?;
+
+        Ok(EkuboPoolLimit {
+            max_amount_in: quote.consumed_amount,
+            max_amount_out: quote.calculated_amount,
+        })
     }
 
     fn finish_transition(
@@ -16,7 +16,7 @@ use tycho_common::{
     Bytes,
 };
 
-use super::{EkuboPool, EkuboPoolQuote};
+use super::{EkuboPool, EkuboPoolLimit, EkuboPoolQuote};
 use crate::{
     evm::protocol::ekubo::{
         attributes::ticks_from_attributes,
@@ -147,7 +147,7 @@ impl EkuboPool for MevResistPool {
         })
     }
 
-    fn get_limit(&self, token_in: U256) -> Result<i128, SimulationError> {
+    fn get_limit(&self, token_in: U256) -> Result<EkuboPoolLimit, SimulationError> {
         base::get_limit(
             token_in,
             self.sqrt_ratio(),
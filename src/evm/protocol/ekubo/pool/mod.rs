@@ -23,6 +23,12 @@ pub struct EkuboPoolQuote {
     pub new_state: EkuboState,
 }
 
+/// Largest swap in one direction, as quoted by selling as much as possible of the input token.
+pub struct EkuboPoolLimit {
+    pub max_amount_in: i128,
+    pub max_amount_out: u128,
+}
+
 #[enum_delegate::register]
 pub trait EkuboPool {
     fn key(&self) -> &NodeKey;
@@ -41,5 +47,5 @@ pub trait EkuboPool {
         &self,
         token_amount: TokenAmount,
     ) -> Result<super::pool::EkuboPoolQuote, SimulationError>;
-    fn get_limit(&self, token_in: U256) -> Result<i128, SimulationError>;
+    fn get_limit(&self, token_in: U256) -> Result<EkuboPoolLimit, SimulationError>;
 }
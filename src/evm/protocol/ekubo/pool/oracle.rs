@@ -13,7 +13,7 @@ use tycho_common::{
     Bytes,
 };
 
-use super::{full_range::FullRangePool, EkuboPool, EkuboPoolQuote};
+use super::{full_range::FullRangePool, EkuboPool, EkuboPoolLimit, EkuboPoolQuote};
 use crate::protocol::errors::InvalidSnapshotError;
 
 #[derive(Debug, Eq, Clone)]
@@ -22,6 +22,8 @@ pub struct OraclePool {
     state: OraclePoolState,
 
     swapped_this_block: bool,
+    /// Timestamp of the block swaps are simulated in, if set
+    target_timestamp: Option<u64>,
 }
 
 impl PartialEq for OraclePool {
@@ -47,8 +49,14 @@ impl OraclePool {
             })?,
             state,
             swapped_this_block: false,
+            target_timestamp: None,
         })
     }
+
+    /// Sets the timestamp of the block swaps are simulated in, or clears it with `None`.
+    pub fn set_target_timestamp(&mut self, timestamp: Option<u64>) {
+        self.target_timestamp = timestamp;
+    }
 }
 
 impl EkuboPool for OraclePool {
@@ -75,12 +83,13 @@ impl EkuboPool for OraclePool {
     }
 
     fn quote(&self, token_amount: TokenAmount) -> Result<EkuboPoolQuote, SimulationError> {
-        // Not actual timestamps but the Ekubo SDK only cares about the existence of time
-        // differences
-        let timestamp = if self.swapped_this_block {
-            self.state.last_snapshot_time
-        } else {
-            self.state.last_snapshot_time + 1
+        let timestamp = match self.target_timestamp {
+            // A snapshot is only written if no swap happened at the target timestamp yet
+            Some(timestamp) => Ord::max(timestamp, self.state.last_snapshot_time),
+            // Not actual timestamps but the Ekubo SDK only cares about the existence of time
+            // differences
+            None if self.swapped_this_block => self.state.last_snapshot_time,
+            None => self.state.last_snapshot_time + 1,
         };
 
         let quote = self
@@ -106,13 +115,14 @@ impl EkuboPool for OraclePool {
                 imp: self.imp.clone(),
                 state: quote.state_after,
                 swapped_this_block: true,
+                target_timestamp: self.target_timestamp,
             }
             .into(),
         })
     }
 
-    fn get_limit(&self, token_in: U256) -> Result<i128, SimulationError> {
-        Ok(self
+    fn get_limit(&self, token_in: U256) -> Result<EkuboPoolLimit, SimulationError> {
+        let quote = self
             .imp
             .quote(QuoteParams {
                 token_amount: TokenAmount { amount: i128::MAX, token: token_in },
@@ -120,8 +130,12 @@ impl EkuboPool for OraclePool {
                 override_state: Some(self.state),
                 meta: 0,
             })
-            .map_err(|err| SimulationError::RecoverableError(format!("quoting error: {err:?}")))?
-            .consumed_amount)
+            .map_err(|err| SimulationError::RecoverableError(format!("quoting error: {err:?}")))?;
+
+        Ok(EkuboPoolLimit {
+            max_amount_in: quote.consumed_amount,
+            max_amount_out: quote.calculated_amount,
+        })
     }
 
     fn finish_transition(
@@ -130,6 +144,7 @@ impl EkuboPool for OraclePool {
         _deleted_attributes: HashSet<String>,
     ) -> Result<(), TransitionError<String>> {
         self.swapped_this_block = false;
+        self.target_timestamp = None;
 
         Ok(())
     }
@@ -18,7 +18,7 @@ use tycho_common::{
     Bytes,
 };
 
-use super::{full_range::FullRangePool, EkuboPool, EkuboPoolQuote};
+use super::{full_range::FullRangePool, EkuboPool, EkuboPoolLimit, EkuboPoolQuote};
 use crate::{
     evm::protocol::ekubo::attributes::sale_rate_deltas_from_attributes,
     protocol::errors::InvalidSnapshotError,
@@ -30,6 +30,8 @@ pub struct TwammPool {
     state: TwammPoolState,
 
     swapped_this_block: bool,
+    /// Timestamp of the block swaps are simulated in, if set
+    target_timestamp: Option<u64>,
 }
 
 impl PartialEq for TwammPool {
@@ -76,15 +78,75 @@ impl TwammPool {
             })?,
             state,
             swapped_this_block: false,
+            target_timestamp: None,
+        })
+    }
+
+    /// Sets the timestamp of the block swaps are simulated in, up to which virtual orders are
+    /// executed, or clears it with `None`.
+    pub fn set_target_timestamp(&mut self, timestamp: Option<u64>) {
+        self.target_timestamp = timestamp;
+    }
+
+    pub fn state(&self) -> &TwammPoolState {
+        &self.state
+    }
+
+    /// Returns the sale rate changes after the last virtual order execution, by ascending time.
+    pub fn upcoming_sale_rate_deltas(&self) -> Vec<TwammSaleRateDelta> {
+        self.imp
+            .get_sale_rate_deltas()
+            .iter()
+            .filter(|delta| delta.time > self.state.last_execution_time)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the pool after executing the virtual orders up to `timestamp`.
+    ///
+    /// Swaps on the returned pool are simulated at `timestamp`.
+    pub fn project(&self, timestamp: u64) -> Result<Self, SimulationError> {
+        if timestamp < self.state.last_execution_time {
+            return Err(SimulationError::InvalidInput(
+                format!(
+                    "timestamp {timestamp} is before the last execution time {}",
+                    self.state.last_execution_time
+                ),
+                None,
+            ));
+        }
+
+        let quote = self
+            .imp
+            .quote(QuoteParams {
+                token_amount: TokenAmount { token: self.key().token0, amount: 0 },
+                sqrt_ratio_limit: None,
+                override_state: Some(self.state),
+                meta: timestamp,
+            })
+            .map_err(|err| {
+                SimulationError::RecoverableError(format!(
+                    "executing virtual orders quote: {err:?}"
+                ))
+            })?;
+
+        Ok(Self {
+            imp: self.imp.clone(),
+            state: quote.state_after,
+            swapped_this_block: false,
+            target_timestamp: Some(timestamp),
         })
     }
 
     fn estimate_block_timestamp(&self) -> u64 {
-        if self.swapped_this_block {
-            self.state.last_execution_time
-        } else {
-            // TODO How accurate is it to take the current timestamp?
-            Ord::max(self.state.last_execution_time + SLOT_DURATION_SECS, current_timestamp())
+        match self.target_timestamp {
+            // Swaps in the target block already executed the virtual orders up to it
+            Some(timestamp) => Ord::max(self.state.last_execution_time, timestamp),
+            None if self.swapped_this_block => self.state.last_execution_time,
+            // Without a target block, assumes the swap lands in the next slot
+            None => {
+                Ord::max(self.state.last_execution_time + SLOT_DURATION_SECS, current_timestamp())
+            }
         }
     }
 }
@@ -141,12 +203,13 @@ impl EkuboPool for TwammPool {
                 imp: self.imp.clone(),
                 state: quote.state_after,
                 swapped_this_block: true,
+                target_timestamp: self.target_timestamp,
             }
             .into(),
         })
     }
 
-    fn get_limit(&self, token_in: U256) -> Result<i128, SimulationError> {
+    fn get_limit(&self, token_in: U256) -> Result<EkuboPoolLimit, SimulationError> {
         let key = self.key();
         let estimated_timestamp = self.estimate_block_timestamp();
 
@@ -189,7 +252,7 @@ impl EkuboPool for TwammPool {
 
         // Quote with the less favorable state (either the current one or the one where future
         // virtual orders are already executed)
-        Ok(self
+        let quote = self
             .imp
             .quote(QuoteParams {
                 token_amount: TokenAmount { amount: i128::MAX, token: token_in },
@@ -197,8 +260,12 @@ impl EkuboPool for TwammPool {
                 override_state: Some(override_state),
                 meta,
             })
-            .map_err(|err| SimulationError::RecoverableError(format!("quoting error: {err:?}")))?
-            .consumed_amount)
+            .map_err(|err| SimulationError::RecoverableError(format!("quoting error: {err:?}")))?;
+
+        Ok(EkuboPoolLimit {
+            max_amount_in: quote.consumed_amount,
+            max_amount_out: quote.calculated_amount,
+        })
     }
 
     fn finish_transition(
@@ -275,6 +342,7 @@ impl EkuboPool for TwammPool {
         }
 
         self.swapped_this_block = false;
+        self.target_timestamp = None;
 
         Ok(())
     }
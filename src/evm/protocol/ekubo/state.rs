@@ -6,7 +6,10 @@ use std::{
 
 use evm_ekubo_sdk::{
     math::uint::U256,
-    quoting::types::{NodeKey, TokenAmount},
+    quoting::{
+        twamm_pool::TwammSaleRateDelta,
+        types::{NodeKey, TokenAmount},
+    },
 };
 use num_bigint::BigUint;
 use tycho_common::{
@@ -21,8 +24,16 @@ use tycho_common::{
 
 use super::pool::{
     base::BasePool, full_range::FullRangePool, oracle::OraclePool, twamm::TwammPool, EkuboPool,
+    EkuboPoolQuote,
+};
+use crate::{
+    evm::protocol::{ekubo::pool::mev_resist::MevResistPool, u256_num::u256_to_f64},
+    protocol::{
+        fee::SwapFee,
+        target_block::{TargetBlock, TimeDependent},
+        transfer_tax::{amount_after_tax, amount_before_tax},
+    },
 };
-use crate::evm::protocol::{ekubo::pool::mev_resist::MevResistPool, u256_num::u256_to_f64};
 
 #[enum_delegate::implement(EkuboPool)]
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -34,6 +45,22 @@ pub enum EkuboState {
     MevResist(MevResistPool),
 }
 
+/// A TWAMM pool after executing its virtual orders up to a future timestamp.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TwammProjection {
+    pub timestamp: u64,
+    /// Virtual reserves of token0 of the pool's full range liquidity
+    pub reserve0: BigUint,
+    /// Virtual reserves of token1 of the pool's full range liquidity
+    pub reserve1: BigUint,
+    /// Rate at which token0 is sold by the virtual orders, in tokens per second scaled by 2^32
+    pub token0_sale_rate: u128,
+    /// Rate at which token1 is sold by the virtual orders, in tokens per second scaled by 2^32
+    pub token1_sale_rate: u128,
+    /// The pool at `timestamp`, simulating swaps at that time
+    pub state: EkuboState,
+}
+
 fn sqrt_price_q128_to_f64(x: U256, (token0_decimals, token1_decimals): (usize, usize)) -> f64 {
     let token_correction = 10f64.powi(token0_decimals as i32 - token1_decimals as i32);
 
@@ -41,6 +68,122 @@ fn sqrt_price_q128_to_f64(x: U256, (token0_decimals, token1_decimals): (usize, u
     price.powi(2) * token_correction
 }
 
+/// Returns the virtual reserves of `liquidity` at the Q128 square root price `sqrt_ratio`.
+fn virtual_reserves(sqrt_ratio: U256, liquidity: u128) -> (BigUint, BigUint) {
+    let sqrt_ratio = BigUint::from_bytes_be(&sqrt_ratio.to_big_endian());
+    let liquidity = BigUint::from(liquidity);
+    if sqrt_ratio == BigUint::ZERO {
+        return (BigUint::ZERO, BigUint::ZERO);
+    }
+
+    ((&liquidity << 128u32) / &sqrt_ratio, (liquidity * sqrt_ratio) >> 128u32)
+}
+
+impl EkuboState {
+    /// Quotes an exact output swap, returning the amount of `token_in` to sell to receive
+    /// `amount_out` of `token_out`.
+    ///
+    /// The `amount` of the returned result is the amount in. If the pool can't provide the full
+    /// amount out, a `SimulationError::InvalidInput` is returned with the result of the largest
+    /// possible swap.
+    pub fn get_amount_in(
+        &self,
+        amount_out: BigUint,
+        token_in: &Token,
+        token_out: &Token,
+    ) -> Result<GetAmountOutResult, SimulationError> {
+        let amount_out = amount_before_tax(&amount_out, token_out).ok_or_else(|| {
+            SimulationError::InvalidInput(
+                format!("token {} takes the full amount out as tax", token_out.address),
+                None,
+            )
+        })?;
+        let quote = self.quote_amount(&amount_out, token_out, true)?;
+
+        let amount_in = amount_before_tax(&BigUint::from(quote.calculated_amount), token_in)
+            .ok_or_else(|| {
+                SimulationError::InvalidInput(
+                    format!("token {} takes the full amount in as tax", token_in.address),
+                    None,
+                )
+            })?;
+        let res = GetAmountOutResult {
+            amount: amount_in,
+            gas: quote.gas.into(),
+            new_state: Box::new(quote.new_state),
+        };
+
+        if BigUint::from(quote.consumed_amount.unsigned_abs()) != amount_out {
+            return Err(SimulationError::InvalidInput(
+                format!("pool does not have enough liquidity to support complete swap. output amount: {amount_out}, received amount: {received_amount}", received_amount = quote.consumed_amount.unsigned_abs()),
+                Some(res),
+            ));
+        }
+
+        Ok(res)
+    }
+
+    /// Projects a TWAMM pool to `timestamp` by executing its virtual orders up to then.
+    ///
+    /// Returns a `SimulationError::InvalidInput` if the pool is not a TWAMM pool or `timestamp`
+    /// is before the last virtual order execution.
+    pub fn project_twamm(&self, timestamp: u64) -> Result<TwammProjection, SimulationError> {
+        let Self::Twamm(pool) = self else {
+            return Err(SimulationError::InvalidInput("not a TWAMM pool".to_string(), None));
+        };
+        let pool = pool.project(timestamp)?;
+        let state = pool.state();
+        let (reserve0, reserve1) = virtual_reserves(
+            state.full_range_pool_state.sqrt_ratio,
+            state.full_range_pool_state.liquidity,
+        );
+
+        Ok(TwammProjection {
+            timestamp,
+            reserve0,
+            reserve1,
+            token0_sale_rate: state.token0_sale_rate,
+            token1_sale_rate: state.token1_sale_rate,
+            state: pool.into(),
+        })
+    }
+
+    /// Returns the upcoming sale rate changes of a TWAMM pool, by ascending time.
+    ///
+    /// Returns a `SimulationError::InvalidInput` if the pool is not a TWAMM pool.
+    pub fn twamm_sale_rate_deltas(&self) -> Result<Vec<TwammSaleRateDelta>, SimulationError> {
+        match self {
+            Self::Twamm(pool) => Ok(pool.upcoming_sale_rate_deltas()),
+            _ => Err(SimulationError::InvalidInput("not a TWAMM pool".to_string(), None)),
+        }
+    }
+
+    /// Quotes a swap specifying `amount` of `token`, as input or as output if `exact_out`.
+    ///
+    /// Amounts above `i128::MAX` are capped, the caller detects the partial swap by comparing the
+    /// consumed amount with `amount`.
+    fn quote_amount(
+        &self,
+        amount: &BigUint,
+        token: &Token,
+        exact_out: bool,
+    ) -> Result<EkuboPoolQuote, SimulationError> {
+        let amount = i128::try_from(amount).unwrap_or(i128::MAX);
+        let quote = self.quote(TokenAmount {
+            token: U256::from_big_endian(&token.address),
+            amount: if exact_out { -amount } else { amount },
+        })?;
+
+        if quote.calculated_amount > i128::MAX as u128 {
+            return Err(SimulationError::RecoverableError(
+                "calculated amount exceeds i128::MAX".to_string(),
+            ));
+        }
+
+        Ok(quote)
+    }
+}
+
 impl ProtocolSim for EkuboState {
     fn fee(&self) -> f64 {
         self.key().config.fee as f64 / (2f64.powi(64))
@@ -61,32 +204,20 @@ impl ProtocolSim for EkuboState {
         &self,
         amount_in: BigUint,
         token_in: &Token,
-        _token_out: &Token,
+        token_out: &Token,
     ) -> Result<GetAmountOutResult, SimulationError> {
-        let token_amount = TokenAmount {
-            token: U256::from_big_endian(&token_in.address),
-            amount: amount_in.try_into().map_err(|_| {
-                SimulationError::InvalidInput("amount in must fit into a i128".to_string(), None)
-            })?,
-        };
-
-        let quote = self.quote(token_amount)?;
-
-        if quote.calculated_amount > i128::MAX as u128 {
-            return Err(SimulationError::RecoverableError(
-                "calculated amount exceeds i128::MAX".to_string(),
-            ));
-        }
+        let amount_in = amount_after_tax(&amount_in, token_in);
+        let quote = self.quote_amount(&amount_in, token_in, false)?;
 
         let res = GetAmountOutResult {
-            amount: BigUint::from(quote.calculated_amount),
+            amount: amount_after_tax(&BigUint::from(quote.calculated_amount), token_out),
             gas: quote.gas.into(),
             new_state: Box::new(quote.new_state),
         };
 
-        if quote.consumed_amount != token_amount.amount {
+        if BigUint::try_from(quote.consumed_amount).ok() != Some(amount_in.clone()) {
             return Err(SimulationError::InvalidInput(
-                format!("pool does not have enough liquidity to support complete swap. input amount: {input_amount}, consumed amount: {consumed_amount}", input_amount = token_amount.amount, consumed_amount = quote.consumed_amount),
+                format!("pool does not have enough liquidity to support complete swap. input amount: {amount_in}, consumed amount: {consumed_amount}", consumed_amount = quote.consumed_amount),
                 Some(res),
             ));
         }
@@ -141,10 +272,35 @@ impl ProtocolSim for EkuboState {
         sell_token: Bytes,
         _buy_token: Bytes,
     ) -> Result<(BigUint, BigUint), SimulationError> {
-        let consumed_amount = self.get_limit(U256::from_big_endian(&sell_token))?;
+        let limit = self.get_limit(U256::from_big_endian(&sell_token))?;
+
+        Ok((
+            BigUint::try_from(limit.max_amount_in).unwrap_or_default(),
+            BigUint::from(limit.max_amount_out),
+        ))
+    }
+}
+
+impl SwapFee for EkuboState {
+    fn swap_fee(
+        &self,
+        _token_in: &Token,
+        _token_out: &Token,
+        _amount_in: Option<&BigUint>,
+    ) -> Result<f64, SimulationError> {
+        Ok(self.fee())
+    }
+}
 
-        // TODO Update once exact out is supported
-        Ok((BigUint::try_from(consumed_amount).unwrap_or_default(), BigUint::ZERO))
+impl TimeDependent for EkuboState {
+    fn set_target_block(&mut self, block: Option<TargetBlock>) {
+        let timestamp = block.map(|block| block.timestamp);
+        match self {
+            Self::Oracle(pool) => pool.set_target_timestamp(timestamp),
+            Self::Twamm(pool) => pool.set_target_timestamp(timestamp),
+            // Quotes of the other pools don't depend on the block time
+            Self::Base(_) | Self::FullRange(_) | Self::MevResist(_) => {}
+        }
     }
 }
 
@@ -187,6 +343,154 @@ mod tests {
         assert_eq!(res.amount, expected_out);
     }
 
+    #[apply(all_cases)]
+    fn test_get_amount_in(case: TestCase) {
+        let (token0, token1) = (case.token0(), case.token1());
+        let (amount_in, expected_out) = case.swap_token0;
+
+        let res = case
+            .state_after_transition
+            .get_amount_in(expected_out, &token0, &token1)
+            .expect("computing exact out quote");
+
+        // Selling `amount_in` yields `expected_out`, so at most `amount_in` is needed
+        assert!(res.amount <= amount_in);
+
+        // The amount in is the smallest one yielding `expected_out`
+        let amount_out = |amount_in: &BigUint| {
+            case.state_after_transition
+                .get_amount_out(amount_in.clone(), &token0, &token1)
+                .expect("computing quote")
+                .amount
+        };
+        assert!(res.amount > BigUint::ZERO);
+        assert!(amount_out(&res.amount) >= expected_out);
+        assert!(amount_out(&(&res.amount - 1u8)) < expected_out);
+    }
+
+    #[apply(all_cases)]
+    fn test_get_amount_in_partial_fill(case: TestCase) {
+        let (token0, token1) = (case.token0(), case.token1());
+
+        // Amounts above `i128::MAX` are capped and exceed the pool's liquidity
+        let res = case
+            .state_after_transition
+            .get_amount_in(BigUint::from(u128::MAX), &token0, &token1);
+
+        match res {
+            Err(SimulationError::InvalidInput(_, Some(partial))) => {
+                assert!(partial.amount > BigUint::ZERO)
+            }
+            res => panic!("Expected a partial fill, got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn test_twamm_target_block() {
+        let case = twamm();
+        let (token0, token1) = (case.token0(), case.token1());
+        let (amount_in, expected_out) = case.swap_token0;
+        let quote = |state: &EkuboState, target_block| {
+            let mut state = state.clone();
+            state.set_target_block(target_block);
+            state
+                .get_amount_out(amount_in.clone(), &token0, &token1)
+                .expect("computing quote")
+        };
+        let at_last_execution = Some(TargetBlock::new(1, 10));
+
+        // The test cases are quoted with the current time mocked to `TEST_TIMESTAMP`
+        let state = &case.state_after_transition;
+        assert_eq!(quote(state, Some(TargetBlock::new(1, TEST_TIMESTAMP))).amount, expected_out);
+        // No virtual orders are executed before swapping at the last execution time
+        assert_ne!(quote(state, at_last_execution).amount, expected_out);
+
+        // Clearing the target block quotes at the current time again
+        let mut targeted = state.clone();
+        targeted.set_target_block(at_last_execution);
+        assert_eq!(quote(&targeted, None).amount, expected_out);
+
+        // A later target block executes the virtual orders up to it even after a swap
+        let swapped = quote(state, at_last_execution).new_state;
+        let swapped = swapped
+            .as_any()
+            .downcast_ref::<EkuboState>()
+            .unwrap();
+        assert_ne!(
+            quote(swapped, Some(TargetBlock::new(2, TEST_TIMESTAMP))).amount,
+            quote(swapped, at_last_execution).amount
+        );
+    }
+
+    #[test]
+    fn test_twamm_transition_clears_target_block() {
+        let case = twamm();
+        let (token0, token1) = (case.token0(), case.token1());
+        let (amount_in, expected_out) = case.swap_token0;
+        let mut state = case.state_before_transition;
+        state.set_target_block(Some(TargetBlock::new(1, 10)));
+
+        state
+            .delta_transition(
+                ProtocolStateDelta {
+                    updated_attributes: case.transition_attributes,
+                    ..Default::default()
+                },
+                &HashMap::default(),
+                &Balances::default(),
+            )
+            .expect("executing transition");
+
+        let res = state
+            .get_amount_out(amount_in, &token0, &token1)
+            .expect("computing quote");
+        assert_eq!(res.amount, expected_out);
+    }
+
+    #[test]
+    fn test_project_twamm() {
+        let case = twamm();
+        let (token0, token1) = (case.token0(), case.token1());
+        let (amount_in, expected_out) = case.swap_token0;
+        let state = case.state_after_transition;
+
+        let deltas = state.twamm_sale_rate_deltas().unwrap();
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0].time, TEST_TIMESTAMP);
+
+        let now = state.project_twamm(10).unwrap();
+        let midway = state.project_twamm(500).unwrap();
+        // More token0 than token1 is sold, so token0 accumulates in the pool
+        assert!(midway.reserve0 > now.reserve0);
+        assert!(midway.reserve1 < now.reserve1);
+        assert_eq!(midway.token0_sale_rate, now.token0_sale_rate);
+
+        let end = state
+            .project_twamm(TEST_TIMESTAMP)
+            .unwrap();
+        assert_eq!(end.token0_sale_rate, 0);
+        assert_eq!(end.token1_sale_rate, 0);
+        assert!(end
+            .state
+            .twamm_sale_rate_deltas()
+            .unwrap()
+            .is_empty());
+        // The test cases are quoted with the current time mocked to `TEST_TIMESTAMP`
+        let res = end
+            .state
+            .get_amount_out(amount_in, &token0, &token1)
+            .unwrap();
+        assert_eq!(res.amount, expected_out);
+
+        assert!(matches!(state.project_twamm(0), Err(SimulationError::InvalidInput(..))));
+        assert!(matches!(
+            base()
+                .state_after_transition
+                .project_twamm(TEST_TIMESTAMP),
+            Err(SimulationError::InvalidInput(..))
+        ));
+    }
+
     #[apply(all_cases)]
     fn test_get_limits(case: TestCase) {
         use std::ops::Deref;
@@ -194,15 +498,17 @@ mod tests {
         let (token0, token1) = (case.token0(), case.token1());
         let state = case.state_after_transition;
 
-        let max_amount_in = state
+        let (max_amount_in, max_amount_out) = state
             .get_limits(token0.address.deref().into(), token1.address.deref().into())
-            .expect("computing limits for token0")
-            .0;
+            .expect("computing limits for token0");
 
         assert_eq!(max_amount_in, case.expected_limit_token0);
 
         state
             .get_amount_out(max_amount_in, &token0, &token1)
             .expect("quoting with limit");
+        state
+            .get_amount_in(max_amount_out, &token0, &token1)
+            .expect("quoting exact out with limit");
     }
 }
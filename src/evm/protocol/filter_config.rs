@@ -0,0 +1,381 @@
+//! Declarative client-side component filters
+//!
+//! A [`FilterConfig`] holds a [`ComponentFilterConfig`] per exchange and can be loaded from a
+//! TOML or JSON file, so that deny lists can be updated without changing code. For example:
+//!
+//! ```toml
+//! ["vm:balancer_v2"]
+//! deny_ids = ["0x848a5564158d84b8a8fb68ab5d004fae11619a5400000000000000000000066a"]
+//! min_tvl = 10.0
+//!
+//! [["vm:balancer_v2".attributes]]
+//! name = "pool_type"
+//! none_of = ["ComposableStablePoolFactory"]
+//! ```
+//!
+//! The filters complement the ones of [`filters`](super::filters) and are registered with
+//! `ProtocolStreamBuilder::filter_config` or `TychoStreamDecoder::add_filter`.
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::debug;
+use tycho_client::feed::synchronizer::ComponentWithState;
+use tycho_common::Bytes;
+
+#[derive(Error, Debug)]
+pub enum FilterConfigError {
+    #[error("Failed to read filter config: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid TOML filter config: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Invalid JSON filter config: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Unsupported filter config format: {0}")]
+    UnsupportedFormat(String),
+}
+
+/// Client-side filters by exchange.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FilterConfig {
+    pub exchanges: HashMap<String, ComponentFilterConfig>,
+}
+
+impl FilterConfig {
+    pub fn from_toml(config: &str) -> Result<Self, FilterConfigError> {
+        Ok(toml::from_str(config)?)
+    }
+
+    pub fn from_json(config: &str) -> Result<Self, FilterConfigError> {
+        Ok(serde_json::from_str(config)?)
+    }
+
+    /// Loads a filter config from a `.toml` or `.json` file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, FilterConfigError> {
+        let path = path.as_ref();
+        let config = fs::read_to_string(path)?;
+        match path
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("toml") => Self::from_toml(&config),
+            Some("json") => Self::from_json(&config),
+            _ => Err(FilterConfigError::UnsupportedFormat(path.display().to_string())),
+        }
+    }
+
+    /// Returns the filter of an exchange, if configured.
+    pub fn exchange(&self, name: &str) -> Option<&ComponentFilterConfig> {
+        self.exchanges.get(name)
+    }
+}
+
+/// Conditions a component has to meet to be included. Unset conditions are ignored.
+///
+/// Component ids are compared case-insensitively. Attribute values are given as `0x` prefixed
+/// hex strings, compared to the raw attribute bytes, or as plain strings, compared
+/// case-insensitively to UTF-8 encoded attributes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ComponentFilterConfig {
+    /// Components to exclude
+    pub deny_ids: HashSet<String>,
+    /// If not empty, only these components are included
+    pub allow_ids: HashSet<String>,
+    /// Excludes components whose `factory` static attribute is one of these
+    pub deny_factories: Vec<String>,
+    /// If not empty, only components whose `factory` static attribute is one of these are
+    /// included
+    pub allow_factories: Vec<String>,
+    /// Excludes components with any of these tokens
+    pub deny_tokens: HashSet<Bytes>,
+    /// If not empty, only components whose tokens are all in this set are included
+    pub allow_tokens: HashSet<Bytes>,
+    /// Minimum TVL, in the native token. Components without a TVL are included.
+    pub min_tvl: Option<f64>,
+    /// Conditions on the component's attributes, all of which must be met
+    pub attributes: Vec<AttributeRule>,
+}
+
+/// Which attributes of a component an [`AttributeRule`] applies to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttributeSource {
+    /// The static attributes of the protocol component
+    #[default]
+    Static,
+    /// The state attributes of the component
+    State,
+}
+
+/// A condition on an attribute of a component. Unset conditions are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AttributeRule {
+    pub name: String,
+    pub source: AttributeSource,
+    /// Whether the attribute must be present. If `false`, the component is excluded if the
+    /// attribute is present.
+    pub present: Option<bool>,
+    /// If not empty, the attribute must be present and have one of these values
+    pub one_of: Vec<String>,
+    /// Excludes components whose attribute has one of these values
+    pub none_of: Vec<String>,
+}
+
+impl AttributeRule {
+    fn matches(&self, component: &ComponentWithState) -> bool {
+        let attributes = match self.source {
+            AttributeSource::Static => &component.component.static_attributes,
+            AttributeSource::State => &component.state.attributes,
+        };
+        let value = attributes.get(&self.name);
+
+        if self
+            .present
+            .is_some_and(|present| present != value.is_some())
+        {
+            return false;
+        }
+        if !self.one_of.is_empty() &&
+            !value.is_some_and(|value| {
+                self.one_of
+                    .iter()
+                    .any(|expected| value_matches(expected, value))
+            })
+        {
+            return false;
+        }
+        !value.is_some_and(|value| {
+            self.none_of
+                .iter()
+                .any(|expected| value_matches(expected, value))
+        })
+    }
+}
+
+fn value_matches(expected: &str, value: &[u8]) -> bool {
+    if let Some(hex_value) = expected.strip_prefix("0x") {
+        if hex::decode(hex_value).is_ok_and(|expected| expected == value) {
+            return true;
+        }
+    }
+    std::str::from_utf8(value).is_ok_and(|value| value.eq_ignore_ascii_case(expected))
+}
+
+impl ComponentFilterConfig {
+    /// Returns whether `component` meets all conditions.
+    pub fn matches(&self, component: &ComponentWithState) -> bool {
+        let id = component.component.id.to_lowercase();
+        if self
+            .deny_ids
+            .iter()
+            .any(|denied| denied.eq_ignore_ascii_case(&id))
+        {
+            debug!("Filtering out component {id} because it is denied");
+            return false;
+        }
+        if !self.allow_ids.is_empty() &&
+            !self
+                .allow_ids
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(&id))
+        {
+            debug!("Filtering out component {id} because it is not allowed");
+            return false;
+        }
+
+        let factory = component
+            .component
+            .static_attributes
+            .get("factory");
+        let factory_in = |factories: &[String]| {
+            factory.is_some_and(|factory| {
+                factories
+                    .iter()
+                    .any(|expected| value_matches(expected, factory))
+            })
+        };
+        if factory_in(&self.deny_factories) {
+            debug!("Filtering out component {id} because its factory is denied");
+            return false;
+        }
+        if !self.allow_factories.is_empty() && !factory_in(&self.allow_factories) {
+            debug!("Filtering out component {id} because its factory is not allowed");
+            return false;
+        }
+
+        let tokens = &component.component.tokens;
+        if tokens
+            .iter()
+            .any(|token| self.deny_tokens.contains(token))
+        {
+            debug!("Filtering out component {id} because it has a denied token");
+            return false;
+        }
+        if !self.allow_tokens.is_empty() &&
+            !tokens
+                .iter()
+                .all(|token| self.allow_tokens.contains(token))
+        {
+            debug!("Filtering out component {id} because it has a token that is not allowed");
+            return false;
+        }
+
+        if let (Some(min_tvl), Some(tvl)) = (self.min_tvl, component.component_tvl) {
+            if tvl < min_tvl {
+                debug!("Filtering out component {id} because its TVL {tvl} is below {min_tvl}");
+                return false;
+            }
+        }
+
+        if let Some(rule) = self
+            .attributes
+            .iter()
+            .find(|rule| !rule.matches(component))
+        {
+            debug!("Filtering out component {id} because of its {} attribute", rule.name);
+            return false;
+        }
+
+        true
+    }
+
+    /// Returns a filter function including the components that meet all conditions.
+    pub fn into_filter(self) -> impl Fn(&ComponentWithState) -> bool + Send + Sync + 'static {
+        move |component| self.matches(component)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use tycho_common::dto::{ProtocolComponent, ResponseProtocolState};
+
+    use super::*;
+
+    const TOKEN_A: &str = "0x6b175474e89094c44da98b954eedeac495271d0f";
+    const TOKEN_B: &str = "0xba100000625a3754423978a60c9317c58a424e3d";
+
+    fn component(id: &str, pool_type: &str, tvl: Option<f64>) -> ComponentWithState {
+        ComponentWithState {
+            state: ResponseProtocolState {
+                component_id: id.to_string(),
+                attributes: HashMap::new(),
+                balances: HashMap::new(),
+            },
+            component: ProtocolComponent {
+                id: id.to_string(),
+                protocol_system: "vm:balancer_v2".to_string(),
+                tokens: vec![Bytes::from_str(TOKEN_A).unwrap(), Bytes::from_str(TOKEN_B).unwrap()],
+                static_attributes: HashMap::from([
+                    ("pool_type".to_string(), Bytes::from(pool_type.as_bytes().to_vec())),
+                    ("factory".to_string(), Bytes::from_str("0x01").unwrap()),
+                ]),
+                ..Default::default()
+            },
+            component_tvl: tvl,
+            entrypoints: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let config = FilterConfig::from_toml(
+            r#"
+            ["vm:balancer_v2"]
+            deny_ids = ["0xAB"]
+            deny_factories = ["0x02"]
+            min_tvl = 10.0
+
+            [["vm:balancer_v2".attributes]]
+            name = "pool_type"
+            none_of = ["ComposableStablePoolFactory"]
+            "#,
+        )
+        .unwrap();
+        let filter = config
+            .exchange("vm:balancer_v2")
+            .unwrap();
+
+        assert!(filter.matches(&component("0x01", "WeightedPoolFactory", Some(20.0))));
+        assert!(filter.matches(&component("0x01", "WeightedPoolFactory", None)));
+        assert!(!filter.matches(&component("0xab", "WeightedPoolFactory", Some(20.0))));
+        assert!(!filter.matches(&component("0x01", "WeightedPoolFactory", Some(5.0))));
+        assert!(!filter.matches(&component("0x01", "ComposableStablePoolFactory", Some(20.0))));
+    }
+
+    #[test]
+    fn test_from_json() {
+        let config = FilterConfig::from_json(&format!(
+            r#"{{
+                "vm:balancer_v2": {{
+                    "allow_ids": ["0x01"],
+                    "allow_factories": ["0x01"],
+                    "allow_tokens": ["{TOKEN_A}"],
+                    "attributes": [{{"name": "pool_type", "one_of": ["weightedpoolfactory"]}}]
+                }},
+                "vm:curve": {{"deny_tokens": ["{TOKEN_B}"]}}
+            }}"#
+        ))
+        .unwrap();
+
+        // TOKEN_B is not allowed
+        assert!(!config
+            .exchange("vm:balancer_v2")
+            .unwrap()
+            .matches(&component("0x01", "WeightedPoolFactory", None)));
+        assert!(!config
+            .exchange("vm:curve")
+            .unwrap()
+            .matches(&component("0x01", "WeightedPoolFactory", None)));
+    }
+
+    #[test]
+    fn test_attribute_rules() {
+        let rule = |present, one_of: &[&str], none_of: &[&str]| AttributeRule {
+            name: "pool_type".to_string(),
+            source: AttributeSource::Static,
+            present,
+            one_of: one_of
+                .iter()
+                .map(|value| value.to_string())
+                .collect(),
+            none_of: none_of
+                .iter()
+                .map(|value| value.to_string())
+                .collect(),
+        };
+        let component = component("0x01", "WeightedPoolFactory", None);
+
+        assert!(rule(Some(true), &[], &[]).matches(&component));
+        assert!(!rule(Some(false), &[], &[]).matches(&component));
+        assert!(rule(None, &["StablePoolFactory", "WeightedPoolFactory"], &[]).matches(&component));
+        assert!(!rule(None, &["StablePoolFactory"], &[]).matches(&component));
+        assert!(!rule(None, &[], &["WeightedPoolFactory"]).matches(&component));
+        // Hex values are compared to the raw bytes
+        assert!(rule(None, &[&format!("0x{}", hex::encode("WeightedPoolFactory"))], &[])
+            .matches(&component));
+        assert!(!AttributeRule { source: AttributeSource::State, ..rule(Some(true), &[], &[]) }
+            .matches(&component));
+    }
+
+    #[test]
+    fn test_from_file_unsupported_format() {
+        let file = tempfile::Builder::new()
+            .suffix(".yaml")
+            .tempfile()
+            .unwrap();
+
+        assert!(matches!(
+            FilterConfig::from_file(file.path()),
+            Err(FilterConfigError::UnsupportedFormat(_))
+        ));
+    }
+}
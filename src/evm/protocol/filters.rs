@@ -4,7 +4,9 @@ use num_bigint::BigInt;
 use tracing::{debug, info};
 use tycho_client::feed::synchronizer::ComponentWithState;
 
-use crate::evm::protocol::vm::utils::json_deserialize_be_bigint_list;
+use crate::evm::protocol::{
+    balancer_v2::is_supported_pool_type, vm::utils::json_deserialize_be_bigint_list,
+};
 
 const ZERO_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
 const ZERO_ADDRESS_ARR: [u8; 20] = [0u8; 20];
@@ -100,6 +102,54 @@ pub fn balancer_v2_pool_filter_pre_dci(component: &ComponentWithState) -> bool {
     true
 }
 
+/// Returns whether a Balancer pool has non-zero `rate_providers` but no `token_rates` attribute
+fn has_dynamic_rate_providers_without_rates(component: &ComponentWithState) -> bool {
+    let has_token_rates = component
+        .state
+        .attributes
+        .contains_key("token_rates");
+    let has_dynamic_rate_provider = component
+        .component
+        .static_attributes
+        .get("rate_providers")
+        .and_then(|rate_providers| std::str::from_utf8(rate_providers).ok())
+        .and_then(|rate_providers| serde_json::from_str::<Vec<String>>(rate_providers).ok())
+        .is_some_and(|rate_providers| {
+            rate_providers
+                .iter()
+                .any(|provider| provider != ZERO_ADDRESS)
+        });
+    has_dynamic_rate_provider && !has_token_rates
+}
+
+/// Filters out Balancer V2 pools that can't be simulated with the native `BalancerV2State`, i.e.
+/// pools that are neither weighted nor stable, or that have dynamic rate providers without
+/// `token_rates`
+pub fn balancer_v2_native_pool_filter(component: &ComponentWithState) -> bool {
+    let pool_type = component
+        .component
+        .static_attributes
+        .get("pool_type")
+        .and_then(|pool_type| std::str::from_utf8(pool_type).ok());
+    if !pool_type.is_some_and(is_supported_pool_type) {
+        debug!(
+            "Filtering out Balancer pool {} because its type {:?} isn't supported natively",
+            component.component.id, pool_type
+        );
+        return false;
+    }
+
+    if has_dynamic_rate_providers_without_rates(component) {
+        debug!(
+            "Filtering out Balancer pool {} because it has dynamic rate_providers without token_rates",
+            component.component.id
+        );
+        return false;
+    }
+
+    true
+}
+
 /// Filters out pools that have unsupported token types in Curve
 pub fn curve_pool_filter(component: &ComponentWithState) -> bool {
     if let Some(asset_types) = component
@@ -219,3 +269,42 @@ pub fn balancer_v3_pool_filter(component: &ComponentWithState) -> bool {
     }
     true
 }
+
+/// Filters out Balancer V3 pools that can't be simulated with the native `BalancerV3State`.
+///
+/// Unlike [`balancer_v3_pool_filter`], boosted pools are kept: their ERC4626 tokens are simulated
+/// with the `token_rates` indexed by Tycho, so pools with dynamic rate providers (including every
+/// ERC4626 pool) are only kept if they have `token_rates`.
+pub fn balancer_v3_native_pool_filter(component: &ComponentWithState) -> bool {
+    let pool_type = component
+        .component
+        .static_attributes
+        .get("pool_type")
+        .and_then(|pool_type| std::str::from_utf8(pool_type).ok());
+    if !pool_type.is_some_and(is_supported_pool_type) {
+        debug!(
+            "Filtering out Balancer V3 pool {} because its type {:?} isn't supported natively",
+            component.component.id, pool_type
+        );
+        return false;
+    }
+
+    let is_erc4626 = component
+        .component
+        .static_attributes
+        .get("erc4626")
+        .is_some_and(|erc4626| erc4626.to_vec() == [1u8]);
+    let has_token_rates = component
+        .state
+        .attributes
+        .contains_key("token_rates");
+    if (is_erc4626 && !has_token_rates) || has_dynamic_rate_providers_without_rates(component) {
+        debug!(
+            "Filtering out Balancer V3 pool {} because it has dynamic rates without token_rates",
+            component.component.id
+        );
+        return false;
+    }
+
+    true
+}
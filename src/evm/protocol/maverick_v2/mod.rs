@@ -0,0 +1,10 @@
+//! Maverick V2 pools
+//!
+//! `MaverickV2State` is not yet validated against the `vm:maverick_v2` adapter:
+//! `test_maverick_v2_matches_vm_adapter` stays ignored until fixtures with bins of every move
+//! mode (static, right, left and both) are recorded with the `record_adapter_fixtures` example.
+//! CI runs it as soon as fixtures are committed. Until it passes, keep decoding `vm:maverick_v2`
+//! components into `EVMPoolState`.
+pub mod state;
+mod tick_math;
+mod tycho_decoder;
@@ -0,0 +1,1005 @@
+use std::{
+    any::Any,
+    collections::{BTreeMap, HashMap},
+};
+
+use alloy::primitives::U256;
+use num_bigint::BigUint;
+use tycho_common::{
+    dto::ProtocolStateDelta,
+    models::token::Token,
+    simulation::{
+        errors::{SimulationError, TransitionError},
+        protocol_sim::{Balances, GetAmountOutResult, ProtocolSim},
+    },
+    Bytes,
+};
+
+use super::tick_math::{
+    mul_div, mul_div_up, tick_liquidity, tick_sqrt_prices, virtual_reserves, PRECISION,
+};
+use crate::{
+    evm::protocol::{
+        safe_math::{safe_add_u256, safe_div_u256, safe_mul_u256, safe_sub_u256},
+        u256_num::{biguint_to_u256, u256_to_biguint, u256_to_f64},
+    },
+    protocol::{
+        fee::SwapFee,
+        target_block::{TargetBlock, TimeDependent},
+        transfer_tax::amount_after_tax,
+    },
+};
+
+const BASE_GAS: u64 = 110_000;
+const GAS_PER_TICK: u64 = 20_000;
+const PROTOCOL_FEE_PRECISION: U256 = U256::from_limbs([1_000, 0, 0, 0]);
+/// Log prices are expressed in ticks, scaled by 2^8.
+const LOG_PRICE_SCALE: i64 = 256;
+
+/// How the liquidity of a bin follows the price.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinKind {
+    /// Never moves.
+    Static,
+    /// Follows the price when it moves up.
+    Right,
+    /// Follows the price when it moves down.
+    Left,
+    /// Follows the price in both directions.
+    Both,
+}
+
+impl TryFrom<u8> for BinKind {
+    type Error = String;
+
+    fn try_from(kind: u8) -> Result<Self, Self::Error> {
+        match kind {
+            0 => Ok(BinKind::Static),
+            1 => Ok(BinKind::Right),
+            2 => Ok(BinKind::Left),
+            3 => Ok(BinKind::Both),
+            _ => Err(format!("Unknown bin kind {kind}")),
+        }
+    }
+}
+
+/// Reserves of a tick, scaled to 18 decimals.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MaverickTick {
+    pub reserve_a: U256,
+    pub reserve_b: U256,
+    /// Sum of the `tick_balance` of the bins in the tick.
+    pub total_supply: U256,
+}
+
+/// A liquidity position that owns `tick_balance / total_supply` of its tick's reserves.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MaverickBin {
+    pub kind: BinKind,
+    pub tick: i32,
+    pub tick_balance: U256,
+}
+
+/// State of a Maverick V2 pool.
+///
+/// Token A is the token with the lower address. Liquidity is held in bins, each bin sitting in a
+/// tick. Swaps consume the reserves of the ticks one after another, starting at the active tick.
+///
+/// Bins of a kind other than `Static` move with the time weighted average (TWA) price: when a swap
+/// moves the TWA into another tick, the movable bins the TWA passed are merged into the bin of the
+/// same kind at the new TWA tick, carrying their share of the reserves along. Within a block the
+/// TWA doesn't move, so only the first swap simulated after `last_timestamp` can move bins.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MaverickV2State {
+    /// Fee charged on token A amounts in, scaled by 1e18.
+    pub fee_a_in: U256,
+    /// Fee charged on token B amounts in, scaled by 1e18.
+    pub fee_b_in: U256,
+    /// Share of the fees that goes to the protocol instead of the ticks, scaled by 1e3.
+    pub protocol_fee_ratio_d3: U256,
+    pub tick_spacing: u32,
+    /// Period in seconds over which the TWA catches up with the price.
+    pub lookback: u64,
+    scale_a: U256,
+    scale_b: U256,
+    pub active_tick: i32,
+    pub last_twa_d8: i64,
+    pub last_log_price_d8: i64,
+    pub last_timestamp: u64,
+    /// Timestamp swaps are simulated at without a target block.
+    pub timestamp: u64,
+    /// Timestamp of the target block, overriding `timestamp` if set.
+    target_timestamp: Option<u64>,
+    pub ticks: BTreeMap<i32, MaverickTick>,
+    pub bins: BTreeMap<u32, MaverickBin>,
+}
+
+/// Result of a swap through the ticks, in 18 decimal amounts.
+struct SwapResult {
+    amount_in: U256,
+    amount_out: U256,
+    ticks_crossed: u64,
+}
+
+impl MaverickV2State {
+    /// Creates a new instance of `MaverickV2State` without any liquidity.
+    ///
+    /// # Arguments
+    ///
+    /// * `fee_a_in` - Fee on token A amounts in, scaled by 1e18.
+    /// * `fee_b_in` - Fee on token B amounts in, scaled by 1e18.
+    /// * `tick_spacing` - Tick spacing of the pool.
+    /// * `lookback` - TWA lookback period in seconds.
+    /// * `decimals` - Decimals of token A and token B.
+    /// * `active_tick` - The tick the price is in.
+    /// * `timestamp` - Timestamp swaps are simulated at.
+    pub fn new(
+        fee_a_in: U256,
+        fee_b_in: U256,
+        tick_spacing: u32,
+        lookback: u64,
+        decimals: (u32, u32),
+        active_tick: i32,
+        timestamp: u64,
+    ) -> Result<Self, SimulationError> {
+        let scale = |decimals: u32| {
+            18u32
+                .checked_sub(decimals)
+                .map(|exponent| U256::from(10).pow(U256::from(exponent)))
+                .ok_or_else(|| {
+                    SimulationError::InvalidInput(
+                        format!("Tokens with {decimals} decimals are not supported"),
+                        None,
+                    )
+                })
+        };
+        Ok(MaverickV2State {
+            fee_a_in,
+            fee_b_in,
+            protocol_fee_ratio_d3: U256::ZERO,
+            tick_spacing,
+            lookback,
+            scale_a: scale(decimals.0)?,
+            scale_b: scale(decimals.1)?,
+            active_tick,
+            last_twa_d8: i64::from(active_tick) * LOG_PRICE_SCALE,
+            last_log_price_d8: i64::from(active_tick) * LOG_PRICE_SCALE,
+            last_timestamp: timestamp,
+            timestamp,
+            target_timestamp: None,
+            ticks: BTreeMap::new(),
+            bins: BTreeMap::new(),
+        })
+    }
+
+    /// Sets the state attribute `name` as indexed by Tycho.
+    ///
+    /// Supports `active_tick`, `last_twa_d8`, `last_log_price_d8`, `last_timestamp`, the fees,
+    /// `ticks/{tick}/{reserve_a|reserve_b|total_supply}` and `bins/{id}/{kind|tick|tick_balance}`.
+    /// Unknown attributes are ignored.
+    pub(super) fn set_attribute(&mut self, name: &str, value: &Bytes) -> Result<(), String> {
+        match name {
+            "active_tick" => self.active_tick = decode_i32(value)?,
+            "last_twa_d8" => self.last_twa_d8 = decode_i64(value),
+            "last_log_price_d8" => self.last_log_price_d8 = decode_i64(value),
+            "last_timestamp" => {
+                self.last_timestamp = decode_u64(value)?;
+                self.timestamp = self.timestamp.max(self.last_timestamp);
+            }
+            "fee_a_in" => self.fee_a_in = U256::from_be_slice(value),
+            "fee_b_in" => self.fee_b_in = U256::from_be_slice(value),
+            "protocol_fee_ratio_d3" => self.protocol_fee_ratio_d3 = U256::from_be_slice(value),
+            _ => {
+                let parts: Vec<&str> = name.split('/').collect();
+                match parts.as_slice() {
+                    ["ticks", tick, field] => {
+                        let tick = tick
+                            .parse::<i32>()
+                            .map_err(|err| format!("Invalid tick in {name}: {err}"))?;
+                        let tick = self.ticks.entry(tick).or_default();
+                        let value = U256::from_be_slice(value);
+                        match *field {
+                            "reserve_a" => tick.reserve_a = value,
+                            "reserve_b" => tick.reserve_b = value,
+                            "total_supply" => tick.total_supply = value,
+                            _ => {}
+                        }
+                    }
+                    ["bins", id, field] => {
+                        let id = id
+                            .parse::<u32>()
+                            .map_err(|err| format!("Invalid bin id in {name}: {err}"))?;
+                        let bin = self
+                            .bins
+                            .entry(id)
+                            .or_insert(MaverickBin {
+                                kind: BinKind::Static,
+                                tick: 0,
+                                tick_balance: U256::ZERO,
+                            });
+                        match *field {
+                            "kind" => bin.kind = BinKind::try_from(decode_u8(value)?)?,
+                            "tick" => bin.tick = decode_i32(value)?,
+                            "tick_balance" => bin.tick_balance = U256::from_be_slice(value),
+                            _ => {}
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the tick or bin of a deleted `ticks/...` or `bins/...` attribute.
+    fn delete_attribute(&mut self, name: &str) {
+        let parts: Vec<&str> = name.split('/').collect();
+        match parts.as_slice() {
+            ["ticks", tick, _] => {
+                if let Ok(tick) = tick.parse::<i32>() {
+                    self.ticks.remove(&tick);
+                }
+            }
+            ["bins", id, _] => {
+                if let Ok(id) = id.parse::<u32>() {
+                    self.bins.remove(&id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn scales(&self, a_in: bool) -> (U256, U256) {
+        if a_in {
+            (self.scale_a, self.scale_b)
+        } else {
+            (self.scale_b, self.scale_a)
+        }
+    }
+
+    fn fee_in(&self, a_in: bool) -> U256 {
+        if a_in {
+            self.fee_a_in
+        } else {
+            self.fee_b_in
+        }
+    }
+
+    /// Returns the timestamp swaps are simulated at, the target block's if set.
+    fn simulation_timestamp(&self) -> u64 {
+        self.target_timestamp
+            .unwrap_or(self.timestamp)
+            .max(self.last_timestamp)
+    }
+
+    /// Returns the TWA at the simulation timestamp, which moves from `last_twa_d8` towards
+    /// `last_log_price_d8` linearly over the lookback period.
+    fn twa_d8(&self) -> i64 {
+        let elapsed = self
+            .simulation_timestamp()
+            .saturating_sub(self.last_timestamp);
+        if elapsed >= self.lookback {
+            return self.last_log_price_d8;
+        }
+        let delta = i128::from(self.last_log_price_d8 - self.last_twa_d8) * i128::from(elapsed) /
+            i128::from(self.lookback);
+        self.last_twa_d8 + delta as i64
+    }
+
+    /// Returns the next tick from `tick` on, in the direction of the swap, holding reserves of
+    /// the token out.
+    fn next_tick(&self, tick: i32, a_in: bool) -> Option<i32> {
+        let has_reserve_out = |state: &MaverickTick| {
+            if a_in {
+                !state.reserve_b.is_zero()
+            } else {
+                !state.reserve_a.is_zero()
+            }
+        };
+        if a_in {
+            self.ticks
+                .range(..=tick)
+                .rev()
+                .find(|(_, state)| has_reserve_out(state))
+                .map(|(tick, _)| *tick)
+        } else {
+            self.ticks
+                .range(tick..)
+                .find(|(_, state)| has_reserve_out(state))
+                .map(|(tick, _)| *tick)
+        }
+    }
+
+    /// Swaps up to `remaining` through `tick`, returns the amounts in and out and whether the
+    /// reserves of the token out are exhausted. A `remaining` of `None` exhausts the tick.
+    fn swap_tick(
+        &mut self,
+        tick: i32,
+        a_in: bool,
+        remaining: Option<U256>,
+    ) -> Result<(U256, U256, bool), SimulationError> {
+        let fee = self.fee_in(a_in);
+        let (sqrt_lower, sqrt_upper) = tick_sqrt_prices(self.tick_spacing, tick)?;
+        let state = self
+            .ticks
+            .get_mut(&tick)
+            .ok_or_else(|| SimulationError::FatalError(format!("Missing tick {tick}")))?;
+        let liquidity = tick_liquidity(state.reserve_a, state.reserve_b, sqrt_lower, sqrt_upper)?;
+        let (x, y) =
+            virtual_reserves(state.reserve_a, state.reserve_b, liquidity, sqrt_lower, sqrt_upper)?;
+        let (virtual_in, reserve_out, virtual_in_at_end) = if a_in {
+            (x, state.reserve_b, mul_div_up(liquidity, PRECISION, sqrt_lower)?)
+        } else {
+            (y, state.reserve_a, mul_div_up(liquidity, sqrt_upper, PRECISION)?)
+        };
+
+        let max_net_in = virtual_in_at_end.saturating_sub(virtual_in);
+        let max_amount_in = mul_div_up(max_net_in, PRECISION, safe_sub_u256(PRECISION, fee)?)?;
+        let (amount_in, net_amount_in, amount_out, exhausted) = match remaining {
+            Some(remaining) if remaining < max_amount_in => {
+                let net_amount_in =
+                    safe_sub_u256(remaining, mul_div_up(remaining, fee, PRECISION)?)?;
+                // x * y = L^2 is kept, rounding in favour of the pool
+                let virtual_out = if a_in { y } else { x };
+                let new_virtual_out =
+                    mul_div_up(liquidity, liquidity, safe_add_u256(virtual_in, net_amount_in)?)?;
+                let amount_out = virtual_out
+                    .saturating_sub(new_virtual_out)
+                    .min(reserve_out);
+                (remaining, net_amount_in, amount_out, false)
+            }
+            _ => (max_amount_in, max_net_in, reserve_out, true),
+        };
+
+        let protocol_fee = mul_div(
+            safe_sub_u256(amount_in, net_amount_in)?,
+            self.protocol_fee_ratio_d3,
+            PROTOCOL_FEE_PRECISION,
+        )?;
+        let added_in = safe_sub_u256(amount_in, protocol_fee)?;
+        if a_in {
+            state.reserve_a = safe_add_u256(state.reserve_a, added_in)?;
+            state.reserve_b = safe_sub_u256(state.reserve_b, amount_out)?;
+        } else {
+            state.reserve_b = safe_add_u256(state.reserve_b, added_in)?;
+            state.reserve_a = safe_sub_u256(state.reserve_a, amount_out)?;
+        }
+        Ok((amount_in, amount_out, exhausted))
+    }
+
+    /// Swaps `amount_in` through the ticks, or until all ticks are exhausted if `None`.
+    fn swap(&mut self, a_in: bool, amount_in: Option<U256>) -> Result<SwapResult, SimulationError> {
+        let mut result =
+            SwapResult { amount_in: U256::ZERO, amount_out: U256::ZERO, ticks_crossed: 0 };
+        let mut tick = self.active_tick;
+        while let Some(next_tick) = self.next_tick(tick, a_in) {
+            let remaining = amount_in
+                .map(|amount_in| safe_sub_u256(amount_in, result.amount_in))
+                .transpose()?;
+            if remaining.is_some_and(|remaining| remaining.is_zero()) {
+                break;
+            }
+            self.active_tick = next_tick;
+            let (tick_amount_in, tick_amount_out, exhausted) =
+                self.swap_tick(next_tick, a_in, remaining)?;
+            result.amount_in = safe_add_u256(result.amount_in, tick_amount_in)?;
+            result.amount_out = safe_add_u256(result.amount_out, tick_amount_out)?;
+            if !exhausted {
+                break;
+            }
+            result.ticks_crossed += 1;
+            tick = if a_in { next_tick - 1 } else { next_tick + 1 };
+        }
+        self.last_log_price_d8 = self.log_price_d8()?;
+        Ok(result)
+    }
+
+    /// Returns the sqrt price of the pool, i.e. within the active tick.
+    fn sqrt_price(&self) -> Result<U256, SimulationError> {
+        let (sqrt_lower, sqrt_upper) = tick_sqrt_prices(self.tick_spacing, self.active_tick)?;
+        let Some(state) = self
+            .ticks
+            .get(&self.active_tick)
+            .filter(|state| !state.reserve_a.is_zero() || !state.reserve_b.is_zero())
+        else {
+            return Ok(sqrt_lower);
+        };
+        let liquidity = tick_liquidity(state.reserve_a, state.reserve_b, sqrt_lower, sqrt_upper)?;
+        let (x, _) =
+            virtual_reserves(state.reserve_a, state.reserve_b, liquidity, sqrt_lower, sqrt_upper)?;
+        mul_div(liquidity, PRECISION, x)
+    }
+
+    /// Returns the log price, i.e. the active tick plus the position of the price within it.
+    fn log_price_d8(&self) -> Result<i64, SimulationError> {
+        let (sqrt_lower, sqrt_upper) = tick_sqrt_prices(self.tick_spacing, self.active_tick)?;
+        let sqrt_price = self
+            .sqrt_price()?
+            .clamp(sqrt_lower, sqrt_upper);
+        let fraction = mul_div(
+            sqrt_price - sqrt_lower,
+            U256::from(LOG_PRICE_SCALE - 1),
+            sqrt_upper - sqrt_lower,
+        )?;
+        Ok(i64::from(self.active_tick) * LOG_PRICE_SCALE + fraction.to::<i64>())
+    }
+
+    /// Moves the movable bins the TWA passed when moving from `from_tick` to `to_tick`.
+    fn move_bins(&mut self, from_tick: i32, to_tick: i32) -> Result<(), SimulationError> {
+        let moving: Vec<u32> = self
+            .bins
+            .iter()
+            .filter(|(_, bin)| {
+                if to_tick > from_tick {
+                    matches!(bin.kind, BinKind::Right | BinKind::Both) &&
+                        (from_tick..to_tick).contains(&bin.tick)
+                } else {
+                    matches!(bin.kind, BinKind::Left | BinKind::Both) &&
+                        (to_tick + 1..=from_tick).contains(&bin.tick)
+                }
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        for id in moving {
+            self.move_bin(id, to_tick)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the bin `id` and its share of its tick's reserves to `to_tick`, merging it into the
+    /// bin of the same kind there if there is one.
+    fn move_bin(&mut self, id: u32, to_tick: i32) -> Result<(), SimulationError> {
+        let Some(bin) = self.bins.get(&id).cloned() else {
+            return Ok(());
+        };
+        let (amount_a, amount_b) = match self.ticks.get_mut(&bin.tick) {
+            Some(source) if !source.total_supply.is_zero() => {
+                let amount_a = mul_div(source.reserve_a, bin.tick_balance, source.total_supply)?;
+                let amount_b = mul_div(source.reserve_b, bin.tick_balance, source.total_supply)?;
+                source.reserve_a = safe_sub_u256(source.reserve_a, amount_a)?;
+                source.reserve_b = safe_sub_u256(source.reserve_b, amount_b)?;
+                source.total_supply = source
+                    .total_supply
+                    .saturating_sub(bin.tick_balance);
+                (amount_a, amount_b)
+            }
+            _ => (U256::ZERO, U256::ZERO),
+        };
+
+        let (sqrt_lower, sqrt_upper) = tick_sqrt_prices(self.tick_spacing, to_tick)?;
+        let destination = self.ticks.entry(to_tick).or_default();
+        let liquidity_before =
+            tick_liquidity(destination.reserve_a, destination.reserve_b, sqrt_lower, sqrt_upper)?;
+        destination.reserve_a = safe_add_u256(destination.reserve_a, amount_a)?;
+        destination.reserve_b = safe_add_u256(destination.reserve_b, amount_b)?;
+        let liquidity_after =
+            tick_liquidity(destination.reserve_a, destination.reserve_b, sqrt_lower, sqrt_upper)?;
+        let tick_balance = if destination.total_supply.is_zero() || liquidity_before.is_zero() {
+            liquidity_after
+        } else {
+            mul_div(
+                destination.total_supply,
+                safe_sub_u256(liquidity_after, liquidity_before)?,
+                liquidity_before,
+            )?
+        };
+        destination.total_supply = safe_add_u256(destination.total_supply, tick_balance)?;
+
+        let merge_into = self
+            .bins
+            .iter()
+            .find(|(other_id, other)| {
+                **other_id != id && other.kind == bin.kind && other.tick == to_tick
+            })
+            .map(|(other_id, _)| *other_id);
+        match merge_into {
+            Some(other_id) => {
+                self.bins.remove(&id);
+                if let Some(other) = self.bins.get_mut(&other_id) {
+                    other.tick_balance = safe_add_u256(other.tick_balance, tick_balance)?;
+                }
+            }
+            None => {
+                self.bins
+                    .insert(id, MaverickBin { kind: bin.kind, tick: to_tick, tick_balance });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn decode_i64(value: &Bytes) -> i64 {
+    // Sign extend big endian two's complement values of any length
+    let fill = if value
+        .first()
+        .is_some_and(|byte| byte & 0x80 != 0)
+    {
+        0xff
+    } else {
+        0
+    };
+    let mut bytes = [fill; 8];
+    let len = value.len().min(8);
+    bytes[8 - len..].copy_from_slice(&value[value.len() - len..]);
+    i64::from_be_bytes(bytes)
+}
+
+fn decode_i32(value: &Bytes) -> Result<i32, String> {
+    let decoded = decode_i64(value);
+    i32::try_from(decoded).map_err(|_| format!("Value {decoded} out of range"))
+}
+
+fn decode_u64(value: &Bytes) -> Result<u64, String> {
+    u64::try_from(U256::from_be_slice(value)).map_err(|_| format!("Value {value} out of range"))
+}
+
+fn decode_u8(value: &Bytes) -> Result<u8, String> {
+    u8::try_from(U256::from_be_slice(value)).map_err(|_| format!("Value {value} out of range"))
+}
+
+impl ProtocolSim for MaverickV2State {
+    /// Returns the highest of the two directional fees.
+    fn fee(&self) -> f64 {
+        u256_to_f64(self.fee_a_in.max(self.fee_b_in)) / 1e18
+    }
+
+    fn spot_price(&self, base: &Token, quote: &Token) -> Result<f64, SimulationError> {
+        // Amounts are scaled to 18 decimals, so the sqrt price is not affected by the decimals
+        let sqrt_price = u256_to_f64(self.sqrt_price()?) / 1e18;
+        let price = sqrt_price * sqrt_price;
+        if base.address < quote.address {
+            Ok(price)
+        } else {
+            Ok(1.0 / price)
+        }
+    }
+
+    fn get_amount_out(
+        &self,
+        amount_in: BigUint,
+        token_in: &Token,
+        token_out: &Token,
+    ) -> Result<GetAmountOutResult, SimulationError> {
+        let a_in = token_in.address < token_out.address;
+        let (scale_in, scale_out) = self.scales(a_in);
+        let amount_in =
+            safe_mul_u256(biguint_to_u256(&amount_after_tax(&amount_in, token_in)), scale_in)?;
+        if amount_in.is_zero() {
+            return Err(SimulationError::InvalidInput("Amount in cannot be zero".to_string(), None));
+        }
+
+        let mut new_state = self.clone();
+        let twa_d8 = self.twa_d8();
+        let swap = new_state.swap(a_in, Some(amount_in))?;
+        new_state.move_bins(
+            self.last_twa_d8
+                .div_euclid(LOG_PRICE_SCALE) as i32,
+            twa_d8.div_euclid(LOG_PRICE_SCALE) as i32,
+        )?;
+        new_state.last_twa_d8 = twa_d8;
+        new_state.last_timestamp = self.simulation_timestamp();
+
+        let amount_out = safe_div_u256(swap.amount_out, scale_out)?;
+        let gas = BASE_GAS + GAS_PER_TICK * swap.ticks_crossed;
+        let result = GetAmountOutResult::new(
+            amount_after_tax(&u256_to_biguint(amount_out), token_out),
+            BigUint::from(gas),
+            Box::new(new_state),
+        );
+        if swap.amount_in < amount_in {
+            return Err(SimulationError::InvalidInput("Ticks exceeded".to_string(), Some(result)));
+        }
+        Ok(result)
+    }
+
+    fn get_limits(
+        &self,
+        sell_token: Bytes,
+        buy_token: Bytes,
+    ) -> Result<(BigUint, BigUint), SimulationError> {
+        let a_in = sell_token < buy_token;
+        let (scale_in, scale_out) = self.scales(a_in);
+        let swap = self.clone().swap(a_in, None)?;
+        Ok((
+            u256_to_biguint(safe_div_u256(swap.amount_in, scale_in)?),
+            u256_to_biguint(safe_div_u256(swap.amount_out, scale_out)?),
+        ))
+    }
+
+    fn delta_transition(
+        &mut self,
+        delta: ProtocolStateDelta,
+        _tokens: &HashMap<Bytes, Token>,
+        _balances: &Balances,
+    ) -> Result<(), TransitionError<String>> {
+        for name in &delta.deleted_attributes {
+            self.delete_attribute(name);
+        }
+        for (name, value) in &delta.updated_attributes {
+            self.set_attribute(name, value)
+                .map_err(TransitionError::DecodeError)?;
+        }
+        self.target_timestamp = None;
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn ProtocolSim> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn eq(&self, other: &dyn ProtocolSim) -> bool {
+        if let Some(other_state) = other.as_any().downcast_ref::<Self>() {
+            self == other_state
+        } else {
+            false
+        }
+    }
+}
+
+impl SwapFee for MaverickV2State {
+    fn swap_fee(
+        &self,
+        token_in: &Token,
+        token_out: &Token,
+        _amount_in: Option<&BigUint>,
+    ) -> Result<f64, SimulationError> {
+        Ok(u256_to_f64(self.fee_in(token_in.address < token_out.address)) / 1e18)
+    }
+}
+
+impl TimeDependent for MaverickV2State {
+    fn set_target_block(&mut self, block: Option<TargetBlock>) {
+        self.target_timestamp = block.map(|block| block.timestamp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, str::FromStr};
+
+    use approx::assert_ulps_eq;
+    use rstest::rstest;
+    use tycho_common::models::Chain;
+
+    use super::*;
+
+    const E18: u128 = 1_000_000_000_000_000_000;
+
+    fn u256(value: &str) -> U256 {
+        U256::from_str(value).unwrap()
+    }
+
+    fn e18(value: u128) -> U256 {
+        U256::from(value * E18)
+    }
+
+    fn token(address: &str, decimals: u32) -> Token {
+        Token::new(
+            &Bytes::from_str(address).unwrap(),
+            "T",
+            decimals,
+            0,
+            &[Some(10_000)],
+            Chain::Ethereum,
+            100,
+        )
+    }
+
+    fn tokens() -> (Token, Token) {
+        (
+            token("0x0000000000000000000000000000000000000000", 18),
+            token("0x0000000000000000000000000000000000000001", 18),
+        )
+    }
+
+    fn tick(reserve_a: U256, reserve_b: U256, total_supply: U256) -> MaverickTick {
+        MaverickTick { reserve_a, reserve_b, total_supply }
+    }
+
+    /// A pool with a 0.1% fee on token A and a 0.2% fee on token B.
+    fn state(ticks: Vec<(i32, MaverickTick)>) -> MaverickV2State {
+        let mut state = MaverickV2State::new(
+            U256::from(1_000_000_000_000_000u64),
+            U256::from(2_000_000_000_000_000u64),
+            10,
+            3600,
+            (18, 18),
+            0,
+            1000,
+        )
+        .unwrap();
+        state.ticks = ticks.into_iter().collect();
+        state
+    }
+
+    fn crossing_state() -> MaverickV2State {
+        state(vec![
+            (0, tick(e18(100), e18(1), e18(100))),
+            (-1, tick(U256::ZERO, e18(500), e18(500))),
+            (-3, tick(U256::ZERO, e18(200), e18(200))),
+        ])
+    }
+
+    #[rstest]
+    #[case::a_in(true, "999499225360028812", "1001000000000000000000", "999000500774639971188")]
+    #[case::b_in(false, "997501025556907082", "999002498974443092918", "1001000000000000000000")]
+    fn test_get_amount_out_single_tick(
+        #[case] a_in: bool,
+        #[case] exp_amount_out: &str,
+        #[case] exp_reserve_a: &str,
+        #[case] exp_reserve_b: &str,
+    ) {
+        let (token_a, token_b) = tokens();
+        let (token_in, token_out) = if a_in { (&token_a, &token_b) } else { (&token_b, &token_a) };
+        let state = state(vec![(0, tick(e18(1000), e18(1000), e18(1000)))]);
+
+        let res = state
+            .get_amount_out(BigUint::from(E18), token_in, token_out)
+            .unwrap();
+
+        assert_eq!(res.amount, BigUint::from_str(exp_amount_out).unwrap());
+        assert_eq!(res.gas, BigUint::from(BASE_GAS));
+        let new_state = res
+            .new_state
+            .as_any()
+            .downcast_ref::<MaverickV2State>()
+            .unwrap();
+        assert_eq!(new_state.ticks[&0], tick(u256(exp_reserve_a), u256(exp_reserve_b), e18(1000)));
+        assert_eq!(new_state.last_log_price_d8, 127);
+        // Assert that the old state is unchanged
+        assert_eq!(state.ticks[&0], tick(e18(1000), e18(1000), e18(1000)));
+    }
+
+    #[test]
+    fn test_get_amount_out_crossing_ticks() {
+        let (token_a, token_b) = tokens();
+        let state = crossing_state();
+
+        let res = state
+            .get_amount_out(BigUint::from(10 * E18), &token_a, &token_b)
+            .unwrap();
+
+        assert_eq!(res.amount, BigUint::from_str("9989924153763698170").unwrap());
+        assert_eq!(res.gas, BigUint::from(BASE_GAS + GAS_PER_TICK));
+        let new_state = res
+            .new_state
+            .as_any()
+            .downcast_ref::<MaverickV2State>()
+            .unwrap();
+        assert_eq!(new_state.active_tick, -1);
+        assert_eq!(new_state.last_log_price_d8, -6);
+        assert_eq!(new_state.ticks[&0], tick(u256("101000996047060911954"), U256::ZERO, e18(100)));
+        assert_eq!(
+            new_state.ticks[&-1],
+            tick(u256("8999003952939088046"), u256("491010075846236301830"), e18(500))
+        );
+    }
+
+    #[test]
+    fn test_get_amount_out_ticks_exceeded() {
+        let (token_a, token_b) = tokens();
+        let state = crossing_state();
+
+        let res = state.get_amount_out(BigUint::from(800 * E18), &token_a, &token_b);
+
+        match res {
+            Err(SimulationError::InvalidInput(_, Some(partial))) => {
+                assert_eq!(partial.amount, BigUint::from(701 * E18));
+            }
+            _ => panic!("Expected a partial result"),
+        }
+    }
+
+    #[test]
+    fn test_get_amount_out_decimals() {
+        let token_a = token("0x0000000000000000000000000000000000000000", 6);
+        let token_b = token("0x0000000000000000000000000000000000000001", 18);
+        let mut state = MaverickV2State::new(
+            U256::from(1_000_000_000_000_000u64),
+            U256::from(2_000_000_000_000_000u64),
+            10,
+            3600,
+            (6, 18),
+            0,
+            1000,
+        )
+        .unwrap();
+        state.ticks = BTreeMap::from([(0, tick(e18(1000), e18(1000), e18(1000)))]);
+
+        let res = state
+            .get_amount_out(BigUint::from(1_000_000u64), &token_a, &token_b)
+            .unwrap();
+
+        assert_eq!(res.amount, BigUint::from_str("999499225360028812").unwrap());
+    }
+
+    #[rstest]
+    #[case::a_in(true, "702453098614881702204", "701000000000000000000")]
+    #[case::b_in(false, "100251007163876666853", "100000000000000000000")]
+    fn test_get_limits(#[case] a_in: bool, #[case] exp_in: &str, #[case] exp_out: &str) {
+        let (token_a, token_b) = tokens();
+        let (sell, buy) = if a_in { (token_a, token_b) } else { (token_b, token_a) };
+
+        let (amount_in, amount_out) = crossing_state()
+            .get_limits(sell.address, buy.address)
+            .unwrap();
+
+        assert_eq!(amount_in, BigUint::from_str(exp_in).unwrap());
+        assert_eq!(amount_out, BigUint::from_str(exp_out).unwrap());
+    }
+
+    #[test]
+    fn test_get_amount_out_moves_bins() {
+        let (token_a, token_b) = tokens();
+        let mut state = state(vec![
+            (0, tick(U256::ZERO, e18(100), e18(100))),
+            (1, tick(U256::ZERO, e18(50), e18(50))),
+            (2, tick(e18(10), e18(10), e18(20))),
+        ]);
+        state.bins = BTreeMap::from([
+            (1, MaverickBin { kind: BinKind::Right, tick: 0, tick_balance: e18(60) }),
+            (2, MaverickBin { kind: BinKind::Static, tick: 0, tick_balance: e18(40) }),
+            (3, MaverickBin { kind: BinKind::Both, tick: 1, tick_balance: e18(50) }),
+            (4, MaverickBin { kind: BinKind::Right, tick: 2, tick_balance: e18(20) }),
+        ]);
+        state.active_tick = 2;
+        state.last_log_price_d8 = 2 * 256 + 100;
+        state.last_twa_d8 = 0;
+        state.last_timestamp = 0;
+        state.timestamp = 3600;
+
+        let res = state
+            .get_amount_out(BigUint::from(E18), &token_a, &token_b)
+            .unwrap();
+
+        // The TWA caught up with the price in tick 2, the right and both bins below it move there
+        assert_eq!(res.amount, BigUint::from_str("1001474834794986340").unwrap());
+        let new_state = res
+            .new_state
+            .as_any()
+            .downcast_ref::<MaverickV2State>()
+            .unwrap();
+        assert_eq!(new_state.last_twa_d8, 612);
+        assert_eq!(new_state.last_timestamp, 3600);
+        assert_eq!(new_state.ticks[&0], tick(U256::ZERO, e18(40), e18(40)));
+        assert_eq!(new_state.ticks[&1], tick(U256::ZERO, U256::ZERO, U256::ZERO));
+        assert_eq!(
+            new_state.ticks[&2],
+            tick(e18(11), u256("118998525165205013660"), u256("129845841491035772138"))
+        );
+        assert_eq!(
+            new_state.bins,
+            BTreeMap::from([
+                (2, MaverickBin { kind: BinKind::Static, tick: 0, tick_balance: e18(40) }),
+                (
+                    3,
+                    MaverickBin {
+                        kind: BinKind::Both,
+                        tick: 2,
+                        tick_balance: u256("49929053690999873793")
+                    }
+                ),
+                (
+                    4,
+                    MaverickBin {
+                        kind: BinKind::Right,
+                        tick: 2,
+                        tick_balance: u256("79916787800035898345")
+                    }
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_twa_d8() {
+        let mut state = state(vec![]);
+        state.last_twa_d8 = 0;
+        state.last_log_price_d8 = 612;
+        state.last_timestamp = 0;
+
+        state.timestamp = 1800;
+        assert_eq!(state.twa_d8(), 306);
+        state.timestamp = 7200;
+        assert_eq!(state.twa_d8(), 612);
+
+        state.set_target_block(Some(TargetBlock::new(1, 1800)));
+        assert_eq!(state.twa_d8(), 306);
+        state.set_target_block(None);
+        assert_eq!(state.twa_d8(), 612);
+    }
+
+    #[test]
+    fn test_spot_price_and_fees() {
+        let (token_a, token_b) = tokens();
+        let state = state(vec![(0, tick(e18(1000), e18(1000), e18(1000)))]);
+
+        assert_ulps_eq!(
+            state
+                .spot_price(&token_a, &token_b)
+                .unwrap(),
+            1.0004999749756327,
+            max_ulps = 4
+        );
+        assert_ulps_eq!(state.fee(), 0.002);
+        assert_ulps_eq!(
+            state
+                .swap_fee(&token_a, &token_b, None)
+                .unwrap(),
+            0.001
+        );
+        assert_ulps_eq!(
+            state
+                .swap_fee(&token_b, &token_a, None)
+                .unwrap(),
+            0.002
+        );
+    }
+
+    #[test]
+    fn test_delta_transition() {
+        let mut state = crossing_state();
+        state.bins = BTreeMap::from([(
+            7,
+            MaverickBin { kind: BinKind::Static, tick: -3, tick_balance: e18(200) },
+        )]);
+        state.set_target_block(Some(TargetBlock::new(1, 1800)));
+        let delta = ProtocolStateDelta {
+            component_id: "pool".to_owned(),
+            updated_attributes: HashMap::from([
+                ("active_tick".to_string(), Bytes::from((-1i32).to_be_bytes().to_vec())),
+                ("ticks/-1/reserve_a".to_string(), Bytes::from(5u64.to_be_bytes().to_vec())),
+                ("bins/8/kind".to_string(), Bytes::from(vec![2u8])),
+                ("bins/8/tick".to_string(), Bytes::from((-1i32).to_be_bytes().to_vec())),
+                ("bins/8/tick_balance".to_string(), Bytes::from(9u64.to_be_bytes().to_vec())),
+                ("last_timestamp".to_string(), Bytes::from(2000u64.to_be_bytes().to_vec())),
+            ]),
+            deleted_attributes: HashSet::from([
+                "ticks/-3/reserve_b".to_string(),
+                "bins/7/tick_balance".to_string(),
+            ]),
+        };
+
+        state
+            .delta_transition(delta, &HashMap::new(), &Balances::default())
+            .unwrap();
+
+        assert_eq!(state.active_tick, -1);
+        assert_eq!(state.ticks[&-1], tick(U256::from(5), e18(500), e18(500)));
+        assert!(!state.ticks.contains_key(&-3));
+        assert_eq!(
+            state.bins,
+            BTreeMap::from([(
+                8,
+                MaverickBin { kind: BinKind::Left, tick: -1, tick_balance: U256::from(9) }
+            )])
+        );
+        assert_eq!(state.timestamp, 2000);
+        assert_eq!(state.target_timestamp, None);
+    }
+
+    #[test]
+    fn test_delta_transition_invalid_bin_kind() {
+        let mut state = crossing_state();
+        let delta = ProtocolStateDelta {
+            component_id: "pool".to_owned(),
+            updated_attributes: HashMap::from([(
+                "bins/1/kind".to_string(),
+                Bytes::from(vec![4u8]),
+            )]),
+            deleted_attributes: HashSet::new(),
+        };
+
+        let res = state.delta_transition(delta, &HashMap::new(), &Balances::default());
+
+        assert!(matches!(res, Err(TransitionError::DecodeError(_))));
+    }
+}
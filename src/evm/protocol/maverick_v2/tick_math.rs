@@ -0,0 +1,125 @@
+//! Tick math of Maverick V2 pools.
+//!
+//! Amounts, reserves and liquidity are 18 decimal fixed point numbers and sqrt prices are the
+//! square root of the price of token A in token B, scaled by 1e18. Within a tick the pool behaves
+//! like a concentrated constant product pool between the sqrt prices of the tick and the next one.
+use alloy::primitives::U256;
+use tycho_common::simulation::errors::SimulationError;
+
+use crate::evm::protocol::{
+    safe_math::{safe_add_u256, safe_div_u256, safe_mul_u256, safe_sub_u256},
+    utils::uniswap::tick_math::get_sqrt_ratio_at_tick,
+};
+
+pub(super) const PRECISION: U256 = U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0]);
+pub(super) const MAX_TICK: i32 = 460_540;
+
+pub(super) fn mul_div(a: U256, b: U256, denominator: U256) -> Result<U256, SimulationError> {
+    safe_div_u256(safe_mul_u256(a, b)?, denominator)
+}
+
+pub(super) fn mul_div_up(a: U256, b: U256, denominator: U256) -> Result<U256, SimulationError> {
+    let product = safe_mul_u256(a, b)?;
+    let result = safe_div_u256(product, denominator)?;
+    if (product % denominator).is_zero() {
+        Ok(result)
+    } else {
+        safe_add_u256(result, U256::from(1))
+    }
+}
+
+/// Returns the sqrt price at the lower end of `tick`, i.e. `sqrt(1.0001^(tick * tick_spacing))`.
+pub(super) fn tick_sqrt_price(tick_spacing: u32, tick: i32) -> Result<U256, SimulationError> {
+    let sub_tick = i64::from(tick) * i64::from(tick_spacing);
+    if sub_tick.abs() > i64::from(MAX_TICK) {
+        return Err(SimulationError::InvalidInput(format!("Tick {tick} out of range"), None));
+    }
+    let sqrt_ratio_x96 = get_sqrt_ratio_at_tick(sub_tick as i32)?;
+    Ok(safe_mul_u256(sqrt_ratio_x96, PRECISION)? >> 96)
+}
+
+/// Returns the sqrt prices at the lower and upper end of `tick`.
+pub(super) fn tick_sqrt_prices(
+    tick_spacing: u32,
+    tick: i32,
+) -> Result<(U256, U256), SimulationError> {
+    Ok((tick_sqrt_price(tick_spacing, tick)?, tick_sqrt_price(tick_spacing, tick + 1)?))
+}
+
+/// Returns the liquidity of a tick holding the given reserves.
+///
+/// Solves `(reserve_a + L / sqrt_upper) * (reserve_b + L * sqrt_lower) = L^2` for `L`.
+pub(super) fn tick_liquidity(
+    reserve_a: U256,
+    reserve_b: U256,
+    sqrt_lower: U256,
+    sqrt_upper: U256,
+) -> Result<U256, SimulationError> {
+    if reserve_a.is_zero() && reserve_b.is_zero() {
+        return Ok(U256::ZERO);
+    }
+    // k = 1 - sqrt_lower / sqrt_upper
+    let k = safe_sub_u256(PRECISION, mul_div(sqrt_lower, PRECISION, sqrt_upper)?)?;
+    // b = reserve_a * sqrt_lower + reserve_b / sqrt_upper
+    let b = safe_add_u256(
+        mul_div(reserve_a, sqrt_lower, PRECISION)?,
+        mul_div(reserve_b, PRECISION, sqrt_upper)?,
+    )?;
+    // L = (b + sqrt(b^2 + 4 * k * reserve_a * reserve_b)) / (2 * k)
+    let discriminant = safe_add_u256(
+        safe_mul_u256(b, b)?,
+        safe_mul_u256(mul_div(reserve_a, k, PRECISION)?, safe_mul_u256(reserve_b, U256::from(4))?)?,
+    )?;
+    mul_div(safe_add_u256(b, discriminant.root(2))?, PRECISION, safe_mul_u256(k, U256::from(2))?)
+}
+
+/// Returns the virtual reserves `(x, y)` of a tick, such that `x * y = L^2` and the sqrt price is
+/// `L / x`.
+pub(super) fn virtual_reserves(
+    reserve_a: U256,
+    reserve_b: U256,
+    liquidity: U256,
+    sqrt_lower: U256,
+    sqrt_upper: U256,
+) -> Result<(U256, U256), SimulationError> {
+    Ok((
+        safe_add_u256(reserve_a, mul_div(liquidity, PRECISION, sqrt_upper)?)?,
+        safe_add_u256(reserve_b, mul_div(liquidity, sqrt_lower, PRECISION)?)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn u256(value: &str) -> U256 {
+        U256::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_tick_sqrt_price() {
+        assert_eq!(tick_sqrt_price(10, 0).unwrap(), PRECISION);
+        // sqrt(1.0001^10) and sqrt(1.0001^-10)
+        assert_eq!(tick_sqrt_price(10, 1).unwrap(), u256("1000500100010000500"));
+        assert_eq!(tick_sqrt_price(10, -1).unwrap(), u256("999500149965006998"));
+        assert!(tick_sqrt_price(10, MAX_TICK / 10 + 1).is_err());
+    }
+
+    #[test]
+    fn test_tick_liquidity() {
+        let (sqrt_lower, sqrt_upper) = tick_sqrt_prices(10, 0).unwrap();
+
+        // Only token A: L = reserve_a * sqrt_lower / (1 - sqrt_lower / sqrt_upper)
+        let only_a = tick_liquidity(PRECISION, U256::ZERO, sqrt_lower, sqrt_upper).unwrap();
+        // Both tokens, the virtual reserves satisfy x * y = L^2
+        let both = tick_liquidity(PRECISION, PRECISION, sqrt_lower, sqrt_upper).unwrap();
+        let (x, y) = virtual_reserves(PRECISION, PRECISION, both, sqrt_lower, sqrt_upper).unwrap();
+
+        assert_eq!(only_a, u256("2000600039997996997399"));
+        assert_eq!(both, u256("4000700142477247393004"));
+        let invariant_error = (x * y / PRECISION).abs_diff(both * both / PRECISION);
+        assert!(invariant_error < both / U256::from(1_000_000_000_000u64));
+    }
+}
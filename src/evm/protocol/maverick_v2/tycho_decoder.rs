@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+
+use alloy::primitives::U256;
+use tycho_client::feed::{synchronizer::ComponentWithState, BlockHeader};
+use tycho_common::{models::token::Token, Bytes};
+
+use super::state::MaverickV2State;
+use crate::protocol::{errors::InvalidSnapshotError, models::TryFromWithBlock};
+
+fn required_static_attribute<'a>(
+    snapshot: &'a ComponentWithState,
+    name: &str,
+) -> Result<&'a Bytes, InvalidSnapshotError> {
+    snapshot
+        .component
+        .static_attributes
+        .get(name)
+        .ok_or_else(|| InvalidSnapshotError::MissingAttribute(name.to_string()))
+}
+
+fn required_u64(snapshot: &ComponentWithState, name: &str) -> Result<u64, InvalidSnapshotError> {
+    let value = U256::from_be_slice(required_static_attribute(snapshot, name)?);
+    u64::try_from(value)
+        .map_err(|_| InvalidSnapshotError::ValueError(format!("Invalid {name} {value}")))
+}
+
+impl TryFromWithBlock<ComponentWithState, BlockHeader> for MaverickV2State {
+    type Error = InvalidSnapshotError;
+
+    /// Decodes a `ComponentWithState` of a Maverick V2 pool into a `MaverickV2State`.
+    ///
+    /// Uses the static attributes `fee_a_in` and `fee_b_in` (scaled by 1e18), `tick_spacing`,
+    /// `lookback` (in seconds) and the optional `protocol_fee_ratio_d3`, and the state attributes
+    /// `active_tick`, `last_twa_d8`, `last_log_price_d8`, `last_timestamp`,
+    /// `ticks/{tick}/{reserve_a|reserve_b|total_supply}` and `bins/{id}/{kind|tick|tick_balance}`.
+    /// Swaps are simulated at the block's timestamp.
+    async fn try_from_with_header(
+        snapshot: ComponentWithState,
+        block: BlockHeader,
+        _account_balances: &HashMap<Bytes, HashMap<Bytes, Bytes>>,
+        all_tokens: &HashMap<Bytes, Token>,
+    ) -> Result<Self, Self::Error> {
+        let id = &snapshot.component.id;
+        let mut tokens = snapshot.component.tokens.clone();
+        if tokens.len() != 2 {
+            return Err(InvalidSnapshotError::ValueError(format!(
+                "Maverick V2 pool {id} has {} tokens",
+                tokens.len()
+            )));
+        }
+        tokens.sort();
+        let decimals = |token: &Bytes| {
+            all_tokens
+                .get(token)
+                .map(|token| token.decimals)
+                .ok_or_else(|| InvalidSnapshotError::ValueError(format!("Token {token} not found")))
+        };
+
+        let tick_spacing = u32::try_from(required_u64(&snapshot, "tick_spacing")?)
+            .map_err(|_| InvalidSnapshotError::ValueError("Invalid tick_spacing".to_string()))?;
+        let mut state = MaverickV2State::new(
+            U256::from_be_slice(required_static_attribute(&snapshot, "fee_a_in")?),
+            U256::from_be_slice(required_static_attribute(&snapshot, "fee_b_in")?),
+            tick_spacing,
+            required_u64(&snapshot, "lookback")?,
+            (decimals(&tokens[0])?, decimals(&tokens[1])?),
+            0,
+            block.timestamp,
+        )
+        .map_err(|err| InvalidSnapshotError::ValueError(err.to_string()))?;
+        if let Some(ratio) = snapshot
+            .component
+            .static_attributes
+            .get("protocol_fee_ratio_d3")
+        {
+            state.protocol_fee_ratio_d3 = U256::from_be_slice(ratio);
+        }
+
+        let attributes = &snapshot.state.attributes;
+        if !attributes.contains_key("active_tick") {
+            return Err(InvalidSnapshotError::MissingAttribute("active_tick".to_string()));
+        }
+        for (name, value) in attributes {
+            state
+                .set_attribute(name, value)
+                .map_err(InvalidSnapshotError::ValueError)?;
+        }
+        if !attributes.contains_key("last_log_price_d8") {
+            state.last_log_price_d8 = i64::from(state.active_tick) * 256;
+        }
+        if !attributes.contains_key("last_twa_d8") {
+            state.last_twa_d8 = state.last_log_price_d8;
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, fs, path::Path, str::FromStr};
+
+    use num_bigint::BigUint;
+    use rstest::rstest;
+    use serde::Deserialize;
+    use tycho_common::{
+        dto::{ProtocolComponent, ResponseProtocolState},
+        models::Chain,
+        simulation::protocol_sim::ProtocolSim,
+    };
+
+    use super::*;
+    use crate::evm::protocol::maverick_v2::state::{BinKind, MaverickBin, MaverickTick};
+
+    const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+    const WETH: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+
+    fn header() -> BlockHeader {
+        BlockHeader {
+            number: 1,
+            hash: Bytes::from(vec![0; 32]),
+            parent_hash: Bytes::from(vec![0; 32]),
+            revert: false,
+            timestamp: 1_700_000_000,
+        }
+    }
+
+    fn token(address: &Bytes, decimals: u32) -> Token {
+        Token::new(address, "T", decimals, 0, &[Some(10_000)], Chain::Ethereum, 100)
+    }
+
+    fn tokens() -> HashMap<Bytes, Token> {
+        [(USDC, 6), (WETH, 18)]
+            .into_iter()
+            .map(|(address, decimals)| {
+                let address = Bytes::from_str(address).unwrap();
+                (address.clone(), token(&address, decimals))
+            })
+            .collect()
+    }
+
+    fn u256_bytes(value: u128) -> Bytes {
+        Bytes::from(U256::from(value).to_be_bytes_vec())
+    }
+
+    fn static_attributes() -> HashMap<String, Bytes> {
+        HashMap::from([
+            ("fee_a_in".to_string(), u256_bytes(100_000_000_000_000)),
+            ("fee_b_in".to_string(), u256_bytes(200_000_000_000_000)),
+            ("tick_spacing".to_string(), u256_bytes(10)),
+            ("lookback".to_string(), u256_bytes(3600)),
+        ])
+    }
+
+    fn state_attributes() -> HashMap<String, Bytes> {
+        HashMap::from([
+            ("active_tick".to_string(), Bytes::from((-2i32).to_be_bytes().to_vec())),
+            ("last_timestamp".to_string(), u256_bytes(1_699_999_000)),
+            ("ticks/-2/reserve_a".to_string(), u256_bytes(1_000)),
+            ("ticks/-2/reserve_b".to_string(), u256_bytes(2_000)),
+            ("ticks/-2/total_supply".to_string(), u256_bytes(3_000)),
+            ("bins/1/kind".to_string(), u256_bytes(3)),
+            ("bins/1/tick".to_string(), Bytes::from((-2i32).to_be_bytes().to_vec())),
+            ("bins/1/tick_balance".to_string(), u256_bytes(3_000)),
+        ])
+    }
+
+    fn snapshot(
+        attributes: HashMap<String, Bytes>,
+        static_attributes: HashMap<String, Bytes>,
+    ) -> ComponentWithState {
+        ComponentWithState {
+            state: ResponseProtocolState {
+                component_id: "0xpool".to_string(),
+                attributes,
+                balances: HashMap::new(),
+            },
+            component: ProtocolComponent {
+                id: "0xpool".to_string(),
+                protocol_system: "vm:maverick_v2".to_string(),
+                tokens: vec![Bytes::from_str(WETH).unwrap(), Bytes::from_str(USDC).unwrap()],
+                static_attributes,
+                ..Default::default()
+            },
+            component_tvl: None,
+            entrypoints: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_maverick_v2_try_from() {
+        let result = MaverickV2State::try_from_with_header(
+            snapshot(state_attributes(), static_attributes()),
+            header(),
+            &HashMap::new(),
+            &tokens(),
+        )
+        .await
+        .unwrap();
+
+        // USDC has the lower address, so it is token A
+        let mut expected = MaverickV2State::new(
+            U256::from(100_000_000_000_000u64),
+            U256::from(200_000_000_000_000u64),
+            10,
+            3600,
+            (6, 18),
+            -2,
+            1_700_000_000,
+        )
+        .unwrap();
+        expected.last_timestamp = 1_699_999_000;
+        expected.ticks = [(
+            -2,
+            MaverickTick {
+                reserve_a: U256::from(1_000),
+                reserve_b: U256::from(2_000),
+                total_supply: U256::from(3_000),
+            },
+        )]
+        .into();
+        expected.bins =
+            [(1, MaverickBin { kind: BinKind::Both, tick: -2, tick_balance: U256::from(3_000) })]
+                .into();
+        assert_eq!(result, expected);
+    }
+
+    #[tokio::test]
+    #[rstest]
+    #[case::missing_fee_a_in("fee_a_in")]
+    #[case::missing_tick_spacing("tick_spacing")]
+    #[case::missing_lookback("lookback")]
+    #[case::missing_active_tick("active_tick")]
+    async fn test_maverick_v2_try_from_missing_attribute(#[case] missing_attribute: &str) {
+        let mut attributes = state_attributes();
+        let mut static_attributes = static_attributes();
+        attributes.remove(missing_attribute);
+        static_attributes.remove(missing_attribute);
+
+        let result = MaverickV2State::try_from_with_header(
+            snapshot(attributes, static_attributes),
+            header(),
+            &HashMap::new(),
+            &tokens(),
+        )
+        .await;
+
+        assert!(matches!(
+            result.unwrap_err(),
+            InvalidSnapshotError::MissingAttribute(attribute) if attribute == missing_attribute
+        ));
+    }
+
+    #[derive(Deserialize)]
+    struct FixtureToken {
+        address: Bytes,
+        decimals: u32,
+    }
+
+    #[derive(Deserialize)]
+    struct FixtureQuote {
+        token_in: Bytes,
+        token_out: Bytes,
+        amount_in: String,
+        /// The amount out returned by the VM adapter
+        amount_out: String,
+    }
+
+    /// A snapshot of a Maverick V2 pool with quotes recorded with `MaverickV2SwapAdapter` at the
+    /// same block.
+    #[derive(Deserialize)]
+    struct Fixture {
+        snapshot: ComponentWithState,
+        block: BlockHeader,
+        tokens: Vec<FixtureToken>,
+        quotes: Vec<FixtureQuote>,
+    }
+
+    /// Compares the native state to the VM adapter on the fixtures in `tests/assets/maverick_v2`.
+    ///
+    /// The native tick math rounds differently from the pool contract, so quotes are expected to
+    /// match within 1e-6 of the adapter's amount. The fixtures have to cover bins of every kind,
+    /// so that all movement modes are checked.
+    #[tokio::test]
+    #[ignore] // Requires fixtures recorded with the `record_adapter_fixtures` example
+    async fn test_maverick_v2_matches_vm_adapter() {
+        let fixtures_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/assets/maverick_v2");
+        let mut checked = 0;
+        let mut bin_kinds = HashSet::new();
+        for entry in fs::read_dir(fixtures_dir).expect("Failed to read the fixtures directory") {
+            let path = entry.unwrap().path();
+            if path
+                .extension()
+                .is_none_or(|extension| extension != "json")
+            {
+                continue;
+            }
+            let fixture: Fixture =
+                serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+            let all_tokens: HashMap<Bytes, Token> = fixture
+                .tokens
+                .iter()
+                .map(|fixture_token| {
+                    (
+                        fixture_token.address.clone(),
+                        token(&fixture_token.address, fixture_token.decimals),
+                    )
+                })
+                .collect();
+
+            bin_kinds.extend(
+                fixture
+                    .snapshot
+                    .state
+                    .attributes
+                    .iter()
+                    .filter(|(name, _)| name.starts_with("bins/") && name.ends_with("/kind"))
+                    .map(|(_, kind)| BigUint::from_bytes_be(kind)),
+            );
+
+            let state = MaverickV2State::try_from_with_header(
+                fixture.snapshot,
+                fixture.block,
+                &HashMap::new(),
+                &all_tokens,
+            )
+            .await
+            .unwrap_or_else(|err| panic!("Failed to decode {path:?}: {err:?}"));
+
+            for quote in fixture.quotes {
+                let result = state
+                    .get_amount_out(
+                        BigUint::from_str(&quote.amount_in).unwrap(),
+                        &all_tokens[&quote.token_in],
+                        &all_tokens[&quote.token_out],
+                    )
+                    .unwrap();
+                let expected = BigUint::from_str(&quote.amount_out).unwrap();
+                let difference = if result.amount > expected {
+                    &result.amount - &expected
+                } else {
+                    &expected - &result.amount
+                };
+                assert!(
+                    difference * 1_000_000u32 <= expected,
+                    "{path:?}: {} {} -> {}: got {}, expected {}",
+                    quote.amount_in,
+                    quote.token_in,
+                    quote.token_out,
+                    result.amount,
+                    expected
+                );
+                checked += 1;
+            }
+        }
+        assert!(checked > 0, "No fixtures found");
+        assert_eq!(bin_kinds.len(), 4, "The fixtures don't cover bins of every kind");
+    }
+}
@@ -1,5 +1,11 @@
+pub mod balancer_v2;
+pub mod balancer_v3;
+pub mod cpmm;
+pub mod curve;
 pub mod ekubo;
+pub mod filter_config;
 pub mod filters;
+pub mod maverick_v2;
 pub mod pancakeswap_v2;
 pub mod safe_math;
 pub mod u256_num;
@@ -9,4 +15,141 @@ pub mod uniswap_v4;
 pub mod utils;
 pub mod vm;
 
-mod cpmm;
+use std::any::Any;
+
+use tycho_client::feed::BlockHeader;
+
+use crate::{
+    evm::engine_db::tycho_db::PreCachedDB,
+    protocol::{fee::SwapFee, target_block::TimeDependent},
+};
+
+/// Returns the [`SwapFee`] implementation of a state of this module, if `state` is one.
+///
+/// Downcasting needs concrete types, so VM pools are only recognised with the [`PreCachedDB`]
+/// the decoder builds them with. VM pools over other databases, e.g. a `SimulationDB`, fall back
+/// to [`infer_swap_fee`](crate::protocol::fee::infer_swap_fee), which infers the fee from the same
+/// round trip swap through the `ProtocolSim` interface.
+pub(crate) fn as_swap_fee(state: &dyn Any) -> Option<&dyn SwapFee> {
+    if let Some(state) = state.downcast_ref::<uniswap_v2::state::UniswapV2State>() {
+        return Some(state);
+    }
+    if let Some(state) = state.downcast_ref::<pancakeswap_v2::state::PancakeswapV2State>() {
+        return Some(state);
+    }
+    if let Some(state) = state.downcast_ref::<cpmm::state::CpmmState>() {
+        return Some(state);
+    }
+    if let Some(state) = state.downcast_ref::<uniswap_v3::state::UniswapV3State>() {
+        return Some(state);
+    }
+    if let Some(state) = state.downcast_ref::<uniswap_v4::state::UniswapV4State>() {
+        return Some(state);
+    }
+    if let Some(state) = state.downcast_ref::<balancer_v2::state::BalancerV2State>() {
+        return Some(state);
+    }
+    if let Some(state) = state.downcast_ref::<balancer_v3::state::BalancerV3State>() {
+        return Some(state);
+    }
+    if let Some(state) = state.downcast_ref::<curve::state::CurveState>() {
+        return Some(state);
+    }
+    if let Some(state) = state.downcast_ref::<maverick_v2::state::MaverickV2State>() {
+        return Some(state);
+    }
+    if let Some(state) = state.downcast_ref::<ekubo::state::EkuboState>() {
+        return Some(state);
+    }
+    if let Some(state) = state.downcast_ref::<vm::state::EVMPoolState<PreCachedDB>>() {
+        return Some(state);
+    }
+    None
+}
+
+/// Returns the [`TimeDependent`] implementation of a state of this module, if `state` is one.
+pub(crate) fn as_time_dependent_mut(state: &mut dyn Any) -> Option<&mut dyn TimeDependent> {
+    // Checking the type first, as returning a conditional mutable borrow would keep `state`
+    // borrowed for the following downcasts
+    fn downcast<T: TimeDependent + 'static>(state: &mut dyn Any) -> Option<&mut dyn TimeDependent> {
+        state
+            .downcast_mut::<T>()
+            .map(|state| state as &mut dyn TimeDependent)
+    }
+
+    if state.is::<ekubo::state::EkuboState>() {
+        return downcast::<ekubo::state::EkuboState>(state);
+    }
+    if state.is::<maverick_v2::state::MaverickV2State>() {
+        return downcast::<maverick_v2::state::MaverickV2State>(state);
+    }
+    if state.is::<uniswap_v4::state::UniswapV4State>() {
+        return downcast::<uniswap_v4::state::UniswapV4State>(state);
+    }
+    if state.is::<vm::state::EVMPoolState<PreCachedDB>>() {
+        return downcast::<vm::state::EVMPoolState<PreCachedDB>>(state);
+    }
+    None
+}
+
+/// Moves a state of this module that keeps the block it was indexed at to `block`.
+///
+/// States decoded from a snapshot are given its block, but deltas don't carry the block they were
+/// indexed in. The decoder calls this after applying deltas, so that these states are not left at
+/// the block of their snapshot.
+pub(crate) fn set_block(state: &mut dyn Any, block: &BlockHeader) {
+    if let Some(state) = state.downcast_mut::<uniswap_v4::state::UniswapV4State>() {
+        state.set_block(block.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    /// Returns the state types of the decoders of this module, i.e. all types decoded from a
+    /// `ComponentWithState` in a `tycho_decoder.rs`.
+    fn decoded_state_types(dir: &Path, types: &mut Vec<String>) {
+        const DECODER_IMPL: &str = "impl TryFromWithBlock<ComponentWithState, BlockHeader> for ";
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                decoded_state_types(&path, types);
+            } else if path.file_name() == Some("tycho_decoder.rs".as_ref()) {
+                let source = fs::read_to_string(&path).unwrap();
+                types.extend(
+                    source
+                        .split(DECODER_IMPL)
+                        .skip(1)
+                        .map(|rest| {
+                            rest.split(" {")
+                                .next()
+                                .unwrap()
+                                .to_string()
+                        }),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_swap_fee_covers_decoded_states() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/evm/protocol");
+        let mut types = Vec::new();
+        decoded_state_types(&dir, &mut types);
+        assert!(types.len() > 1, "No decoders found in {dir:?}");
+
+        let source = include_str!("mod.rs");
+        let as_swap_fee = source
+            .split("fn as_swap_fee")
+            .nth(1)
+            .and_then(|rest| rest.split("\n}\n").next())
+            .unwrap();
+        for state_type in types {
+            assert!(
+                as_swap_fee.contains(&format!("::{state_type}>()")),
+                "as_swap_fee doesn't downcast to {state_type}"
+            );
+        }
+    }
+}
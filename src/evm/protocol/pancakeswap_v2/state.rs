@@ -12,12 +12,15 @@ use tycho_common::{
     Bytes,
 };
 
-use crate::evm::protocol::{
-    cpmm::protocol::{
-        cpmm_delta_transition, cpmm_fee, cpmm_get_amount_out, cpmm_get_limits, cpmm_spot_price,
+use crate::{
+    evm::protocol::{
+        cpmm::protocol::{
+            cpmm_delta_transition, cpmm_fee, cpmm_get_amount_out, cpmm_get_limits, cpmm_spot_price,
+        },
+        safe_math::{safe_add_u256, safe_sub_u256},
+        u256_num::{biguint_to_u256, u256_to_biguint},
     },
-    safe_math::{safe_add_u256, safe_sub_u256},
-    u256_num::{biguint_to_u256, u256_to_biguint},
+    protocol::{fee::SwapFee, transfer_tax::amount_after_tax},
 };
 
 const PANCAKESWAP_V2_FEE: u32 = 25; // 0.25% fee
@@ -55,7 +58,7 @@ impl ProtocolSim for PancakeswapV2State {
         token_in: &Token,
         token_out: &Token,
     ) -> Result<GetAmountOutResult, SimulationError> {
-        let amount_in = biguint_to_u256(&amount_in);
+        let amount_in = biguint_to_u256(&amount_after_tax(&amount_in, token_in));
         let zero2one = token_in.address < token_out.address;
         let amount_out = cpmm_get_amount_out(
             amount_in,
@@ -74,7 +77,7 @@ impl ProtocolSim for PancakeswapV2State {
             *reserve1_mut = safe_add_u256(self.reserve1, amount_in)?;
         };
         Ok(GetAmountOutResult::new(
-            u256_to_biguint(amount_out),
+            amount_after_tax(&u256_to_biguint(amount_out), token_out),
             120_000
                 .to_biguint()
                 .expect("Expected an unsigned integer as gas value"),
@@ -125,6 +128,17 @@ impl ProtocolSim for PancakeswapV2State {
     }
 }
 
+impl SwapFee for PancakeswapV2State {
+    fn swap_fee(
+        &self,
+        _token_in: &Token,
+        _token_out: &Token,
+        _amount_in: Option<&BigUint>,
+    ) -> Result<f64, SimulationError> {
+        Ok(self.fee())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -12,12 +12,15 @@ use tycho_common::{
     Bytes,
 };
 
-use crate::evm::protocol::{
-    cpmm::protocol::{
-        cpmm_delta_transition, cpmm_fee, cpmm_get_amount_out, cpmm_get_limits, cpmm_spot_price,
+use crate::{
+    evm::protocol::{
+        cpmm::protocol::{
+            cpmm_delta_transition, cpmm_fee, cpmm_get_amount_out, cpmm_get_limits, cpmm_spot_price,
+        },
+        safe_math::{safe_add_u256, safe_sub_u256},
+        u256_num::{biguint_to_u256, u256_to_biguint},
     },
-    safe_math::{safe_add_u256, safe_sub_u256},
-    u256_num::{biguint_to_u256, u256_to_biguint},
+    protocol::{fee::SwapFee, transfer_tax::amount_after_tax},
 };
 
 const UNISWAP_V2_FEE_BPS: u32 = 30; // 0.3% fee
@@ -55,7 +58,7 @@ impl ProtocolSim for UniswapV2State {
         token_in: &Token,
         token_out: &Token,
     ) -> Result<GetAmountOutResult, SimulationError> {
-        let amount_in = biguint_to_u256(&amount_in);
+        let amount_in = biguint_to_u256(&amount_after_tax(&amount_in, token_in));
         let zero2one = token_in.address < token_out.address;
         let amount_out = cpmm_get_amount_out(
             amount_in,
@@ -74,7 +77,7 @@ impl ProtocolSim for UniswapV2State {
             *reserve1_mut = safe_add_u256(self.reserve1, amount_in)?;
         };
         Ok(GetAmountOutResult::new(
-            u256_to_biguint(amount_out),
+            amount_after_tax(&u256_to_biguint(amount_out), token_out),
             120_000
                 .to_biguint()
                 .expect("Expected an unsigned integer as gas value"),
@@ -125,6 +128,17 @@ impl ProtocolSim for UniswapV2State {
     }
 }
 
+impl SwapFee for UniswapV2State {
+    fn swap_fee(
+        &self,
+        _token_in: &Token,
+        _token_out: &Token,
+        _amount_in: Option<&BigUint>,
+    ) -> Result<f64, SimulationError> {
+        Ok(self.fee())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -211,6 +225,45 @@ mod tests {
         assert_eq!(state.reserve1, r1);
     }
 
+    #[test]
+    fn test_get_amount_out_fee_on_transfer() {
+        let reserve = U256::from_str("1000000000000000000000").unwrap();
+        // 5% tax on token 0 and 1% tax on token 1
+        let t0 = Token::new(
+            &Bytes::from_str("0x0000000000000000000000000000000000000000").unwrap(),
+            "T0",
+            18,
+            500,
+            &[Some(10_000)],
+            Chain::Ethereum,
+            100,
+        );
+        let t1 = Token::new(
+            &Bytes::from_str("0x0000000000000000000000000000000000000001").unwrap(),
+            "T1",
+            18,
+            100,
+            &[Some(10_000)],
+            Chain::Ethereum,
+            100,
+        );
+        let state = UniswapV2State::new(reserve, reserve);
+
+        let res = state
+            .get_amount_out(BigUint::from_str("1000000000000000000").unwrap(), &t0, &t1)
+            .unwrap();
+
+        // The pool receives 0.95 T0 and sends 0.946253755755236427 T1, of which 1% is taxed
+        assert_eq!(res.amount, BigUint::from_str("936791218197684063").unwrap());
+        let new_state = res
+            .new_state
+            .as_any()
+            .downcast_ref::<UniswapV2State>()
+            .unwrap();
+        assert_eq!(new_state.reserve0, reserve + U256::from(950_000_000_000_000_000u64));
+        assert_eq!(new_state.reserve1, reserve - U256::from(946_253_755_755_236_427u64));
+    }
+
     #[test]
     fn test_get_amount_out_overflow() {
         let r0 = U256::from_str("33372357002392258830279").unwrap();
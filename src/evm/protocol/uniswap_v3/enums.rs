@@ -8,6 +8,20 @@ pub enum FeeAmount {
     High = 10_000,     // 1%
 }
 
+impl FeeAmount {
+    /// Returns the tick spacing Uniswap V3 and PancakeSwap V3 enable for this fee tier.
+    pub fn tick_spacing(&self) -> u16 {
+        match self {
+            FeeAmount::Lowest => 1,
+            FeeAmount::Low => 10,
+            FeeAmount::MediumLow => 50,
+            FeeAmount::Medium => 60,
+            FeeAmount::MediumHigh => 100,
+            FeeAmount::High => 200,
+        }
+    }
+}
+
 impl std::convert::TryFrom<i32> for FeeAmount {
     type Error = ();
 
@@ -15,19 +15,25 @@ use tycho_common::{
 };
 
 use super::enums::FeeAmount;
-use crate::evm::protocol::{
-    safe_math::{safe_add_u256, safe_sub_u256},
-    u256_num::u256_to_biguint,
-    utils::uniswap::{
-        i24_be_bytes_to_i32, liquidity_math,
-        sqrt_price_math::{get_amount0_delta, get_amount1_delta, sqrt_price_q96_to_f64},
-        swap_math,
-        tick_list::{TickInfo, TickList, TickListErrorKind},
-        tick_math::{
-            get_sqrt_ratio_at_tick, get_tick_at_sqrt_ratio, MAX_SQRT_RATIO, MAX_TICK,
-            MIN_SQRT_RATIO, MIN_TICK,
+use crate::{
+    evm::protocol::{
+        safe_math::{safe_add_u256, safe_sub_u256},
+        u256_num::u256_to_biguint,
+        utils::uniswap::{
+            i24_be_bytes_to_i32, liquidity_math,
+            sqrt_price_math::{get_amount0_delta, get_amount1_delta, sqrt_price_q96_to_f64},
+            swap_math,
+            tick_list::{TickInfo, TickList, TickListErrorKind},
+            tick_math::{
+                get_sqrt_ratio_at_tick, get_tick_at_sqrt_ratio, MAX_SQRT_RATIO, MAX_TICK,
+                MIN_SQRT_RATIO, MIN_TICK,
+            },
+            StepComputation, SwapResults, SwapState,
         },
-        StepComputation, SwapResults, SwapState,
+    },
+    protocol::{
+        fee::SwapFee,
+        transfer_tax::{amount_after_tax, tax_partial_amount_out},
     },
 };
 
@@ -35,7 +41,7 @@ use crate::evm::protocol::{
 pub struct UniswapV3State {
     liquidity: u128,
     sqrt_price: U256,
-    fee: FeeAmount,
+    fee: u32,
     tick: i32,
     ticks: TickList,
 }
@@ -56,20 +62,36 @@ impl UniswapV3State {
         tick: i32,
         ticks: Vec<TickInfo>,
     ) -> Self {
-        let spacing = UniswapV3State::get_spacing(fee);
-        let tick_list = TickList::from(spacing, ticks);
-        UniswapV3State { liquidity, sqrt_price, fee, tick, ticks: tick_list }
+        Self::new_with_tick_spacing(
+            liquidity,
+            sqrt_price,
+            fee as u32,
+            fee.tick_spacing(),
+            tick,
+            ticks,
+        )
     }
 
-    fn get_spacing(fee: FeeAmount) -> u16 {
-        match fee {
-            FeeAmount::Lowest => 1,
-            FeeAmount::Low => 10,
-            FeeAmount::MediumLow => 50,
-            FeeAmount::Medium => 60,
-            FeeAmount::MediumHigh => 100,
-            FeeAmount::High => 200,
-        }
+    /// Creates a new instance of `UniswapV3State` with an arbitrary fee and tick spacing, as used
+    /// by Uniswap V3 forks with custom fee tiers.
+    ///
+    /// # Arguments
+    /// - `liquidity`: The initial liquidity of the pool.
+    /// - `sqrt_price`: The square root of the current price.
+    /// - `fee`: The fee of the pool in hundredths of a bip, below 1_000_000.
+    /// - `tick_spacing`: The tick spacing of the pool.
+    /// - `tick`: The current tick of the pool.
+    /// - `ticks`: A vector of `TickInfo` representing the tick information for the pool.
+    pub fn new_with_tick_spacing(
+        liquidity: u128,
+        sqrt_price: U256,
+        fee: u32,
+        tick_spacing: u16,
+        tick: i32,
+        ticks: Vec<TickInfo>,
+    ) -> Self {
+        let tick_list = TickList::from(tick_spacing, ticks);
+        UniswapV3State { liquidity, sqrt_price, fee, tick, ticks: tick_list }
     }
 
     fn swap(
@@ -143,7 +165,7 @@ impl UniswapV3State {
                 UniswapV3State::get_sqrt_ratio_target(sqrt_price_next, price_limit, zero_for_one),
                 state.liquidity,
                 state.amount_remaining,
-                self.fee as u32,
+                self.fee,
             )?;
             state.sqrt_price = sqrt_price;
 
@@ -220,7 +242,7 @@ impl UniswapV3State {
 
 impl ProtocolSim for UniswapV3State {
     fn fee(&self) -> f64 {
-        (self.fee as u32) as f64 / 1_000_000.0
+        self.fee as f64 / 1_000_000.0
     }
 
     fn spot_price(&self, a: &Token, b: &Token) -> Result<f64, SimulationError> {
@@ -238,6 +260,7 @@ impl ProtocolSim for UniswapV3State {
         token_b: &Token,
     ) -> Result<GetAmountOutResult, SimulationError> {
         let zero_for_one = token_a < token_b;
+        let amount_in = amount_after_tax(&amount_in, token_a);
         let amount_specified = I256::checked_from_sign_and_abs(
             Sign::Positive,
             U256::from_be_slice(&amount_in.to_bytes_be()),
@@ -246,7 +269,9 @@ impl ProtocolSim for UniswapV3State {
             SimulationError::InvalidInput("I256 overflow: amount_in".to_string(), None)
         })?;
 
-        let result = self.swap(zero_for_one, amount_specified, None)?;
+        let result = self
+            .swap(zero_for_one, amount_specified, None)
+            .map_err(|err| tax_partial_amount_out(err, token_b))?;
 
         trace!(?amount_in, ?token_a, ?token_b, ?zero_for_one, ?result, "V3 SWAP");
         let mut new_state = self.clone();
@@ -254,13 +279,14 @@ impl ProtocolSim for UniswapV3State {
         new_state.tick = result.tick;
         new_state.sqrt_price = result.sqrt_price;
 
+        let amount_out = u256_to_biguint(
+            result
+                .amount_calculated
+                .abs()
+                .into_raw(),
+        );
         Ok(GetAmountOutResult::new(
-            u256_to_biguint(
-                result
-                    .amount_calculated
-                    .abs()
-                    .into_raw(),
-            ),
+            amount_after_tax(&amount_out, token_b),
             u256_to_biguint(result.gas_used),
             Box::new(new_state),
         ))
@@ -465,6 +491,17 @@ impl ProtocolSim for UniswapV3State {
     }
 }
 
+impl SwapFee for UniswapV3State {
+    fn swap_fee(
+        &self,
+        _token_in: &Token,
+        _token_out: &Token,
+        _amount_in: Option<&BigUint>,
+    ) -> Result<f64, SimulationError> {
+        Ok(self.fee())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
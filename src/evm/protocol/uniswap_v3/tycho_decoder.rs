@@ -10,11 +10,39 @@ use crate::{
     protocol::{errors::InvalidSnapshotError, models::TryFromWithBlock},
 };
 
+/// Largest tick spacing supported by the tick math, as enforced by Uniswap V3's factory
+const MAX_TICK_SPACING: u16 = 16_384;
+
+/// Fee tiers enabled by known Uniswap V3 forks, keyed by their Tycho protocol system. Pairs are
+/// `(fee, tick_spacing)`.
+const FORK_FEE_TIERS: [(&str, &[(u32, u16)]); 3] = [
+    ("uniswap_v3", &[(100, 1), (500, 10), (3_000, 60), (10_000, 200)]),
+    ("sushiswap_v3", &[(100, 1), (500, 10), (3_000, 60), (10_000, 200)]),
+    ("pancakeswap_v3", &[(100, 1), (500, 10), (2_500, 50), (10_000, 200)]),
+];
+
+/// Returns the tick spacing a known Uniswap V3 fork uses for `fee`.
+fn fork_tick_spacing(protocol_system: &str, fee: u32) -> Option<u16> {
+    FORK_FEE_TIERS
+        .iter()
+        .find(|(system, _)| *system == protocol_system)
+        .and_then(|(_, tiers)| {
+            tiers
+                .iter()
+                .find(|(tier_fee, _)| *tier_fee == fee)
+        })
+        .map(|(_, tick_spacing)| *tick_spacing)
+}
+
 impl TryFromWithBlock<ComponentWithState, BlockHeader> for UniswapV3State {
     type Error = InvalidSnapshotError;
 
     /// Decodes a `ComponentWithState` into a `UniswapV3State`. Errors with a `InvalidSnapshotError`
     /// if the snapshot is missing any required attributes or if the fee amount is not supported.
+    ///
+    /// The tick spacing is read from the optional `tick_spacing` static attribute, which allows
+    /// arbitrary fee tiers. Without it, the fee tier must be known for the component's protocol
+    /// system or be one of the `FeeAmount` tiers.
     async fn try_from_with_header(
         snapshot: ComponentWithState,
         _block: BlockHeader,
@@ -62,8 +90,37 @@ impl TryFromWithBlock<ComponentWithState, BlockHeader> for UniswapV3State {
                 .ok_or_else(|| InvalidSnapshotError::MissingAttribute("fee".to_string()))?
                 .clone(),
         );
-        let fee = FeeAmount::try_from(fee_value)
-            .map_err(|_| InvalidSnapshotError::ValueError("Unsupported fee amount".to_string()))?;
+        if !(0..1_000_000).contains(&fee_value) {
+            return Err(InvalidSnapshotError::ValueError("Unsupported fee amount".to_string()));
+        }
+        let fee = fee_value as u32;
+
+        let tick_spacing = match snapshot
+            .component
+            .static_attributes
+            .get("tick_spacing")
+        {
+            Some(tick_spacing) => {
+                let tick_spacing = i32::from(tick_spacing.clone());
+                u16::try_from(tick_spacing)
+                    .ok()
+                    .filter(|tick_spacing| (1..=MAX_TICK_SPACING).contains(tick_spacing))
+                    .ok_or_else(|| {
+                        InvalidSnapshotError::ValueError(format!(
+                            "Invalid tick spacing {tick_spacing}"
+                        ))
+                    })?
+            }
+            None => fork_tick_spacing(&snapshot.component.protocol_system, fee)
+                .or_else(|| {
+                    FeeAmount::try_from(fee_value)
+                        .ok()
+                        .map(|fee| fee.tick_spacing())
+                })
+                .ok_or_else(|| {
+                    InvalidSnapshotError::ValueError("Unsupported fee amount".to_string())
+                })?,
+        };
 
         let tick = snapshot
             .state
@@ -118,7 +175,14 @@ impl TryFromWithBlock<ComponentWithState, BlockHeader> for UniswapV3State {
 
         ticks.sort_by_key(|tick| tick.index);
 
-        Ok(UniswapV3State::new(liquidity, sqrt_price, fee, tick, ticks))
+        Ok(UniswapV3State::new_with_tick_spacing(
+            liquidity,
+            sqrt_price,
+            fee,
+            tick_spacing,
+            tick,
+            ticks,
+        ))
     }
 }
 
@@ -294,4 +358,132 @@ mod tests {
             InvalidSnapshotError::ValueError(err) if err == *"Unsupported fee amount"
         ));
     }
+
+    #[tokio::test]
+    async fn test_usv3_try_from_custom_tick_spacing() {
+        let mut component = usv3_component();
+        component
+            .static_attributes
+            .insert("fee".to_string(), Bytes::from(1_234_i32.to_be_bytes().to_vec()));
+        component
+            .static_attributes
+            .insert("tick_spacing".to_string(), Bytes::from(20_i32.to_be_bytes().to_vec()));
+
+        let snapshot = ComponentWithState {
+            state: ResponseProtocolState {
+                component_id: "State1".to_owned(),
+                attributes: usv3_attributes(),
+                balances: HashMap::new(),
+            },
+            component,
+            component_tvl: None,
+            entrypoints: Vec::new(),
+        };
+
+        let result = UniswapV3State::try_from_with_header(
+            snapshot,
+            header(),
+            &HashMap::new(),
+            &HashMap::new(),
+        )
+        .await;
+
+        let expected = UniswapV3State::new_with_tick_spacing(
+            100,
+            U256::from(200),
+            1_234,
+            20,
+            300,
+            vec![TickInfo::new(60, 400)],
+        );
+        assert_eq!(result.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    #[rstest]
+    #[case::pancakeswap_v3("pancakeswap_v3", 2_500, Some(50))]
+    #[case::uniswap_v3_medium("uniswap_v3", 3_000, Some(60))]
+    #[case::uniswap_v3_unknown_tier("uniswap_v3", 5_000, Some(100))]
+    #[case::unknown_tier("sushiswap_v3", 4_000, None)]
+    async fn test_usv3_try_from_fork_fee_tiers(
+        #[case] protocol_system: &str,
+        #[case] fee: i32,
+        #[case] expected_tick_spacing: Option<u16>,
+    ) {
+        let mut component = usv3_component();
+        component.protocol_system = protocol_system.to_string();
+        component
+            .static_attributes
+            .insert("fee".to_string(), Bytes::from(fee.to_be_bytes().to_vec()));
+
+        let snapshot = ComponentWithState {
+            state: ResponseProtocolState {
+                component_id: "State1".to_owned(),
+                attributes: usv3_attributes(),
+                balances: HashMap::new(),
+            },
+            component,
+            component_tvl: None,
+            entrypoints: Vec::new(),
+        };
+
+        let result = UniswapV3State::try_from_with_header(
+            snapshot,
+            header(),
+            &HashMap::new(),
+            &HashMap::new(),
+        )
+        .await;
+
+        match expected_tick_spacing {
+            Some(tick_spacing) => assert_eq!(
+                result.unwrap(),
+                UniswapV3State::new_with_tick_spacing(
+                    100,
+                    U256::from(200),
+                    fee as u32,
+                    tick_spacing,
+                    300,
+                    vec![TickInfo::new(60, 400)],
+                )
+            ),
+            None => assert!(matches!(
+                result.unwrap_err(),
+                InvalidSnapshotError::ValueError(err) if err == *"Unsupported fee amount"
+            )),
+        }
+    }
+
+    #[tokio::test]
+    #[rstest]
+    #[case::zero(0)]
+    #[case::negative(-10)]
+    #[case::too_large(20_000)]
+    async fn test_usv3_try_from_invalid_tick_spacing(#[case] tick_spacing: i32) {
+        let mut component = usv3_component();
+        component
+            .static_attributes
+            .insert("tick_spacing".to_string(), Bytes::from(tick_spacing.to_be_bytes().to_vec()));
+
+        let snapshot = ComponentWithState {
+            state: ResponseProtocolState {
+                component_id: "State1".to_owned(),
+                attributes: usv3_attributes(),
+                balances: HashMap::new(),
+            },
+            component,
+            component_tvl: None,
+            entrypoints: Vec::new(),
+        };
+
+        let result = UniswapV3State::try_from_with_header(
+            snapshot,
+            header(),
+            &HashMap::new(),
+            &HashMap::new(),
+        )
+        .await;
+
+        assert!(matches!(result.unwrap_err(), InvalidSnapshotError::ValueError(_)));
+    }
 }
@@ -1,2 +1,37 @@
 #![allow(dead_code)]
+use alloy::primitives::{address, Address};
+use tycho_common::models::Chain;
+
 pub const POOL_MANAGER_BYTECODE: &[u8] = include_bytes!("assets/pool_manager_bytecode.bin");
+
+// Hook permissions are encoded in the lowest bits of the hook address, see
+// https://github.com/Uniswap/v4-core/blob/main/src/libraries/Hooks.sol
+pub const BEFORE_SWAP_FLAG: u16 = 1 << 7;
+pub const AFTER_SWAP_FLAG: u16 = 1 << 6;
+pub const BEFORE_SWAP_RETURNS_DELTA_FLAG: u16 = 1 << 3;
+pub const AFTER_SWAP_RETURNS_DELTA_FLAG: u16 = 1 << 2;
+
+// LP fee flags, see https://github.com/Uniswap/v4-core/blob/main/src/libraries/LPFeeLibrary.sol
+/// Set as `key_lp_fee` for pools whose LP fee is managed by the hook
+pub const DYNAMIC_FEE_FLAG: u32 = 0x800000;
+/// Set on the fee returned by `beforeSwap` to override the LP fee of the swap
+pub const OVERRIDE_FEE_FLAG: u32 = 0x400000;
+pub const REMOVE_OVERRIDE_MASK: u32 = 0xBFFFFF;
+
+/// Sender passed to the hooks when simulating a swap
+pub const HOOK_SWAP_SENDER: Address = Address::ZERO;
+
+/// Returns the address of the Uniswap V4 pool manager on the given chain.
+pub fn pool_manager_address(chain: Chain) -> Option<Address> {
+    match chain {
+        Chain::Ethereum => Some(address!("000000000004444c5dc75cB358380D2e3dE08A90")),
+        Chain::Base => Some(address!("498581fF718922c3f8e6A244956aF099B2652b2b")),
+        Chain::Unichain => Some(address!("1F98400000000000000000000000000000000004")),
+        _ => None,
+    }
+}
+
+/// Returns whether the hook at `hook_address` has the given permission flag set.
+pub fn has_permission(hook_address: Address, flag: u16) -> bool {
+    u16::from_be_bytes([hook_address[18], hook_address[19]]) & flag != 0
+}
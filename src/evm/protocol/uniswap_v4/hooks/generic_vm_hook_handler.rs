@@ -254,15 +254,18 @@ where
         ))
     }
 
+    /// Ignores the delta, as the handler keeps no state derived from it.
+    ///
+    /// The handler only holds the hook and pool manager addresses. The hook's storage is read from
+    /// the simulation engine's database, which the decoder updates with the contract changes of
+    /// every block, so the next hook call already sees the new block's state.
     fn delta_transition(
         &mut self,
         _delta: ProtocolStateDelta,
         _tokens: &HashMap<Bytes, Token>,
         _balances: &Balances,
     ) -> Result<(), TransitionError<String>> {
-        Err(TransitionError::SimulationError(SimulationError::RecoverableError(
-            "delta_transition is not implemented for GenericVMHookHandler".to_string(),
-        )))
+        Ok(())
     }
 
     fn clone_box(&self) -> Box<dyn HookHandler> {
@@ -290,11 +293,40 @@ mod tests {
         engine_db::{
             create_engine,
             simulation_db::SimulationDB,
+            tycho_db::PreCachedDB,
             utils::{get_client, get_runtime},
         },
         protocol::uniswap_v4::{hooks::hook_handler::StateContext, state::UniswapV4Fees},
     };
 
+    #[test]
+    fn test_delta_transition_keeps_handler() {
+        let engine = create_engine(PreCachedDB::new().unwrap(), true).unwrap();
+        let mut hook_handler = GenericVMHookHandler::new(
+            Address::repeat_byte(1),
+            engine,
+            Address::from_str("0x000000000004444c5dc75cb358380d2e3de08a90").unwrap(),
+            HashMap::new(),
+            HashMap::new(),
+        )
+        .unwrap();
+        let before = hook_handler.clone();
+
+        let delta = ProtocolStateDelta {
+            component_id: "pool".to_string(),
+            updated_attributes: HashMap::from([(
+                "liquidity".to_string(),
+                Bytes::from(2000_u64.to_be_bytes().to_vec()),
+            )]),
+            deleted_attributes: Default::default(),
+        };
+        hook_handler
+            .delta_transition(delta, &HashMap::new(), &Balances::default())
+            .unwrap();
+
+        assert_eq!(hook_handler, before);
+    }
+
     #[test]
     fn test_before_swap() {
         let block = BlockHeader {
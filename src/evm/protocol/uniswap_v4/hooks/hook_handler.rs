@@ -42,6 +42,34 @@ pub struct BeforeSwapParameters {
 
 pub type BeforeSwapDelta = I256;
 
+fn i128_to_i256(value: i128) -> I256 {
+    I256::try_from(value).expect("i128 always fits into I256")
+}
+
+/// Splits a packed `BeforeSwapDelta` into its `(specified, unspecified)` token amounts.
+///
+/// The specified delta is stored in the upper 128 bits and the unspecified delta in the lower 128
+/// bits. Positive amounts are owed to the hook.
+pub fn split_before_swap_delta(delta: BeforeSwapDelta) -> (I256, I256) {
+    let raw = delta.into_raw();
+    let specified = (raw >> 128).to::<u128>() as i128;
+    let unspecified = (raw & U256::from(u128::MAX)).to::<u128>() as i128;
+    (i128_to_i256(specified), i128_to_i256(unspecified))
+}
+
+/// Packs the token amounts of a swap into a `BalanceDelta`, with `amount_0` in the upper 128 bits
+/// and `amount_1` in the lower 128 bits.
+pub fn to_balance_delta(amount_0: I256, amount_1: I256) -> Result<I256, SimulationError> {
+    let to_i128 = |amount: I256| {
+        i128::try_from(amount).map_err(|e| {
+            SimulationError::FatalError(format!("Balance delta overflows int128: {e:?}"))
+        })
+    };
+    let amount_0 = to_i128(amount_0)? as u128;
+    let amount_1 = to_i128(amount_1)? as u128;
+    Ok(I256::from_raw((U256::from(amount_0) << 128) | U256::from(amount_1)))
+}
+
 sol! {
     #[derive(Debug)]
     struct BeforeSwapSolOutput {
@@ -158,3 +186,25 @@ impl Clone for Box<dyn HookHandler> {
         self.clone_box()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_before_swap_delta_round_trip() {
+        let specified = I256::try_from(200_000_000_000_000_000i128).unwrap();
+        let unspecified = I256::try_from(-56_868_629_622_924_134_286_587i128).unwrap();
+
+        let packed = to_balance_delta(specified, unspecified).unwrap();
+
+        assert_eq!(split_before_swap_delta(packed), (specified, unspecified));
+    }
+
+    #[test]
+    fn test_to_balance_delta_overflow() {
+        let too_large = I256::try_from(i128::MAX).unwrap() + I256::ONE;
+
+        assert!(to_balance_delta(too_large, I256::ZERO).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-mod constants;
-mod generic_vm_hook_handler;
-mod hook_handler;
-mod hook_handler_creator;
+pub(crate) mod constants;
+pub mod generic_vm_hook_handler;
+pub mod hook_handler;
+pub mod hook_handler_creator;
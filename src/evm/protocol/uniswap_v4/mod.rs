@@ -1,3 +1,3 @@
-mod hooks;
+pub mod hooks;
 pub mod state;
 mod tycho_decoder;
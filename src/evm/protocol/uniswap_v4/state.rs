@@ -4,6 +4,7 @@ use alloy::primitives::{Sign, I256, U256};
 use num_bigint::BigUint;
 use num_traits::Zero;
 use tracing::trace;
+use tycho_client::feed::BlockHeader;
 use tycho_common::{
     dto::ProtocolStateDelta,
     models::token::Token,
@@ -14,29 +15,72 @@ use tycho_common::{
     Bytes,
 };
 
-use crate::evm::protocol::{
-    safe_math::{safe_add_u256, safe_sub_u256},
-    u256_num::u256_to_biguint,
-    utils::uniswap::{
-        i24_be_bytes_to_i32, liquidity_math,
-        sqrt_price_math::{get_amount0_delta, get_amount1_delta, sqrt_price_q96_to_f64},
-        swap_math,
-        tick_list::{TickInfo, TickList, TickListErrorKind},
-        tick_math::{
-            get_sqrt_ratio_at_tick, get_tick_at_sqrt_ratio, MAX_SQRT_RATIO, MAX_TICK,
-            MIN_SQRT_RATIO, MIN_TICK,
+use crate::{
+    evm::protocol::{
+        safe_math::{safe_add_u256, safe_sub_u256},
+        u256_num::u256_to_biguint,
+        uniswap_v4::hooks::{
+            constants::{
+                has_permission, AFTER_SWAP_FLAG, AFTER_SWAP_RETURNS_DELTA_FLAG, BEFORE_SWAP_FLAG,
+                BEFORE_SWAP_RETURNS_DELTA_FLAG, HOOK_SWAP_SENDER, OVERRIDE_FEE_FLAG,
+                REMOVE_OVERRIDE_MASK,
+            },
+            hook_handler::{
+                split_before_swap_delta, to_balance_delta, AfterSwapParameters,
+                BeforeSwapParameters, HookHandler, StateContext, SwapParams,
+            },
+        },
+        utils::{
+            bytes_to_address,
+            uniswap::{
+                i24_be_bytes_to_i32, liquidity_math,
+                sqrt_price_math::{get_amount0_delta, get_amount1_delta, sqrt_price_q96_to_f64},
+                swap_math,
+                tick_list::{TickInfo, TickList, TickListErrorKind},
+                tick_math::{
+                    get_sqrt_ratio_at_tick, get_tick_at_sqrt_ratio, MAX_SQRT_RATIO, MAX_TICK,
+                    MIN_SQRT_RATIO, MIN_TICK,
+                },
+                StepComputation, SwapResults, SwapState,
+            },
         },
-        StepComputation, SwapResults, SwapState,
+    },
+    protocol::{
+        fee::SwapFee,
+        target_block::{TargetBlock, TimeDependent},
+        transfer_tax::{amount_after_tax, tax_amount_out, tax_partial_amount_out},
     },
 };
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug)]
 pub struct UniswapV4State {
     liquidity: u128,
     sqrt_price: U256,
     fees: UniswapV4Fees,
     tick: i32,
     ticks: TickList,
+    /// Simulates the pool's hook, `None` for pools without hooks
+    hook_handler: Option<Box<dyn HookHandler>>,
+    /// Block used as context when calling the hook
+    block: BlockHeader,
+    /// Block hook calls are simulated in instead of `block`, if set
+    target_block: Option<TargetBlock>,
+}
+
+impl PartialEq for UniswapV4State {
+    fn eq(&self, other: &Self) -> bool {
+        let hooks_equal = match (&self.hook_handler, &other.hook_handler) {
+            (Some(hook), Some(other_hook)) => hook.is_equal(other_hook.as_ref()),
+            (None, None) => true,
+            _ => false,
+        };
+        self.liquidity == other.liquidity &&
+            self.sqrt_price == other.sqrt_price &&
+            self.fees == other.fees &&
+            self.tick == other.tick &&
+            self.ticks == other.ticks &&
+            hooks_equal
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -58,6 +102,13 @@ impl UniswapV4Fees {
         let protocol_fees = if zero_for_one { self.zero_for_one } else { self.one_for_zero };
         protocol_fees + self.lp_fee
     }
+
+    /// Overrides the LP fee if the fee returned by a hook has the override flag set.
+    fn apply_override(&mut self, hook_fee: u32) {
+        if hook_fee & OVERRIDE_FEE_FLAG != 0 {
+            self.lp_fee = hook_fee & REMOVE_OVERRIDE_MASK;
+        }
+    }
 }
 
 impl UniswapV4State {
@@ -78,7 +129,221 @@ impl UniswapV4State {
                 .expect("tick_spacing should always be positive"),
             ticks,
         );
-        UniswapV4State { liquidity, sqrt_price, fees, tick, ticks: tick_list }
+        UniswapV4State {
+            liquidity,
+            sqrt_price,
+            fees,
+            tick,
+            ticks: tick_list,
+            hook_handler: None,
+            block: BlockHeader::default(),
+            target_block: None,
+        }
+    }
+
+    /// Attaches the handler simulating the pool's hook.
+    ///
+    /// `block` is passed to the hook as execution context on every swap.
+    pub fn with_hook_handler(
+        mut self,
+        hook_handler: Box<dyn HookHandler>,
+        block: BlockHeader,
+    ) -> Self {
+        self.hook_handler = Some(hook_handler);
+        self.block = block;
+        self
+    }
+
+    /// Sets the block hook calls are made in to the latest indexed block.
+    pub(crate) fn set_block(&mut self, block: BlockHeader) {
+        self.block = block;
+    }
+
+    /// Returns the block hook calls are made in, the target block if set.
+    fn hook_block(&self) -> BlockHeader {
+        let mut block = self.block.clone();
+        if let Some(target_block) = self.target_block {
+            block.number = target_block.number;
+            block.timestamp = target_block.timestamp;
+        }
+        block
+    }
+
+    /// Quotes an exact input swap on a pool with a hook.
+    ///
+    /// Mirrors `PoolManager.swap`: `beforeSwap` may take part of the input (`amount_delta`) and
+    /// override the LP fee, the remaining input is swapped natively, and `afterSwap` may take
+    /// part of the output. Hook calls are only made if the hook address has the respective
+    /// permission flags set.
+    fn get_amount_out_with_hook(
+        &self,
+        hook_handler: &dyn HookHandler,
+        amount_in: I256,
+        zero_for_one: bool,
+        token_in: &Token,
+        token_out: &Token,
+    ) -> Result<GetAmountOutResult, SimulationError> {
+        let hook_address = hook_handler.address();
+        let (context, swap_params) =
+            self.hook_swap_context(amount_in, zero_for_one, token_in, token_out)?;
+
+        let mut hook_gas = U256::ZERO;
+        let mut fees = self.fees.clone();
+        let mut amount_to_swap = amount_in;
+        // Amount of the output token taken by the hook
+        let mut hook_delta_unspecified = I256::ZERO;
+        let mut overwrites = None;
+        let mut transient_storage = None;
+
+        if has_permission(hook_address, BEFORE_SWAP_FLAG) {
+            let before_swap = hook_handler.before_swap(
+                BeforeSwapParameters {
+                    context: context.clone(),
+                    sender: HOOK_SWAP_SENDER,
+                    swap_params: swap_params.clone(),
+                    hook_data: Bytes::new(),
+                },
+                self.hook_block(),
+                None,
+                None,
+            )?;
+            hook_gas = safe_add_u256(hook_gas, U256::from(before_swap.gas_estimate))?;
+            let output = before_swap.result;
+
+            fees.apply_override(output.fee.to());
+            if has_permission(hook_address, BEFORE_SWAP_RETURNS_DELTA_FLAG) {
+                let (delta_specified, delta_unspecified) =
+                    split_before_swap_delta(output.amount_delta);
+                amount_to_swap -= delta_specified;
+                hook_delta_unspecified = delta_unspecified;
+                if amount_to_swap.is_negative() {
+                    return Err(SimulationError::FatalError(
+                        "Hook delta exceeds swap amount".to_string(),
+                    ));
+                }
+            }
+            overwrites = Some(output.overwrites);
+            transient_storage = Some(output.transient_storage);
+        }
+
+        let mut new_state = self.clone();
+        let (swap_amount_out, swap_gas) = if amount_to_swap.is_zero() {
+            (I256::ZERO, U256::ZERO)
+        } else {
+            let mut swap_state = self.clone();
+            swap_state.fees = fees;
+            let result = swap_state.swap(zero_for_one, amount_to_swap, None)?;
+            new_state.liquidity = result.liquidity;
+            new_state.tick = result.tick;
+            new_state.sqrt_price = result.sqrt_price;
+            (-result.amount_calculated, result.gas_used)
+        };
+
+        if has_permission(hook_address, AFTER_SWAP_FLAG) {
+            let (amount_0, amount_1) = if zero_for_one {
+                (-amount_to_swap, swap_amount_out)
+            } else {
+                (swap_amount_out, -amount_to_swap)
+            };
+            let after_swap = hook_handler.after_swap(
+                AfterSwapParameters {
+                    context,
+                    sender: HOOK_SWAP_SENDER,
+                    swap_params,
+                    delta: to_balance_delta(amount_0, amount_1)?,
+                    hook_data: Bytes::new(),
+                },
+                self.hook_block(),
+                overwrites,
+                transient_storage,
+            )?;
+            hook_gas = safe_add_u256(hook_gas, U256::from(after_swap.gas_estimate))?;
+            if has_permission(hook_address, AFTER_SWAP_RETURNS_DELTA_FLAG) {
+                hook_delta_unspecified += after_swap.result;
+            }
+        }
+
+        let amount_out = swap_amount_out - hook_delta_unspecified;
+        if amount_out.is_negative() {
+            return Err(SimulationError::FatalError("Hook delta exceeds swap output".to_string()));
+        }
+
+        Ok(GetAmountOutResult::new(
+            u256_to_biguint(amount_out.into_raw()),
+            u256_to_biguint(safe_add_u256(swap_gas, hook_gas)?),
+            Box::new(new_state),
+        ))
+    }
+
+    /// Builds the pool context and swap parameters passed to the hook for an exact input swap.
+    fn hook_swap_context(
+        &self,
+        amount_in: I256,
+        zero_for_one: bool,
+        token_in: &Token,
+        token_out: &Token,
+    ) -> Result<(StateContext, SwapParams), SimulationError> {
+        let (currency_0, currency_1) =
+            if zero_for_one { (token_in, token_out) } else { (token_out, token_in) };
+        let context = StateContext {
+            currency_0: bytes_to_address(&currency_0.address)?,
+            currency_1: bytes_to_address(&currency_1.address)?,
+            fees: self.fees.clone(),
+            tick: self.tick,
+        };
+        let swap_params = SwapParams {
+            zero_for_one,
+            // Uniswap V4 uses negative amounts for exact input swaps
+            amount_specified: -amount_in,
+            sqrt_price_limit: Self::default_sqrt_price_limit(zero_for_one)?,
+        };
+        Ok((context, swap_params))
+    }
+
+    /// Returns the fees of a swap, including the LP fee override returned by `beforeSwap`.
+    fn hook_swap_fees(
+        &self,
+        hook_handler: &dyn HookHandler,
+        amount_in: I256,
+        zero_for_one: bool,
+        token_in: &Token,
+        token_out: &Token,
+    ) -> Result<UniswapV4Fees, SimulationError> {
+        let mut fees = self.fees.clone();
+        if !has_permission(hook_handler.address(), BEFORE_SWAP_FLAG) {
+            return Ok(fees);
+        }
+        let (context, swap_params) =
+            self.hook_swap_context(amount_in, zero_for_one, token_in, token_out)?;
+        let before_swap = hook_handler.before_swap(
+            BeforeSwapParameters {
+                context,
+                sender: HOOK_SWAP_SENDER,
+                swap_params,
+                hook_data: Bytes::new(),
+            },
+            self.hook_block(),
+            None,
+            None,
+        )?;
+        fees.apply_override(before_swap.result.fee.to());
+        Ok(fees)
+    }
+
+    fn amount_in_to_i256(amount_in: &BigUint) -> Result<I256, SimulationError> {
+        I256::checked_from_sign_and_abs(
+            Sign::Positive,
+            U256::from_be_slice(&amount_in.to_bytes_be()),
+        )
+        .ok_or_else(|| SimulationError::InvalidInput("I256 overflow: amount_in".to_string(), None))
+    }
+
+    fn default_sqrt_price_limit(zero_for_one: bool) -> Result<U256, SimulationError> {
+        if zero_for_one {
+            safe_add_u256(MIN_SQRT_RATIO, U256::from(1u64))
+        } else {
+            safe_sub_u256(MAX_SQRT_RATIO, U256::from(1u64))
+        }
     }
 
     fn swap(
@@ -90,12 +355,9 @@ impl UniswapV4State {
         if self.liquidity == 0 {
             return Err(SimulationError::RecoverableError("No liquidity".to_string()));
         }
-        let price_limit = if let Some(limit) = sqrt_price_limit {
-            limit
-        } else if zero_for_one {
-            safe_add_u256(MIN_SQRT_RATIO, U256::from(1u64))?
-        } else {
-            safe_sub_u256(MAX_SQRT_RATIO, U256::from(1u64))?
+        let price_limit = match sqrt_price_limit {
+            Some(limit) => limit,
+            None => Self::default_sqrt_price_limit(zero_for_one)?,
         };
 
         if zero_for_one {
@@ -229,12 +491,24 @@ impl UniswapV4State {
 }
 
 impl ProtocolSim for UniswapV4State {
-    // Not possible to implement correctly with the current interface because we need to know the
-    // swap direction.
+    /// Returns the highest fee of both swap directions, without hook fee overrides.
+    ///
+    /// Fees depend on the swap direction and, for pools with hooks, on the swap itself; use
+    /// [`SwapFee::swap_fee`] to get the fee of a specific swap.
     fn fee(&self) -> f64 {
-        todo!()
+        self.fees
+            .calculate_swap_fees_pips(true)
+            .max(
+                self.fees
+                    .calculate_swap_fees_pips(false),
+            ) as f64 /
+            1_000_000.0
     }
 
+    /// Returns the price of the pool's curve.
+    ///
+    /// Hooks are not called, so this ignores any fee or amount the hook of the pool takes. Use
+    /// `get_amount_out` to price pools with a hook.
     fn spot_price(&self, base: &Token, quote: &Token) -> Result<f64, SimulationError> {
         if base < quote {
             Ok(sqrt_price_q96_to_f64(self.sqrt_price, base.decimals, quote.decimals))
@@ -250,15 +524,25 @@ impl ProtocolSim for UniswapV4State {
         token_out: &Token,
     ) -> Result<GetAmountOutResult, SimulationError> {
         let zero_for_one = token_in < token_out;
-        let amount_specified = I256::checked_from_sign_and_abs(
-            Sign::Positive,
-            U256::from_be_slice(&amount_in.to_bytes_be()),
-        )
-        .ok_or_else(|| {
-            SimulationError::InvalidInput("I256 overflow: amount_in".to_string(), None)
-        })?;
+        let amount_in = amount_after_tax(&amount_in, token_in);
+        let amount_specified = Self::amount_in_to_i256(&amount_in)?;
+
+        if let Some(hook_handler) = &self.hook_handler {
+            return tax_amount_out(
+                self.get_amount_out_with_hook(
+                    hook_handler.as_ref(),
+                    amount_specified,
+                    zero_for_one,
+                    token_in,
+                    token_out,
+                ),
+                token_out,
+            );
+        }
 
-        let result = self.swap(zero_for_one, amount_specified, None)?;
+        let result = self
+            .swap(zero_for_one, amount_specified, None)
+            .map_err(|err| tax_partial_amount_out(err, token_out))?;
 
         trace!(?amount_in, ?token_in, ?token_out, ?zero_for_one, ?result, "V4 SWAP");
         let mut new_state = self.clone();
@@ -266,18 +550,23 @@ impl ProtocolSim for UniswapV4State {
         new_state.tick = result.tick;
         new_state.sqrt_price = result.sqrt_price;
 
+        let amount_out = u256_to_biguint(
+            result
+                .amount_calculated
+                .abs()
+                .into_raw(),
+        );
         Ok(GetAmountOutResult::new(
-            u256_to_biguint(
-                result
-                    .amount_calculated
-                    .abs()
-                    .into_raw(),
-            ),
+            amount_after_tax(&amount_out, token_out),
             u256_to_biguint(result.gas_used),
             Box::new(new_state),
         ))
     }
 
+    /// Returns the limits of the pool's liquidity.
+    ///
+    /// Hooks are not called, so the limits of pools whose hook takes part of the amounts, or
+    /// restricts swaps, may be off.
     fn get_limits(
         &self,
         token_in: Bytes,
@@ -369,9 +658,13 @@ impl ProtocolSim for UniswapV4State {
     fn delta_transition(
         &mut self,
         delta: ProtocolStateDelta,
-        _tokens: &HashMap<Bytes, Token>,
-        _balances: &Balances,
+        tokens: &HashMap<Bytes, Token>,
+        balances: &Balances,
     ) -> Result<(), TransitionError<String>> {
+        if let Some(hook_handler) = &mut self.hook_handler {
+            hook_handler.delta_transition(delta.clone(), tokens, balances)?;
+        }
+
         // Apply attribute changes
         if let Some(liquidity) = delta
             .updated_attributes
@@ -430,6 +723,7 @@ impl ProtocolSim for UniswapV4State {
                 )
             }
         }
+        self.target_block = None;
 
         Ok(())
     }
@@ -451,28 +745,108 @@ impl ProtocolSim for UniswapV4State {
             .as_any()
             .downcast_ref::<UniswapV4State>()
         {
-            self.liquidity == other_state.liquidity &&
-                self.sqrt_price == other_state.sqrt_price &&
-                self.fees == other_state.fees &&
-                self.tick == other_state.tick &&
-                self.ticks == other_state.ticks
+            self == other_state
         } else {
             false
         }
     }
 }
 
+impl TimeDependent for UniswapV4State {
+    /// Sets the block context of hook calls. Pools without a hook quote the same in any block.
+    fn set_target_block(&mut self, block: Option<TargetBlock>) {
+        self.target_block = block;
+    }
+}
+
+impl SwapFee for UniswapV4State {
+    fn swap_fee(
+        &self,
+        token_in: &Token,
+        token_out: &Token,
+        amount_in: Option<&BigUint>,
+    ) -> Result<f64, SimulationError> {
+        let zero_for_one = token_in < token_out;
+        let fees = match (&self.hook_handler, amount_in) {
+            (Some(hook_handler), Some(amount_in)) => self.hook_swap_fees(
+                hook_handler.as_ref(),
+                Self::amount_in_to_i256(amount_in)?,
+                zero_for_one,
+                token_in,
+                token_out,
+            )?,
+            _ => self.fees.clone(),
+        };
+        Ok(fees.calculate_swap_fees_pips(zero_for_one) as f64 / 1_000_000.0)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{collections::HashSet, fs, path::Path, str::FromStr};
 
+    use alloy::primitives::{aliases::U24, Address};
     use num_traits::FromPrimitive;
     use serde_json::Value;
     use tycho_client::feed::synchronizer::ComponentWithState;
     use tycho_common::models::Chain;
 
     use super::*;
-    use crate::protocol::models::TryFromWithBlock;
+    use crate::{
+        evm::protocol::uniswap_v4::hooks::hook_handler::{
+            AmountRanges, BeforeSwapDelta, BeforeSwapOutput, WithGasEstimate,
+        },
+        protocol::models::TryFromWithBlock,
+    };
+
+    #[test]
+    fn test_set_block() {
+        let mut pool = UniswapV4State::new(
+            1000,
+            U256::from_str("1000").unwrap(),
+            UniswapV4Fees { zero_for_one: 100, one_for_zero: 90, lp_fee: 700 },
+            100,
+            60,
+            vec![TickInfo::new(120, 10000), TickInfo::new(180, -10000)],
+        );
+        let block = BlockHeader { number: 2, timestamp: 24, ..Default::default() };
+
+        crate::evm::protocol::set_block(&mut pool, &block);
+
+        assert_eq!(pool.block, block);
+    }
+
+    #[test]
+    fn test_set_target_block() {
+        let mut pool = UniswapV4State::new(
+            1000,
+            U256::from_str("1000").unwrap(),
+            UniswapV4Fees { zero_for_one: 100, one_for_zero: 90, lp_fee: 700 },
+            100,
+            60,
+            vec![TickInfo::new(120, 10000), TickInfo::new(180, -10000)],
+        );
+        let block = BlockHeader { number: 2, timestamp: 24, ..Default::default() };
+        pool.set_block(block.clone());
+
+        pool.set_target_block(Some(TargetBlock::new(3, 36)));
+        let hook_block = pool.hook_block();
+        assert_eq!((hook_block.number, hook_block.timestamp), (3, 36));
+        assert_eq!(hook_block.hash, block.hash);
+
+        pool.set_target_block(None);
+        assert_eq!(pool.hook_block(), block);
+
+        pool.set_target_block(Some(TargetBlock::new(3, 36)));
+        let delta = ProtocolStateDelta {
+            component_id: "State1".to_owned(),
+            updated_attributes: HashMap::new(),
+            deleted_attributes: HashSet::new(),
+        };
+        pool.delta_transition(delta, &HashMap::new(), &Balances::default())
+            .unwrap();
+        assert_eq!(pool.hook_block(), block);
+    }
 
     #[test]
     fn test_delta_transition() {
@@ -529,6 +903,311 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_swap_fee() {
+        let pool = UniswapV4State::new(
+            1000,
+            U256::from_str("1000").unwrap(),
+            UniswapV4Fees { zero_for_one: 100, one_for_zero: 90, lp_fee: 700 },
+            100,
+            60,
+            vec![TickInfo::new(120, 10000), TickInfo::new(180, -10000)],
+        );
+        let token_0 = Token::new(
+            &Bytes::from_str("0x0000000000000000000000000000000000000001").unwrap(),
+            "T0",
+            18,
+            0,
+            &[],
+            Chain::Ethereum,
+            100,
+        );
+        let token_1 = Token::new(
+            &Bytes::from_str("0x0000000000000000000000000000000000000002").unwrap(),
+            "T1",
+            18,
+            0,
+            &[],
+            Chain::Ethereum,
+            100,
+        );
+
+        let zero_for_one = pool
+            .swap_fee(&token_0, &token_1, None)
+            .unwrap();
+        let one_for_zero = pool
+            .swap_fee(&token_1, &token_0, None)
+            .unwrap();
+
+        assert_eq!(zero_for_one, 0.0008);
+        assert_eq!(one_for_zero, 0.00079);
+        assert_eq!(pool.fee(), 0.0008);
+    }
+
+    /// Hook returning fixed deltas, fee and gas, with permissions given by its address.
+    #[derive(Debug, Clone)]
+    struct MockHookHandler {
+        address: Address,
+        before_swap_delta: BeforeSwapDelta,
+        fee: u32,
+        after_swap_delta: I256,
+        before_swap_gas: u64,
+        after_swap_gas: u64,
+    }
+
+    impl MockHookHandler {
+        fn new(flags: u16) -> Self {
+            let mut address = [0u8; 20];
+            address[18..].copy_from_slice(&flags.to_be_bytes());
+            Self {
+                address: Address::from(address),
+                before_swap_delta: I256::ZERO,
+                fee: 0,
+                after_swap_delta: I256::ZERO,
+                before_swap_gas: 1_000,
+                after_swap_gas: 2_000,
+            }
+        }
+    }
+
+    impl HookHandler for MockHookHandler {
+        fn address(&self) -> Address {
+            self.address
+        }
+
+        fn before_swap(
+            &self,
+            _params: BeforeSwapParameters,
+            _block: BlockHeader,
+            _overwrites: Option<HashMap<Address, HashMap<U256, U256>>>,
+            _transient_storage: Option<HashMap<Address, HashMap<U256, U256>>>,
+        ) -> Result<WithGasEstimate<BeforeSwapOutput>, SimulationError> {
+            Ok(WithGasEstimate {
+                gas_estimate: self.before_swap_gas,
+                result: BeforeSwapOutput {
+                    amount_delta: self.before_swap_delta,
+                    fee: U24::from(self.fee),
+                    overwrites: HashMap::new(),
+                    transient_storage: HashMap::new(),
+                },
+            })
+        }
+
+        fn after_swap(
+            &self,
+            _params: AfterSwapParameters,
+            _block: BlockHeader,
+            _overwrites: Option<HashMap<Address, HashMap<U256, U256>>>,
+            _transient_storage_params: Option<HashMap<Address, HashMap<U256, U256>>>,
+        ) -> Result<WithGasEstimate<BeforeSwapDelta>, SimulationError> {
+            Ok(WithGasEstimate { gas_estimate: self.after_swap_gas, result: self.after_swap_delta })
+        }
+
+        fn fee(
+            &self,
+            _context: &UniswapV4State,
+            _params: SwapParams,
+        ) -> Result<f64, SimulationError> {
+            Err(SimulationError::FatalError("not implemented".to_string()))
+        }
+
+        fn spot_price(&self, _base: &Token, _quote: &Token) -> Result<f64, SimulationError> {
+            Err(SimulationError::FatalError("not implemented".to_string()))
+        }
+
+        fn get_amount_ranges(
+            &self,
+            _token_in: Address,
+            _token_out: Address,
+        ) -> Result<AmountRanges, SimulationError> {
+            Err(SimulationError::FatalError("not implemented".to_string()))
+        }
+
+        fn delta_transition(
+            &mut self,
+            _delta: ProtocolStateDelta,
+            _tokens: &HashMap<Bytes, Token>,
+            _balances: &Balances,
+        ) -> Result<(), TransitionError<String>> {
+            Ok(())
+        }
+
+        fn clone_box(&self) -> Box<dyn HookHandler> {
+            Box::new(self.clone())
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn is_equal(&self, other: &dyn HookHandler) -> bool {
+            other
+                .as_any()
+                .downcast_ref::<Self>()
+                .is_some_and(|other| other.address == self.address)
+        }
+    }
+
+    /// A pool with a price of 1 and enough liquidity around it for the amounts of the hook tests.
+    fn hook_test_pool() -> UniswapV4State {
+        let liquidity = 10u128.pow(20);
+        UniswapV4State::new(
+            liquidity,
+            U256::from(1u8) << 96,
+            UniswapV4Fees { zero_for_one: 0, one_for_zero: 0, lp_fee: 500 },
+            0,
+            60,
+            vec![TickInfo::new(-600, liquidity as i128), TickInfo::new(600, -(liquidity as i128))],
+        )
+    }
+
+    fn hook_test_tokens() -> (Token, Token) {
+        let token = |address: &str, symbol: &str| {
+            Token::new(&Bytes::from_str(address).unwrap(), symbol, 18, 0, &[], Chain::Ethereum, 100)
+        };
+        (
+            token("0x0000000000000000000000000000000000000001", "T0"),
+            token("0x0000000000000000000000000000000000000002", "T1"),
+        )
+    }
+
+    /// Quotes selling `amount_in` of token 0 on `pool`, with `hook` attached if given.
+    fn quote_with_hook(
+        pool: &UniswapV4State,
+        hook: Option<MockHookHandler>,
+        amount_in: u64,
+    ) -> GetAmountOutResult {
+        let (token_0, token_1) = hook_test_tokens();
+        let pool = match hook {
+            Some(hook) => pool
+                .clone()
+                .with_hook_handler(Box::new(hook), BlockHeader::default()),
+            None => pool.clone(),
+        };
+        pool.get_amount_out(BigUint::from(amount_in), &token_0, &token_1)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_hook_before_swap_delta() {
+        let pool = hook_test_pool();
+        let mut hook = MockHookHandler::new(BEFORE_SWAP_FLAG | BEFORE_SWAP_RETURNS_DELTA_FLAG);
+        // The hook takes 400 of the input and 50 of the output
+        hook.before_swap_delta =
+            to_balance_delta(I256::try_from(400).unwrap(), I256::try_from(50).unwrap()).unwrap();
+
+        let res = quote_with_hook(&pool, Some(hook), 1_000_000);
+        let expected = quote_with_hook(&pool, None, 999_600);
+
+        assert_eq!(res.amount, &expected.amount - 50u32);
+        assert_eq!(res.gas, &expected.gas + 1_000u32);
+        let new_state = res
+            .new_state
+            .as_any()
+            .downcast_ref::<UniswapV4State>()
+            .unwrap();
+        let expected_state = expected
+            .new_state
+            .as_any()
+            .downcast_ref::<UniswapV4State>()
+            .unwrap();
+        assert_eq!(new_state.sqrt_price, expected_state.sqrt_price);
+
+        // Without the delta permission, the delta is ignored
+        let mut hook = MockHookHandler::new(BEFORE_SWAP_FLAG);
+        hook.before_swap_delta =
+            to_balance_delta(I256::try_from(400).unwrap(), I256::try_from(50).unwrap()).unwrap();
+        let res = quote_with_hook(&pool, Some(hook), 1_000_000);
+        assert_eq!(res.amount, quote_with_hook(&pool, None, 1_000_000).amount);
+    }
+
+    #[test]
+    fn test_hook_before_swap_delta_consumes_amount_in() {
+        let pool = hook_test_pool();
+        let mut hook = MockHookHandler::new(BEFORE_SWAP_FLAG | BEFORE_SWAP_RETURNS_DELTA_FLAG);
+        // The hook fills the whole swap itself, paying 990_000 of token 1
+        hook.before_swap_delta =
+            to_balance_delta(I256::try_from(1_000_000).unwrap(), I256::try_from(-990_000).unwrap())
+                .unwrap();
+
+        let res = quote_with_hook(&pool, Some(hook), 1_000_000);
+
+        assert_eq!(res.amount, BigUint::from(990_000u32));
+        // No pool swap is executed
+        assert_eq!(res.gas, BigUint::from(1_000u32));
+        let new_state = res
+            .new_state
+            .as_any()
+            .downcast_ref::<UniswapV4State>()
+            .unwrap();
+        assert_eq!(new_state.sqrt_price, pool.sqrt_price);
+        assert_eq!(new_state.tick, pool.tick);
+    }
+
+    #[test]
+    fn test_hook_fee_override() {
+        let pool = hook_test_pool();
+        let (token_0, token_1) = hook_test_tokens();
+        let mut overridden = pool.clone();
+        overridden.fees.lp_fee = 3_000;
+
+        let mut hook = MockHookHandler::new(BEFORE_SWAP_FLAG);
+        hook.fee = OVERRIDE_FEE_FLAG | 3_000;
+        let res = quote_with_hook(&pool, Some(hook.clone()), 1_000_000);
+        assert_eq!(res.amount, quote_with_hook(&overridden, None, 1_000_000).amount);
+        assert_eq!(res.gas, &quote_with_hook(&overridden, None, 1_000_000).gas + 1_000u32);
+
+        let with_hook = pool
+            .clone()
+            .with_hook_handler(Box::new(hook), BlockHeader::default());
+        let amount_in = BigUint::from(1_000_000u32);
+        assert_eq!(
+            with_hook
+                .swap_fee(&token_0, &token_1, Some(&amount_in))
+                .unwrap(),
+            0.003
+        );
+
+        // Without the override flag, the pool's LP fee applies
+        let mut hook = MockHookHandler::new(BEFORE_SWAP_FLAG);
+        hook.fee = 3_000;
+        let res = quote_with_hook(&pool, Some(hook), 1_000_000);
+        assert_eq!(res.amount, quote_with_hook(&pool, None, 1_000_000).amount);
+    }
+
+    #[test]
+    fn test_hook_after_swap_delta() {
+        let pool = hook_test_pool();
+        let expected = quote_with_hook(&pool, None, 1_000_000);
+
+        let mut hook = MockHookHandler::new(AFTER_SWAP_FLAG | AFTER_SWAP_RETURNS_DELTA_FLAG);
+        // The hook takes 70 of the output
+        hook.after_swap_delta = I256::try_from(70).unwrap();
+        let res = quote_with_hook(&pool, Some(hook.clone()), 1_000_000);
+        assert_eq!(res.amount, &expected.amount - 70u32);
+        assert_eq!(res.gas, &expected.gas + 2_000u32);
+
+        // Without the delta permission, the delta is ignored
+        hook.address = MockHookHandler::new(AFTER_SWAP_FLAG).address;
+        let res = quote_with_hook(&pool, Some(hook), 1_000_000);
+        assert_eq!(res.amount, expected.amount);
+    }
+
+    #[test]
+    fn test_hook_gas() {
+        let pool = hook_test_pool();
+        let expected = quote_with_hook(&pool, None, 1_000_000);
+
+        // Only the hook calls the address has permissions for are made and paid for
+        let hook = MockHookHandler::new(0);
+        assert_eq!(quote_with_hook(&pool, Some(hook), 1_000_000).gas, expected.gas);
+
+        let hook = MockHookHandler::new(BEFORE_SWAP_FLAG | AFTER_SWAP_FLAG);
+        let res = quote_with_hook(&pool, Some(hook), 1_000_000);
+        assert_eq!(res.amount, expected.amount);
+        assert_eq!(res.gas, &expected.gas + 3_000u32);
+    }
+
     #[tokio::test]
     /// Compares a quote that we got from the UniswapV4 Quoter contract on Sepolia with a simulation
     /// using Tycho-simulation and a state extracted with Tycho-indexer
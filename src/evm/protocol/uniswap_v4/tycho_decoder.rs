@@ -1,13 +1,22 @@
 use std::collections::HashMap;
 
-use alloy::primitives::U256;
+use alloy::primitives::{Address, U256};
 use tycho_client::feed::{synchronizer::ComponentWithState, BlockHeader};
-use tycho_common::{models::token::Token, Bytes};
+use tycho_common::{
+    models::{token::Token, Chain},
+    Bytes,
+};
 
 use super::state::UniswapV4State;
 use crate::{
     evm::protocol::{
-        uniswap_v4::state::UniswapV4Fees,
+        uniswap_v4::{
+            hooks::{
+                constants::{pool_manager_address, DYNAMIC_FEE_FLAG},
+                hook_handler_creator::{instantiate_hook_handler, HookCreationParams},
+            },
+            state::UniswapV4Fees,
+        },
         utils::uniswap::{i24_be_bytes_to_i32, tick_list::TickInfo},
     },
     protocol::{errors::InvalidSnapshotError, models::TryFromWithBlock},
@@ -20,9 +29,9 @@ impl TryFromWithBlock<ComponentWithState, BlockHeader> for UniswapV4State {
     /// if the snapshot is missing any required attributes.
     async fn try_from_with_header(
         snapshot: ComponentWithState,
-        _block: BlockHeader,
-        _account_balances: &HashMap<Bytes, HashMap<Bytes, Bytes>>,
-        _all_tokens: &HashMap<Bytes, Token>,
+        block: BlockHeader,
+        account_balances: &HashMap<Bytes, HashMap<Bytes, Bytes>>,
+        all_tokens: &HashMap<Bytes, Token>,
     ) -> Result<Self, Self::Error> {
         let liq = snapshot
             .state
@@ -41,7 +50,7 @@ impl TryFromWithBlock<ComponentWithState, BlockHeader> for UniswapV4State {
                 .ok_or_else(|| InvalidSnapshotError::MissingAttribute("sqrt_price".to_string()))?,
         );
 
-        let lp_fee = u32::from(
+        let mut lp_fee = u32::from(
             snapshot
                 .component
                 .static_attributes
@@ -49,6 +58,15 @@ impl TryFromWithBlock<ComponentWithState, BlockHeader> for UniswapV4State {
                 .ok_or_else(|| InvalidSnapshotError::MissingAttribute("key_lp_fee".to_string()))?
                 .clone(),
         );
+        // The LP fee of dynamic fee pools is set by the hook and tracked in the state
+        if lp_fee == DYNAMIC_FEE_FLAG {
+            lp_fee = snapshot
+                .state
+                .attributes
+                .get("fee")
+                .map(|fee| u32::from(fee.clone()))
+                .ok_or_else(|| InvalidSnapshotError::MissingAttribute("fee".to_string()))?;
+        }
 
         let zero2one_protocol_fee = u32::from(
             snapshot
@@ -120,7 +138,56 @@ impl TryFromWithBlock<ComponentWithState, BlockHeader> for UniswapV4State {
 
         ticks.sort_by_key(|tick| tick.index);
 
-        Ok(UniswapV4State::new(liquidity, sqrt_price, fees, tick, tick_spacing, ticks))
+        let state = UniswapV4State::new(liquidity, sqrt_price, fees, tick, tick_spacing, ticks);
+
+        let hook_address = match snapshot
+            .component
+            .static_attributes
+            .get("hooks")
+        {
+            Some(hooks) if hooks.len() == 20 => Address::from_slice(hooks),
+            Some(hooks) => {
+                return Err(InvalidSnapshotError::ValueError(format!(
+                    "Invalid hook address: {hooks}"
+                )))
+            }
+            None => Address::ZERO,
+        };
+        if hook_address == Address::ZERO {
+            return Ok(state);
+        }
+
+        // The hook handler creators expect the hook and pool manager addresses as attributes
+        let mut attributes = snapshot
+            .component
+            .static_attributes
+            .clone();
+        attributes.extend(snapshot.state.attributes.clone());
+        attributes.insert("hook_address".to_string(), Bytes::from(hook_address.to_vec()));
+        if !attributes.contains_key("pool_manager_address") {
+            let chain = Chain::from(snapshot.component.chain);
+            let pool_manager = pool_manager_address(chain).ok_or_else(|| {
+                InvalidSnapshotError::ValueError(format!(
+                    "Unknown Uniswap V4 pool manager on {chain}"
+                ))
+            })?;
+            attributes
+                .insert("pool_manager_address".to_string(), Bytes::from(pool_manager.to_vec()));
+        }
+
+        let hook_handler = instantiate_hook_handler(
+            &hook_address,
+            HookCreationParams::new(
+                block.clone(),
+                account_balances,
+                all_tokens,
+                state.clone(),
+                &attributes,
+                &snapshot.state.balances,
+            ),
+        )?;
+
+        Ok(state.with_hook_handler(hook_handler, block))
     }
 }
 
@@ -219,6 +286,76 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[tokio::test]
+    async fn test_usv4_try_from_dynamic_fee() {
+        let mut component = usv4_component();
+        component
+            .static_attributes
+            .insert("key_lp_fee".to_string(), Bytes::from(DYNAMIC_FEE_FLAG.to_be_bytes().to_vec()));
+        let mut attributes = usv4_attributes();
+        attributes.insert("fee".to_string(), Bytes::from(3000_u32.to_be_bytes().to_vec()));
+        let snapshot = ComponentWithState {
+            state: ResponseProtocolState {
+                component_id: "State1".to_owned(),
+                attributes,
+                balances: HashMap::new(),
+            },
+            component,
+            component_tvl: None,
+            entrypoints: Vec::new(),
+        };
+
+        let result = UniswapV4State::try_from_with_header(
+            snapshot,
+            header(),
+            &HashMap::new(),
+            &HashMap::new(),
+        )
+        .await
+        .unwrap();
+
+        let expected = UniswapV4State::new(
+            100,
+            U256::from(79228162514264337593543950336_u128),
+            UniswapV4Fees::new(0, 0, 3000),
+            300,
+            60,
+            vec![TickInfo::new(60, 400)],
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[tokio::test]
+    async fn test_usv4_try_from_dynamic_fee_missing_fee() {
+        let mut component = usv4_component();
+        component
+            .static_attributes
+            .insert("key_lp_fee".to_string(), Bytes::from(DYNAMIC_FEE_FLAG.to_be_bytes().to_vec()));
+        let snapshot = ComponentWithState {
+            state: ResponseProtocolState {
+                component_id: "State1".to_owned(),
+                attributes: usv4_attributes(),
+                balances: HashMap::new(),
+            },
+            component,
+            component_tvl: None,
+            entrypoints: Vec::new(),
+        };
+
+        let result = UniswapV4State::try_from_with_header(
+            snapshot,
+            header(),
+            &HashMap::new(),
+            &HashMap::new(),
+        )
+        .await;
+
+        assert!(matches!(
+            result.unwrap_err(),
+            InvalidSnapshotError::MissingAttribute(attribute) if attribute == "fee"
+        ));
+    }
+
     #[tokio::test]
     #[rstest]
     #[case::missing_liquidity("liquidity")]
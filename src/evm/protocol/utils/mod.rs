@@ -1,8 +1,15 @@
 pub mod uniswap;
 
-use alloy::primitives::Address;
+use alloy::primitives::{Address, U256};
+use num_bigint::ToBigUint;
+use tycho_client::feed::synchronizer::ComponentWithState;
 use tycho_common::{simulation::errors::SimulationError, Bytes};
 
+use crate::{
+    evm::protocol::{u256_num::biguint_to_u256, vm::utils::json_deserialize_be_bigint_list},
+    protocol::errors::InvalidSnapshotError,
+};
+
 /// Safely converts a `Bytes` object to an `Address` object.
 ///
 /// Checks the length of the `Bytes` before attempting to convert, and returns a `SimulationError`
@@ -18,6 +25,43 @@ pub(crate) fn bytes_to_address(address: &Bytes) -> Result<Address, SimulationErr
     }
 }
 
+/// Decodes a JSON list of big endian encoded unsigned integers, as used for list attributes.
+pub(crate) fn decode_u256_list(value: &Bytes) -> Result<Vec<U256>, SimulationError> {
+    json_deserialize_be_bigint_list(value)?
+        .into_iter()
+        .map(|value| {
+            value
+                .to_biguint()
+                .map(|value| biguint_to_u256(&value))
+                .ok_or_else(|| SimulationError::FatalError(format!("Negative value: {value}")))
+        })
+        .collect()
+}
+
+/// Looks up an attribute of a snapshot in the state attributes first, then in the static
+/// attributes.
+pub(crate) fn attribute<'a>(snapshot: &'a ComponentWithState, name: &str) -> Option<&'a Bytes> {
+    snapshot
+        .state
+        .attributes
+        .get(name)
+        .or_else(|| {
+            snapshot
+                .component
+                .static_attributes
+                .get(name)
+        })
+}
+
+/// Looks up an attribute like [`attribute`], failing if the snapshot doesn't have it.
+pub(crate) fn required_attribute<'a>(
+    snapshot: &'a ComponentWithState,
+    name: &str,
+) -> Result<&'a Bytes, InvalidSnapshotError> {
+    attribute(snapshot, name)
+        .ok_or_else(|| InvalidSnapshotError::MissingAttribute(name.to_string()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -54,6 +54,7 @@ where
     <D as DatabaseRef>::Error: Debug,
     <D as EngineDatabaseInterface>::Error: Debug,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn price(
         &self,
         pair_id: &str,
@@ -61,13 +62,14 @@ where
         buy_token: Address,
         amounts: Vec<U256>,
         block: u64,
+        timestamp: Option<u64>,
         overwrites: Option<HashMap<Address, Overwrites>>,
     ) -> Result<Vec<f64>, SimulationError> {
         let args = (string_to_bytes32(pair_id)?, sell_token, buy_token, amounts);
         let selector = "price(bytes32,address,address,uint256[])";
 
         let res = self
-            .call(selector, args, block, None, overwrites, None, U256::from(0u64), None)?
+            .call(selector, args, block, timestamp, overwrites, None, U256::from(0u64), None)?
             .return_value;
 
         let decoded: PriceReturn = PriceReturn::abi_decode(&res).map_err(|e| {
@@ -87,13 +89,14 @@ where
         is_buy: bool,
         amount: U256,
         block: u64,
+        timestamp: Option<u64>,
         overwrites: Option<HashMap<Address, HashMap<U256, U256>>>,
     ) -> Result<(Trade, HashMap<Address, StateUpdate>), SimulationError> {
         let args = (string_to_bytes32(pair_id)?, sell_token, buy_token, is_buy, amount);
         let selector = "swap(bytes32,address,address,uint8,uint256)";
 
         let res =
-            self.call(selector, args, block, None, overwrites, None, U256::from(0u64), None)?;
+            self.call(selector, args, block, timestamp, overwrites, None, U256::from(0u64), None)?;
 
         let decoded: SwapReturn = SwapReturn::abi_decode(&res.return_value).map_err(|_| {
             SimulationError::FatalError(format!(
@@ -123,13 +126,14 @@ where
         sell_token: Address,
         buy_token: Address,
         block: u64,
+        timestamp: Option<u64>,
         overwrites: Option<HashMap<Address, HashMap<U256, U256>>>,
     ) -> Result<(U256, U256), SimulationError> {
         let args = (string_to_bytes32(pair_id)?, sell_token, buy_token);
         let selector = "getLimits(bytes32,address,address)";
 
         let res = self
-            .call(selector, args, block, None, overwrites, None, U256::from(0u64), None)?
+            .call(selector, args, block, timestamp, overwrites, None, U256::from(0u64), None)?
             .return_value;
 
         let decoded: LimitsReturn = LimitsReturn::abi_decode(&res).map_err(|e| {
@@ -0,0 +1,174 @@
+//! Registry of the adapter bytecode used to simulate `vm:` protocol systems.
+//!
+//! Adapters of the protocols supported by this crate are embedded (see
+//! [`get_adapter_file`]). Applications can register the adapter of any other `vm:<name>`
+//! protocol system, or override an embedded one, at runtime. Registered adapters are used by the
+//! `EVMPoolState` decoder.
+use std::{collections::HashMap, fs, path::Path, sync::RwLock};
+
+use alloy::primitives::{keccak256, Address, Bytes};
+use lazy_static::lazy_static;
+use revm::{
+    primitives::KECCAK_EMPTY,
+    state::{AccountInfo, Bytecode},
+};
+use tycho_common::simulation::errors::SimulationError;
+
+use super::{
+    constants::{get_adapter_file, EXTERNAL_ACCOUNT, MAX_BALANCE},
+    tycho_simulation_contract::TychoSimulationContract,
+};
+use crate::evm::engine_db::{create_engine, tycho_db::PreCachedDB};
+
+lazy_static! {
+    static ref ADAPTERS: RwLock<HashMap<String, Bytes>> = RwLock::new(HashMap::new());
+}
+
+/// Registers the adapter runtime bytecode of a protocol system.
+///
+/// `protocol` is the protocol system with or without its `vm:` prefix, e.g. `vm:my_protocol`.
+/// The bytecode is validated by calling its `getCapabilities` function in an empty VM, so it
+/// must answer without reading any protocol state. Registering a protocol again replaces its
+/// adapter. The adapter is deployed at the name left padded into an address, or at the hash of
+/// the name if it is longer than 20 bytes.
+pub fn register_adapter(protocol: &str, bytecode: impl Into<Bytes>) -> Result<(), SimulationError> {
+    let bytecode = bytecode.into();
+    validate_adapter(&bytecode)?;
+    ADAPTERS
+        .write()
+        .map_err(|_| SimulationError::FatalError("Adapter registry lock poisoned".to_string()))?
+        .insert(protocol_name(protocol).to_string(), bytecode);
+    Ok(())
+}
+
+/// Registers the adapter runtime bytecode of a protocol system from a file, see
+/// [`register_adapter`].
+///
+/// The file contains the raw runtime bytecode, like the `.evm.runtime` files embedded in this
+/// crate.
+pub fn register_adapter_file(
+    protocol: &str,
+    path: impl AsRef<Path>,
+) -> Result<(), SimulationError> {
+    let path = path.as_ref();
+    let bytecode = fs::read(path).map_err(|err| {
+        SimulationError::FatalError(format!("Failed to read adapter file {path:?}: {err:?}"))
+    })?;
+    register_adapter(protocol, bytecode)
+}
+
+/// Returns the adapter runtime bytecode of a protocol system, preferring registered adapters
+/// over the embedded ones.
+pub fn get_adapter_bytecode(protocol: &str) -> Result<Bytes, SimulationError> {
+    let protocol = protocol_name(protocol);
+    if let Some(bytecode) = ADAPTERS
+        .read()
+        .map_err(|_| SimulationError::FatalError("Adapter registry lock poisoned".to_string()))?
+        .get(protocol)
+    {
+        return Ok(bytecode.clone());
+    }
+    get_adapter_file(protocol).map(Bytes::from_static)
+}
+
+/// Returns the address the adapter of a protocol system is deployed at in the VM.
+///
+/// Names of up to 20 bytes are left padded into the address, e.g. `vm:curve` is simulated at
+/// `0x0000000000000000000000000000006375727665`. Longer names don't fit, so their address is
+/// the last 20 bytes of the keccak hash of the name instead.
+pub(crate) fn adapter_address(protocol: &str) -> Address {
+    let name = protocol_name(protocol).as_bytes();
+    if name.len() > Address::len_bytes() {
+        return Address::from_word(keccak256(name));
+    }
+    let mut address = [0u8; 20];
+    address[20 - name.len()..].copy_from_slice(name);
+    Address::from(address)
+}
+
+fn protocol_name(protocol: &str) -> &str {
+    protocol
+        .strip_prefix("vm:")
+        .unwrap_or(protocol)
+}
+
+/// Checks that `bytecode` implements the adapter interface by calling `getCapabilities`.
+fn validate_adapter(bytecode: &Bytes) -> Result<(), SimulationError> {
+    let db = PreCachedDB::new().map_err(|err| {
+        SimulationError::FatalError(format!("Failed to create adapter validation DB: {err:?}"))
+    })?;
+    let engine = create_engine(db, false)?;
+    engine.state.init_account(
+        *EXTERNAL_ACCOUNT,
+        AccountInfo { balance: *MAX_BALANCE, nonce: 0, code_hash: KECCAK_EMPTY, code: None },
+        None,
+        false,
+    );
+
+    let adapter = TychoSimulationContract::new_contract(
+        Address::repeat_byte(0xad),
+        Bytecode::new_raw(bytecode.clone()),
+        engine,
+    )?;
+    adapter
+        .get_capabilities("", Address::ZERO, Address::repeat_byte(1))
+        .map_err(|err| {
+            SimulationError::FatalError(format!(
+                "Adapter bytecode does not answer getCapabilities: {err:?}"
+            ))
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::evm::protocol::vm::constants::{BALANCER_V2, CURVE};
+
+    #[test]
+    fn test_register_adapter() {
+        register_adapter("vm:test_registered_balancer", BALANCER_V2).unwrap();
+
+        assert_eq!(
+            get_adapter_bytecode("vm:test_registered_balancer").unwrap(),
+            Bytes::from_static(BALANCER_V2)
+        );
+        assert_eq!(
+            get_adapter_bytecode("test_registered_balancer").unwrap(),
+            Bytes::from_static(BALANCER_V2)
+        );
+    }
+
+    #[test]
+    fn test_register_invalid_adapter() {
+        // A single STOP opcode returns nothing
+        let result = register_adapter("vm:test_invalid_adapter", vec![0u8]);
+
+        assert!(matches!(result, Err(SimulationError::FatalError(_))));
+        assert!(get_adapter_bytecode("vm:test_invalid_adapter").is_err());
+    }
+
+    #[test]
+    fn test_adapter_address() {
+        assert_eq!(
+            adapter_address("vm:curve"),
+            Address::from_str("0x0000000000000000000000000000006375727665").unwrap()
+        );
+        assert_eq!(adapter_address("curve"), adapter_address("vm:curve"));
+
+        // Names longer than an address are hashed
+        let long_name = "vm:a_protocol_with_a_long_name";
+        assert_eq!(adapter_address(long_name), Address::from_word(keccak256(&long_name[3..])));
+        assert_ne!(
+            adapter_address(long_name),
+            adapter_address("vm:another_protocol_with_a_long_name")
+        );
+    }
+
+    #[test]
+    fn test_get_embedded_adapter() {
+        assert_eq!(get_adapter_bytecode("vm:curve").unwrap(), Bytes::from_static(CURVE));
+    }
+}
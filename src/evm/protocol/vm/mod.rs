@@ -1,4 +1,5 @@
 mod adapter_contract;
+pub mod adapter_registry;
 pub mod constants;
 pub mod erc20_token;
 mod models;
@@ -27,14 +27,26 @@ use super::{
     models::Capability,
     tycho_simulation_contract::TychoSimulationContract,
 };
-use crate::evm::{
-    engine_db::{engine_db_interface::EngineDatabaseInterface, tycho_db::PreCachedDB},
+use crate::{
+    evm::{
+        engine_db::{engine_db_interface::EngineDatabaseInterface, tycho_db::PreCachedDB},
+        protocol::{
+            u256_num::{biguint_to_u256, u256_to_biguint, u256_to_f64},
+            utils::bytes_to_address,
+        },
+    },
     protocol::{
-        u256_num::{u256_to_biguint, u256_to_f64},
-        utils::bytes_to_address,
+        fee::{default_fee_probe_amount, round_trip_fee, SwapFee},
+        target_block::{TargetBlock, TimeDependent},
     },
 };
 
+/// Overwrites and sell limit shared by the swaps of a token pair.
+struct SellContext {
+    overwrites: HashMap<Address, Overwrites>,
+    sell_amount_limit: U256,
+}
+
 #[derive(Clone, Debug)]
 pub struct EVMPoolState<D: EngineDatabaseInterface + Clone + Debug>
 where
@@ -47,6 +59,9 @@ where
     pub tokens: Vec<Bytes>,
     /// The current block, will be used to set vm context
     block: BlockHeader,
+    /// The block simulations are run in, if set. Defaults to the current block and wall-clock
+    /// time.
+    target_block: Option<TargetBlock>,
     /// The pool's component balances.
     balances: HashMap<Address, U256>,
     /// The contract address for where protocol balances are stored (i.e. a vault contract).
@@ -102,6 +117,7 @@ where
             id,
             tokens,
             block,
+            target_block: None,
             balances: component_balances,
             balance_owner,
             spot_prices,
@@ -114,6 +130,14 @@ where
         }
     }
 
+    /// Returns the block number and timestamp simulations are run at.
+    fn simulation_block(&self) -> (u64, Option<u64>) {
+        match self.target_block {
+            Some(block) => (block.number, Some(block.timestamp)),
+            None => (self.block.number, None),
+        }
+    }
+
     /// Ensures the pool supports the given capability
     ///
     /// # Arguments
@@ -192,12 +216,14 @@ where
                         vec![sell_token_address, buy_token_address],
                         overwrites.clone(),
                     )?;
+                    let (block_number, timestamp) = self.simulation_block();
                     let price_result = self.adapter_contract.price(
                         &self.id,
                         sell_token_address,
                         buy_token_address,
                         vec![sell_amount_limit / U256::from(100)],
-                        self.block.number,
+                        block_number,
+                        timestamp,
                         overwrites,
                     )?;
 
@@ -247,11 +273,22 @@ where
                     // limit
                     let x2 = x1 + (x1 / U256::from(100));
 
+                    let (block_number, timestamp) = self.simulation_block();
+
                     // Perform a swap for the first sell amount (x1) and retrieve the received
                     // amount (y1).
                     let y1 = self
                         .adapter_contract
-                        .swap(&self.id, t0, t1, false, x1, self.block.number, overwrites.clone())?
+                        .swap(
+                            &self.id,
+                            t0,
+                            t1,
+                            false,
+                            x1,
+                            block_number,
+                            timestamp,
+                            overwrites.clone(),
+                        )?
                         .0
                         .received_amount;
 
@@ -259,7 +296,7 @@ where
                     // amount (y2).
                     let y2 = self
                         .adapter_contract
-                        .swap(&self.id, t0, t1, false, x2, self.block.number, overwrites)?
+                        .swap(&self.id, t0, t1, false, x2, block_number, timestamp, overwrites)?
                         .0
                         .received_amount;
 
@@ -321,11 +358,13 @@ where
         tokens: Vec<Address>,
         overwrites: Option<HashMap<Address, HashMap<U256, U256>>>,
     ) -> Result<(U256, U256), SimulationError> {
+        let (block_number, timestamp) = self.simulation_block();
         let limits = self.adapter_contract.get_limits(
             &self.id,
             tokens[0],
             tokens[1],
-            self.block.number,
+            block_number,
+            timestamp,
             overwrites,
         )?;
 
@@ -507,53 +546,13 @@ where
         merged
     }
 
-    #[cfg(test)]
-    pub fn get_involved_contracts(&self) -> HashSet<Address> {
-        self.involved_contracts.clone()
-    }
-
-    #[cfg(test)]
-    pub fn get_manual_updates(&self) -> bool {
-        self.manual_updates
-    }
-
-    #[cfg(test)]
-    #[deprecated]
-    pub fn get_balance_owner(&self) -> Option<Address> {
-        self.balance_owner
-    }
-}
-
-impl<D> ProtocolSim for EVMPoolState<D>
-where
-    D: EngineDatabaseInterface + Clone + Debug + 'static,
-    <D as DatabaseRef>::Error: Debug,
-    <D as EngineDatabaseInterface>::Error: Debug,
-{
-    fn fee(&self) -> f64 {
-        todo!()
-    }
-
-    fn spot_price(&self, base: &Token, quote: &Token) -> Result<f64, SimulationError> {
-        let base_address = bytes_to_address(&base.address)?;
-        let quote_address = bytes_to_address(&quote.address)?;
-        self.spot_prices
-            .get(&(base_address, quote_address))
-            .cloned()
-            .ok_or(SimulationError::FatalError(format!(
-                "Spot price not found for base token {base_address} and quote token {quote_address}"
-            )))
-    }
-
-    fn get_amount_out(
+    /// Computes the overwrites and sell limit used by all swaps of `sell_token_address` for
+    /// `buy_token_address`.
+    fn sell_context(
         &self,
-        amount_in: BigUint,
-        token_in: &Token,
-        token_out: &Token,
-    ) -> Result<GetAmountOutResult, SimulationError> {
-        let sell_token_address = bytes_to_address(&token_in.address)?;
-        let buy_token_address = bytes_to_address(&token_out.address)?;
-        let sell_amount = U256::from_be_slice(&amount_in.to_bytes_be());
+        sell_token_address: Address,
+        buy_token_address: Address,
+    ) -> Result<SellContext, SimulationError> {
         let overwrites = self.get_overwrites(
             vec![sell_token_address, buy_token_address],
             *MAX_BALANCE / U256::from(100),
@@ -562,6 +561,36 @@ where
             vec![sell_token_address, buy_token_address],
             Some(overwrites.clone()),
         )?;
+        let overwrites_with_sell_limit =
+            self.get_overwrites(vec![sell_token_address, buy_token_address], sell_amount_limit)?;
+
+        Ok(SellContext {
+            overwrites: self.merge(&overwrites, &overwrites_with_sell_limit),
+            sell_amount_limit,
+        })
+    }
+
+    /// Simulates selling `sell_amount` of `sell_token_address` for `buy_token_address`.
+    fn sell(
+        &self,
+        sell_token_address: Address,
+        buy_token_address: Address,
+        sell_amount: U256,
+    ) -> Result<GetAmountOutResult, SimulationError> {
+        let context = self.sell_context(sell_token_address, buy_token_address)?;
+        self.sell_with_context(&context, sell_token_address, buy_token_address, sell_amount)
+    }
+
+    /// Simulates selling `sell_amount` of `sell_token_address` for `buy_token_address`, with the
+    /// overwrites and sell limit of `context`.
+    fn sell_with_context(
+        &self,
+        context: &SellContext,
+        sell_token_address: Address,
+        buy_token_address: Address,
+        sell_amount: U256,
+    ) -> Result<GetAmountOutResult, SimulationError> {
+        let sell_amount_limit = context.sell_amount_limit;
         let (sell_amount_respecting_limit, sell_amount_exceeds_limit) = if self
             .capabilities
             .contains(&Capability::HardLimits) &&
@@ -572,18 +601,16 @@ where
             (sell_amount, false)
         };
 
-        let overwrites_with_sell_limit =
-            self.get_overwrites(vec![sell_token_address, buy_token_address], sell_amount_limit)?;
-        let complete_overwrites = self.merge(&overwrites, &overwrites_with_sell_limit);
-
+        let (block_number, timestamp) = self.simulation_block();
         let (trade, state_changes) = self.adapter_contract.swap(
             &self.id,
             sell_token_address,
             buy_token_address,
             false,
             sell_amount_respecting_limit,
-            self.block.number,
-            Some(complete_overwrites),
+            block_number,
+            timestamp,
+            Some(context.overwrites.clone()),
         )?;
 
         let mut new_state = self.clone();
@@ -637,6 +664,173 @@ where
         ))
     }
 
+    /// Returns the prices of `token_in` in `token_out` after selling each of `amounts_in`, from a
+    /// single call to the adapter's `price` function.
+    ///
+    /// Requires the `PriceFunction` capability. If the pool has the `HardLimits` capability, the
+    /// amounts must not exceed the sell limit.
+    pub fn get_prices(
+        &self,
+        amounts_in: &[BigUint],
+        token_in: &Token,
+        token_out: &Token,
+    ) -> Result<Vec<f64>, SimulationError> {
+        self.ensure_capability(Capability::PriceFunction)?;
+        let sell_token_address = bytes_to_address(&token_in.address)?;
+        let buy_token_address = bytes_to_address(&token_out.address)?;
+        let context = self.sell_context(sell_token_address, buy_token_address)?;
+
+        let amounts: Vec<U256> = amounts_in
+            .iter()
+            .map(biguint_to_u256)
+            .collect();
+        if self
+            .capabilities
+            .contains(&Capability::HardLimits)
+        {
+            if let Some(amount) = amounts
+                .iter()
+                .find(|amount| **amount > context.sell_amount_limit)
+            {
+                return Err(SimulationError::InvalidInput(
+                    format!(
+                        "Sell amount {amount} exceeds limit {limit}",
+                        limit = context.sell_amount_limit
+                    ),
+                    None,
+                ));
+            }
+        }
+
+        let (block_number, timestamp) = self.simulation_block();
+        let prices = self.adapter_contract.price(
+            &self.id,
+            sell_token_address,
+            buy_token_address,
+            amounts,
+            block_number,
+            timestamp,
+            Some(context.overwrites),
+        )?;
+
+        if self
+            .capabilities
+            .contains(&Capability::ScaledPrice)
+        {
+            return Ok(prices);
+        }
+        let token_correction = 10f64.powi(token_in.decimals as i32 - token_out.decimals as i32);
+        Ok(prices
+            .into_iter()
+            .map(|price| price * token_correction)
+            .collect())
+    }
+
+    /// Infers the fee of selling `sell_token_address` for `buy_token_address` from a round trip
+    /// swap, see [`infer_swap_fee`](crate::protocol::fee::infer_swap_fee).
+    fn infer_fee(
+        &self,
+        sell_token_address: Address,
+        buy_token_address: Address,
+        amount_in: Option<&BigUint>,
+    ) -> Result<f64, SimulationError> {
+        let amount_in = match amount_in {
+            Some(amount) => amount.clone(),
+            None => {
+                let overwrites = self.get_overwrites(
+                    vec![sell_token_address, buy_token_address],
+                    *MAX_BALANCE / U256::from(100),
+                )?;
+                let (sell_limit, _) = self.get_amount_limits(
+                    vec![sell_token_address, buy_token_address],
+                    Some(overwrites),
+                )?;
+                default_fee_probe_amount(u256_to_biguint(sell_limit))?
+            }
+        };
+        let forward =
+            self.sell(sell_token_address, buy_token_address, biguint_to_u256(&amount_in))?;
+        let new_state = forward
+            .new_state
+            .as_any()
+            .downcast_ref::<Self>()
+            .ok_or_else(|| {
+                SimulationError::FatalError("Unexpected state type after swap".to_string())
+            })?;
+        let backward = new_state.sell(
+            buy_token_address,
+            sell_token_address,
+            biguint_to_u256(&forward.amount),
+        )?;
+        round_trip_fee(&amount_in, &backward.amount)
+    }
+
+    /// Infers the fee of selling the pool's first token for its second one.
+    fn pool_fee(&self) -> Result<f64, SimulationError> {
+        let [token_0, token_1, ..] = self.tokens.as_slice() else {
+            return Err(SimulationError::FatalError("Pool has less than two tokens".to_string()));
+        };
+        self.infer_fee(bytes_to_address(token_0)?, bytes_to_address(token_1)?, None)
+    }
+
+    #[cfg(test)]
+    pub fn get_involved_contracts(&self) -> HashSet<Address> {
+        self.involved_contracts.clone()
+    }
+
+    #[cfg(test)]
+    pub fn get_manual_updates(&self) -> bool {
+        self.manual_updates
+    }
+
+    #[cfg(test)]
+    #[deprecated]
+    pub fn get_balance_owner(&self) -> Option<Address> {
+        self.balance_owner
+    }
+}
+
+impl<D> ProtocolSim for EVMPoolState<D>
+where
+    D: EngineDatabaseInterface + Clone + Debug + 'static,
+    <D as DatabaseRef>::Error: Debug,
+    <D as EngineDatabaseInterface>::Error: Debug,
+{
+    /// Returns the fee of selling the pool's first token for its second one, inferred from a
+    /// round trip swap of a small amount.
+    ///
+    /// Returns `NaN` if the fee can't be inferred, e.g. if the pool has no liquidity. Use
+    /// [`SwapFee::swap_fee`] to get the fee of a specific swap, or the reason it can't be inferred.
+    fn fee(&self) -> f64 {
+        self.pool_fee().unwrap_or(f64::NAN)
+    }
+
+    fn spot_price(&self, base: &Token, quote: &Token) -> Result<f64, SimulationError> {
+        let base_address = bytes_to_address(&base.address)?;
+        let quote_address = bytes_to_address(&quote.address)?;
+        self.spot_prices
+            .get(&(base_address, quote_address))
+            .cloned()
+            .ok_or(SimulationError::FatalError(format!(
+                "Spot price not found for base token {base_address} and quote token {quote_address}"
+            )))
+    }
+
+    fn get_amount_out(
+        &self,
+        amount_in: BigUint,
+        token_in: &Token,
+        token_out: &Token,
+    ) -> Result<GetAmountOutResult, SimulationError> {
+        let sell_token_address = bytes_to_address(&token_in.address)?;
+        let buy_token_address = bytes_to_address(&token_out.address)?;
+        self.sell(
+            sell_token_address,
+            buy_token_address,
+            U256::from_be_slice(&amount_in.to_bytes_be()),
+        )
+    }
+
     fn get_limits(
         &self,
         sell_token: Bytes,
@@ -670,6 +864,7 @@ where
         } else {
             self.update_pool_state(tokens, balances)?;
         }
+        self.target_block = None;
 
         Ok(())
     }
@@ -698,6 +893,39 @@ where
     }
 }
 
+impl<D> SwapFee for EVMPoolState<D>
+where
+    D: EngineDatabaseInterface + Clone + Debug + 'static,
+    <D as DatabaseRef>::Error: Debug,
+    <D as EngineDatabaseInterface>::Error: Debug,
+{
+    fn swap_fee(
+        &self,
+        token_in: &Token,
+        token_out: &Token,
+        amount_in: Option<&BigUint>,
+    ) -> Result<f64, SimulationError> {
+        self.infer_fee(
+            bytes_to_address(&token_in.address)?,
+            bytes_to_address(&token_out.address)?,
+            amount_in,
+        )
+    }
+}
+
+impl<D> TimeDependent for EVMPoolState<D>
+where
+    D: EngineDatabaseInterface + Clone + Debug + 'static,
+    <D as DatabaseRef>::Error: Debug,
+    <D as EngineDatabaseInterface>::Error: Debug,
+{
+    /// Runs the adapter calls at the given block number and timestamp. Spot prices are only
+    /// recomputed on the next state update.
+    fn set_target_block(&mut self, block: Option<TargetBlock>) {
+        self.target_block = block;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::default::Default;
@@ -996,6 +1224,62 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_fee() {
+        let pool_state = setup_pool_state().await;
+
+        let swap_fee = pool_state
+            .swap_fee(&dai(), &bal(), None)
+            .unwrap();
+        assert!(swap_fee > 0.0 && swap_fee < 0.1, "Unexpected fee {swap_fee}");
+        // The pool fee is the fee of selling the first token (DAI) for the second one (BAL)
+        assert_eq!(pool_state.fee(), swap_fee);
+    }
+
+    #[tokio::test]
+    async fn test_set_target_block() {
+        let mut pool_state = setup_pool_state().await;
+        let block_number = pool_state.block.number;
+
+        pool_state.set_target_block(Some(TargetBlock::new(block_number + 1, 1_700_000_012)));
+        assert_eq!(pool_state.simulation_block(), (block_number + 1, Some(1_700_000_012)));
+        pool_state.set_target_block(None);
+        assert_eq!(pool_state.simulation_block(), (block_number, None));
+
+        pool_state.set_target_block(Some(TargetBlock::new(block_number + 1, 1_700_000_012)));
+        let delta = ProtocolStateDelta {
+            component_id: pool_state.id.clone(),
+            updated_attributes: HashMap::new(),
+            deleted_attributes: HashSet::new(),
+        };
+        let tokens = HashMap::from([(dai().address, dai()), (bal().address, bal())]);
+        pool_state
+            .delta_transition(delta, &tokens, &Balances::default())
+            .unwrap();
+        assert_eq!(pool_state.simulation_block(), (block_number, None));
+    }
+
+    #[tokio::test]
+    async fn test_get_prices() {
+        let pool_state = setup_pool_state().await;
+
+        let prices = pool_state
+            .get_prices(
+                &[
+                    BigUint::from_str("1000000000000000000").unwrap(),
+                    BigUint::from_str("2000000000000000000").unwrap(),
+                ],
+                &dai(),
+                &bal(),
+            )
+            .unwrap();
+
+        assert_eq!(prices.len(), 2);
+        // Close to the spot price, which is sampled at 1% of the sell limit
+        assert!((prices[0] - 0.137_778_914_319_047_9).abs() < 1e-3);
+        assert!(prices[1] > 0.0);
+    }
+
     #[tokio::test]
     async fn test_get_amount_limits() {
         let pool_state = setup_pool_state().await;
@@ -1,7 +1,4 @@
-use std::{
-    collections::{HashMap, HashSet},
-    str::FromStr,
-};
+use std::collections::{HashMap, HashSet};
 
 use alloy::primitives::{Address, U256};
 use revm::state::Bytecode;
@@ -12,7 +9,7 @@ use super::{state::EVMPoolState, state_builder::EVMPoolStateBuilder};
 use crate::{
     evm::{
         engine_db::{tycho_db::PreCachedDB, SHARED_TYCHO_DB},
-        protocol::vm::constants::get_adapter_file,
+        protocol::vm::adapter_registry::{adapter_address, get_adapter_bytecode},
     },
     protocol::{errors::InvalidSnapshotError, models::TryFromWithBlock},
 };
@@ -119,16 +116,8 @@ impl TryFromWithBlock<ComponentWithState, BlockHeader> for EVMPoolState<PreCache
                     .protocol_system
                     .as_str()
             });
-        let adapter_bytecode = Bytecode::new_raw(get_adapter_file(protocol_name)?.into());
-        let adapter_contract_address = Address::from_str(&format!(
-            "{hex_protocol_name:0>40}",
-            hex_protocol_name = hex::encode(protocol_name)
-        ))
-        .map_err(|_| {
-            InvalidSnapshotError::ValueError(
-                "Error converting protocol name to address".to_string(),
-            )
-        })?;
+        let adapter_bytecode = Bytecode::new_raw(get_adapter_bytecode(protocol_name)?);
+        let adapter_contract_address = adapter_address(protocol_name);
 
         let mut pool_state_builder =
             EVMPoolStateBuilder::new(id.clone(), tokens.clone(), block, adapter_contract_address)
@@ -156,7 +145,7 @@ impl TryFromWithBlock<ComponentWithState, BlockHeader> for EVMPoolState<PreCache
 
 #[cfg(test)]
 mod tests {
-    use std::{collections::HashSet, fs, path::Path};
+    use std::{collections::HashSet, fs, path::Path, str::FromStr};
 
     use chrono::DateTime;
     use revm::{primitives::KECCAK_EMPTY, state::AccountInfo};
@@ -166,7 +155,7 @@ mod tests {
     use super::*;
     use crate::evm::{
         engine_db::{create_engine, engine_db_interface::EngineDatabaseInterface},
-        protocol::vm::constants::{BALANCER_V2, CURVE},
+        protocol::vm::constants::{get_adapter_file, BALANCER_V2, CURVE},
         tycho_models::AccountUpdate,
     };
 
@@ -1,10 +1,26 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Debug,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
 
-use futures::{Stream, StreamExt};
+use futures::{stream, Stream};
+use tokio::{
+    sync::{mpsc, watch, Notify},
+    task::JoinHandle,
+};
 use tokio_stream::wrappers::ReceiverStream;
-use tracing::warn;
+use tracing::{debug, warn};
 use tycho_client::{
-    feed::{component_tracker::ComponentFilter, synchronizer::ComponentWithState, BlockHeader},
+    feed::{
+        component_tracker::ComponentFilter, synchronizer::ComponentWithState, BlockHeader,
+        FeedMessage,
+    },
     stream::{StreamError, TychoStreamBuilder},
 };
 use tycho_common::{
@@ -14,10 +30,15 @@ use tycho_common::{
 };
 
 use crate::{
-    evm::decoder::{StreamDecodeError, TychoStreamDecoder},
+    evm::{
+        decoder::{StreamDecodeError, TychoStreamDecoder},
+        protocol::filter_config::FilterConfig,
+        token_registry::TokenSource,
+    },
     protocol::{
         errors::InvalidSnapshotError,
-        models::{TryFromWithBlock, Update},
+        models::{ProtocolComponent, TryFromWithBlock, Update},
+        token_policy::TokenPolicy,
     },
 };
 
@@ -31,8 +52,8 @@ use crate::{
 /// Decoding is performed using the `TychoStreamDecoder`.
 /// The decoding process involves several key aspects:
 /// - **Token Registry:** Protocol components are decoded only if their associated tokens are
-///   present in the registry. Missing tokens will cause the corresponding pools or components to be
-///   skipped.
+///   present in the registry. Missing tokens are fetched from the `token_source` if one is set,
+///   otherwise the corresponding pools or components are skipped until their tokens become known.
 /// - **State Updates:** Decoded state updates are constructed using the registered decoders for the
 ///   protocol. If a decoder is not registered for a protocol, its components cannot be decoded.
 /// - **Custom Filters:** Client-side filters can be applied to exclude specific components or pools
@@ -53,6 +74,22 @@ use crate::{
 pub struct ProtocolStreamBuilder {
     decoder: TychoStreamDecoder<BlockHeader>,
     stream_builder: TychoStreamBuilder,
+    lag_policy: LagPolicy,
+    buffer_size: usize,
+}
+
+/// Default number of decoded updates buffered for a slow consumer with [`LagPolicy::Block`].
+pub const DEFAULT_BUFFER_SIZE: usize = 16;
+
+/// What the stream does when its consumer is slower than Tycho.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LagPolicy {
+    /// Decoding waits once the buffer is full, which in turn slows down the Tycho client.
+    #[default]
+    Block,
+    /// Updates the consumer hasn't received yet are merged into the latest one, so that the
+    /// consumer always receives the most recent states without falling further behind.
+    DropToLatest,
 }
 
 impl ProtocolStreamBuilder {
@@ -60,18 +97,62 @@ impl ProtocolStreamBuilder {
         Self {
             decoder: TychoStreamDecoder::new(),
             stream_builder: TychoStreamBuilder::new(tycho_url, chain.into()),
+            lag_policy: LagPolicy::default(),
+            buffer_size: DEFAULT_BUFFER_SIZE,
         }
     }
 
     /// Adds an exchange and its corresponding filter to the Tycho client and decoder.
     ///
-    /// These are the exchanges for which `BlockUpdate`s will be provided.
+    /// These are the exchanges for which `BlockUpdate`s will be provided. Use
+    /// [`Self::exchange_with_filter`] to filter the components with a closure.
     pub fn exchange<T>(
-        mut self,
+        self,
         name: &str,
         filter: ComponentFilter,
         filter_fn: Option<fn(&ComponentWithState) -> bool>,
     ) -> Self
+    where
+        T: ProtocolSim
+            + TryFromWithBlock<ComponentWithState, BlockHeader, Error = InvalidSnapshotError>
+            + Send
+            + 'static,
+    {
+        match filter_fn {
+            Some(predicate) => self.exchange_with_filter::<T, _>(name, filter, predicate),
+            None => {
+                if ["uniswap_v4", "vm:balancer_v2", "vm:curve"].contains(&name) {
+                    warn!("Warning: For exchange type '{}', it is necessary to set a filter function because not all pools are supported. See all filters at src/evm/protocol/filters.rs", name);
+                }
+                self.add_exchange::<T>(name, filter)
+            }
+        }
+    }
+
+    /// Adds an exchange to the Tycho client and decoder, only including the components that pass
+    /// `filter_fn`.
+    ///
+    /// Like [`TychoStreamDecoder::register_filter`], the filter can be any closure, e.g. one
+    /// capturing runtime configuration.
+    pub fn exchange_with_filter<T, F>(
+        mut self,
+        name: &str,
+        filter: ComponentFilter,
+        filter_fn: F,
+    ) -> Self
+    where
+        T: ProtocolSim
+            + TryFromWithBlock<ComponentWithState, BlockHeader, Error = InvalidSnapshotError>
+            + Send
+            + 'static,
+        F: Fn(&ComponentWithState) -> bool + Send + Sync + 'static,
+    {
+        self.decoder
+            .register_filter(name, filter_fn);
+        self.add_exchange::<T>(name, filter)
+    }
+
+    fn add_exchange<T>(mut self, name: &str, filter: ComponentFilter) -> Self
     where
         T: ProtocolSim
             + TryFromWithBlock<ComponentWithState, BlockHeader, Error = InvalidSnapshotError>
@@ -82,15 +163,29 @@ impl ProtocolStreamBuilder {
             .stream_builder
             .exchange(name, filter);
         self.decoder.register_decoder::<T>(name);
-        if let Some(predicate) = filter_fn {
-            self.decoder
-                .register_filter(name, predicate);
-        }
+        self
+    }
 
-        if ["uniswap_v4", "vm:balancer_v2", "vm:curve"].contains(&name) && filter_fn.is_none() {
-            warn!("Warning: For exchange type '{}', it is necessary to set a filter function because not all pools are supported. See all filters at src/evm/protocol/filters.rs", name);
-        }
+    /// Registers a filter re-evaluated on every new or updated state of an exchange, see
+    /// [`TychoStreamDecoder::register_state_filter`].
+    pub fn state_filter<F>(mut self, name: &str, predicate: F) -> Self
+    where
+        F: Fn(&ProtocolComponent, &dyn ProtocolSim) -> bool + Send + Sync + 'static,
+    {
+        self.decoder
+            .register_state_filter(name, predicate);
+        self
+    }
 
+    /// Adds the client-side filters of `config` to the exchanges it configures.
+    ///
+    /// The filters apply in addition to the filter functions given to [`Self::exchange`],
+    /// whether the exchanges are added before or after.
+    pub fn filter_config(mut self, config: &FilterConfig) -> Self {
+        for (name, filter) in &config.exchanges {
+            self.decoder
+                .add_filter(name, filter.clone().into_filter());
+        }
         self
     }
 
@@ -135,6 +230,28 @@ impl ProtocolStreamBuilder {
         self
     }
 
+    /// Sets the policy deciding which tokens are tracked, see
+    /// [`TychoStreamDecoder::set_token_policy`].
+    ///
+    /// The policy filters the tokens set with [`Self::set_tokens`], whichever order they are set
+    /// in, and the new tokens streamed by Tycho. Use the same policy with
+    /// [`load_tokens`](crate::utils::load_tokens) to load the initial tokens.
+    pub async fn token_policy(mut self, policy: TokenPolicy) -> Self {
+        self.decoder
+            .set_token_policy(policy)
+            .await;
+        self
+    }
+
+    /// Sets the transfer taxes of fee-on-transfer tokens, in basis points, overriding the tax
+    /// indexed by Tycho. See [`TychoStreamDecoder::set_transfer_taxes`].
+    pub async fn set_transfer_taxes(self, transfer_taxes: HashMap<Bytes, u64>) -> Self {
+        self.decoder
+            .set_transfer_taxes(transfer_taxes)
+            .await;
+        self
+    }
+
     /// Skips state decode failures, allowing the stream to continue processing. It raises a warning
     /// instead of panic.
     pub fn skip_state_decode_failures(mut self, skip: bool) -> Self {
@@ -143,18 +260,373 @@ impl ProtocolStreamBuilder {
         self
     }
 
-    pub async fn build(
-        self,
-    ) -> Result<impl Stream<Item = Result<Update, StreamDecodeError>>, StreamError> {
-        let (_, rx) = self.stream_builder.build().await?;
-        let decoder = Arc::new(self.decoder);
-
-        Ok(Box::pin(ReceiverStream::new(rx).then({
-            let decoder = decoder.clone(); // Clone the decoder for the closure
-            move |msg| {
-                let decoder = decoder.clone(); // Clone again for the async block
-                async move { decoder.decode(msg).await }
+    /// Sets the source used to resolve tokens of new components that are not known yet, see
+    /// [`TychoStreamDecoder::set_token_source`].
+    pub fn token_source<S>(mut self, source: S) -> Self
+    where
+        S: TokenSource + 'static,
+    {
+        self.decoder.set_token_source(source);
+        self
+    }
+
+    /// Attaches the per-protocol decode failures of each block to the `decode_stats` of its
+    /// update.
+    pub fn include_decode_stats(mut self, include: bool) -> Self {
+        self.decoder
+            .include_decode_stats(include);
+        self
+    }
+
+    /// Sets what the stream does when the consumer falls behind. Defaults to
+    /// [`LagPolicy::Block`].
+    pub fn lag_policy(mut self, lag_policy: LagPolicy) -> Self {
+        self.lag_policy = lag_policy;
+        self
+    }
+
+    /// Sets the number of decoded updates buffered with [`LagPolicy::Block`]. Defaults to
+    /// [`DEFAULT_BUFFER_SIZE`].
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+
+    /// Connects to Tycho and starts decoding its messages in the background.
+    ///
+    /// If the Tycho client stops, the stream yields a final `StreamDecodeError::Fatal` with the
+    /// reason before ending. Use [`ProtocolStream::shutdown`] to stop it cleanly.
+    pub async fn build(self) -> Result<ProtocolStream, StreamError> {
+        let (client_handle, rx) = self.stream_builder.build().await?;
+        Ok(ProtocolStream::spawn(
+            Arc::new(self.decoder),
+            rx,
+            client_handle,
+            self.lag_policy,
+            self.buffer_size,
+        ))
+    }
+}
+
+type UpdateStream = Pin<Box<dyn Stream<Item = Result<Update, StreamDecodeError>> + Send>>;
+
+/// The stream of decoded updates built by [`ProtocolStreamBuilder`].
+///
+/// Messages are decoded in a background task, which stops once the stream or all its
+/// [`ShutdownHandle`]s are dropped.
+pub struct ProtocolStream {
+    updates: UpdateStream,
+    shutdown: ShutdownHandle,
+    task: JoinHandle<()>,
+}
+
+impl ProtocolStream {
+    fn spawn<T: Debug + Send + 'static>(
+        decoder: Arc<TychoStreamDecoder<BlockHeader>>,
+        rx: mpsc::Receiver<FeedMessage<BlockHeader>>,
+        client_handle: JoinHandle<T>,
+        lag_policy: LagPolicy,
+        buffer_size: usize,
+    ) -> Self {
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let (sink, updates): (UpdateSink, UpdateStream) = match lag_policy {
+            LagPolicy::Block => {
+                let (tx, rx) = mpsc::channel(buffer_size);
+                (UpdateSink::Channel(tx), Box::pin(ReceiverStream::new(rx)))
+            }
+            LagPolicy::DropToLatest => {
+                let latest = Arc::new(LatestUpdate::default());
+                (UpdateSink::Latest(latest.clone()), latest.into_stream())
             }
-        })))
+        };
+        let task = tokio::spawn(run_decoder(decoder, rx, client_handle, shutdown_rx, sink));
+
+        Self { updates, shutdown: ShutdownHandle(Arc::new(shutdown_tx)), task }
+    }
+
+    /// Returns a handle to stop the stream from elsewhere, e.g. a signal handler.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Stops the Tycho client and waits for the message being decoded, if any.
+    ///
+    /// Updates that were decoded but not received are discarded.
+    pub async fn shutdown(self) {
+        let ProtocolStream { updates, shutdown, task } = self;
+        // Dropping the receiving end unblocks a decoder waiting on a full buffer
+        drop(updates);
+        shutdown.shutdown();
+        if let Err(e) = task.await {
+            warn!(error = %e, "Protocol stream task failed");
+        }
+    }
+}
+
+impl Stream for ProtocolStream {
+    type Item = Result<Update, StreamDecodeError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.updates.as_mut().poll_next(cx)
+    }
+}
+
+/// Stops a [`ProtocolStream`]. The stream ends after the message being decoded.
+#[derive(Clone)]
+pub struct ShutdownHandle(Arc<watch::Sender<bool>>);
+
+impl ShutdownHandle {
+    pub fn shutdown(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        *self.0.borrow()
+    }
+}
+
+enum UpdateSink {
+    Channel(mpsc::Sender<Result<Update, StreamDecodeError>>),
+    Latest(Arc<LatestUpdate>),
+}
+
+impl UpdateSink {
+    /// Returns false if the consumer is gone.
+    async fn send(&self, update: Result<Update, StreamDecodeError>) -> bool {
+        match self {
+            UpdateSink::Channel(tx) => tx.send(update).await.is_ok(),
+            UpdateSink::Latest(latest) => {
+                latest.push(update);
+                Arc::strong_count(latest) > 1
+            }
+        }
+    }
+
+    fn close(&self) {
+        if let UpdateSink::Latest(latest) = self {
+            latest.close();
+        }
+    }
+}
+
+/// The updates not received yet with [`LagPolicy::DropToLatest`]. Consecutive updates are merged,
+/// errors are kept in order.
+#[derive(Default)]
+struct LatestUpdate {
+    pending: Mutex<VecDeque<Result<Update, StreamDecodeError>>>,
+    notify: Notify,
+    closed: AtomicBool,
+}
+
+impl LatestUpdate {
+    fn push(&self, update: Result<Update, StreamDecodeError>) {
+        {
+            let mut pending = self
+                .pending
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            match (pending.pop_back(), update) {
+                (Some(Ok(previous)), Ok(update)) => {
+                    debug!(block = previous.block_number_or_timestamp, "Merging lagging update");
+                    pending.push_back(Ok(merge_updates(previous, update)));
+                }
+                (previous, update) => {
+                    pending.extend(previous);
+                    pending.push_back(update);
+                }
+            }
+        }
+        self.notify.notify_one();
+    }
+
+    fn pop(&self) -> Option<Result<Update, StreamDecodeError>> {
+        self.pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .pop_front()
+    }
+
+    fn close(&self) {
+        self.closed
+            .store(true, Ordering::Release);
+        self.notify.notify_one();
+    }
+
+    fn into_stream(self: Arc<Self>) -> UpdateStream {
+        Box::pin(stream::unfold(self, |latest| async move {
+            loop {
+                if let Some(update) = latest.pop() {
+                    return Some((update, latest));
+                }
+                if latest.closed.load(Ordering::Acquire) {
+                    return None;
+                }
+                latest.notify.notified().await;
+            }
+        }))
+    }
+}
+
+/// Merges `update` into the `previous` update the consumer hasn't received yet.
+fn merge_updates(mut previous: Update, update: Update) -> Update {
+    for id in update.removed_pairs.keys() {
+        previous.states.remove(id);
+        previous.new_pairs.remove(id);
+    }
+    for id in update.new_pairs.keys() {
+        previous.removed_pairs.remove(id);
+    }
+    let block_number_or_timestamp = update.block_number_or_timestamp;
+    let sync_states = update.sync_states.clone();
+    let mut merged = previous.merge(update);
+    merged.block_number_or_timestamp = block_number_or_timestamp;
+    merged.sync_states = sync_states;
+    merged
+}
+
+/// Decodes the messages of the Tycho client until it stops, the stream is shut down or the
+/// consumer is gone.
+async fn run_decoder<T: Debug + Send + 'static>(
+    decoder: Arc<TychoStreamDecoder<BlockHeader>>,
+    mut rx: mpsc::Receiver<FeedMessage<BlockHeader>>,
+    client_handle: JoinHandle<T>,
+    mut shutdown: watch::Receiver<bool>,
+    sink: UpdateSink,
+) {
+    loop {
+        let msg = tokio::select! {
+            biased;
+            // Also stops once all shutdown handles are dropped
+            _ = shutdown.wait_for(|stop| *stop) => break,
+            msg = rx.recv() => msg,
+        };
+        let Some(msg) = msg else {
+            let reason = match client_handle.await {
+                Ok(result) => format!("Tycho client stopped: {result:?}"),
+                Err(e) => format!("Tycho client failed: {e}"),
+            };
+            warn!(reason, "Protocol stream ended");
+            tokio::select! {
+                biased;
+                _ = shutdown.wait_for(|stop| *stop) => {}
+                _ = sink.send(Err(StreamDecodeError::Fatal(reason))) => {}
+            }
+            sink.close();
+            return;
+        };
+        let update = decoder.decode(msg).await;
+        let sent = tokio::select! {
+            biased;
+            _ = shutdown.wait_for(|stop| *stop) => false,
+            sent = sink.send(update) => sent,
+        };
+        if !sent {
+            break;
+        }
+    }
+    client_handle.abort();
+    sink.close();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, time::Duration};
+
+    use futures::StreamExt;
+    use rstest::rstest;
+
+    use super::*;
+
+    fn test_msg() -> FeedMessage<BlockHeader> {
+        let asset_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/assets/decoder/uniswap_v2_delta.json");
+        let json_data = fs::read_to_string(asset_path).expect("Failed to read test asset");
+        serde_json::from_str(&json_data).expect("Failed to deserialize FeedMsg json!")
+    }
+
+    fn test_stream<T: Debug + Send + 'static>(
+        lag_policy: LagPolicy,
+        client_handle: JoinHandle<T>,
+    ) -> (ProtocolStream, mpsc::Sender<FeedMessage<BlockHeader>>) {
+        let (feed, rx) = mpsc::channel(8);
+        let stream = ProtocolStream::spawn(
+            Arc::new(TychoStreamDecoder::new()),
+            rx,
+            client_handle,
+            lag_policy,
+            1,
+        );
+        (stream, feed)
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_with_full_buffer() {
+        let client = tokio::spawn(futures::future::pending::<()>());
+        let (stream, feed) = test_stream(LagPolicy::Block, client);
+        for _ in 0..3 {
+            feed.send(test_msg()).await.unwrap();
+        }
+        // Let the decoder fill the buffer and block on the next update
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        tokio::time::timeout(Duration::from_secs(5), stream.shutdown())
+            .await
+            .expect("Shutdown should not wait for the consumer");
+        assert!(feed.is_closed());
+    }
+
+    #[rstest]
+    #[case(LagPolicy::Block)]
+    #[case(LagPolicy::DropToLatest)]
+    #[tokio::test]
+    async fn test_client_error_ends_stream(#[case] lag_policy: LagPolicy) {
+        let client = tokio::spawn(async { "connection closed" });
+        let (mut stream, feed) = test_stream(lag_policy, client);
+        drop(feed);
+
+        match stream.next().await {
+            Some(Err(StreamDecodeError::Fatal(reason))) => {
+                assert!(reason.contains("connection closed"), "{reason}")
+            }
+            other => panic!("Expected the client error, got {other:?}"),
+        }
+        assert!(stream.next().await.is_none());
+    }
+
+    #[rstest]
+    #[case(LagPolicy::Block)]
+    #[case(LagPolicy::DropToLatest)]
+    #[tokio::test]
+    async fn test_consumer_drop_stops_decoder(#[case] lag_policy: LagPolicy) {
+        let client = tokio::spawn(futures::future::pending::<()>());
+        let (stream, feed) = test_stream(lag_policy, client);
+        // Keep a handle so the decoder can only stop because the consumer is gone
+        let shutdown = stream.shutdown_handle();
+        drop(stream);
+
+        feed.send(test_msg()).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), feed.closed())
+            .await
+            .expect("Decoder should stop once the consumer is dropped");
+        assert!(!shutdown.is_shutdown());
+    }
+
+    #[tokio::test]
+    async fn test_latest_update_merges_lagging_updates() {
+        let latest = Arc::new(LatestUpdate::default());
+        let mut updates = latest.clone().into_stream();
+
+        latest.push(Ok(Update::new(1, HashMap::new(), HashMap::new())));
+        latest.push(Ok(Update::new(2, HashMap::new(), HashMap::new())));
+        latest.push(Err(StreamDecodeError::Fatal("failure".to_string())));
+        latest.push(Ok(Update::new(3, HashMap::new(), HashMap::new())));
+        latest.close();
+
+        let merged = updates.next().await.unwrap().unwrap();
+        assert_eq!(merged.block_number_or_timestamp, 2);
+        assert!(updates.next().await.unwrap().is_err());
+        let last = updates.next().await.unwrap().unwrap();
+        assert_eq!(last.block_number_or_timestamp, 3);
+        assert!(updates.next().await.is_none());
     }
 }
@@ -0,0 +1,261 @@
+//! Sources of token metadata for tokens the decoder doesn't know yet.
+//!
+//! The [`TychoStreamDecoder`](crate::evm::decoder::TychoStreamDecoder) only decodes components
+//! whose tokens are all known. With a [`TokenSource`] set, it resolves the unknown tokens of new
+//! components before decoding them, and caches the fetched tokens with the known ones.
+use std::{collections::HashMap, fmt::Debug, fs, future::Future, path::Path, pin::Pin};
+
+use alloy::{
+    primitives::{B256, U256},
+    sol_types::SolValue,
+};
+use revm::DatabaseRef;
+use tracing::warn;
+use tycho_client::HttpRPCClient;
+use tycho_common::{
+    dto::ResponseToken,
+    models::{token::Token, Chain},
+    simulation::errors::SimulationError,
+    Bytes,
+};
+
+use crate::{
+    evm::{
+        engine_db::engine_db_interface::EngineDatabaseInterface,
+        protocol::{
+            utils::bytes_to_address, vm::tycho_simulation_contract::TychoSimulationContract,
+        },
+        simulation::SimulationEngine,
+    },
+    protocol::token_policy::TokenPolicy,
+    utils::{convert_tokens, create_rpc_client, fetch_tokens},
+};
+
+pub type TokenFut<'a> =
+    Pin<Box<dyn Future<Output = Result<HashMap<Bytes, Token>, SimulationError>> + Send + 'a>>;
+
+/// A source resolving token addresses into tokens.
+pub trait TokenSource: Send + Sync {
+    /// Fetches the tokens at the given addresses.
+    ///
+    /// Addresses the source can't resolve are left out of the result rather than failing the
+    /// whole request.
+    fn fetch_tokens<'a>(&'a self, addresses: &'a [Bytes]) -> TokenFut<'a>;
+}
+
+/// Fetches tokens from the Tycho RPC.
+pub struct TychoTokenSource {
+    client: HttpRPCClient,
+    chain: Chain,
+    min_quality: Option<i32>,
+    traded_n_days_ago: Option<u64>,
+}
+
+impl TychoTokenSource {
+    /// Creates a source querying the Tycho RPC at `tycho_url` (without the url prefix, e.g.
+    /// 'https://').
+    pub fn new(
+        tycho_url: &str,
+        no_tls: bool,
+        auth_key: Option<&str>,
+        chain: Chain,
+    ) -> Result<Self, SimulationError> {
+        Ok(Self {
+            client: create_rpc_client(tycho_url, no_tls, auth_key)?,
+            chain,
+            min_quality: None,
+            traded_n_days_ago: None,
+        })
+    }
+
+    /// Only resolves tokens with at least the given quality.
+    pub fn min_quality(mut self, min_quality: i32) -> Self {
+        self.min_quality = Some(min_quality);
+        self
+    }
+
+    /// Only resolves tokens meeting the quality and last trade requirements of `policy`.
+    pub fn token_policy(mut self, policy: &TokenPolicy) -> Self {
+        self.min_quality = Some(policy.min_quality as i32);
+        self.traded_n_days_ago = policy.max_days_since_last_trade;
+        self
+    }
+}
+
+impl TokenSource for TychoTokenSource {
+    fn fetch_tokens<'a>(&'a self, addresses: &'a [Bytes]) -> TokenFut<'a> {
+        Box::pin(fetch_tokens(
+            &self.client,
+            self.chain,
+            addresses,
+            self.min_quality,
+            self.traded_n_days_ago,
+        ))
+    }
+}
+
+/// Reads the metadata of ERC20 tokens on-chain, by calling `symbol()` and `decimals()` through a
+/// [`SimulationEngine`].
+///
+/// The engine's database must be able to load the token contracts, e.g. a `SimulationDB`
+/// connected to a node. Tokens resolved this way have no quality score and are given a quality of
+/// 100.
+pub struct Erc20TokenSource<D: EngineDatabaseInterface + Clone + Debug>
+where
+    <D as DatabaseRef>::Error: Debug,
+    <D as EngineDatabaseInterface>::Error: Debug,
+{
+    engine: SimulationEngine<D>,
+    chain: Chain,
+}
+
+impl<D: EngineDatabaseInterface + Clone + Debug> Erc20TokenSource<D>
+where
+    <D as DatabaseRef>::Error: Debug,
+    <D as EngineDatabaseInterface>::Error: Debug,
+{
+    pub fn new(engine: SimulationEngine<D>, chain: Chain) -> Self {
+        Self { engine, chain }
+    }
+
+    fn fetch_token(&self, address: &Bytes) -> Result<Token, SimulationError> {
+        let contract =
+            TychoSimulationContract::new(bytes_to_address(address)?, self.engine.clone())?;
+        let call = |selector: &str| {
+            contract
+                .call(selector, (), 0, None, None, None, U256::ZERO, None)
+                .map(|res| res.return_value)
+        };
+
+        let decimals = U256::abi_decode(&call("decimals()")?)
+            .ok()
+            .and_then(|decimals| u32::try_from(decimals).ok())
+            .ok_or_else(|| {
+                SimulationError::FatalError(format!("Invalid decimals of token {address}"))
+            })?;
+        let symbol = decode_symbol(&call("symbol()")?).ok_or_else(|| {
+            SimulationError::FatalError(format!("Invalid symbol of token {address}"))
+        })?;
+
+        Ok(Token::new(address, &symbol, decimals, 0, &[], self.chain, 100))
+    }
+}
+
+impl<D> TokenSource for Erc20TokenSource<D>
+where
+    D: EngineDatabaseInterface + Clone + Debug + Send + Sync,
+    <D as DatabaseRef>::Error: Debug,
+    <D as EngineDatabaseInterface>::Error: Debug,
+{
+    fn fetch_tokens<'a>(&'a self, addresses: &'a [Bytes]) -> TokenFut<'a> {
+        Box::pin(async move {
+            Ok(addresses
+                .iter()
+                .filter_map(|address| {
+                    self.fetch_token(address)
+                        .inspect_err(
+                            |e| warn!(token = %address, error = %e, "Failed to fetch token"),
+                        )
+                        .ok()
+                        .map(|token| (address.clone(), token))
+                })
+                .collect())
+        })
+    }
+}
+
+/// Decodes the return value of `symbol()`, which is a `string` for most tokens and a `bytes32`
+/// for some older ones.
+fn decode_symbol(data: &[u8]) -> Option<String> {
+    if let Ok(symbol) = String::abi_decode(data) {
+        return Some(symbol);
+    }
+    let symbol = B256::abi_decode(data).ok()?;
+    let len = symbol
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(32);
+    String::from_utf8(symbol[..len].to_vec()).ok()
+}
+
+/// Resolves tokens from a JSON file containing a list of tokens in the format of the Tycho RPC.
+pub struct FileTokenSource {
+    tokens: HashMap<Bytes, Token>,
+}
+
+impl FileTokenSource {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, SimulationError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|e| {
+            SimulationError::FatalError(format!("Failed to read token file {path:?}: {e}"))
+        })?;
+        let tokens: Vec<ResponseToken> = serde_json::from_str(&content).map_err(|e| {
+            SimulationError::FatalError(format!("Failed to parse token file {path:?}: {e}"))
+        })?;
+        Ok(Self { tokens: convert_tokens(tokens) })
+    }
+}
+
+impl TokenSource for FileTokenSource {
+    fn fetch_tokens<'a>(&'a self, addresses: &'a [Bytes]) -> TokenFut<'a> {
+        Box::pin(async move {
+            Ok(addresses
+                .iter()
+                .filter_map(|address| {
+                    self.tokens
+                        .get(address)
+                        .map(|token| (address.clone(), token.clone()))
+                })
+                .collect())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use alloy::primitives::Address;
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    #[test]
+    fn test_decode_symbol() {
+        assert_eq!(decode_symbol(&"WETH".to_string().abi_encode()), Some("WETH".to_string()));
+
+        let mut bytes32 = [0u8; 32];
+        bytes32[..3].copy_from_slice(b"MKR");
+        assert_eq!(decode_symbol(&bytes32), Some("MKR".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_file_token_source() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(
+            file,
+            r#"[{{
+                "chain": "ethereum",
+                "address": "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+                "symbol": "WETH",
+                "decimals": 18,
+                "tax": 0,
+                "gas": [29000],
+                "quality": 100
+            }}]"#
+        )
+        .unwrap();
+        let source = FileTokenSource::new(file.path()).unwrap();
+
+        let weth = Bytes::from("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2");
+        let unknown = Bytes::from(Address::repeat_byte(1).to_vec());
+        let tokens = source
+            .fetch_tokens(&[weth.clone(), unknown])
+            .await
+            .unwrap();
+
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[&weth].symbol, "WETH");
+        assert_eq!(tokens[&weth].decimals, 18);
+    }
+}
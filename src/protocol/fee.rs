@@ -0,0 +1,155 @@
+//! Direction and amount aware swap fees
+//!
+//! `ProtocolSim::fee` returns a single fee per pool, which can't represent protocols whose fees
+//! depend on the swap direction (e.g. Uniswap V4 protocol fees), on the swapped amount (e.g. hooks
+//! overriding the LP fee) or that can only be observed through simulation (e.g. VM pools).
+//! [`SwapFee`] queries the fee of a specific swap instead, and [`swap_fee`] resolves it for any
+//! `ProtocolSim`.
+use std::any::Any;
+
+use num_bigint::BigUint;
+use num_traits::{ToPrimitive, Zero};
+use tycho_common::{
+    models::token::Token,
+    simulation::{errors::SimulationError, protocol_sim::ProtocolSim},
+};
+
+/// Fraction of the maximum sell amount used to infer fees when no amount is given. Small enough to
+/// keep the price impact negligible, large enough to avoid rounding errors.
+const DEFAULT_FEE_PROBE_DIVISOR: u32 = 1_000;
+
+/// Fee charged for a specific swap.
+pub trait SwapFee {
+    /// Returns the fee charged when selling `token_in` for `token_out`, as a fraction of the
+    /// input amount (e.g. `0.003` for 0.3%).
+    ///
+    /// `amount_in` is the amount being sold. States whose fee doesn't depend on the amount ignore
+    /// it; states that need an amount fall back to a small default amount if it is `None`.
+    fn swap_fee(
+        &self,
+        token_in: &Token,
+        token_out: &Token,
+        amount_in: Option<&BigUint>,
+    ) -> Result<f64, SimulationError>;
+}
+
+/// Returns the fee charged by `state` when selling `token_in` for `token_out`.
+///
+/// Uses the [`SwapFee`] implementation of the states in this crate, and infers the fee from a
+/// round trip swap for any other state (see [`infer_swap_fee`]).
+pub fn swap_fee(
+    state: &dyn ProtocolSim,
+    token_in: &Token,
+    token_out: &Token,
+    amount_in: Option<&BigUint>,
+) -> Result<f64, SimulationError> {
+    match as_swap_fee(state.as_any()) {
+        Some(fee) => fee.swap_fee(token_in, token_out, amount_in),
+        None => infer_swap_fee(state, token_in, token_out, amount_in),
+    }
+}
+
+#[allow(unused_variables)]
+fn as_swap_fee(state: &dyn Any) -> Option<&dyn SwapFee> {
+    #[cfg(feature = "evm")]
+    if let Some(fee) = crate::evm::protocol::as_swap_fee(state) {
+        return Some(fee);
+    }
+    #[cfg(feature = "rfq")]
+    if let Some(fee) = crate::rfq::protocols::as_swap_fee(state) {
+        return Some(fee);
+    }
+    None
+}
+
+/// Infers the fee of a swap through simulation.
+///
+/// Sells `amount_in` of `token_in` and sells the proceeds back on the resulting state. The price
+/// impact of the first swap is undone by the second one, so what is lost on the round trip is the
+/// fee charged twice. Assumes the fee is the same in both directions.
+///
+/// If `amount_in` is `None`, a small fraction of the sell limit is used.
+pub fn infer_swap_fee(
+    state: &dyn ProtocolSim,
+    token_in: &Token,
+    token_out: &Token,
+    amount_in: Option<&BigUint>,
+) -> Result<f64, SimulationError> {
+    let amount_in = match amount_in {
+        Some(amount) => amount.clone(),
+        None => {
+            let (max_sell, _) =
+                state.get_limits(token_in.address.clone(), token_out.address.clone())?;
+            default_fee_probe_amount(max_sell)?
+        }
+    };
+    let forward = state.get_amount_out(amount_in.clone(), token_in, token_out)?;
+    let backward = forward
+        .new_state
+        .get_amount_out(forward.amount, token_out, token_in)?;
+    round_trip_fee(&amount_in, &backward.amount)
+}
+
+/// Returns the amount used to probe fees, given the maximum amount that can be sold.
+pub fn default_fee_probe_amount(max_sell: BigUint) -> Result<BigUint, SimulationError> {
+    let amount = max_sell / DEFAULT_FEE_PROBE_DIVISOR;
+    if amount.is_zero() {
+        return Err(SimulationError::RecoverableError(
+            "Not enough liquidity to infer the fee".to_string(),
+        ));
+    }
+    Ok(amount)
+}
+
+/// Returns the fee charged per swap, given the amount sold and the amount received back after a
+/// round trip through the pool.
+pub fn round_trip_fee(amount_in: &BigUint, amount_back: &BigUint) -> Result<f64, SimulationError> {
+    if amount_in.is_zero() {
+        return Err(SimulationError::InvalidInput(
+            "Can't infer the fee of a zero amount swap".to_string(),
+            None,
+        ));
+    }
+    let amount_in = amount_in
+        .to_f64()
+        .ok_or_else(|| SimulationError::FatalError("Amount in overflows f64".to_string()))?;
+    let amount_back = amount_back
+        .to_f64()
+        .ok_or_else(|| SimulationError::FatalError("Amount back overflows f64".to_string()))?;
+    // (1 - fee)^2 of the amount sold is received back
+    Ok((1.0 - (amount_back / amount_in).sqrt()).max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_fee() {
+        // 0.3% charged twice
+        let amount_in = BigUint::from(1_000_000_000u64);
+        let amount_back = BigUint::from(994_009_000u64);
+
+        let fee = round_trip_fee(&amount_in, &amount_back).unwrap();
+
+        assert!((fee - 0.003).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_round_trip_fee_gain() {
+        let fee = round_trip_fee(&BigUint::from(100u32), &BigUint::from(101u32)).unwrap();
+
+        assert_eq!(fee, 0.0);
+    }
+
+    #[test]
+    fn test_round_trip_fee_zero_amount() {
+        assert!(round_trip_fee(&BigUint::zero(), &BigUint::zero()).is_err());
+    }
+
+    #[test]
+    fn test_default_fee_probe_amount() {
+        assert_eq!(default_fee_probe_amount(BigUint::from(5_000u32)).unwrap(), BigUint::from(5u32));
+        assert!(default_fee_probe_amount(BigUint::from(999u32)).is_err());
+    }
+}
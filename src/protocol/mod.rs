@@ -1,2 +1,6 @@
 pub mod errors;
+pub mod fee;
 pub mod models;
+pub mod target_block;
+pub mod token_policy;
+pub mod transfer_tax;
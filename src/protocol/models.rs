@@ -151,6 +151,8 @@ pub struct Update {
     pub new_pairs: HashMap<String, ProtocolComponent>,
     /// The pairs that were removed in this block
     pub removed_pairs: HashMap<String, ProtocolComponent>,
+    /// Decoding failures of this block per protocol, if the decoder is configured to attach them
+    pub decode_stats: HashMap<String, DecodeStats>,
 }
 
 impl Update {
@@ -165,6 +167,7 @@ impl Update {
             states,
             new_pairs,
             removed_pairs: HashMap::new(),
+            decode_stats: HashMap::new(),
         }
     }
 
@@ -178,11 +181,49 @@ impl Update {
         self
     }
 
+    pub fn set_decode_stats(mut self, decode_stats: HashMap<String, DecodeStats>) -> Self {
+        self.decode_stats = decode_stats;
+        self
+    }
+
     pub fn merge(mut self, other: Update) -> Self {
         self.states.extend(other.states);
         self.new_pairs.extend(other.new_pairs);
         self.removed_pairs
             .extend(other.removed_pairs);
+        for (protocol, stats) in other.decode_stats {
+            self.decode_stats
+                .entry(protocol)
+                .or_default()
+                .add(&stats);
+        }
         self
     }
 }
+
+/// Counts of the components of a protocol that could not be decoded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DecodeStats {
+    /// Snapshots that failed to decode into a state
+    pub snapshot_failures: u64,
+    /// States that failed to apply a delta
+    pub transition_failures: u64,
+    /// Snapshots skipped because some of their tokens are unknown
+    pub token_skips: u64,
+    /// Snapshots skipped because no decoder is registered for their protocol
+    pub missing_decoder: u64,
+}
+
+impl DecodeStats {
+    pub fn add(&mut self, other: &DecodeStats) {
+        self.snapshot_failures += other.snapshot_failures;
+        self.transition_failures += other.transition_failures;
+        self.token_skips += other.token_skips;
+        self.missing_decoder += other.missing_decoder;
+    }
+
+    /// Returns the total number of skipped or failed components.
+    pub fn total(&self) -> u64 {
+        self.snapshot_failures + self.transition_failures + self.token_skips + self.missing_decoder
+    }
+}
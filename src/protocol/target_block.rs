@@ -0,0 +1,77 @@
+//! Block context of time-dependent simulations
+//!
+//! Some states quote differently depending on the block a swap is executed in, e.g. Ekubo TWAMM
+//! pools execute virtual orders up to the block timestamp and VM pools run their adapter in a
+//! block context. By default they simulate at the latest indexed block or at the current time.
+//! [`set_target_block`] makes them simulate at a given block instead, e.g. the next block when
+//! quoting live, or a historical block in backtests.
+//!
+//! Target blocks are honoured by Ekubo, Maverick V2 and Uniswap V4 states and by
+//! `EVMPoolState<PreCachedDB>`. Other states, including RFQ states, VM states over other
+//! databases and states implemented outside this crate, ignore them.
+use std::any::Any;
+
+use tycho_common::simulation::protocol_sim::ProtocolSim;
+
+/// Block at which time-dependent states simulate swaps.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TargetBlock {
+    pub number: u64,
+    /// Block timestamp, in seconds
+    pub timestamp: u64,
+}
+
+impl TargetBlock {
+    pub fn new(number: u64, timestamp: u64) -> Self {
+        Self { number, timestamp }
+    }
+
+    /// Returns the block following this one, `block_time` seconds later.
+    pub fn next(&self, block_time: u64) -> Self {
+        Self { number: self.number + 1, timestamp: self.timestamp + block_time }
+    }
+}
+
+/// State whose quotes depend on the block they are simulated in.
+pub trait TimeDependent {
+    /// Sets the block at which subsequent quotes are simulated, or clears it with `None`.
+    ///
+    /// States resulting from a swap keep the target block, so that consecutive swaps are simulated
+    /// in the same block. State transitions clear it, as the target refers to the block following
+    /// the one the state was indexed at.
+    fn set_target_block(&mut self, block: Option<TargetBlock>);
+}
+
+/// Sets the block at which `state` simulates swaps, or clears it with `None`.
+///
+/// Returns whether the state honours target blocks. States that don't are left unchanged: either
+/// they quote the same in any block, e.g. Uniswap V2 pools, or their block context isn't
+/// configurable, e.g. RFQ states or VM states not backed by a `PreCachedDB`.
+pub fn set_target_block(state: &mut dyn ProtocolSim, block: Option<TargetBlock>) -> bool {
+    match as_time_dependent_mut(state.as_any_mut()) {
+        Some(state) => {
+            state.set_target_block(block);
+            true
+        }
+        None => false,
+    }
+}
+
+#[allow(unused_variables)]
+fn as_time_dependent_mut(state: &mut dyn Any) -> Option<&mut dyn TimeDependent> {
+    #[cfg(feature = "evm")]
+    if let Some(state) = crate::evm::protocol::as_time_dependent_mut(state) {
+        return Some(state);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next() {
+        assert_eq!(TargetBlock::new(100, 1_000).next(12), TargetBlock::new(101, 1_012));
+    }
+}
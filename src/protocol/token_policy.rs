@@ -0,0 +1,116 @@
+//! Admission policy of the tokens a stream tracks
+//!
+//! A [`TokenPolicy`] decides which tokens are known to the decoder, and therefore which
+//! components can be decoded. It is applied to the tokens loaded with
+//! [`load_tokens`](crate::utils::load_tokens), to the tokens set on the decoder and to the new
+//! tokens streamed by Tycho, so that all of them follow the same rules.
+use std::collections::HashSet;
+
+use tycho_common::{models::token::Token, Bytes};
+
+/// Default minimum quality, which admits rebasing tokens and excludes tokens whose analysis
+/// failed.
+pub const DEFAULT_MIN_TOKEN_QUALITY: u32 = 51;
+
+/// Rules a token has to meet to be tracked.
+///
+/// Denied tokens are always rejected and allowed tokens are always admitted, regardless of the
+/// other rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenPolicy {
+    /// Minimum quality assigned to the token by Tycho
+    pub min_quality: u32,
+    /// Max number of days since the token was last traded. Tycho doesn't stream the last trade of
+    /// new tokens, so this only applies to the tokens queried from Tycho. If unset, chain
+    /// specific defaults apply.
+    pub max_days_since_last_trade: Option<u64>,
+    /// Tokens admitted regardless of the other rules
+    pub allow: HashSet<Bytes>,
+    /// Tokens that are never admitted
+    pub deny: HashSet<Bytes>,
+    /// Whether to admit fee-on-transfer tokens, i.e. tokens with a non-zero `tax`
+    pub allow_tax_tokens: bool,
+}
+
+impl Default for TokenPolicy {
+    fn default() -> Self {
+        Self {
+            min_quality: DEFAULT_MIN_TOKEN_QUALITY,
+            max_days_since_last_trade: None,
+            allow: HashSet::new(),
+            deny: HashSet::new(),
+            allow_tax_tokens: true,
+        }
+    }
+}
+
+impl TokenPolicy {
+    pub fn new(min_quality: u32) -> Self {
+        Self { min_quality, ..Default::default() }
+    }
+
+    pub fn max_days_since_last_trade(mut self, days: u64) -> Self {
+        self.max_days_since_last_trade = Some(days);
+        self
+    }
+
+    pub fn allow(mut self, tokens: impl IntoIterator<Item = Bytes>) -> Self {
+        self.allow.extend(tokens);
+        self
+    }
+
+    pub fn deny(mut self, tokens: impl IntoIterator<Item = Bytes>) -> Self {
+        self.deny.extend(tokens);
+        self
+    }
+
+    pub fn allow_tax_tokens(mut self, allow: bool) -> Self {
+        self.allow_tax_tokens = allow;
+        self
+    }
+
+    /// Returns whether `token` is admitted.
+    pub fn admits(&self, token: &Token) -> bool {
+        if self.deny.contains(&token.address) {
+            return false;
+        }
+        if self.allow.contains(&token.address) {
+            return true;
+        }
+        token.quality >= self.min_quality && (self.allow_tax_tokens || token.tax == 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tycho_common::models::Chain;
+
+    use super::*;
+
+    fn token(byte: u8, quality: u32, tax: u64) -> Token {
+        Token::new(
+            &Bytes::from(vec![byte; 20]),
+            "TKN",
+            18,
+            tax,
+            &[Some(10_000)],
+            Chain::Ethereum,
+            quality,
+        )
+    }
+
+    #[test]
+    fn test_admits() {
+        let policy = TokenPolicy::new(100)
+            .allow([Bytes::from(vec![2; 20])])
+            .deny([Bytes::from(vec![3; 20])])
+            .allow_tax_tokens(false);
+
+        assert!(policy.admits(&token(1, 100, 0)));
+        assert!(!policy.admits(&token(1, 51, 0)));
+        assert!(!policy.admits(&token(1, 100, 200)));
+        // Allowed tokens skip the other rules
+        assert!(policy.admits(&token(2, 5, 200)));
+        assert!(!policy.admits(&token(3, 100, 0)));
+    }
+}
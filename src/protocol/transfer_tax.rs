@@ -0,0 +1,112 @@
+//! Transfer taxes of fee-on-transfer tokens
+//!
+//! Fee-on-transfer tokens burn or redirect part of every transfer, so a pool receives less than
+//! the amount sold and the trader receives less than the amount the pool sends. The tax of a token
+//! is its `Token::tax`, in basis points, and can be set on the tokens known to the decoder with
+//! `TychoStreamDecoder::set_transfer_taxes`. Native states apply it on both legs of a swap so their
+//! quotes match on-chain execution; VM states simulate the token contracts instead.
+use num_bigint::BigUint;
+use tycho_common::{
+    models::token::Token,
+    simulation::{errors::SimulationError, protocol_sim::GetAmountOutResult},
+};
+
+/// Denominator of `Token::tax`, i.e. taxes are in basis points.
+pub const TAX_DENOMINATOR: u64 = 10_000;
+
+/// Returns the amount received when transferring `amount` of `token`.
+///
+/// The tax is rounded down, as fee-on-transfer tokens usually compute it as
+/// `amount * tax / 10000`.
+pub fn amount_after_tax(amount: &BigUint, token: &Token) -> BigUint {
+    if token.tax == 0 {
+        return amount.clone();
+    }
+    if token.tax >= TAX_DENOMINATOR {
+        return BigUint::ZERO;
+    }
+    amount - amount * token.tax / TAX_DENOMINATOR
+}
+
+/// Returns the smallest amount to transfer for `amount` of `token` to be received, or `None` if
+/// the token takes the full amount as tax.
+pub fn amount_before_tax(amount: &BigUint, token: &Token) -> Option<BigUint> {
+    if token.tax == 0 {
+        return Some(amount.clone());
+    }
+    if token.tax >= TAX_DENOMINATOR {
+        return None;
+    }
+    let amount_times_tax = amount * token.tax;
+    if amount_times_tax < BigUint::from(TAX_DENOMINATOR) {
+        return Some(amount.clone());
+    }
+    // The smallest tax `t` such that `floor((amount + t) * tax / 10000) <= t`
+    Some(amount + (amount_times_tax - TAX_DENOMINATOR) / (TAX_DENOMINATOR - token.tax) + 1u32)
+}
+
+/// Applies the transfer tax of `token_out` to the amount of a swap result, including the partial
+/// result of a `SimulationError::InvalidInput`.
+pub(crate) fn tax_amount_out(
+    result: Result<GetAmountOutResult, SimulationError>,
+    token_out: &Token,
+) -> Result<GetAmountOutResult, SimulationError> {
+    result
+        .map(|result| tax_result(result, token_out))
+        .map_err(|err| tax_partial_amount_out(err, token_out))
+}
+
+/// Applies the transfer tax of `token_out` to the partial result of a
+/// `SimulationError::InvalidInput`, leaving other errors unchanged.
+pub(crate) fn tax_partial_amount_out(err: SimulationError, token_out: &Token) -> SimulationError {
+    match err {
+        SimulationError::InvalidInput(message, partial) => SimulationError::InvalidInput(
+            message,
+            partial.map(|partial| tax_result(partial, token_out)),
+        ),
+        err => err,
+    }
+}
+
+fn tax_result(mut result: GetAmountOutResult, token_out: &Token) -> GetAmountOutResult {
+    result.amount = amount_after_tax(&result.amount, token_out);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use tycho_common::{models::Chain, Bytes};
+
+    use super::*;
+
+    fn token(tax: u64) -> Token {
+        Token::new(&Bytes::from(vec![1; 20]), "TAX", 18, tax, &[Some(10_000)], Chain::Ethereum, 100)
+    }
+
+    #[rstest]
+    #[case::untaxed(0, 1_000, 1_000)]
+    #[case::one_percent(100, 1_000, 990)]
+    #[case::rounds_tax_down(100, 1_099, 1_088)]
+    #[case::full_tax(10_000, 1_000, 0)]
+    fn test_amount_after_tax(#[case] tax: u64, #[case] amount: u64, #[case] expected: u64) {
+        assert_eq!(amount_after_tax(&BigUint::from(amount), &token(tax)), BigUint::from(expected));
+    }
+
+    #[rstest]
+    #[case::untaxed(0, 1_000)]
+    #[case::below_one_wei_of_tax(100, 99)]
+    #[case::one_percent(100, 990)]
+    #[case::rounds_tax_down(100, 1_088)]
+    #[case::odd_tax(333, 12_345)]
+    #[case::high_tax(9_999, 3)]
+    fn test_amount_before_tax(#[case] tax: u64, #[case] amount: u64) {
+        let token = token(tax);
+
+        let before_tax = amount_before_tax(&BigUint::from(amount), &token).unwrap();
+
+        // The smallest amount receiving at least `amount` after tax
+        assert!(amount_after_tax(&before_tax, &token) >= BigUint::from(amount));
+        assert!(amount_after_tax(&(&before_tax - 1u32), &token) < BigUint::from(amount));
+    }
+}
@@ -0,0 +1,380 @@
+use std::{collections::HashMap, str::FromStr, time::Duration};
+
+use http::{Request, Uri};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Client, Proxy, RequestBuilder, Response, StatusCode, Url,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::{sleep, timeout},
+};
+use tokio_tungstenite::{client_async_tls_with_config, MaybeTlsStream, WebSocketStream};
+use tracing::warn;
+
+use crate::rfq::errors::RFQError;
+
+/// Policy for retrying failed HTTP requests to an RFQ provider.
+///
+/// Requests are retried on connection errors, timeouts, `429 Too Many Requests` and server errors.
+/// The backoff doubles after every attempt, starting at `initial_backoff` and capped at
+/// `max_backoff`.
+///
+/// Only idempotent requests, like price level queries, are retried by default. Requests for
+/// binding quotes may have been processed by the maker before failing, so retrying them can
+/// produce duplicate signed quotes. Set `retry_quotes` to retry them as well.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt. `0` disables retries.
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Whether binding quote requests are retried too
+    pub retry_quotes: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(5),
+            retry_quotes: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Disables retries.
+    pub fn none() -> Self {
+        Self { max_retries: 0, ..Default::default() }
+    }
+
+    /// Returns the policy to use for binding quote requests.
+    pub(crate) fn for_quotes(&self) -> Self {
+        if self.retry_quotes {
+            self.clone()
+        } else {
+            Self::none()
+        }
+    }
+
+    /// Returns the time to wait before the retry following the given (zero based) attempt.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+}
+
+/// Maximum size of the response of a proxy to a `CONNECT` request, without the tunnelled data.
+const MAX_PROXY_RESPONSE_SIZE: usize = 8 * 1024;
+
+/// HTTP settings used by the RFQ clients.
+///
+/// WebSocket connections are tunnelled through the proxy with HTTP `CONNECT`, which only supports
+/// `http://` proxy URLs without credentials (see [`HttpConfig::websocket_proxy`]).
+#[derive(Clone, Debug, Default)]
+pub struct HttpConfig {
+    /// Total timeout of a single request. Uses `reqwest`'s default (no timeout) if not set.
+    pub timeout: Option<Duration>,
+    /// Timeout for establishing a connection. Uses `reqwest`'s default (no timeout) if not set.
+    pub connect_timeout: Option<Duration>,
+    /// Proxy URL all HTTP requests are sent through, e.g. `http://proxy.internal:8080`
+    pub proxy: Option<String>,
+    /// Extra headers sent with every request
+    pub headers: HashMap<String, String>,
+    pub retry_policy: RetryPolicy,
+}
+
+impl HttpConfig {
+    /// Returns the extra headers as a `HeaderMap`, validating names and values.
+    pub fn header_map(&self) -> Result<HeaderMap, RFQError> {
+        self.headers
+            .iter()
+            .map(|(name, value)| {
+                let name = HeaderName::from_str(name).map_err(|e| {
+                    RFQError::InvalidInput(format!("Invalid header name {name}: {e}"))
+                })?;
+                let value = HeaderValue::from_str(value).map_err(|e| {
+                    RFQError::InvalidInput(format!("Invalid value for header {name}: {e}"))
+                })?;
+                Ok((name, value))
+            })
+            .collect()
+    }
+
+    /// Builds a `reqwest::Client` with these settings.
+    pub fn build_client(&self) -> Result<Client, RFQError> {
+        let mut builder = Client::builder().default_headers(self.header_map()?);
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(connect_timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            let proxy = Proxy::all(proxy)
+                .map_err(|e| RFQError::InvalidInput(format!("Invalid proxy URL {proxy}: {e}")))?;
+            builder = builder.proxy(proxy);
+        }
+        builder
+            .build()
+            .map_err(|e| RFQError::FatalError(format!("Failed to build HTTP client: {e}")))
+    }
+
+    /// Returns the `host:port` of the proxy WebSocket connections are tunnelled through, if a
+    /// proxy is set.
+    ///
+    /// Fails if the proxy can't be used for WebSockets, i.e. if its URL doesn't use the `http`
+    /// scheme or contains credentials.
+    pub fn websocket_proxy(&self) -> Result<Option<String>, RFQError> {
+        let Some(proxy) = &self.proxy else {
+            return Ok(None);
+        };
+        let url = Url::parse(proxy)
+            .map_err(|e| RFQError::InvalidInput(format!("Invalid proxy URL {proxy}: {e}")))?;
+        if url.scheme() != "http" {
+            return Err(RFQError::InvalidInput(format!(
+                "Proxy {proxy} is not supported for WebSocket connections, only http:// proxies are"
+            )));
+        }
+        if !url.username().is_empty() || url.password().is_some() {
+            return Err(RFQError::InvalidInput(format!(
+                "Proxy credentials are not supported for WebSocket connections: {proxy}"
+            )));
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| RFQError::InvalidInput(format!("Proxy URL {proxy} has no host")))?;
+        let port = url
+            .port_or_known_default()
+            .unwrap_or(80);
+        Ok(Some(format!("{host}:{port}")))
+    }
+}
+
+/// Opens the WebSocket connection of `request` with these settings.
+///
+/// The connection goes through the proxy, if one is set, and is subject to the connect timeout.
+/// The timeout covers the WebSocket handshake too. Headers are not added to `request`.
+pub(crate) async fn connect_websocket(
+    http_config: &HttpConfig,
+    request: Request<()>,
+) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, RFQError> {
+    let proxy = http_config.websocket_proxy()?;
+    let target = websocket_target(request.uri())?;
+    let connect = async {
+        let stream = match &proxy {
+            Some(proxy) => connect_through_proxy(proxy, &target).await?,
+            None => TcpStream::connect(&target)
+                .await
+                .map_err(|e| {
+                    RFQError::ConnectionError(format!("Failed to connect to {target}: {e}"))
+                })?,
+        };
+        let (ws_stream, _) = client_async_tls_with_config(request, stream, None, None)
+            .await
+            .map_err(|e| {
+                RFQError::ConnectionError(format!("WebSocket handshake with {target} failed: {e}"))
+            })?;
+        Ok(ws_stream)
+    };
+    match http_config.connect_timeout {
+        Some(connect_timeout) => timeout(connect_timeout, connect)
+            .await
+            .map_err(|_| RFQError::ConnectionError(format!("Connection to {target} timed out")))?,
+        None => connect.await,
+    }
+}
+
+/// Returns the `host:port` a WebSocket URI connects to.
+fn websocket_target(uri: &Uri) -> Result<String, RFQError> {
+    let host = uri
+        .host()
+        .ok_or_else(|| RFQError::InvalidInput(format!("WebSocket URL {uri} has no host")))?;
+    let port = match (uri.port_u16(), uri.scheme_str()) {
+        (Some(port), _) => port,
+        (None, Some("wss")) => 443,
+        (None, Some("ws")) => 80,
+        _ => {
+            return Err(RFQError::InvalidInput(format!("Invalid WebSocket URL {uri}")));
+        }
+    };
+    Ok(format!("{host}:{port}"))
+}
+
+/// Opens a tunnel to `target` through the HTTP proxy at `proxy` with a `CONNECT` request.
+async fn connect_through_proxy(proxy: &str, target: &str) -> Result<TcpStream, RFQError> {
+    let proxy_error =
+        |e: std::io::Error| RFQError::ConnectionError(format!("Proxy {proxy} failed: {e}"));
+    let mut stream = TcpStream::connect(proxy)
+        .await
+        .map_err(proxy_error)?;
+    stream
+        .write_all(format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n\r\n").as_bytes())
+        .await
+        .map_err(proxy_error)?;
+
+    // Reading byte by byte, so that no data of the tunnelled connection is consumed
+    let mut response = Vec::new();
+    let mut byte = [0u8; 1];
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_PROXY_RESPONSE_SIZE {
+            return Err(RFQError::ConnectionError(format!(
+                "Response of proxy {proxy} to CONNECT is too large"
+            )));
+        }
+        if stream
+            .read(&mut byte)
+            .await
+            .map_err(proxy_error)? ==
+            0
+        {
+            return Err(RFQError::ConnectionError(format!(
+                "Proxy {proxy} closed the connection before tunnelling to {target}"
+            )));
+        }
+        response.push(byte[0]);
+    }
+
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response
+        .lines()
+        .next()
+        .unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(stream),
+        _ => Err(RFQError::ConnectionError(format!(
+            "Proxy {proxy} refused to tunnel to {target}: {status_line}"
+        ))),
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn is_retryable_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout()
+}
+
+/// Sends the request created by `build_request`, retrying according to `retry_policy`.
+///
+/// A new request is built for every attempt. Once retries are exhausted, the last response or
+/// error is returned as is.
+pub(crate) async fn send_with_retry<F>(
+    retry_policy: &RetryPolicy,
+    build_request: F,
+) -> Result<Response, reqwest::Error>
+where
+    F: Fn() -> RequestBuilder,
+{
+    let mut attempt = 0;
+    loop {
+        let result = build_request().send().await;
+        let retryable = match &result {
+            Ok(response) => is_retryable_status(response.status()),
+            Err(e) => is_retryable_error(e),
+        };
+        if !retryable || attempt >= retry_policy.max_retries {
+            return result;
+        }
+
+        let backoff = retry_policy.backoff(attempt);
+        match &result {
+            Ok(response) => warn!(
+                "RFQ request failed with status {}, retrying in {backoff:?}",
+                response.status()
+            ),
+            Err(e) => warn!("RFQ request failed: {e}, retrying in {backoff:?}"),
+        }
+        sleep(backoff).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_policy_backoff() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            retry_quotes: false,
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(2), Duration::from_millis(400));
+        assert_eq!(policy.backoff(3), Duration::from_millis(500));
+        assert_eq!(policy.backoff(40), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_quote_retries_are_opt_in() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.for_quotes().max_retries, 0);
+
+        let policy = RetryPolicy { retry_quotes: true, ..Default::default() };
+        assert_eq!(policy.for_quotes(), policy);
+    }
+
+    #[test]
+    fn test_build_client() {
+        let config = HttpConfig {
+            timeout: Some(Duration::from_secs(5)),
+            connect_timeout: Some(Duration::from_secs(1)),
+            proxy: Some("http://127.0.0.1:3128".to_string()),
+            headers: HashMap::from([("x-api-env".to_string(), "staging".to_string())]),
+            retry_policy: RetryPolicy::none(),
+        };
+        assert!(config.build_client().is_ok());
+    }
+
+    #[test]
+    fn test_websocket_proxy() {
+        let proxy = |proxy: &str| {
+            HttpConfig { proxy: Some(proxy.to_string()), ..Default::default() }.websocket_proxy()
+        };
+        assert_eq!(
+            HttpConfig::default()
+                .websocket_proxy()
+                .unwrap(),
+            None
+        );
+        assert_eq!(proxy("http://127.0.0.1:3128").unwrap(), Some("127.0.0.1:3128".to_string()));
+        assert_eq!(proxy("http://proxy.internal").unwrap(), Some("proxy.internal:80".to_string()));
+        assert!(matches!(proxy("socks5://127.0.0.1:1080"), Err(RFQError::InvalidInput(_))));
+        assert!(matches!(proxy("http://user:pw@127.0.0.1:3128"), Err(RFQError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_websocket_target() {
+        let target = |url: &str| websocket_target(&url.parse::<Uri>().unwrap());
+        assert_eq!(
+            target("wss://api.bebop.xyz/pmm/ethereum/v3/pricing").unwrap(),
+            "api.bebop.xyz:443"
+        );
+        assert_eq!(target("ws://127.0.0.1:8080/pricing").unwrap(), "127.0.0.1:8080");
+        assert!(matches!(target("http://api.bebop.xyz"), Err(RFQError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_invalid_headers() {
+        let config = HttpConfig {
+            headers: HashMap::from([("invalid header".to_string(), "value".to_string())]),
+            ..Default::default()
+        };
+        assert!(matches!(config.build_client(), Err(RFQError::InvalidInput(_))));
+
+        let config = HttpConfig {
+            headers: HashMap::from([("x-valid".to_string(), "invalid\nvalue".to_string())]),
+            ..Default::default()
+        };
+        assert!(matches!(config.header_map(), Err(RFQError::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,440 @@
+//! Exact arithmetic for walking RFQ price levels.
+//!
+//! RFQ providers publish price levels as floating point numbers. To avoid losing precision on
+//! tokens with many decimals, each level is converted to an exact decimal and all swap amounts are
+//! computed on atomic token units using `BigUint`. Amounts paid out by the maker are always rounded
+//! down, and amounts required from the taker are always rounded up.
+use std::str::FromStr;
+
+use num_bigint::BigUint;
+use num_traits::Zero;
+use tycho_common::simulation::errors::SimulationError;
+
+/// Rounding direction of an integer division.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+/// Computes `value * numerator / denominator` with the given rounding direction.
+pub fn mul_div(
+    value: &BigUint,
+    numerator: &BigUint,
+    denominator: &BigUint,
+    rounding: Rounding,
+) -> BigUint {
+    let product = value * numerator;
+    let quotient = &product / denominator;
+    if rounding == Rounding::Up && !(&product % denominator).is_zero() {
+        quotient + 1u32
+    } else {
+        quotient
+    }
+}
+
+fn pow10(exponent: u32) -> BigUint {
+    BigUint::from(10u32).pow(exponent)
+}
+
+/// A non-negative decimal number represented exactly as `mantissa / 10^scale`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decimal {
+    mantissa: BigUint,
+    scale: u32,
+}
+
+impl Decimal {
+    /// Converts an `f32` into the shortest decimal that round-trips to the same `f32`.
+    ///
+    /// This recovers the decimal value published by the maker (e.g. `3070.05`) instead of the
+    /// exact binary value of the float (`3070.050048828125`).
+    pub fn from_f32(value: f32) -> Result<Self, SimulationError> {
+        Self::parse(&value.to_string())
+    }
+
+    /// Converts an `f64` into the shortest decimal that round-trips to the same `f64`.
+    pub fn from_f64(value: f64) -> Result<Self, SimulationError> {
+        Self::parse(&value.to_string())
+    }
+
+    fn parse(repr: &str) -> Result<Self, SimulationError> {
+        let (integer, fraction) = repr
+            .split_once('.')
+            .unwrap_or((repr, ""));
+        let mantissa = BigUint::from_str(&format!("{integer}{fraction}")).map_err(|_| {
+            SimulationError::RecoverableError(format!("Invalid price level value: {repr}"))
+        })?;
+        Ok(Self { mantissa, scale: fraction.len() as u32 })
+    }
+
+    /// Converts the decimal into atomic units of a token with `decimals` decimals.
+    pub fn to_atomic(&self, decimals: u32, rounding: Rounding) -> BigUint {
+        mul_div(&self.mantissa, &pow10(decimals), &pow10(self.scale), rounding)
+    }
+
+    /// Creates the decimal worth `amount` atomic units of a token with `decimals` decimals.
+    pub fn from_atomic(amount: &BigUint, decimals: u32) -> Self {
+        Self { mantissa: amount.clone(), scale: decimals }
+    }
+
+    /// Returns the largest `f32` whose decimal, as read back by [`Self::from_f32`], doesn't
+    /// exceed this one, i.e. the decimal rounded toward zero.
+    ///
+    /// Used for the sizes remaining on partly consumed levels, which must never offer more than
+    /// the maker has left.
+    pub fn to_f32_down(&self) -> f32 {
+        let mut value = self
+            .to_float_repr()
+            .parse::<f32>()
+            .expect("A decimal is a valid float")
+            .min(f32::MAX);
+        while Self::from_f32(value).is_ok_and(|decimal| decimal.exceeds(self)) {
+            value = value.next_down();
+        }
+        value
+    }
+
+    /// Returns the largest `f64` whose decimal, as read back by [`Self::from_f64`], doesn't
+    /// exceed this one, i.e. the decimal rounded toward zero.
+    pub fn to_f64_down(&self) -> f64 {
+        let mut value = self
+            .to_float_repr()
+            .parse::<f64>()
+            .expect("A decimal is a valid float")
+            .min(f64::MAX);
+        while Self::from_f64(value).is_ok_and(|decimal| decimal.exceeds(self)) {
+            value = value.next_down();
+        }
+        value
+    }
+
+    fn to_float_repr(&self) -> String {
+        format!("{}e-{}", self.mantissa, self.scale)
+    }
+
+    /// Returns whether the value of this decimal is greater than the value of `other`.
+    fn exceeds(&self, other: &Self) -> bool {
+        &self.mantissa * pow10(other.scale) > &other.mantissa * pow10(self.scale)
+    }
+}
+
+/// A single RFQ price level expressed in atomic token units.
+///
+/// The price is stored as the exact ratio `price_numerator / price_denominator` of quote token
+/// atomic units per base token atomic unit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AtomicLevel {
+    price_numerator: BigUint,
+    price_denominator: BigUint,
+    /// Base token amount available at this level, rounded down.
+    pub size: BigUint,
+}
+
+impl AtomicLevel {
+    /// Creates a level from a decimal `price` (quote per base token) and decimal `size` (in base
+    /// token).
+    pub fn new(price: &Decimal, size: &Decimal, base_decimals: u32, quote_decimals: u32) -> Self {
+        Self {
+            price_numerator: &price.mantissa * pow10(quote_decimals),
+            price_denominator: pow10(price.scale + base_decimals),
+            size: size.to_atomic(base_decimals, Rounding::Down),
+        }
+    }
+
+    /// Creates a level from the floating point `(price, size)` pair published by a maker.
+    pub fn from_f64(
+        price: f64,
+        size: f64,
+        base_decimals: u32,
+        quote_decimals: u32,
+    ) -> Result<Self, SimulationError> {
+        Ok(Self::new(
+            &Decimal::from_f64(price)?,
+            &Decimal::from_f64(size)?,
+            base_decimals,
+            quote_decimals,
+        ))
+    }
+
+    /// Creates a level from the single precision `(price, size)` pair published by a maker.
+    pub fn from_f32(
+        price: f32,
+        size: f32,
+        base_decimals: u32,
+        quote_decimals: u32,
+    ) -> Result<Self, SimulationError> {
+        Ok(Self::new(
+            &Decimal::from_f32(price)?,
+            &Decimal::from_f32(size)?,
+            base_decimals,
+            quote_decimals,
+        ))
+    }
+
+    /// Returns the quote token amount worth `base_amount` at this level's price.
+    pub fn quote_for_base(&self, base_amount: &BigUint, rounding: Rounding) -> BigUint {
+        mul_div(base_amount, &self.price_numerator, &self.price_denominator, rounding)
+    }
+
+    /// Returns the base token amount worth `quote_amount` at this level's price.
+    pub fn base_for_quote(&self, quote_amount: &BigUint, rounding: Rounding) -> BigUint {
+        mul_div(quote_amount, &self.price_denominator, &self.price_numerator, rounding)
+    }
+}
+
+/// Sells `amount_in` base tokens into the given levels, best level first.
+///
+/// Returns a tuple `(amount_out, remaining_amount_in)` where `amount_out` is the quote token
+/// amount received, rounded down per level, and `remaining_amount_in` is the base token amount
+/// that could not be filled due to lack of liquidity.
+pub fn sell_base_into_levels(levels: &[AtomicLevel], amount_in: &BigUint) -> (BigUint, BigUint) {
+    let mut remaining_amount_in = amount_in.clone();
+    let mut amount_out = BigUint::ZERO;
+
+    for level in levels {
+        if remaining_amount_in.is_zero() {
+            break;
+        }
+        let filled = (&remaining_amount_in)
+            .min(&level.size)
+            .clone();
+        amount_out += level.quote_for_base(&filled, Rounding::Down);
+        remaining_amount_in -= filled;
+    }
+    (amount_out, remaining_amount_in)
+}
+
+/// Sells `amount_in` quote tokens into the given levels to buy base tokens, best level first.
+///
+/// Taking a whole level costs its size times its price, rounded up. A partially taken level
+/// pays out base tokens rounded down. Returns a tuple `(amount_out, remaining_amount_in)` where
+/// `amount_out` is the base token amount received and `remaining_amount_in` is the quote token
+/// amount that could not be filled due to lack of liquidity.
+pub fn sell_quote_into_levels(levels: &[AtomicLevel], amount_in: &BigUint) -> (BigUint, BigUint) {
+    let mut remaining_amount_in = amount_in.clone();
+    let mut amount_out = BigUint::ZERO;
+
+    for level in levels {
+        if remaining_amount_in.is_zero() {
+            break;
+        }
+        let level_cost = level.quote_for_base(&level.size, Rounding::Up);
+        if remaining_amount_in >= level_cost {
+            amount_out += &level.size;
+            remaining_amount_in -= level_cost;
+        } else {
+            amount_out += level.base_for_quote(&remaining_amount_in, Rounding::Down);
+            remaining_amount_in = BigUint::ZERO;
+        }
+    }
+    (amount_out, remaining_amount_in)
+}
+
+/// Returns the base token amount a swap of `amount_in` takes from each level, in the order of
+/// `levels`.
+///
+/// If `sell_base` is true, `amount_in` is denominated in the base token, otherwise in the quote
+/// token. The fills match the amounts quoted by [`sell_base_into_levels`] and
+/// [`sell_quote_into_levels`], so that the liquidity removed from the levels is exactly the
+/// liquidity quoted.
+pub fn fill_levels(levels: &[AtomicLevel], amount_in: &BigUint, sell_base: bool) -> Vec<BigUint> {
+    let mut remaining_amount_in = amount_in.clone();
+    levels
+        .iter()
+        .map(|level| {
+            if sell_base {
+                let filled = (&remaining_amount_in)
+                    .min(&level.size)
+                    .clone();
+                remaining_amount_in -= &filled;
+                filled
+            } else {
+                let level_cost = level.quote_for_base(&level.size, Rounding::Up);
+                if remaining_amount_in >= level_cost {
+                    remaining_amount_in -= level_cost;
+                    level.size.clone()
+                } else {
+                    let filled = level.base_for_quote(&remaining_amount_in, Rounding::Down);
+                    remaining_amount_in = BigUint::ZERO;
+                    filled
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_decimal_from_float() {
+        let price = Decimal::from_f32(3070.05f32).unwrap();
+        assert_eq!(price.to_atomic(2, Rounding::Down), BigUint::from(307005u32));
+
+        let size = Decimal::from_f64(0.000001).unwrap();
+        assert_eq!(size.to_atomic(18, Rounding::Down), BigUint::from(1_000_000_000_000u64));
+
+        assert!(Decimal::from_f64(-1.0).is_err());
+        assert!(Decimal::from_f64(f64::NAN).is_err());
+        assert!(Decimal::from_f64(f64::INFINITY).is_err());
+    }
+
+    #[test]
+    fn test_mul_div_rounding() {
+        let (a, b, c) = (BigUint::from(10u32), BigUint::from(1u32), BigUint::from(3u32));
+        assert_eq!(mul_div(&a, &b, &c, Rounding::Down), BigUint::from(3u32));
+        assert_eq!(mul_div(&a, &b, &c, Rounding::Up), BigUint::from(4u32));
+        assert_eq!(mul_div(&a, &c, &b, Rounding::Up), BigUint::from(30u32));
+    }
+
+    #[test]
+    fn test_sell_base_into_levels() {
+        // WETH (18 decimals) / USDC (6 decimals)
+        let levels = vec![
+            AtomicLevel::from_f64(3000.0, 2.0, 18, 6).unwrap(),
+            AtomicLevel::from_f64(2900.0, 2.5, 18, 6).unwrap(),
+        ];
+
+        let (amount_out, remaining) =
+            sell_base_into_levels(&levels, &BigUint::from(3_000_000_000_000_000_000u128));
+        assert_eq!(amount_out, BigUint::from(8_900_000_000u64));
+        assert!(remaining.is_zero());
+
+        let (amount_out, remaining) =
+            sell_base_into_levels(&levels, &BigUint::from(5_000_000_000_000_000_000u128));
+        assert_eq!(amount_out, BigUint::from(13_250_000_000u64));
+        assert_eq!(remaining, BigUint::from(500_000_000_000_000_000u128));
+    }
+
+    #[test]
+    fn test_sell_quote_into_levels() {
+        // WETH (18 decimals) / USDC (6 decimals)
+        let levels = vec![
+            AtomicLevel::from_f64(3100.0, 1.5, 18, 6).unwrap(),
+            AtomicLevel::from_f64(3000.0, 3.0, 18, 6).unwrap(),
+        ];
+
+        // 1.5 WETH for 4650 USDC + 2350 / 3000 WETH
+        let (amount_out, remaining) =
+            sell_quote_into_levels(&levels, &BigUint::from(7_000_000_000u64));
+        assert_eq!(amount_out, BigUint::from(2_283_333_333_333_333_333u128));
+        assert!(remaining.is_zero());
+
+        // All levels cost 4650 + 9000 USDC
+        let (amount_out, remaining) =
+            sell_quote_into_levels(&levels, &BigUint::from(15_000_000_000u64));
+        assert_eq!(amount_out, BigUint::from(4_500_000_000_000_000_000u128));
+        assert_eq!(remaining, BigUint::from(1_350_000_000u64));
+    }
+
+    #[test]
+    fn test_fill_levels() {
+        // WETH (18 decimals) / USDC (6 decimals)
+        let levels = vec![
+            AtomicLevel::from_f64(3100.0, 1.5, 18, 6).unwrap(),
+            AtomicLevel::from_f64(3000.0, 3.0, 18, 6).unwrap(),
+        ];
+
+        let fills = fill_levels(&levels, &BigUint::from(2_000_000_000_000_000_000u128), true);
+        assert_eq!(
+            fills,
+            vec![
+                BigUint::from(1_500_000_000_000_000_000u128),
+                BigUint::from(500_000_000_000_000_000u128)
+            ]
+        );
+
+        // Matches the amount out of `sell_quote_into_levels`
+        let fills = fill_levels(&levels, &BigUint::from(7_000_000_000u64), false);
+        assert_eq!(
+            fills,
+            vec![
+                BigUint::from(1_500_000_000_000_000_000u128),
+                BigUint::from(783_333_333_333_333_333u128)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decimal_from_atomic() {
+        let size = Decimal::from_atomic(&BigUint::from(2_500_000_000_000_000_000u128), 18);
+        assert_eq!(size.to_f32_down(), 2.5f32);
+        assert_eq!(size.to_f64_down(), 2.5);
+
+        // 0.1 + 1e-17 is closest to the float read back as 0.1, which doesn't exceed it
+        let size = Decimal::from_atomic(&BigUint::from(100_000_000_000_000_010u128), 18);
+        assert_eq!(size.to_f64_down(), 0.1);
+        // 0.1 - 1e-18 is also closest to it, which exceeds it
+        let size = Decimal::from_atomic(&BigUint::from(99_999_999_999_999_999u128), 18);
+        assert_eq!(size.to_f64_down(), 0.1f64.next_down());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_remaining_size_never_exceeds_exact_remainder(
+            price in 1e-6f32..1e6f32,
+            size in 1e-6f32..1e6f32,
+            fraction in 0.0f64..1.0f64,
+            base_decimals in 0u32..=18,
+        ) {
+            let level = AtomicLevel::from_f32(price, size, base_decimals, 6).unwrap();
+            let filled = BigUint::from((fraction * 1e6) as u64) * &level.size / 1_000_000u32;
+            let remainder = &level.size - &filled;
+
+            let remaining_size =
+                Decimal::from_atomic(&remainder, base_decimals).to_f32_down();
+            let remaining_level =
+                AtomicLevel::from_f32(price, remaining_size, base_decimals, 6).unwrap();
+            prop_assert!(remaining_level.size <= remainder);
+            prop_assert!(
+                remaining_level.quote_for_base(&remaining_level.size, Rounding::Down) <=
+                    level.quote_for_base(&remainder, Rounding::Down)
+            );
+
+            let remaining_size =
+                Decimal::from_atomic(&remainder, base_decimals).to_f64_down();
+            prop_assert!(
+                Decimal::from_f64(remaining_size)
+                    .unwrap()
+                    .to_atomic(base_decimals, Rounding::Down) <= remainder
+            );
+        }
+
+        #[test]
+        fn prop_sell_base_no_overflow_with_18_decimals(
+            price in 1e-12f64..1e12f64,
+            size in 1e-12f64..1e15f64,
+            amount_in in any::<u128>(),
+        ) {
+            let level = AtomicLevel::from_f64(price, size, 18, 18).unwrap();
+            let amount_in = BigUint::from(amount_in) * pow10(18);
+            let (amount_out, remaining) =
+                sell_base_into_levels(std::slice::from_ref(&level), &amount_in);
+
+            let filled = &amount_in - &remaining;
+            prop_assert!(filled <= level.size);
+            prop_assert_eq!(amount_out, level.quote_for_base(&filled, Rounding::Down));
+        }
+
+        #[test]
+        fn prop_sell_quote_never_exceeds_level_size(
+            price in 1e-12f64..1e12f64,
+            size in 1e-12f64..1e15f64,
+            amount_in in any::<u128>(),
+        ) {
+            let level = AtomicLevel::from_f64(price, size, 18, 18).unwrap();
+            let amount_in = BigUint::from(amount_in) * pow10(18);
+            let (amount_out, remaining) =
+                sell_quote_into_levels(std::slice::from_ref(&level), &amount_in);
+
+            prop_assert!(amount_out <= level.size);
+            // The maker is never paid less than the value of what it sold
+            let paid = &amount_in - &remaining;
+            prop_assert!(level.quote_for_base(&amount_out, Rounding::Down) <= paid);
+        }
+    }
+}
@@ -0,0 +1,359 @@
+//! A local mock RFQ maker used to test the RFQ clients without network access.
+//!
+//! The mock serves:
+//! - a WebSocket which publishes protobuf encoded `BebopPricingUpdate`s to every connection,
+//! - Bebop's HTTP quote endpoint,
+//! - Hashflow's HTTP `market-makers`, `price-levels` and `rfq` endpoints.
+//!
+//! Point the clients to `MockMaker::ws_url` and `MockMaker::http_url` to use it.
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use futures::{SinkExt, StreamExt};
+use num_bigint::BigUint;
+use prost::Message as ProstMessage;
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+    task::JoinHandle,
+};
+use tokio_tungstenite::{accept_async, tungstenite::Message};
+
+use crate::rfq::protocols::{
+    bebop::models::BebopPricingUpdate,
+    hashflow::models::{HashflowMarketMakerLevels, HashflowQuoteRequest},
+};
+
+/// Data served by the `MockMaker`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MockMakerConfig {
+    /// Pricing updates sent, in order, to every Bebop WebSocket connection
+    pub bebop_updates: Vec<BebopPricingUpdate>,
+    /// Hashflow price levels by market maker name
+    pub hashflow_levels: HashMap<String, Vec<HashflowMarketMakerLevels>>,
+    /// Amount out returned by every binding quote
+    pub quote_amount_out: BigUint,
+}
+
+/// A running mock maker. The servers are shut down when it is dropped.
+pub(crate) struct MockMaker {
+    ws_addr: SocketAddr,
+    http_addr: SocketAddr,
+    ws_connections: Arc<AtomicU32>,
+    ws_disconnect: broadcast::Sender<()>,
+    http_requests: Arc<Mutex<Vec<String>>>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl MockMaker {
+    /// Starts the mock maker on random local ports.
+    pub async fn start(config: MockMakerConfig) -> Self {
+        let ws_listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind mock maker WebSocket listener");
+        let http_listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind mock maker HTTP listener");
+        let ws_addr = ws_listener.local_addr().unwrap();
+        let http_addr = http_listener.local_addr().unwrap();
+
+        let config = Arc::new(config);
+        let ws_connections = Arc::new(AtomicU32::new(0));
+        let (ws_disconnect, _) = broadcast::channel(1);
+        let http_requests = Arc::new(Mutex::new(Vec::new()));
+
+        let handles = vec![
+            tokio::spawn(serve_ws(
+                ws_listener,
+                config.clone(),
+                ws_connections.clone(),
+                ws_disconnect.clone(),
+            )),
+            tokio::spawn(serve_http(http_listener, config, http_requests.clone())),
+        ];
+
+        Self { ws_addr, http_addr, ws_connections, ws_disconnect, http_requests, handles }
+    }
+
+    /// Base URL of the Bebop pricing WebSocket
+    pub fn ws_url(&self) -> String {
+        format!("ws://{}", self.ws_addr)
+    }
+
+    /// Base URL of the HTTP endpoints
+    pub fn http_url(&self) -> String {
+        format!("http://{}", self.http_addr)
+    }
+
+    /// Number of WebSocket connections accepted so far
+    pub fn ws_connections(&self) -> u32 {
+        self.ws_connections
+            .load(Ordering::SeqCst)
+    }
+
+    /// Drops all open WebSocket connections. New connections are accepted as before.
+    pub fn drop_ws_connections(&self) {
+        // Fails only if no connection is open
+        let _ = self.ws_disconnect.send(());
+    }
+
+    /// Paths of all HTTP requests received so far, in order
+    pub fn http_requests(&self) -> Vec<String> {
+        self.http_requests
+            .lock()
+            .unwrap()
+            .clone()
+    }
+}
+
+impl Drop for MockMaker {
+    fn drop(&mut self) {
+        for handle in &self.handles {
+            handle.abort();
+        }
+    }
+}
+
+async fn serve_ws(
+    listener: TcpListener,
+    config: Arc<MockMakerConfig>,
+    connections: Arc<AtomicU32>,
+    disconnect: broadcast::Sender<()>,
+) {
+    while let Ok((stream, _)) = listener.accept().await {
+        connections.fetch_add(1, Ordering::SeqCst);
+        let config = config.clone();
+        let mut disconnect = disconnect.subscribe();
+        tokio::spawn(async move {
+            let Ok(ws_stream) = accept_async(stream).await else {
+                return;
+            };
+            let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+            for update in &config.bebop_updates {
+                let message = Message::Binary(update.encode_to_vec().into());
+                if ws_sender.send(message).await.is_err() {
+                    return;
+                }
+            }
+            // Keep the connection open until the client goes away or the connections are dropped
+            loop {
+                tokio::select! {
+                    message = ws_receiver.next() => {
+                        if !matches!(message, Some(Ok(_))) {
+                            return;
+                        }
+                    }
+                    _ = disconnect.recv() => return,
+                }
+            }
+        });
+    }
+}
+
+async fn serve_http(
+    listener: TcpListener,
+    config: Arc<MockMakerConfig>,
+    requests: Arc<Mutex<Vec<String>>>,
+) {
+    while let Ok((mut stream, _)) = listener.accept().await {
+        let config = config.clone();
+        let requests = requests.clone();
+        tokio::spawn(async move {
+            let Some((method, target, body)) = read_http_request(&mut stream).await else {
+                return;
+            };
+            let (path, query) = target
+                .split_once('?')
+                .unwrap_or((target.as_str(), ""));
+            requests
+                .lock()
+                .unwrap()
+                .push(path.to_string());
+
+            let (status, response) = match (method.as_str(), path) {
+                ("GET", path) if path.starts_with("/pmm/") && path.ends_with("/quote") => {
+                    (200, bebop_quote(&config, query))
+                }
+                ("GET", "/taker/v3/market-makers") => (
+                    200,
+                    json!({ "marketMakers": config.hashflow_levels.keys().collect::<Vec<_>>() }),
+                ),
+                ("GET", "/taker/v3/price-levels") => (200, hashflow_price_levels(&config)),
+                ("POST", "/taker/v3/rfq") => match serde_json::from_slice(&body) {
+                    Ok(request) => (200, hashflow_quote(&config, request)),
+                    Err(e) => (400, json!({ "status": "fail", "error": e.to_string() })),
+                },
+                _ => (404, json!({ "error": format!("Unknown endpoint: {method} {path}") })),
+            };
+
+            let body = response.to_string();
+            let reason = match status {
+                200 => "OK",
+                400 => "Bad Request",
+                _ => "Not Found",
+            };
+            let response = format!(
+                "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            let _ = stream
+                .write_all(response.as_bytes())
+                .await;
+            let _ = stream.shutdown().await;
+        });
+    }
+}
+
+/// Reads a single HTTP/1.1 request and returns its method, target and body.
+async fn read_http_request(stream: &mut TcpStream) -> Option<(String, String, Vec<u8>)> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buffer
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+        {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let target = request_line.next()?.to_string();
+    let content_length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+
+    let mut body = buffer[header_end..].to_vec();
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    Some((method, target, body))
+}
+
+fn bebop_quote(config: &MockMakerConfig, query: &str) -> Value {
+    let params: HashMap<&str, &str> = query
+        .split('&')
+        .filter_map(|param| param.split_once('='))
+        .collect();
+    let param = |name: &str| {
+        params
+            .get(name)
+            .copied()
+            .unwrap_or_default()
+    };
+
+    json!({
+        "status": "QUOTE_SUCCESS",
+        "settlementAddress": "0xbbbbbBB520d69a9775E85b458C58c648259FAD5F",
+        "tx": {
+            "to": "0xbbbbbBB520d69a9775E85b458C58c648259FAD5F",
+            "data": "0x4dcebcba",
+            "value": "0",
+            "from": param("taker_address"),
+            "gas": 100000,
+            "gasPrice": 1000000000u64,
+        },
+        "toSign": {
+            "maker_address": "0x0000000000000000000000000000000000000001",
+            "taker_address": param("taker_address"),
+            "maker_token": param("buy_tokens"),
+            "taker_token": param("sell_tokens"),
+            "maker_amount": config.quote_amount_out.to_string(),
+            "taker_amount": param("sell_amounts"),
+            "maker_nonce": "1",
+            "expiry": u64::MAX,
+            "receiver": param("receiver_address"),
+            "packed_commands": "0",
+        },
+        "partialFillOffset": 12,
+    })
+}
+
+fn hashflow_price_levels(config: &MockMakerConfig) -> Value {
+    let levels: HashMap<&String, Vec<Value>> = config
+        .hashflow_levels
+        .iter()
+        .map(|(mm, mm_levels)| {
+            let mm_levels: Vec<Value> = mm_levels
+                .iter()
+                .map(|mm_level| {
+                    // Hashflow publishes quantities and prices as strings
+                    let levels: Vec<Value> = mm_level
+                        .levels
+                        .iter()
+                        .map(|level| {
+                            json!({ "q": level.quantity.to_string(), "p": level.price.to_string() })
+                        })
+                        .collect();
+                    json!({
+                        "pair": {
+                            "baseToken": mm_level.pair.base_token.to_string(),
+                            "quoteToken": mm_level.pair.quote_token.to_string(),
+                        },
+                        "levels": levels,
+                    })
+                })
+                .collect();
+            (mm, mm_levels)
+        })
+        .collect();
+
+    json!({ "status": "success", "levels": levels, "error": null })
+}
+
+fn hashflow_quote(config: &MockMakerConfig, request: HashflowQuoteRequest) -> Value {
+    let quotes: Vec<Value> = request
+        .rfqs
+        .iter()
+        .map(|rfq| {
+            json!({
+                "quoteData": {
+                    "baseToken": rfq.base_token,
+                    "quoteToken": rfq.quote_token,
+                    "baseTokenAmount": rfq.base_token_amount.clone().unwrap_or_default(),
+                    "quoteTokenAmount": config.quote_amount_out.to_string(),
+                    "trader": rfq.trader,
+                    "effectiveTrader": rfq.effective_trader,
+                    "txid": "0x0000000000000000000000000000000000000000000000000000000000000001",
+                    "pool": "0x0000000000000000000000000000000000000002",
+                    "quoteExpiry": u64::MAX,
+                    "nonce": 1,
+                    "externalAccount": null,
+                },
+                "signature": "0x01",
+                "targetContract": null,
+                "value": null,
+            })
+        })
+        .collect();
+
+    json!({
+        "status": "success",
+        "error": null,
+        "rfqId": "mock-rfq",
+        "internalRfqIds": null,
+        "quotes": quotes,
+    })
+}
@@ -1,5 +1,9 @@
 pub mod client;
 pub mod errors;
+pub mod http;
+pub mod math;
+#[cfg(test)]
+pub(crate) mod mock_maker;
 pub mod models;
 pub mod protocols;
 pub mod stream;
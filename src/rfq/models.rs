@@ -1,4 +1,12 @@
+use std::{collections::HashSet, str::FromStr, time::SystemTime};
+
 use tycho_client::feed::{BlockHeader, HeaderLike};
+use tycho_common::{models::Chain, simulation::errors::SimulationError, Bytes};
+
+use crate::rfq::errors::RFQError;
+
+/// Default time (in seconds) for which RFQ price levels are considered valid after being received.
+pub const DEFAULT_LEVELS_TTL: u64 = 10;
 
 #[derive(Clone, Default)]
 pub struct TimestampHeader {
@@ -14,3 +22,68 @@ impl HeaderLike for TimestampHeader {
         self.timestamp
     }
 }
+
+/// Returns the current unix timestamp in seconds.
+pub fn now_timestamp() -> Result<u64, SimulationError> {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .map_err(|_| SimulationError::FatalError("SystemTime before UNIX EPOCH!".into()))
+}
+
+/// Checks that price levels received at `received_at` (unix seconds) are still within their `ttl`.
+///
+/// Returns a `SimulationError::RecoverableError` if the levels are stale, since fresh levels are
+/// expected to arrive with the next update from the RFQ provider.
+pub fn check_levels_freshness(received_at: u64, ttl: u64) -> Result<(), SimulationError> {
+    let now = now_timestamp()?;
+    let age = now.saturating_sub(received_at);
+    if age > ttl {
+        return Err(SimulationError::RecoverableError(format!(
+            "Price levels are stale: received {age}s ago, ttl is {ttl}s"
+        )));
+    }
+    Ok(())
+}
+
+fn str_to_bytes(address: &str) -> Result<Bytes, RFQError> {
+    Bytes::from_str(address).map_err(|_| {
+        RFQError::FatalError(format!("Failed to parse default quote token: {address}"))
+    })
+}
+
+/// Returns default quote tokens for TVL calculation based on the chain
+pub fn default_quote_tokens_for_chain(chain: Chain) -> Result<HashSet<Bytes>, RFQError> {
+    match chain {
+        Chain::Ethereum => Ok(HashSet::from([
+            str_to_bytes("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")?, // USDC
+            str_to_bytes("0xdac17f958d2ee523a2206206994597c13d831ec7")?, // USDT
+            str_to_bytes("0x6b175474e89094c44da98b954eedeac495271d0f")?, // DAI
+        ])),
+        Chain::Base => Ok(HashSet::from([
+            str_to_bytes("0x833589fcd6edb6e08f4c7c32d4f71b54bda02913")?, // USDC
+            str_to_bytes("0xfde4c96c8593536e31f229ea8f37b2ada2699bb2")?, // USDT
+        ])),
+        _ => Ok(HashSet::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_levels_freshness() {
+        let now = now_timestamp().unwrap();
+
+        assert!(check_levels_freshness(now, DEFAULT_LEVELS_TTL).is_ok());
+        assert!(check_levels_freshness(now - 5, 10).is_ok());
+
+        let result = check_levels_freshness(now - 11, 10);
+        if let Err(SimulationError::RecoverableError(msg)) = result {
+            assert!(msg.contains("stale"));
+        } else {
+            panic!("Expected RecoverableError");
+        }
+    }
+}
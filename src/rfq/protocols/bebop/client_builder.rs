@@ -1,31 +1,13 @@
-use std::{collections::HashSet, str::FromStr};
+use std::{collections::HashSet, time::Duration};
 
 use tycho_common::{models::Chain, Bytes};
 
-use super::client::BebopClient;
-use crate::rfq::errors::RFQError;
-
-fn str_to_bytes(address: &str) -> Result<Bytes, RFQError> {
-    Bytes::from_str(address).map_err(|_| {
-        RFQError::FatalError(format!("Failed to parse default quote token: {address}"))
-    })
-}
-
-/// Returns default quote tokens for TVL calculation based on the chain
-fn default_quote_tokens_for_chain(chain: Chain) -> Result<HashSet<Bytes>, RFQError> {
-    match chain {
-        Chain::Ethereum => Ok(HashSet::from([
-            str_to_bytes("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")?, // USDC
-            str_to_bytes("0xdac17f958d2ee523a2206206994597c13d831ec7")?, // USDT
-            str_to_bytes("0x6b175474e89094c44da98b954eedeac495271d0f")?, // DAI
-        ])),
-        Chain::Base => Ok(HashSet::from([
-            str_to_bytes("0x833589fcd6edb6e08f4c7c32d4f71b54bda02913")?, // USDC
-            str_to_bytes("0xfde4c96c8593536e31f229ea8f37b2ada2699bb2")?, // USDT
-        ])),
-        _ => Ok(HashSet::new()),
-    }
-}
+use super::client::{BebopClient, BEBOP_HTTP_URL, BEBOP_WS_URL};
+use crate::rfq::{
+    errors::RFQError,
+    http::{HttpConfig, RetryPolicy},
+    models::{default_quote_tokens_for_chain, DEFAULT_LEVELS_TTL},
+};
 
 /// `BebopClientBuilder` is a builder pattern implementation for creating instances of
 /// `BebopClient`.
@@ -57,6 +39,10 @@ pub struct BebopClientBuilder {
     tokens: HashSet<Bytes>,
     tvl: f64,
     quote_tokens: Option<HashSet<Bytes>>,
+    ttl: u64,
+    ws_url: String,
+    http_url: String,
+    http_config: HttpConfig,
 }
 
 impl BebopClientBuilder {
@@ -68,6 +54,10 @@ impl BebopClientBuilder {
             tokens: HashSet::new(),
             tvl: 100.0, // Default $100 minimum TVL
             quote_tokens: None,
+            ttl: DEFAULT_LEVELS_TTL,
+            ws_url: BEBOP_WS_URL.to_string(),
+            http_url: BEBOP_HTTP_URL.to_string(),
+            http_config: HttpConfig::default(),
         }
     }
 
@@ -90,6 +80,60 @@ impl BebopClientBuilder {
         self
     }
 
+    /// Set the number of seconds for which received price levels are considered valid.
+    /// Quoting against older levels fails with a recoverable error.
+    pub fn ttl(mut self, ttl: u64) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Set the base URL of the pricing WebSocket (defaults to `wss://api.bebop.xyz`)
+    pub fn ws_url(mut self, ws_url: &str) -> Self {
+        self.ws_url = ws_url.to_string();
+        self
+    }
+
+    /// Set the base URL of the HTTP API used for quotes (defaults to `https://api.bebop.xyz`)
+    pub fn http_url(mut self, http_url: &str) -> Self {
+        self.http_url = http_url.to_string();
+        self
+    }
+
+    /// Set the total timeout of a single HTTP request
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.http_config.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the timeout for establishing HTTP and WebSocket connections
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.http_config.connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    /// Send HTTP requests and the pricing WebSocket connection through the given proxy URL.
+    ///
+    /// The WebSocket connection is tunnelled with HTTP `CONNECT`, so `build` fails for proxies
+    /// other than `http://` ones without credentials.
+    pub fn proxy(mut self, proxy: &str) -> Self {
+        self.http_config.proxy = Some(proxy.to_string());
+        self
+    }
+
+    /// Set the policy for retrying failed HTTP requests and reconnecting the pricing WebSocket
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.http_config.retry_policy = retry_policy;
+        self
+    }
+
+    /// Add a custom header sent with every HTTP request and WebSocket handshake
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.http_config
+            .headers
+            .insert(name.to_string(), value.to_string());
+        self
+    }
+
     pub fn build(self) -> Result<BebopClient, RFQError> {
         if self.tokens.is_empty() {
             return Err(RFQError::InvalidInput(
@@ -103,12 +147,24 @@ impl BebopClientBuilder {
             quote_tokens = default_quote_tokens_for_chain(self.chain)?
         }
 
-        BebopClient::new(self.chain, self.tokens, self.tvl, self.ws_user, self.ws_key, quote_tokens)
+        BebopClient::new(
+            self.chain,
+            self.tokens,
+            self.tvl,
+            self.ws_user,
+            self.ws_key,
+            quote_tokens,
+            self.ttl,
+        )?
+        .with_base_urls(&self.ws_url, &self.http_url)?
+        .with_http_config(self.http_config)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::str::FromStr;
+
     use super::*;
 
     #[test]
@@ -145,10 +201,34 @@ mod tests {
         .tokens(tokens)
         .tvl_threshold(500.0)
         .quote_tokens(custom_quote_tokens.clone())
+        .ttl(30)
+        .ws_url("wss://staging.bebop.xyz")
+        .http_url("https://staging.bebop.xyz")
+        .timeout(Duration::from_secs(5))
+        .connect_timeout(Duration::from_secs(2))
+        .proxy("http://127.0.0.1:3128")
+        .retry_policy(RetryPolicy::none())
+        .header("x-api-env", "staging")
         .build();
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_bebop_client_builder_invalid_http_config() {
+        let mut tokens = HashSet::new();
+        tokens.insert(Bytes::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").unwrap());
+
+        let result = BebopClientBuilder::new(
+            Chain::Ethereum,
+            "test_user".to_string(),
+            "test_key".to_string(),
+        )
+        .tokens(tokens)
+        .header("invalid header", "value")
+        .build();
+        assert!(matches!(result, Err(RFQError::InvalidInput(_))), "Wrong error.");
+    }
+
     #[test]
     fn test_bebop_client_builder_validation() {
         let result = BebopClientBuilder::new(
@@ -1,5 +1,5 @@
 pub mod client;
 pub mod client_builder;
-mod models;
+pub(crate) mod models;
 pub mod state;
 pub mod tycho_decoder;
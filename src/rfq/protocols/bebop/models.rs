@@ -1,7 +1,13 @@
 use alloy::primitives::Address;
+use num_bigint::BigUint;
+use num_traits::Zero;
 use prost::Message;
 use serde::{Deserialize, Serialize};
-use tycho_common::Bytes;
+use tycho_common::{simulation::errors::SimulationError, Bytes};
+
+use crate::rfq::math::{
+    fill_levels, sell_base_into_levels, sell_quote_into_levels, AtomicLevel, Decimal,
+};
 
 /// Protobuf message for Bebop pricing updates
 #[derive(Clone, PartialEq, Message)]
@@ -45,6 +51,19 @@ impl BebopPriceData {
         Self::to_price_size_pairs(&self.asks)
     }
 
+    /// Convert flat array to exact levels in atomic token units
+    /// Input: [price1, size1, price2, size2, ...]
+    pub fn to_atomic_levels(
+        array: &[f32],
+        base_decimals: u32,
+        quote_decimals: u32,
+    ) -> Result<Vec<AtomicLevel>, SimulationError> {
+        array
+            .chunks_exact(2)
+            .map(|chunk| AtomicLevel::from_f32(chunk[0], chunk[1], base_decimals, quote_decimals))
+            .collect()
+    }
+
     pub fn get_pair_key(&self) -> String {
         // Convert raw bytes to Address (which provides checksum formatting)
         let base_addr = Address::from_slice(&self.base);
@@ -129,36 +148,56 @@ impl BebopPriceData {
         }
 
         let (total_quote_token, remaining_base_token) =
-            self.get_amount_out_from_levels(base_token_amount, price_levels);
+            Self::estimate_amount_out_from_levels(base_token_amount, price_levels);
 
         // If we can't fill the whole order (ran out of liquidity), calculate the price based on
         // the amount that we could fill, in order to have at least some price estimate
         Some(total_quote_token / (base_token_amount - remaining_base_token))
     }
 
-    /// Calculates the total token output for a given token input using provided price levels.
+    /// Calculates the exact token output for a given token input, in atomic token units.
     ///
-    /// Iterates over the given `price_levels`, consuming as much liquidity as available at each
-    /// price level until the input amount is fully consumed or liquidity runs out.
+    /// If `sell_base` is true, `amount_in` is denominated in the base token and is filled against
+    /// the bids. Otherwise, it is denominated in the quote token and is filled against the asks.
+    /// Levels are walked best first, consuming as much liquidity as available at each level. The
+    /// output is rounded down, matching how makers fill.
     ///
-    /// This method assumes that the size of the price levels is already in the same token
-    /// denomination as the `amount_in`. It does not return an error if liquidity is
-    /// insufficient to fill the entire `amount_in`. Instead, it returns the partially filled
-    /// `amount_out` along with the `remaining_amount_in`.
-    ///
-    ///
-    /// # Parameters
-    /// - `amount_in`: The amount of base tokens to trade.
-    /// - `price_levels`: A vector of `(price, size)` tuples representing available liquidity at
-    ///   each price level.
+    /// It does not return an error if liquidity is insufficient to fill the entire `amount_in`.
+    /// Instead, it returns the partially filled `amount_out` along with the `remaining_amount_in`.
     ///
     /// # Returns
     /// A tuple `(amount_out, remaining_amount_in)`:
-    /// - `amount_out`: The total quote token output from the trade.
+    /// - `amount_out`: The total output token amount.
     /// - `remaining_amount_in`: The portion of `amount_in` that could not be filled due to lack of
     ///   liquidity.
     pub fn get_amount_out_from_levels(
         &self,
+        amount_in: &BigUint,
+        sell_base: bool,
+        base_decimals: u32,
+        quote_decimals: u32,
+    ) -> Result<(BigUint, BigUint), SimulationError> {
+        if sell_base {
+            let bids = Self::to_atomic_levels(&self.bids, base_decimals, quote_decimals)?;
+            Ok(sell_base_into_levels(&bids, amount_in))
+        } else {
+            let asks = Self::to_atomic_levels(&self.asks, base_decimals, quote_decimals)?;
+            Ok(sell_quote_into_levels(&asks, amount_in))
+        }
+    }
+
+    /// Estimates the total token output for a given token input using provided price levels.
+    ///
+    /// This uses floating point arithmetic and is only meant for estimates such as TVL and mid
+    /// price calculations. Use `get_amount_out_from_levels` for quoting.
+    ///
+    /// This method assumes that the size of the price levels is already in the same token
+    /// denomination as the `amount_in`. It does not return an error if liquidity is
+    /// insufficient to fill the entire `amount_in`.
+    ///
+    /// # Returns
+    /// A tuple `(amount_out, remaining_amount_in)`.
+    fn estimate_amount_out_from_levels(
         amount_in: f64,
         price_levels: Vec<(f64, f64)>,
     ) -> (f64, f64) {
@@ -177,6 +216,42 @@ impl BebopPriceData {
         }
         (amount_out, remaining_amount_in)
     }
+
+    /// Removes the liquidity consumed by a swap of `amount_in` from the price levels, in atomic
+    /// token units.
+    ///
+    /// If `sell_base` is true, `amount_in` is denominated in the base token and is consumed from
+    /// the bids. Otherwise, it is denominated in the quote token and is consumed from the asks.
+    /// The levels are consumed with the same exact arithmetic as `get_amount_out_from_levels`, and
+    /// fully consumed levels are removed.
+    pub fn consume_levels(
+        &mut self,
+        amount_in: &BigUint,
+        sell_base: bool,
+        base_decimals: u32,
+        quote_decimals: u32,
+    ) -> Result<(), SimulationError> {
+        let levels = if sell_base { &mut self.bids } else { &mut self.asks };
+        let atomic_levels = Self::to_atomic_levels(levels, base_decimals, quote_decimals)?;
+        let fills = fill_levels(&atomic_levels, amount_in, sell_base);
+        let mut remaining_levels = Vec::with_capacity(levels.len());
+
+        for ((chunk, level), filled) in levels
+            .chunks_exact(2)
+            .zip(&atomic_levels)
+            .zip(fills)
+        {
+            if filled.is_zero() {
+                remaining_levels.extend_from_slice(chunk);
+            } else if filled < level.size {
+                let size =
+                    Decimal::from_atomic(&(&level.size - filled), base_decimals).to_f32_down();
+                remaining_levels.extend([chunk[0], size]);
+            }
+        }
+        *levels = remaining_levels;
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -336,4 +411,76 @@ mod tests {
         let insufficient_mid = price_data.get_mid_price(10.0);
         assert_eq!(insufficient_mid, Some(2000.325));
     }
+
+    #[test]
+    fn test_get_amount_out_from_levels() {
+        let price_data = BebopPriceData {
+            base: hex::decode("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").unwrap(), // WETH
+            quote: hex::decode("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap(), // USDC
+            last_update_ts: 1752617378,
+            bids: vec![3070.05f32, 0.325717f32],
+            asks: vec![3070.527f32, 0.325717f32],
+        };
+
+        // Sell 0.1 WETH at 3070.05
+        let (amount_out, remaining) = price_data
+            .get_amount_out_from_levels(&BigUint::from(100_000_000_000_000_000u64), true, 18, 6)
+            .unwrap();
+        assert_eq!(amount_out, BigUint::from(307_005_000u64));
+        assert_eq!(remaining, BigUint::ZERO);
+
+        // Buy the whole ask level, which costs 1000.122842859 USDC (rounded up)
+        let (amount_out, remaining) = price_data
+            .get_amount_out_from_levels(&BigUint::from(1_000_122_843u64), false, 18, 6)
+            .unwrap();
+        assert_eq!(amount_out, BigUint::from(325_717_000_000_000_000u64));
+        assert_eq!(remaining, BigUint::ZERO);
+    }
+
+    #[test]
+    fn test_consume_levels() {
+        let mut price_data = BebopPriceData {
+            base: hex::decode("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").unwrap(), // WETH
+            quote: hex::decode("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap(), // USDC
+            last_update_ts: 1234567890,
+            bids: vec![2000.0f32, 2.0f32, 1999.0f32, 3.0f32],
+            asks: vec![2000.0f32, 1.0f32, 2002.0f32, 1.0f32],
+        };
+
+        // Sell 2.5 WETH: consumes the first bid level and half of the second one
+        price_data
+            .consume_levels(&BigUint::from(2_500_000_000_000_000_000u128), true, 18, 6)
+            .unwrap();
+        assert_eq!(price_data.bids, vec![1999.0f32, 2.5f32]);
+
+        // Buy with 3001 USDC: consumes the first ask level and half of the second one
+        price_data
+            .consume_levels(&BigUint::from(3_001_000_000u64), false, 18, 6)
+            .unwrap();
+        assert_eq!(price_data.asks, vec![2002.0f32, 0.5f32]);
+
+        // Consuming more than available leaves no levels
+        price_data
+            .consume_levels(&BigUint::from(10_000_000_000_000_000_000u128), true, 18, 6)
+            .unwrap();
+        assert!(price_data.bids.is_empty());
+    }
+
+    #[test]
+    fn test_consume_levels_is_exact() {
+        let mut price_data = BebopPriceData {
+            base: hex::decode("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").unwrap(), // WETH
+            quote: hex::decode("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap(), // USDC
+            last_update_ts: 1234567890,
+            bids: vec![],
+            asks: vec![3070.05f32, 1.0f32, 3071.0f32, 1.0f32],
+        };
+
+        // Paying exactly the quoted cost of the first level takes all of it, without leaving dust
+        // from the float representation of its price
+        price_data
+            .consume_levels(&BigUint::from(3_070_050_000u64), false, 18, 6)
+            .unwrap();
+        assert_eq!(price_data.asks, vec![3071.0f32, 1.0f32]);
+    }
 }
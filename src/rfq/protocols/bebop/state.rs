@@ -2,7 +2,7 @@ use std::{any::Any, collections::HashMap};
 
 use async_trait::async_trait;
 use num_bigint::BigUint;
-use num_traits::{FromPrimitive, Pow, ToPrimitive};
+use num_traits::Zero;
 use tycho_common::{
     dto::ProtocolStateDelta,
     models::{protocol::GetAmountOutParams, token::Token},
@@ -14,9 +14,14 @@ use tycho_common::{
     Bytes,
 };
 
-use crate::rfq::{
-    client::RFQClient,
-    protocols::bebop::{client::BebopClient, models::BebopPriceData},
+use crate::{
+    protocol::fee::SwapFee,
+    rfq::{
+        client::RFQClient,
+        math::Rounding,
+        models::check_levels_freshness,
+        protocols::bebop::{client::BebopClient, models::BebopPriceData},
+    },
 };
 
 #[derive(Debug, Clone)]
@@ -25,6 +30,10 @@ pub struct BebopState {
     pub quote_token: Token,
     pub price_data: BebopPriceData,
     pub client: BebopClient,
+    /// Unix timestamp (in seconds) at which the price levels were received
+    pub received_at: u64,
+    /// Number of seconds after `received_at` for which the price levels are considered valid
+    pub ttl: u64,
 }
 
 impl BebopState {
@@ -33,8 +42,19 @@ impl BebopState {
         quote_token: Token,
         price_data: BebopPriceData,
         client: BebopClient,
+        received_at: u64,
+        ttl: u64,
     ) -> Self {
-        BebopState { base_token, quote_token, price_data, client }
+        BebopState { base_token, quote_token, price_data, client, received_at, ttl }
+    }
+
+    /// Replaces the client used to request binding quotes.
+    ///
+    /// Decoded states are built with an unauthenticated client using the default endpoints, so
+    /// they need the configured client to request binding quotes.
+    pub fn with_client(mut self, client: BebopClient) -> Self {
+        self.client = client;
+        self
     }
 }
 
@@ -44,6 +64,8 @@ impl ProtocolSim for BebopState {
     }
 
     fn spot_price(&self, base: &Token, quote: &Token) -> Result<f64, SimulationError> {
+        check_levels_freshness(self.received_at, self.ttl)?;
+
         // Since this method does not care about sell direction, we average the price of the best
         // bid and ask
         let best_bid = self
@@ -99,41 +121,43 @@ impl ProtocolSim for BebopState {
                 token_in.address, token_out.address
             )))
         };
-        // if sell base is true -> use bids
-        // if sell base is false -> use asks AND amount is in quote token so the levels need to be
-        // adjusted
-        let price_levels = if sell_base {
-            self.price_data.get_bids()
-        } else {
-            self.price_data
-                .get_asks()
-                .iter()
-                .map(|(price, size)| (1.0 / price, price * size))
-                .collect()
-        };
+        check_levels_freshness(self.received_at, self.ttl)?;
 
-        if price_levels.is_empty() {
+        // if sell base is true -> use bids
+        // if sell base is false -> use asks AND amount is in quote token
+        let levels = if sell_base { &self.price_data.bids } else { &self.price_data.asks };
+        if levels.is_empty() {
             return Err(SimulationError::RecoverableError("No liquidity".into()));
         }
 
-        let amount_in = amount_in.to_f64().ok_or_else(|| {
-            SimulationError::RecoverableError("Can't convert amount in to f64".into())
-        })? / 10f64.powi(token_in.decimals as i32);
         let (amount_out, remaining_amount_in) = self
             .price_data
-            .get_amount_out_from_levels(amount_in, price_levels);
+            .get_amount_out_from_levels(
+                &amount_in,
+                sell_base,
+                self.base_token.decimals,
+                self.quote_token.decimals,
+            )?;
+        let consumed_amount_in = &amount_in - &remaining_amount_in;
+
+        // The liquidity used by this swap is no longer available to subsequent swaps
+        let mut new_state = self.clone();
+        new_state.price_data.consume_levels(
+            &consumed_amount_in,
+            sell_base,
+            self.base_token.decimals,
+            self.quote_token.decimals,
+        )?;
+
         let res = GetAmountOutResult {
-            amount: BigUint::from_f64(amount_out * 10f64.powi(token_out.decimals as i32))
-                .ok_or_else(|| {
-                    SimulationError::RecoverableError("Can't convert amount out to BigUInt".into())
-                })?,
+            amount: amount_out,
             gas: BigUint::from(70_000u64), // Rough gas estimation
-            new_state: self.clone_box(),   // The state doesn't change after a swap
+            new_state: Box::new(new_state),
         };
 
-        if remaining_amount_in > 0.0 {
+        if !remaining_amount_in.is_zero() {
             return Err(SimulationError::InvalidInput(
-                format!("Pool has not enough liquidity to support complete swap. input amount: {amount_in}, consumed amount: {}", amount_in-remaining_amount_in),
+                format!("Pool has not enough liquidity to support complete swap. input amount: {amount_in}, consumed amount: {consumed_amount_in}"),
                 Some(res)))
         }
 
@@ -147,44 +171,46 @@ impl ProtocolSim for BebopState {
     ) -> Result<(BigUint, BigUint), SimulationError> {
         // If selling BASE for QUOTE, we need to look at [BASE/QUOTE].bids
         // If buying BASE with QUOTE, we need to look at [BASE/QUOTE].asks
-        let (sell_decimals, buy_decimals, price_levels) = if sell_token == self.base_token.address &&
+        let sell_base = if sell_token == self.base_token.address &&
             buy_token == self.quote_token.address
         {
-            (self.base_token.decimals, self.quote_token.decimals, self.price_data.get_bids())
+            true
         } else if buy_token == self.base_token.address && sell_token == self.quote_token.address {
-            (self.quote_token.decimals, self.base_token.decimals, self.price_data.get_asks())
+            false
         } else {
             return Err(SimulationError::RecoverableError(format!(
                 "Invalid token addresses: {sell_token}, {buy_token}"
             )))
         };
+        check_levels_freshness(self.received_at, self.ttl)?;
 
-        // If there are no price levels, return 0 for both limits
-        if price_levels.is_empty() {
-            return Ok((BigUint::from(0u64), BigUint::from(0u64)));
-        }
+        let levels = BebopPriceData::to_atomic_levels(
+            if sell_base { &self.price_data.bids } else { &self.price_data.asks },
+            self.base_token.decimals,
+            self.quote_token.decimals,
+        )?;
 
-        let total_base_amount: f64 = price_levels
-            .iter()
-            .map(|(_, amount)| amount)
-            .sum();
-        let total_quote_amount: f64 = price_levels
+        // If there are no price levels, both limits are 0
+        let total_base_amount: BigUint = levels
             .iter()
-            .map(|(price, amount)| price * amount)
+            .map(|level| &level.size)
             .sum();
 
-        let (total_sell_amount, total_buy_amount) =
-            if sell_token == self.base_token.address && buy_token == self.quote_token.address {
-                (total_base_amount, total_quote_amount)
-            } else {
-                (total_quote_amount, total_base_amount)
-            };
-
-        let sell_limit =
-            BigUint::from((total_sell_amount * 10_f64.pow(sell_decimals as f64)) as u128);
-        let buy_limit = BigUint::from((total_buy_amount * 10_f64.pow(buy_decimals as f64)) as u128);
-
-        Ok((sell_limit, buy_limit))
+        if sell_base {
+            // Selling base pays out every level's quote value, rounded down
+            let total_quote_amount: BigUint = levels
+                .iter()
+                .map(|level| level.quote_for_base(&level.size, Rounding::Down))
+                .sum();
+            Ok((total_base_amount, total_quote_amount))
+        } else {
+            // Buying every level's base amount costs its quote value, rounded up
+            let total_quote_amount: BigUint = levels
+                .iter()
+                .map(|level| level.quote_for_base(&level.size, Rounding::Up))
+                .sum();
+            Ok((total_quote_amount, total_base_amount))
+        }
     }
 
     fn delta_transition(
@@ -215,7 +241,9 @@ impl ProtocolSim for BebopState {
         {
             self.base_token == other_state.base_token &&
                 self.quote_token == other_state.quote_token &&
-                self.price_data == other_state.price_data
+                self.price_data == other_state.price_data &&
+                self.received_at == other_state.received_at &&
+                self.ttl == other_state.ttl
         } else {
             false
         }
@@ -239,6 +267,17 @@ impl IndicativelyPriced for BebopState {
     }
 }
 
+impl SwapFee for BebopState {
+    fn swap_fee(
+        &self,
+        _token_in: &Token,
+        _token_out: &Token,
+        _amount_in: Option<&BigUint>,
+    ) -> Result<f64, SimulationError> {
+        Ok(self.fee())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{collections::HashSet, str::FromStr};
@@ -246,6 +285,7 @@ mod tests {
     use tycho_common::models::Chain;
 
     use super::*;
+    use crate::rfq::models::{now_timestamp, DEFAULT_LEVELS_TTL};
 
     fn wbtc() -> Token {
         Token::new(
@@ -295,6 +335,7 @@ mod tests {
             "".to_string(),
             "".to_string(),
             HashSet::new(),
+            DEFAULT_LEVELS_TTL,
         )
         .unwrap()
     }
@@ -311,6 +352,8 @@ mod tests {
                 asks: vec![65100.0f32, 1.0f32, 65150.0f32, 2.5f32, 65200.0f32, 1.5f32],
             },
             client: empty_bebop_client(),
+            received_at: now_timestamp().unwrap(),
+            ttl: DEFAULT_LEVELS_TTL,
         }
     }
 
@@ -483,7 +526,14 @@ mod tests {
 
         let weth = weth();
         let usdc = usdc();
-        let state = BebopState::new(weth.clone(), usdc.clone(), price_data, empty_bebop_client());
+        let state = BebopState::new(
+            weth.clone(),
+            usdc.clone(),
+            price_data,
+            empty_bebop_client(),
+            now_timestamp().unwrap(),
+            DEFAULT_LEVELS_TTL,
+        );
 
         // swap 3 WETH -> USDC
         let amount_out_result = state
@@ -498,7 +548,49 @@ mod tests {
             .get_amount_out(BigUint::from_str("7000_000_000").unwrap(), &usdc, &weth)
             .unwrap();
 
-        // 1.5 from level 1 + 0.78333 from level 2 = 2.283333 WETH
-        assert_eq!(amount_out_result.amount, BigUint::from_str("2_283333333333333248").unwrap());
+        // 1.5 from level 1 + 2350 / 3000 from level 2 = 2.283333 WETH, rounded down
+        assert_eq!(amount_out_result.amount, BigUint::from_str("2_283333333333333333").unwrap());
+    }
+
+    #[test]
+    fn test_new_state_consumes_levels() {
+        let state = create_test_bebop_state();
+
+        // Sell 2 WBTC -> USDC: consumes the first bid level and 0.5 of the second one
+        let result = state
+            .get_amount_out(BigUint::from(200_000_000u64), &wbtc(), &usdc())
+            .unwrap();
+        // 1.5 * 65000 + 0.5 * 64950 = 129975 USDC
+        assert_eq!(result.amount, BigUint::from(129_975_000_000u64));
+
+        let new_state = result
+            .new_state
+            .as_any()
+            .downcast_ref::<BebopState>()
+            .unwrap();
+        assert_eq!(new_state.price_data.get_bids(), vec![(64950.0, 1.5), (64900.0, 0.5)]);
+        assert_eq!(new_state.price_data.asks, state.price_data.asks);
+
+        // Repeating the same swap on the new state now fills at worse prices
+        let second_result = new_state
+            .get_amount_out(BigUint::from(200_000_000u64), &wbtc(), &usdc())
+            .unwrap();
+        // 1.5 * 64950 + 0.5 * 64900 = 129875 USDC
+        assert_eq!(second_result.amount, BigUint::from(129_875_000_000u64));
+    }
+
+    #[test]
+    fn test_stale_levels() {
+        let mut state = create_test_bebop_state();
+        state.received_at = now_timestamp().unwrap() - DEFAULT_LEVELS_TTL - 1;
+
+        let result = state.get_amount_out(BigUint::from(100_000_000u64), &wbtc(), &usdc());
+        assert!(matches!(result, Err(SimulationError::RecoverableError(_))));
+
+        let result = state.spot_price(&wbtc(), &usdc());
+        assert!(matches!(result, Err(SimulationError::RecoverableError(_))));
+
+        let result = state.get_limits(wbtc().address.clone(), usdc().address.clone());
+        assert!(matches!(result, Err(SimulationError::RecoverableError(_))));
     }
 }
@@ -6,7 +6,10 @@ use tycho_common::{models::token::Token, Bytes};
 use super::{models::BebopPriceData, state::BebopState};
 use crate::{
     protocol::{errors::InvalidSnapshotError, models::TryFromWithBlock},
-    rfq::{models::TimestampHeader, protocols::bebop::client::BebopClient},
+    rfq::{
+        models::{TimestampHeader, DEFAULT_LEVELS_TTL},
+        protocols::bebop::client::BebopClient,
+    },
 };
 
 impl TryFromWithBlock<ComponentWithState, TimestampHeader> for BebopState {
@@ -75,6 +78,14 @@ impl TryFromWithBlock<ComponentWithState, TimestampHeader> for BebopState {
                 .collect(),
         };
 
+        let ttl = state_attrs
+            .get("ttl")
+            .map(|ttl| u64::from(ttl.clone()))
+            .unwrap_or(DEFAULT_LEVELS_TTL);
+
+        // Snapshots don't carry the client's configuration, so this client is unauthenticated and
+        // uses the default endpoints. Register the decoder with `RFQStreamBuilder::add_client_with`
+        // and `BebopState::with_client` to request binding quotes with the configured client.
         let ws_user = "".to_string();
         let ws_key = "".to_string();
 
@@ -85,12 +96,20 @@ impl TryFromWithBlock<ComponentWithState, TimestampHeader> for BebopState {
             ws_user,
             ws_key,
             HashSet::new(),
+            ttl,
         )
         .map_err(|e| {
             InvalidSnapshotError::MissingAttribute(format!("Couldn't create BebopClient: {e}"))
         })?;
 
-        Ok(BebopState { base_token, quote_token, price_data, client })
+        Ok(BebopState {
+            base_token,
+            quote_token,
+            price_data,
+            client,
+            received_at: timestamp_header.timestamp,
+            ttl,
+        })
     }
 }
 
@@ -200,6 +219,28 @@ mod tests {
         assert_eq!(result.price_data.get_asks().len(), 3);
         assert_eq!(result.price_data.get_bids()[0], (65000.0, 1.5));
         assert_eq!(result.price_data.get_asks()[0], (65100.0, 1.0));
+        assert_eq!(result.received_at, 1703097600);
+        assert_eq!(result.ttl, DEFAULT_LEVELS_TTL);
+    }
+
+    #[tokio::test]
+    async fn test_try_from_with_ttl() {
+        let (mut snapshot, tokens) = create_test_snapshot();
+        snapshot
+            .state
+            .attributes
+            .insert("ttl".to_string(), Bytes::from(30u64.to_be_bytes().to_vec()));
+
+        let result = BebopState::try_from_with_header(
+            snapshot,
+            TimestampHeader { timestamp: 1703097600u64 },
+            &HashMap::new(),
+            &tokens,
+        )
+        .await
+        .expect("create state from snapshot");
+
+        assert_eq!(result.ttl, 30);
     }
 
     #[tokio::test]
@@ -22,6 +22,7 @@ use crate::{
     rfq::{
         client::RFQClient,
         errors::RFQError,
+        http::{send_with_retry, HttpConfig},
         models::TimestampHeader,
         protocols::hashflow::models::{
             HashflowChain, HashflowMarketMakerLevels, HashflowMarketMakersResponse,
@@ -32,6 +33,9 @@ use crate::{
     tycho_common::dto::{ProtocolComponent, ResponseProtocolState},
 };
 
+/// Default base URL of the Hashflow API
+pub const HASHFLOW_API_URL: &str = "https://api.hashflow.com";
+
 #[derive(Clone, Debug)]
 pub struct HashflowClient {
     chain: Chain,
@@ -48,6 +52,9 @@ pub struct HashflowClient {
     // Quote tokens to normalize to for TVL purposes. Should have the same prices.
     quote_tokens: HashSet<Bytes>,
     poll_time: u64,
+    // Seconds for which the polled price levels are considered valid
+    ttl: u64,
+    http_config: HttpConfig,
 }
 
 impl HashflowClient {
@@ -59,22 +66,45 @@ impl HashflowClient {
         auth_user: String,
         auth_key: String,
         poll_time: u64,
+        ttl: u64,
     ) -> Result<Self, RFQError> {
+        let http_config = HttpConfig::default();
         Ok(Self {
             chain,
-            price_levels_endpoint: "https://api.hashflow.com/taker/v3/price-levels".to_string(),
-            market_makers_endpoint: "https://api.hashflow.com/taker/v3/market-makers".to_string(),
-            quote_endpoint: "https://api.hashflow.com/taker/v3/rfq".to_string(),
+            price_levels_endpoint: format!("{HASHFLOW_API_URL}/taker/v3/price-levels"),
+            market_makers_endpoint: format!("{HASHFLOW_API_URL}/taker/v3/market-makers"),
+            quote_endpoint: format!("{HASHFLOW_API_URL}/taker/v3/rfq"),
             tokens,
             tvl,
-            http_client: Client::new(),
+            http_client: http_config.build_client()?,
             auth_key,
             auth_user,
             quote_tokens,
             poll_time,
+            ttl,
+            http_config,
         })
     }
 
+    /// Points the client to a different Hashflow API host, e.g. a local mock maker.
+    ///
+    /// `base_url` is a base URL such as `https://api.hashflow.com`. The endpoint paths are
+    /// appended to it.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        let base_url = base_url.trim_end_matches('/');
+        self.price_levels_endpoint = format!("{base_url}/taker/v3/price-levels");
+        self.market_makers_endpoint = format!("{base_url}/taker/v3/market-makers");
+        self.quote_endpoint = format!("{base_url}/taker/v3/rfq");
+        self
+    }
+
+    /// Sets the HTTP settings used for polling price levels and requesting quotes.
+    pub fn with_http_config(mut self, http_config: HttpConfig) -> Result<Self, RFQError> {
+        self.http_client = http_config.build_client()?;
+        self.http_config = http_config;
+        Ok(self)
+    }
+
     /// Normalize TVL to a common quote token for comparison
     /// Returns the normalized TVL value, or 0.0 if normalization fails due to no liquidity
     fn normalize_tvl(
@@ -135,6 +165,7 @@ impl HashflowClient {
             attributes.insert("levels".to_string(), levels_json.as_bytes().to_vec().into());
         }
         attributes.insert("mm".to_string(), mm_name.as_bytes().to_vec().into());
+        attributes.insert("ttl".to_string(), Bytes::from(self.ttl.to_be_bytes().to_vec()));
 
         ComponentWithState {
             state: ResponseProtocolState {
@@ -155,16 +186,15 @@ impl HashflowClient {
             ("baseChainId", self.chain.id().to_string()),
         ];
 
-        let request = self
-            .http_client
-            .get(&self.market_makers_endpoint)
-            .query(&query_params)
-            .header("accept", "application/json")
-            .header("Authorization", &self.auth_key);
-
-        let response = request.send().await.map_err(|e| {
-            RFQError::ConnectionError(format!("Failed to fetch market makers: {e}"))
-        })?;
+        let response = send_with_retry(&self.http_config.retry_policy, || {
+            self.http_client
+                .get(&self.market_makers_endpoint)
+                .query(&query_params)
+                .header("accept", "application/json")
+                .header("Authorization", &self.auth_key)
+        })
+        .await
+        .map_err(|e| RFQError::ConnectionError(format!("Failed to fetch market makers: {e}")))?;
 
         if !response.status().is_success() {
             return Err(RFQError::ConnectionError(format!(
@@ -205,17 +235,15 @@ impl HashflowClient {
             query_params.push(("marketMakers[]", mm.clone()));
         }
 
-        let request = self
-            .http_client
-            .get(&self.price_levels_endpoint)
-            .query(&query_params)
-            .header("accept", "application/json")
-            .header("Authorization", &self.auth_key);
-
-        let response = request
-            .send()
-            .await
-            .map_err(|e| RFQError::ConnectionError(format!("Failed to fetch price levels: {e}")))?;
+        let response = send_with_retry(&self.http_config.retry_policy, || {
+            self.http_client
+                .get(&self.price_levels_endpoint)
+                .query(&query_params)
+                .header("accept", "application/json")
+                .header("Authorization", &self.auth_key)
+        })
+        .await
+        .map_err(|e| RFQError::ConnectionError(format!("Failed to fetch price levels: {e}")))?;
 
         if !response.status().is_success() {
             return Err(RFQError::ConnectionError(format!(
@@ -377,16 +405,21 @@ impl RFQClient for HashflowClient {
             calldata: false,
         };
 
-        let url = self.quote_endpoint.clone();
-
-        let request = self
-            .http_client
-            .post(&url)
-            .json(&quote_request)
-            .header("accept", "application/json")
-            .header("Authorization", &self.auth_key);
-
-        let response = request.send().await.map_err(|e| {
+        let response = send_with_retry(
+            &self
+                .http_config
+                .retry_policy
+                .for_quotes(),
+            || {
+                self.http_client
+                    .post(&self.quote_endpoint)
+                    .json(&quote_request)
+                    .header("accept", "application/json")
+                    .header("Authorization", &self.auth_key)
+            },
+        )
+        .await
+        .map_err(|e| {
             RFQError::ConnectionError(format!("Failed to send Hashflow quote request: {e}"))
         })?;
 
@@ -514,7 +547,11 @@ mod tests {
     use tycho_common::models::token::Token;
 
     use super::*;
-    use crate::rfq::protocols::hashflow::models::{HashflowPair, HashflowPriceLevel};
+    use crate::rfq::{
+        mock_maker::{MockMaker, MockMakerConfig},
+        models::DEFAULT_LEVELS_TTL,
+        protocols::hashflow::models::{HashflowPair, HashflowPriceLevel},
+    };
 
     #[test]
     fn test_normalize_tvl_same_quote_token() {
@@ -582,8 +619,133 @@ mod tests {
             "test_user".to_string(),
             "test_key".to_string(),
             5,
+            DEFAULT_LEVELS_TTL,
+        )
+        .unwrap()
+    }
+
+    fn weth() -> Bytes {
+        Bytes::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").unwrap()
+    }
+
+    fn usdc() -> Bytes {
+        Bytes::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap()
+    }
+
+    fn mock_hashflow_client(mock: &MockMaker) -> HashflowClient {
+        HashflowClient::new(
+            Chain::Ethereum,
+            HashSet::from([weth(), usdc()]),
+            1.0,
+            HashSet::from([usdc()]),
+            "test_user".to_string(),
+            "test_key".to_string(),
+            1,
+            DEFAULT_LEVELS_TTL,
         )
         .unwrap()
+        .with_base_url(&mock.http_url())
+    }
+
+    #[tokio::test]
+    async fn test_hashflow_stream_with_mock_maker() {
+        let mm_levels = HashflowMarketMakerLevels {
+            pair: HashflowPair { base_token: weth(), quote_token: usdc() },
+            levels: vec![
+                HashflowPriceLevel { quantity: 0.5, price: 3000.0 },
+                HashflowPriceLevel { quantity: 1.5, price: 2999.0 },
+            ],
+        };
+        let mock = MockMaker::start(MockMakerConfig {
+            hashflow_levels: HashMap::from([("mm1".to_string(), vec![mm_levels])]),
+            ..Default::default()
+        })
+        .await;
+        let client = mock_hashflow_client(&mock);
+
+        let mut stream = client.stream();
+        let (provider, msg) = timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("Timed out waiting for the mock maker")
+            .expect("Stream ended unexpectedly")
+            .expect("Stream returned an error");
+
+        assert_eq!(provider, "hashflow");
+        assert_eq!(msg.snapshots.states.len(), 1);
+
+        let component_with_state = msg
+            .snapshots
+            .states
+            .values()
+            .next()
+            .unwrap();
+        assert_eq!(
+            component_with_state
+                .component
+                .protocol_system,
+            "rfq:hashflow"
+        );
+        assert_eq!(component_with_state.component.tokens, vec![weth(), usdc()]);
+        // 0.5 * 3000 + 1.5 * 2999 = 5998.5 USDC
+        assert_eq!(component_with_state.component_tvl, Some(5998.5));
+
+        let attributes = &component_with_state.state.attributes;
+        assert_eq!(attributes["mm"], Bytes::from("mm1".as_bytes().to_vec()));
+        assert!(attributes.contains_key("levels"));
+
+        assert_eq!(
+            mock.http_requests()[..2],
+            ["/taker/v3/market-makers".to_string(), "/taker/v3/price-levels".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hashflow_quote_with_mock_maker() {
+        let mock = MockMaker::start(MockMakerConfig {
+            quote_amount_out: BigUint::from(3_000_000_000u64),
+            ..Default::default()
+        })
+        .await;
+        let client = mock_hashflow_client(&mock);
+
+        let weth_token = Token {
+            address: weth(),
+            symbol: "WETH".to_string(),
+            decimals: 18,
+            tax: 0,
+            gas: vec![],
+            chain: Default::default(),
+            quality: 100,
+        };
+        let usdc_token = Token {
+            address: usdc(),
+            symbol: "USDC".to_string(),
+            decimals: 6,
+            tax: 0,
+            gas: vec![],
+            chain: Default::default(),
+            quality: 100,
+        };
+        let router = Bytes::from_str("0xfD0b31d2E955fA55e3fa641Fe90e08b677188d35").unwrap();
+
+        let params = GetAmountOutParams {
+            amount_in: BigUint::from(1_000000000000000000u64),
+            token_in: weth_token,
+            token_out: usdc_token,
+            sender: router.clone(),
+            receiver: router.clone(),
+        };
+        let quote = client
+            .request_binding_quote(&params)
+            .await
+            .unwrap();
+
+        assert_eq!(quote.base_token, weth());
+        assert_eq!(quote.quote_token, usdc());
+        assert_eq!(quote.amount_in, BigUint::from(1_000000000000000000u64));
+        assert_eq!(quote.amount_out, BigUint::from(3_000_000_000u64));
+        assert_eq!(quote.quote_attributes["trader"], router);
+        assert_eq!(mock.http_requests(), vec!["/taker/v3/rfq".to_string()]);
     }
 
     #[tokio::test]
@@ -610,6 +772,7 @@ mod tests {
             "propellerheads".to_string(),
             hashflow_key,
             1,
+            DEFAULT_LEVELS_TTL,
         )
         .unwrap();
 
@@ -709,6 +872,7 @@ mod tests {
             auth_user,
             auth_key,
             0,
+            DEFAULT_LEVELS_TTL,
         )
         .unwrap();
 
@@ -0,0 +1,244 @@
+use std::{collections::HashSet, time::Duration};
+
+use tycho_common::{models::Chain, Bytes};
+
+use super::client::{HashflowClient, HASHFLOW_API_URL};
+use crate::rfq::{
+    errors::RFQError,
+    http::{HttpConfig, RetryPolicy},
+    models::{default_quote_tokens_for_chain, DEFAULT_LEVELS_TTL},
+};
+
+/// `HashflowClientBuilder` is a builder pattern implementation for creating instances of
+/// `HashflowClient`.
+///
+/// # Example
+/// ```rust
+/// use tycho_simulation::rfq::protocols::hashflow::client_builder::HashflowClientBuilder;
+/// use tycho_common::{models::Chain, Bytes};
+/// use std::{collections::HashSet, str::FromStr, time::Duration};
+///
+/// let mut tokens = HashSet::new();
+/// tokens.insert(Bytes::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").unwrap()); // WETH
+/// tokens.insert(Bytes::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap()); // USDC
+///
+/// let client = HashflowClientBuilder::new(
+///     Chain::Ethereum,
+///     "auth_user".to_string(),
+///     "auth_key".to_string()
+/// )
+/// .tokens(tokens)
+/// .tvl_threshold(500.0)
+/// .poll_time(Duration::from_secs(2))
+/// .build()
+/// .unwrap();
+/// ```
+pub struct HashflowClientBuilder {
+    chain: Chain,
+    auth_user: String,
+    auth_key: String,
+    tokens: HashSet<Bytes>,
+    tvl: f64,
+    quote_tokens: Option<HashSet<Bytes>>,
+    poll_time: Duration,
+    ttl: u64,
+    base_url: String,
+    http_config: HttpConfig,
+}
+
+impl HashflowClientBuilder {
+    pub fn new(chain: Chain, auth_user: String, auth_key: String) -> Self {
+        Self {
+            chain,
+            auth_user,
+            auth_key,
+            tokens: HashSet::new(),
+            tvl: 100.0, // Default $100 minimum TVL
+            quote_tokens: None,
+            poll_time: Duration::from_secs(5),
+            ttl: DEFAULT_LEVELS_TTL,
+            base_url: HASHFLOW_API_URL.to_string(),
+            http_config: HttpConfig::default(),
+        }
+    }
+
+    /// Set the tokens for which to monitor prices
+    pub fn tokens(mut self, tokens: HashSet<Bytes>) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    /// Set the minimum TVL threshold for pools
+    pub fn tvl_threshold(mut self, tvl: f64) -> Self {
+        self.tvl = tvl;
+        self
+    }
+
+    /// Set custom quote tokens for TVL calculation
+    /// If not set, will use chain-specific defaults
+    pub fn quote_tokens(mut self, quote_tokens: HashSet<Bytes>) -> Self {
+        self.quote_tokens = Some(quote_tokens);
+        self
+    }
+
+    /// Set the interval at which price levels are polled. Hashflow only supports whole seconds.
+    pub fn poll_time(mut self, poll_time: Duration) -> Self {
+        self.poll_time = poll_time;
+        self
+    }
+
+    /// Set the number of seconds for which polled price levels are considered valid.
+    /// Quoting against older levels fails with a recoverable error.
+    pub fn ttl(mut self, ttl: u64) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Set the base URL of the Hashflow API (defaults to `https://api.hashflow.com`)
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.to_string();
+        self
+    }
+
+    /// Set the total timeout of a single HTTP request
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.http_config.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the timeout for establishing HTTP connections
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.http_config.connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    /// Send HTTP requests through the given proxy URL
+    pub fn proxy(mut self, proxy: &str) -> Self {
+        self.http_config.proxy = Some(proxy.to_string());
+        self
+    }
+
+    /// Set the policy for retrying failed HTTP requests
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.http_config.retry_policy = retry_policy;
+        self
+    }
+
+    /// Add a custom header sent with every HTTP request
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.http_config
+            .headers
+            .insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn build(self) -> Result<HashflowClient, RFQError> {
+        if self.tokens.is_empty() {
+            return Err(RFQError::InvalidInput(
+                "At least one token pair must be specified".to_string(),
+            ));
+        }
+        if self.poll_time.as_secs() == 0 {
+            return Err(RFQError::InvalidInput("Poll time must be at least one second".to_string()));
+        }
+        let quote_tokens = match self.quote_tokens {
+            Some(tokens) => tokens,
+            None => default_quote_tokens_for_chain(self.chain)?,
+        };
+
+        HashflowClient::new(
+            self.chain,
+            self.tokens,
+            self.tvl,
+            quote_tokens,
+            self.auth_user,
+            self.auth_key,
+            self.poll_time.as_secs(),
+            self.ttl,
+        )?
+        .with_base_url(&self.base_url)
+        .with_http_config(self.http_config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn tokens() -> HashSet<Bytes> {
+        HashSet::from([
+            Bytes::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").unwrap(),
+            Bytes::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_hashflow_client_builder_basic_config() {
+        let result = HashflowClientBuilder::new(
+            Chain::Ethereum,
+            "test_user".to_string(),
+            "test_key".to_string(),
+        )
+        .tokens(tokens())
+        .build();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_hashflow_client_builder_custom_configuration() {
+        let result = HashflowClientBuilder::new(
+            Chain::Ethereum,
+            "test_user".to_string(),
+            "test_key".to_string(),
+        )
+        .tokens(tokens())
+        .tvl_threshold(500.0)
+        .quote_tokens(HashSet::from([Bytes::from_str(
+            "0x1234567890123456789012345678901234567890",
+        )
+        .unwrap()]))
+        .poll_time(Duration::from_secs(2))
+        .ttl(30)
+        .base_url("https://staging.hashflow.com")
+        .timeout(Duration::from_secs(5))
+        .connect_timeout(Duration::from_secs(2))
+        .proxy("http://127.0.0.1:3128")
+        .retry_policy(RetryPolicy::none())
+        .header("x-api-env", "staging")
+        .build();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_hashflow_client_builder_validation() {
+        let result = HashflowClientBuilder::new(
+            Chain::Ethereum,
+            "test_user".to_string(),
+            "test_key".to_string(),
+        )
+        .build();
+        assert!(matches!(result, Err(RFQError::InvalidInput(_))), "Wrong error.");
+
+        let result = HashflowClientBuilder::new(
+            Chain::Ethereum,
+            "test_user".to_string(),
+            "test_key".to_string(),
+        )
+        .tokens(tokens())
+        .poll_time(Duration::from_millis(500))
+        .build();
+        assert!(matches!(result, Err(RFQError::InvalidInput(_))), "Wrong error.");
+
+        let result = HashflowClientBuilder::new(
+            Chain::Ethereum,
+            "test_user".to_string(),
+            "test_key".to_string(),
+        )
+        .tokens(tokens())
+        .proxy("not a proxy url")
+        .build();
+        assert!(matches!(result, Err(RFQError::InvalidInput(_))), "Wrong error.");
+    }
+}
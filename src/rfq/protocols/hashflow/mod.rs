@@ -1,3 +1,4 @@
 pub mod client;
-mod models;
-mod state;
+pub mod client_builder;
+pub(crate) mod models;
+pub(crate) mod state;
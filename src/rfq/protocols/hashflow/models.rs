@@ -1,8 +1,12 @@
 use std::{collections::HashMap, str::FromStr};
 
 use alloy::primitives::Address;
+use num_bigint::BigUint;
+use num_traits::Zero;
 use serde::{Deserialize, Serialize};
-use tycho_common::{models::Chain, Bytes};
+use tycho_common::{models::Chain, simulation::errors::SimulationError, Bytes};
+
+use crate::rfq::math::{fill_levels, sell_base_into_levels, AtomicLevel, Decimal};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HashflowPriceLevelsResponse {
@@ -82,26 +86,62 @@ impl HashflowMarketMakerLevels {
         }
 
         let (total_quote_token, remaining_base_token) =
-            self.get_amount_out_from_levels(base_token_amount);
+            self.estimate_amount_out_from_levels(base_token_amount);
 
         // If we can't fill the whole order (ran out of liquidity), calculate the price based on
         // the amount that we could fill, in order to have at least some price estimate
         Some(total_quote_token / (base_token_amount - remaining_base_token))
     }
 
-    /// Calculates the total token output for a given token input using available price levels.
+    /// Converts the price levels to exact levels in atomic token units.
+    pub fn to_atomic_levels(
+        &self,
+        base_decimals: u32,
+        quote_decimals: u32,
+    ) -> Result<Vec<AtomicLevel>, SimulationError> {
+        self.levels
+            .iter()
+            .map(|level| {
+                AtomicLevel::from_f64(level.price, level.quantity, base_decimals, quote_decimals)
+            })
+            .collect()
+    }
+
+    /// Calculates the exact token output for a given token input using available price levels.
     ///
     /// Iterates over the price levels, consuming as much liquidity as available at each
-    /// price level until the input amount is fully consumed or liquidity runs out.
+    /// price level until the input amount is fully consumed or liquidity runs out. All amounts
+    /// are in atomic token units and the output is rounded down.
     ///
     /// # Parameters
     /// - `amount_in`: The amount of base tokens to trade.
+    /// - `base_decimals`: The decimals of the base token.
+    /// - `quote_decimals`: The decimals of the quote token.
+    ///
+    /// # Returns
+    /// A tuple of (amount_out, remaining_amount_in) where:
+    /// - `amount_out`: The total quote tokens that can be obtained
+    /// - `remaining_amount_in`: Any remaining base tokens that couldn't be filled
+    pub fn get_amount_out_from_levels(
+        &self,
+        amount_in: &BigUint,
+        base_decimals: u32,
+        quote_decimals: u32,
+    ) -> Result<(BigUint, BigUint), SimulationError> {
+        let levels = self.to_atomic_levels(base_decimals, quote_decimals)?;
+        Ok(sell_base_into_levels(&levels, amount_in))
+    }
+
+    /// Estimates the total token output for a given token input using available price levels.
+    ///
+    /// This uses floating point arithmetic and is only meant for estimates. Use
+    /// `get_amount_out_from_levels` for quoting.
     ///
     /// # Returns
     /// A tuple of (amount_out, remaining_amount_in) where:
     /// - `amount_out`: The total quote tokens that can be obtained
     /// - `remaining_amount_in`: Any remaining base tokens that couldn't be filled
-    pub fn get_amount_out_from_levels(&self, amount_in: f64) -> (f64, f64) {
+    pub fn estimate_amount_out_from_levels(&self, amount_in: f64) -> (f64, f64) {
         let mut remaining_amount_in = amount_in;
         let mut total_amount_out = 0.0;
 
@@ -117,6 +157,39 @@ impl HashflowMarketMakerLevels {
 
         (total_amount_out, remaining_amount_in)
     }
+
+    /// Removes the liquidity consumed by a swap of `amount_in` base tokens, in atomic units, from
+    /// the price levels.
+    ///
+    /// The levels are consumed with the same exact arithmetic as `get_amount_out_from_levels`, and
+    /// fully consumed levels are removed.
+    pub fn consume_levels(
+        &mut self,
+        amount_in: &BigUint,
+        base_decimals: u32,
+        quote_decimals: u32,
+    ) -> Result<(), SimulationError> {
+        let atomic_levels = self.to_atomic_levels(base_decimals, quote_decimals)?;
+        let fills = fill_levels(&atomic_levels, amount_in, true);
+        let mut remaining_levels = Vec::with_capacity(self.levels.len());
+
+        for ((level, atomic_level), filled) in self
+            .levels
+            .iter()
+            .zip(&atomic_levels)
+            .zip(fills)
+        {
+            if filled.is_zero() {
+                remaining_levels.push(level.clone());
+            } else if filled < atomic_level.size {
+                let quantity = Decimal::from_atomic(&(&atomic_level.size - filled), base_decimals)
+                    .to_f64_down();
+                remaining_levels.push(HashflowPriceLevel { quantity, price: level.price });
+            }
+        }
+        self.levels = remaining_levels;
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -264,22 +337,58 @@ mod tests {
     }
 
     #[test]
-    fn test_get_amount_out_from_levels() {
+    fn test_estimate_amount_out_from_levels() {
         let mm_level = hashflow_level();
 
         // Test exact amount that can be filled with a single level
-        let (amount_out, remaining) = mm_level.get_amount_out_from_levels(1.0);
+        let (amount_out, remaining) = mm_level.estimate_amount_out_from_levels(1.0);
         assert_eq!(amount_out, 3000.0); // 1.0 * 3000.0
         assert_eq!(remaining, 0.0);
 
         // Test amount spanning multiple levels
-        let (amount_out, remaining) = mm_level.get_amount_out_from_levels(2.0);
+        let (amount_out, remaining) = mm_level.estimate_amount_out_from_levels(2.0);
         assert_eq!(amount_out, 5999.0); // 1.0 * 3000.0 + 1.0 * 2999.0
         assert_eq!(remaining, 0.0);
 
         // Test amount exceeding available liquidity
-        let (amount_out, remaining) = mm_level.get_amount_out_from_levels(5.0);
+        let (amount_out, remaining) = mm_level.estimate_amount_out_from_levels(5.0);
         assert_eq!(amount_out, 8998.0); // 1.0 * 3000.0 + 2.0 * 2999.0 = 3000.0 + 5998.0
         assert_eq!(remaining, 2.0); // 5.0 - 3.0 (total available)
     }
+
+    #[test]
+    fn test_get_amount_out_from_levels() {
+        let mm_level = hashflow_level();
+
+        // 2 WETH -> 1.0 * 3000.0 + 1.0 * 2999.0 USDC
+        let (amount_out, remaining) = mm_level
+            .get_amount_out_from_levels(&BigUint::from(2_000_000_000_000_000_000u128), 18, 6)
+            .unwrap();
+        assert_eq!(amount_out, BigUint::from(5_999_000_000u64));
+        assert_eq!(remaining, BigUint::ZERO);
+
+        // 5 WETH exceeds the 3 WETH available
+        let (amount_out, remaining) = mm_level
+            .get_amount_out_from_levels(&BigUint::from(5_000_000_000_000_000_000u128), 18, 6)
+            .unwrap();
+        assert_eq!(amount_out, BigUint::from(8_998_000_000u64));
+        assert_eq!(remaining, BigUint::from(2_000_000_000_000_000_000u128));
+    }
+
+    #[test]
+    fn test_consume_levels() {
+        let mut mm_level = hashflow_level();
+
+        // Consumes the first level and half of the second one
+        mm_level
+            .consume_levels(&BigUint::from(2_000_000_000_000_000_000u128), 18, 6)
+            .unwrap();
+        assert_eq!(mm_level.levels, vec![HashflowPriceLevel { quantity: 1.0, price: 2999.0 }]);
+
+        // Consuming more than available leaves no levels
+        mm_level
+            .consume_levels(&BigUint::from(5_000_000_000_000_000_000u128), 18, 6)
+            .unwrap();
+        assert!(mm_level.levels.is_empty());
+    }
 }
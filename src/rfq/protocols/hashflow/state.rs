@@ -2,7 +2,7 @@ use std::{any::Any, collections::HashMap};
 
 use async_trait::async_trait;
 use num_bigint::BigUint;
-use num_traits::{FromPrimitive, Pow, ToPrimitive};
+use num_traits::Zero;
 use tycho_common::{
     dto::ProtocolStateDelta,
     models::{protocol::GetAmountOutParams, token::Token},
@@ -14,9 +14,14 @@ use tycho_common::{
     Bytes,
 };
 
-use crate::rfq::{
-    client::RFQClient,
-    protocols::hashflow::{client::HashflowClient, models::HashflowMarketMakerLevels},
+use crate::{
+    protocol::fee::SwapFee,
+    rfq::{
+        client::RFQClient,
+        math::{sell_base_into_levels, Decimal, Rounding},
+        models::check_levels_freshness,
+        protocols::hashflow::{client::HashflowClient, models::HashflowMarketMakerLevels},
+    },
 };
 
 #[derive(Debug, Clone)]
@@ -25,6 +30,13 @@ pub struct HashflowState {
     pub quote_token: Token,
     pub levels: HashflowMarketMakerLevels,
     pub client: HashflowClient,
+    /// Unix timestamp (in seconds) at which the price levels were received
+    pub received_at: u64,
+    /// Number of seconds after `received_at` for which the price levels are considered valid
+    pub ttl: u64,
+    /// Minimum base token quantity the maker trades, i.e. the quantity of the first price level
+    /// as received. Swaps consume the levels, so it's kept separately.
+    pub min_quantity: f64,
 }
 
 impl HashflowState {
@@ -34,8 +46,14 @@ impl HashflowState {
         quote_token: Token,
         levels: HashflowMarketMakerLevels,
         client: HashflowClient,
+        received_at: u64,
+        ttl: u64,
     ) -> Self {
-        Self { base_token, quote_token, levels, client }
+        let min_quantity = levels
+            .levels
+            .first()
+            .map_or(0.0, |level| level.quantity);
+        Self { base_token, quote_token, levels, client, received_at, ttl, min_quantity }
     }
 
     fn valid_direction_guard(
@@ -66,11 +84,13 @@ impl HashflowState {
 
 impl ProtocolSim for HashflowState {
     fn fee(&self) -> f64 {
-        todo!()
+        // Fees are included in the quoted price levels
+        0.0
     }
 
     fn spot_price(&self, base: &Token, quote: &Token) -> Result<f64, SimulationError> {
         self.valid_direction_guard(&base.address, &quote.address)?;
+        check_levels_freshness(self.received_at, self.ttl)?;
 
         // Hashflow's levels are sorted by price, so the first level represents the best price.
         self.levels
@@ -87,14 +107,16 @@ impl ProtocolSim for HashflowState {
         token_out: &Token,
     ) -> Result<GetAmountOutResult, SimulationError> {
         self.valid_direction_guard(&token_in.address, &token_out.address)?;
+        check_levels_freshness(self.received_at, self.ttl)?;
         self.valid_levels_guard()?;
 
-        let amount_in = amount_in.to_f64().ok_or_else(|| {
-            SimulationError::RecoverableError("Can't convert amount in to f64".into())
-        })? / 10f64.powi(token_in.decimals as i32);
+        let levels = self
+            .levels
+            .to_atomic_levels(self.base_token.decimals, self.quote_token.decimals)?;
 
-        // First level represents the minimum amount that can be traded
-        let min_amount = self.levels.levels[0].quantity;
+        // The first level as received represents the minimum amount that can be traded
+        let min_amount = Decimal::from_f64(self.min_quantity)?
+            .to_atomic(self.base_token.decimals, Rounding::Down);
         if amount_in < min_amount {
             return Err(SimulationError::RecoverableError(format!(
                 "Amount below minimum. Input amount: {amount_in}, min amount: {min_amount}"
@@ -102,22 +124,26 @@ impl ProtocolSim for HashflowState {
         }
 
         // Calculate amount out
-        let (amount_out, remaining_amount_in) = self
-            .levels
-            .get_amount_out_from_levels(amount_in);
+        let (amount_out, remaining_amount_in) = sell_base_into_levels(&levels, &amount_in);
+        let consumed_amount_in = &amount_in - &remaining_amount_in;
+
+        // The liquidity used by this swap is no longer available to subsequent swaps
+        let mut new_state = self.clone();
+        new_state.levels.consume_levels(
+            &consumed_amount_in,
+            self.base_token.decimals,
+            self.quote_token.decimals,
+        )?;
 
         let res = GetAmountOutResult {
-            amount: BigUint::from_f64(amount_out * 10f64.powi(token_out.decimals as i32))
-                .ok_or_else(|| {
-                    SimulationError::RecoverableError("Can't convert amount out to BigUInt".into())
-                })?,
+            amount: amount_out,
             gas: BigUint::from(134_000u64), // Rough gas estimation
-            new_state: self.clone_box(),    // The state doesn't change after a swap
+            new_state: Box::new(new_state),
         };
 
-        if remaining_amount_in > 0.0 {
+        if !remaining_amount_in.is_zero() {
             return Err(SimulationError::InvalidInput(
-                format!("Pool has not enough liquidity to support complete swap. Input amount: {amount_in}, consumed amount: {}", amount_in-remaining_amount_in),
+                format!("Pool has not enough liquidity to support complete swap. Input amount: {amount_in}, consumed amount: {consumed_amount_in}"),
                 Some(res)));
         }
 
@@ -130,21 +156,20 @@ impl ProtocolSim for HashflowState {
         buy_token: Bytes,
     ) -> Result<(BigUint, BigUint), SimulationError> {
         self.valid_direction_guard(&sell_token, &buy_token)?;
+        check_levels_freshness(self.received_at, self.ttl)?;
         self.valid_levels_guard()?;
 
-        let sell_decimals = self.base_token.decimals;
-        let buy_decimals = self.quote_token.decimals;
-        let (total_sell_amount, total_buy_amount) =
-            self.levels
-                .levels
-                .iter()
-                .fold((0.0, 0.0), |(sell_sum, buy_sum), level| {
-                    (sell_sum + level.quantity, buy_sum + level.quantity * level.price)
-                });
-
-        let sell_limit =
-            BigUint::from((total_sell_amount * 10_f64.pow(sell_decimals as f64)) as u128);
-        let buy_limit = BigUint::from((total_buy_amount * 10_f64.pow(buy_decimals as f64)) as u128);
+        let levels = self
+            .levels
+            .to_atomic_levels(self.base_token.decimals, self.quote_token.decimals)?;
+        let sell_limit: BigUint = levels
+            .iter()
+            .map(|level| &level.size)
+            .sum();
+        let buy_limit: BigUint = levels
+            .iter()
+            .map(|level| level.quote_for_base(&level.size, Rounding::Down))
+            .sum();
 
         Ok((sell_limit, buy_limit))
     }
@@ -177,7 +202,9 @@ impl ProtocolSim for HashflowState {
         {
             self.base_token == other_state.base_token &&
                 self.quote_token == other_state.quote_token &&
-                self.levels == other_state.levels
+                self.levels == other_state.levels &&
+                self.received_at == other_state.received_at &&
+                self.ttl == other_state.ttl
         } else {
             false
         }
@@ -197,6 +224,17 @@ impl IndicativelyPriced for HashflowState {
     }
 }
 
+impl SwapFee for HashflowState {
+    fn swap_fee(
+        &self,
+        _token_in: &Token,
+        _token_out: &Token,
+        _amount_in: Option<&BigUint>,
+    ) -> Result<f64, SimulationError> {
+        Ok(self.fee())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{collections::HashSet, str::FromStr};
@@ -204,7 +242,10 @@ mod tests {
     use tycho_common::models::Chain;
 
     use super::*;
-    use crate::rfq::protocols::hashflow::models::{HashflowPair, HashflowPriceLevel};
+    use crate::rfq::{
+        models::{now_timestamp, DEFAULT_LEVELS_TTL},
+        protocols::hashflow::models::{HashflowPair, HashflowPriceLevel},
+    };
 
     fn wbtc() -> Token {
         Token::new(
@@ -255,6 +296,7 @@ mod tests {
             "".to_string(),
             "".to_string(),
             0,
+            DEFAULT_LEVELS_TTL,
         )
         .unwrap()
     }
@@ -277,6 +319,9 @@ mod tests {
                 ],
             },
             client: empty_hashflow_client(),
+            received_at: now_timestamp().unwrap(),
+            ttl: DEFAULT_LEVELS_TTL,
+            min_quantity: 0.5,
         }
     }
 
@@ -342,6 +387,74 @@ mod tests {
             assert_eq!(amount_out_result.gas, BigUint::from(134_000u64));
         }
 
+        #[test]
+        fn new_state_consumes_levels() {
+            let state = create_test_hashflow_state();
+
+            // Swapping 1.5 WETH consumes the first level and 1.0 WETH of the second one
+            let amount_out_result = state
+                .get_amount_out(BigUint::from_str("1500000000000000000").unwrap(), &weth(), &usdc())
+                .unwrap();
+
+            let new_state = amount_out_result
+                .new_state
+                .as_any()
+                .downcast_ref::<HashflowState>()
+                .unwrap();
+            assert_eq!(
+                new_state.levels.levels,
+                vec![
+                    HashflowPriceLevel { quantity: 0.5, price: 3000.0 },
+                    HashflowPriceLevel { quantity: 5.0, price: 2999.0 },
+                ]
+            );
+        }
+
+        #[test]
+        fn chained_quote_keeps_minimum() {
+            let state = create_test_hashflow_state();
+
+            // Leaves 0.8 WETH of the second level, which becomes the first level
+            let new_state = state
+                .get_amount_out(BigUint::from_str("700000000000000000").unwrap(), &weth(), &usdc())
+                .unwrap()
+                .new_state;
+
+            // 0.6 WETH is above the maker's minimum of 0.5 WETH, though below the first level
+            let amount_out_result = new_state
+                .get_amount_out(BigUint::from_str("600000000000000000").unwrap(), &weth(), &usdc())
+                .unwrap();
+            assert_eq!(amount_out_result.amount, BigUint::from_str("1800000000").unwrap());
+
+            let result = new_state.get_amount_out(
+                BigUint::from_str("400000000000000000").unwrap(), // 0.4 WETH
+                &weth(),
+                &usdc(),
+            );
+            if let Err(SimulationError::RecoverableError(msg)) = result {
+                assert!(msg.contains("Amount below minimum"));
+            } else {
+                panic!("Expected RecoverableError");
+            }
+        }
+
+        #[test]
+        fn stale_levels() {
+            let mut state = create_test_hashflow_state();
+            state.received_at = now_timestamp().unwrap() - DEFAULT_LEVELS_TTL - 1;
+
+            let result = state.get_amount_out(
+                BigUint::from_str("1000000000000000000").unwrap(), // 1.0 WETH
+                &weth(),
+                &usdc(),
+            );
+            if let Err(SimulationError::RecoverableError(msg)) = result {
+                assert!(msg.contains("stale"));
+            } else {
+                panic!("Expected RecoverableError");
+            }
+        }
+
         #[test]
         fn usdc_to_wbtc() {
             let state = create_test_hashflow_state();
@@ -1,2 +1,17 @@
 pub mod bebop;
 pub mod hashflow;
+
+use std::any::Any;
+
+use crate::protocol::fee::SwapFee;
+
+/// Returns the [`SwapFee`] implementation of an RFQ state, if `state` is one.
+pub(crate) fn as_swap_fee(state: &dyn Any) -> Option<&dyn SwapFee> {
+    if let Some(state) = state.downcast_ref::<bebop::state::BebopState>() {
+        return Some(state);
+    }
+    if let Some(state) = state.downcast_ref::<hashflow::state::HashflowState>() {
+        return Some(state);
+    }
+    None
+}
@@ -54,6 +54,30 @@ impl RFQStreamBuilder {
         self
     }
 
+    /// Adds a client whose decoded states are passed through `configure`.
+    ///
+    /// States requesting binding quotes through their own client should be given the configured
+    /// one here, e.g. with `BebopState::with_client`, since the decoded snapshots don't carry the
+    /// client's endpoints, credentials or HTTP configuration.
+    pub fn add_client_with<T, F>(
+        mut self,
+        name: &str,
+        provider: Box<dyn RFQClient>,
+        configure: F,
+    ) -> Self
+    where
+        T: ProtocolSim
+            + TryFromWithBlock<ComponentWithState, TimestampHeader, Error = InvalidSnapshotError>
+            + Send
+            + 'static,
+        F: Fn(T) -> T + Send + Sync + 'static,
+    {
+        self.clients.push(provider);
+        self.decoder
+            .register_decoder_with::<T, F>(name, configure);
+        self
+    }
+
     pub async fn build(self, tx: tokio::sync::mpsc::Sender<Update>) {
         let streams: Vec<_> = self
             .clients
@@ -97,17 +121,17 @@ impl RFQStreamBuilder {
 
 #[cfg(test)]
 mod tests {
-    use std::{any::Any, time::Duration};
+    use std::{any::Any, collections::HashSet, str::FromStr, time::Duration};
 
     use async_trait::async_trait;
     use futures::stream::BoxStream;
     use num_bigint::BigUint;
-    use tokio::sync::mpsc;
+    use tokio::{sync::mpsc, time::timeout};
     use tokio_stream::wrappers::IntervalStream;
     use tycho_client::feed::synchronizer::{Snapshot, StateSyncMessage};
     use tycho_common::{
         dto::{ProtocolComponent, ProtocolStateDelta, ResponseProtocolState},
-        models::{protocol::GetAmountOutParams, token::Token},
+        models::{protocol::GetAmountOutParams, token::Token, Chain},
         simulation::{
             errors::{SimulationError, TransitionError},
             indicatively_priced::SignedQuote,
@@ -117,7 +141,15 @@ mod tests {
     };
 
     use super::*;
-    use crate::rfq::errors::RFQError;
+    use crate::rfq::{
+        errors::RFQError,
+        mock_maker::{MockMaker, MockMakerConfig},
+        protocols::bebop::{
+            client_builder::BebopClientBuilder,
+            models::{BebopPriceData, BebopPricingUpdate},
+            state::BebopState,
+        },
+    };
 
     #[derive(Clone, Debug)]
     pub struct DummyProtocol;
@@ -302,4 +334,81 @@ mod tests {
         assert_eq!(bebop_updates.len(), 3);
         assert_eq!(hashflow_updates[2].block_number_or_timestamp, 400);
     }
+
+    #[tokio::test]
+    async fn test_rfq_stream_builder_with_mock_maker() {
+        let weth = Token::new(
+            &Bytes::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").unwrap(),
+            "WETH",
+            18,
+            0,
+            &[],
+            Chain::Ethereum,
+            100,
+        );
+        let usdc = Token::new(
+            &Bytes::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap(),
+            "USDC",
+            6,
+            0,
+            &[],
+            Chain::Ethereum,
+            100,
+        );
+
+        let mock = MockMaker::start(MockMakerConfig {
+            bebop_updates: vec![BebopPricingUpdate {
+                pairs: vec![BebopPriceData {
+                    base: weth.address.to_vec(),
+                    quote: usdc.address.to_vec(),
+                    last_update_ts: 1752617378,
+                    bids: vec![3070.05f32, 0.325717f32],
+                    asks: vec![3070.527f32, 0.325717f32],
+                }],
+            }],
+            ..Default::default()
+        })
+        .await;
+
+        let bebop_client = BebopClientBuilder::new(
+            Chain::Ethereum,
+            "test_user".to_string(),
+            "test_key".to_string(),
+        )
+        .tokens(HashSet::from([weth.address.clone(), usdc.address.clone()]))
+        .ws_url(&mock.ws_url())
+        .http_url(&mock.http_url())
+        .build()
+        .unwrap();
+
+        let (tx, mut rx) = mpsc::channel::<Update>(10);
+        let builder = RFQStreamBuilder::new()
+            .add_client_with::<BebopState, _>(
+                "bebop",
+                Box::new(bebop_client.clone()),
+                move |state| state.with_client(bebop_client.clone()),
+            )
+            .set_tokens(HashMap::from([
+                (weth.address.clone(), weth.clone()),
+                (usdc.address.clone(), usdc.clone()),
+            ]))
+            .await;
+
+        tokio::spawn(builder.build(tx));
+
+        let update = timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("Timed out waiting for the mock maker")
+            .expect("Channel closed unexpectedly");
+
+        assert_eq!(update.new_pairs.len(), 1);
+        assert_eq!(update.states.len(), 1);
+
+        // Sell 0.1 WETH at the best bid of 3070.05
+        let state = update.states.values().next().unwrap();
+        let result = state
+            .get_amount_out(BigUint::from(100_000_000_000_000_000u64), &weth, &usdc)
+            .unwrap();
+        assert_eq!(result.amount, BigUint::from(307_005_000u64));
+    }
 }
@@ -1,13 +1,16 @@
 use std::collections::HashMap;
 
-use tracing::info;
+use tracing::{info, warn};
 use tycho_client::{rpc::RPCClient, HttpRPCClient};
 use tycho_common::{
+    dto::{PaginationParams, ResponseToken, TokensRequestBody},
     models::{token::Token, Chain},
     simulation::errors::SimulationError,
     Bytes,
 };
 
+use crate::protocol::token_policy::TokenPolicy;
+
 /// Converts a hexadecimal string into a `Vec<u8>`.
 ///
 /// This function accepts a hexadecimal string with or without the `0x` prefix. If the prefix
@@ -49,6 +52,8 @@ pub fn hexstring_to_vec(hexstring: &str) -> Result<Vec<u8>, SimulationError> {
 /// * `min_quality` - The minimum quality of tokens to load. Defaults to 100 if not provided.
 /// * `max_days_since_last_trade` - The max number of days since the token was last traded. Defaults
 ///   are chain specific and applied if not provided.
+///
+/// Tokens that can't be converted into ERC20 tokens are skipped.
 pub async fn load_all_tokens(
     tycho_url: &str,
     no_tls: bool,
@@ -56,39 +61,121 @@ pub async fn load_all_tokens(
     chain: Chain,
     min_quality: Option<i32>,
     max_days_since_last_trade: Option<u64>,
-) -> HashMap<Bytes, Token> {
+) -> Result<HashMap<Bytes, Token>, SimulationError> {
+    let mut policy = TokenPolicy::new(min_quality.unwrap_or(100).max(0) as u32);
+    policy.max_days_since_last_trade = max_days_since_last_trade;
+    load_tokens(tycho_url, no_tls, auth_key, chain, &policy).await
+}
+
+/// Loads the tokens admitted by `policy` from Tycho and returns them as a Hashmap of
+/// address->Token.
+///
+/// Allowed tokens are loaded even if they don't meet the quality and last trade requirements.
+/// See [`load_all_tokens`] for the other arguments.
+pub async fn load_tokens(
+    tycho_url: &str,
+    no_tls: bool,
+    auth_key: Option<&str>,
+    chain: Chain,
+    policy: &TokenPolicy,
+) -> Result<HashMap<Bytes, Token>, SimulationError> {
     info!("Loading tokens from Tycho...");
-    let rpc_url =
-        if no_tls { format!("http://{tycho_url}") } else { format!("https://{tycho_url}") };
-    let rpc_client = HttpRPCClient::new(rpc_url.as_str(), auth_key).unwrap();
+    let rpc_client = create_rpc_client(tycho_url, no_tls, auth_key)?;
 
     // Chain specific defaults for special case chains. Otherwise defaults to 42 days.
     let default_min_days = HashMap::from([(Chain::Base, 1_u64)]);
 
     #[allow(clippy::mutable_key_type)]
-    rpc_client
+    let tokens = rpc_client
         .get_all_tokens(
             chain.into(),
-            min_quality.or(Some(100)),
-            max_days_since_last_trade.or(default_min_days
-                .get(&chain)
-                .or(Some(&42))
-                .copied()),
+            Some(policy.min_quality as i32),
+            policy
+                .max_days_since_last_trade
+                .or(default_min_days
+                    .get(&chain)
+                    .or(Some(&42))
+                    .copied()),
             3_000,
         )
         .await
-        .expect("Unable to load tokens")
+        .map_err(|e| {
+            SimulationError::RecoverableError(format!("Unable to load tokens from Tycho: {e}"))
+        })?;
+    let mut tokens = convert_tokens(tokens);
+
+    let missing_allowed: Vec<Bytes> = policy
+        .allow
+        .iter()
+        .filter(|address| !tokens.contains_key(*address))
+        .cloned()
+        .collect();
+    if !missing_allowed.is_empty() {
+        tokens.extend(fetch_tokens(&rpc_client, chain, &missing_allowed, None, None).await?);
+    }
+
+    tokens.retain(|_, token| policy.admits(token));
+    Ok(tokens)
+}
+
+// Max number of addresses requested from Tycho at once
+const TOKEN_REQUEST_SIZE: usize = 100;
+
+/// Fetches the tokens at the given addresses from Tycho. Unknown addresses are left out.
+pub(crate) async fn fetch_tokens(
+    rpc_client: &HttpRPCClient,
+    chain: Chain,
+    addresses: &[Bytes],
+    min_quality: Option<i32>,
+    traded_n_days_ago: Option<u64>,
+) -> Result<HashMap<Bytes, Token>, SimulationError> {
+    let mut tokens = HashMap::new();
+    for chunk in addresses.chunks(TOKEN_REQUEST_SIZE) {
+        let request = TokensRequestBody {
+            token_addresses: Some(chunk.to_vec()),
+            min_quality,
+            traded_n_days_ago,
+            pagination: PaginationParams::new(0, chunk.len() as i64),
+            chain: chain.into(),
+        };
+        let response = rpc_client
+            .get_tokens(&request)
+            .await
+            .map_err(|e| {
+                SimulationError::RecoverableError(format!("Failed to fetch tokens from Tycho: {e}"))
+            })?;
+        tokens.extend(convert_tokens(response.tokens));
+    }
+    Ok(tokens)
+}
+
+/// Converts Tycho tokens, skipping the ones that are not valid ERC20 tokens.
+pub(crate) fn convert_tokens(tokens: Vec<ResponseToken>) -> HashMap<Bytes, Token> {
+    tokens
         .into_iter()
-        .map(|token| {
-            let token_clone = token.clone();
-            (
-                token.address.clone(),
-                token.try_into().unwrap_or_else(|_| {
-                    panic!("Couldn't convert {token_clone:?} into ERC20 token.")
-                }),
-            )
+        .filter_map(|token| {
+            let address = token.address.clone();
+            token
+                .try_into()
+                .inspect_err(
+                    |e| warn!(token = %address, error = ?e, "Couldn't convert into ERC20 token"),
+                )
+                .ok()
+                .map(|token| (address, token))
         })
-        .collect::<HashMap<_, Token>>()
+        .collect()
+}
+
+/// Creates a client for the Tycho RPC at `tycho_url`, which must not include the url prefix.
+pub(crate) fn create_rpc_client(
+    tycho_url: &str,
+    no_tls: bool,
+    auth_key: Option<&str>,
+) -> Result<HttpRPCClient, SimulationError> {
+    let rpc_url =
+        if no_tls { format!("http://{tycho_url}") } else { format!("https://{tycho_url}") };
+    HttpRPCClient::new(rpc_url.as_str(), auth_key)
+        .map_err(|e| SimulationError::FatalError(format!("Failed to create Tycho RPC client: {e}")))
 }
 
 /// Get the default Tycho URL for the given chain.
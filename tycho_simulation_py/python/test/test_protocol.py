@@ -0,0 +1,78 @@
+import os
+
+import pytest
+
+from tycho_simulation_py.protocol import ProtocolStreamBuilder, Token
+
+_TYCHO_URL = os.getenv("TYCHO_URL")
+_TYCHO_API_KEY = os.getenv("TYCHO_API_KEY")
+
+WETH = Token("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", "WETH", 18)
+USDC = Token("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", "USDC", 6)
+
+
+def test_token():
+    assert WETH.address == "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2"
+    assert WETH.symbol == "WETH"
+    assert WETH.decimals == 18
+    assert WETH.chain == "ethereum"
+
+    with pytest.raises(ValueError):
+        Token("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", "WETH", 18, chain="unknown")
+    with pytest.raises(ValueError):
+        Token("not an address", "WETH", 18)
+
+
+def test_unsupported_exchange():
+    builder = ProtocolStreamBuilder("localhost:4242", "ethereum")
+    builder.exchange("uniswap_v2", 10.0).exchange("vm:curve", 10.0, 20.0)
+
+    with pytest.raises(ValueError):
+        builder.exchange("unknown_exchange", 10.0)
+
+
+@pytest.mark.parametrize(
+    "name",
+    [
+        "uniswap_v2",
+        "sushiswap_v2",
+        "pancakeswap_v2",
+        "uniswap_v3",
+        "sushiswap_v3",
+        "pancakeswap_v3",
+        "uniswap_v4",
+        "ekubo_v2",
+        "vm:balancer_v2",
+        "vm:balancer_v3",
+        "vm:curve",
+        "vm:maverick_v2",
+    ],
+)
+def test_supported_exchange(name):
+    ProtocolStreamBuilder("localhost:4242", "ethereum").exchange(name, 10.0)
+
+
+@pytest.mark.parametrize("name", ["balancer_v2", "balancer_v3", "curve", "maverick_v2"])
+def test_native_states_not_exposed(name):
+    with pytest.raises(ValueError):
+        ProtocolStreamBuilder("localhost:4242", "ethereum").exchange(name, 10.0)
+
+
+@pytest.mark.skipif(
+    _TYCHO_URL is None,
+    reason="Tycho access required. Please set `TYCHO_URL` and `TYCHO_API_KEY` env variables.",
+)
+def test_protocol_stream():
+    builder = ProtocolStreamBuilder(_TYCHO_URL, "ethereum", auth_key=_TYCHO_API_KEY)
+    builder.exchange("uniswap_v2", 100.0).exchange("uniswap_v3", 100.0)
+    builder.set_tokens([WETH, USDC])
+    stream = builder.build()
+
+    update = next(stream)
+    assert update.block_number_or_timestamp > 0
+    for component_id, state in update.states.items():
+        component = update.new_pairs[component_id]
+        token_in, token_out = component.tokens[:2]
+        result = state.get_amount_out(10 ** token_in.decimals, token_in, token_out)
+        assert result.amount > 0
+        assert state.get_limits(token_in.address, token_out.address)[0] > 0
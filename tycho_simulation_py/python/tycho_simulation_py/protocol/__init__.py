@@ -0,0 +1,11 @@
+from tycho_simulation_py._tycho_simulation_py import (
+    Token,
+    ProtocolComponent,
+    ProtocolState,
+    GetAmountOutResult,
+    Update,
+    ProtocolStreamBuilder,
+    ProtocolStream,
+    ProtocolSimulationError,
+    RecoverableProtocolSimulationError,
+)
@@ -1,3 +1,7 @@
+use protocol_py::{
+    GetAmountOutResult, ProtocolComponent, ProtocolSimulationError, ProtocolState, ProtocolStream,
+    ProtocolStreamBuilder, RecoverableProtocolSimulationError, Token, Update,
+};
 use pyo3::prelude::*;
 use simulation_py::SimulationEngine;
 use structs_py::{
@@ -6,12 +10,13 @@ use structs_py::{
 };
 use tracing_subscriber::EnvFilter;
 
+mod protocol_py;
 mod simulation_py;
 mod structs_py;
 
 /// Transaction simulation using EVM implemented in Rust
 #[pymodule]
-fn _tycho_simulation_py(_py: Python, m: &PyModule) -> PyResult<()> {
+fn _tycho_simulation_py(py: Python, m: &PyModule) -> PyResult<()> {
     // initialize up a logger
     pyo3_log::init();
 
@@ -43,5 +48,17 @@ fn _tycho_simulation_py(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<SimulationDB>()?;
     m.add_class::<TychoDB>()?;
     m.add_class::<AccountUpdate>()?;
+    m.add_class::<Token>()?;
+    m.add_class::<ProtocolComponent>()?;
+    m.add_class::<ProtocolState>()?;
+    m.add_class::<GetAmountOutResult>()?;
+    m.add_class::<Update>()?;
+    m.add_class::<ProtocolStreamBuilder>()?;
+    m.add_class::<ProtocolStream>()?;
+    m.add("ProtocolSimulationError", py.get_type::<ProtocolSimulationError>())?;
+    m.add(
+        "RecoverableProtocolSimulationError",
+        py.get_type::<RecoverableProtocolSimulationError>(),
+    )?;
     Ok(())
 }
@@ -0,0 +1,740 @@
+#![allow(non_local_definitions)] //TODO: Update PYO3 to >= 0.21.2 (https://github.com/PyO3/pyo3/issues/4094#issuecomment-2064510190)
+use std::{collections::HashMap, pin::Pin, str::FromStr, sync::Arc};
+
+use futures::{Stream, StreamExt};
+use num_bigint::BigUint;
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyRuntimeError, PyValueError},
+    prelude::*,
+};
+use tokio::runtime::Runtime;
+use tracing::info;
+use tycho_simulation::{
+    evm::{
+        decoder::StreamDecodeError,
+        engine_db::tycho_db::PreCachedDB,
+        protocol::{
+            ekubo::state::EkuboState,
+            filters::{
+                balancer_v2_pool_filter, balancer_v3_pool_filter, curve_pool_filter,
+                uniswap_v4_pool_with_hook_filter,
+            },
+            pancakeswap_v2::state::PancakeswapV2State,
+            uniswap_v2::state::UniswapV2State,
+            uniswap_v3::state::UniswapV3State,
+            uniswap_v4::state::UniswapV4State,
+            vm::state::EVMPoolState,
+        },
+        stream,
+    },
+    protocol::{fee, models},
+    tycho_client::feed::component_tracker::ComponentFilter,
+    tycho_common::{
+        models::{token, Chain},
+        simulation::{errors::SimulationError, protocol_sim::ProtocolSim},
+        Bytes,
+    },
+    utils::load_all_tokens,
+};
+
+create_exception!(_tycho_simulation_py, ProtocolSimulationError, PyException);
+create_exception!(
+    _tycho_simulation_py,
+    RecoverableProtocolSimulationError,
+    ProtocolSimulationError
+);
+
+// `SimulationError` is defined in an external crate, so we can't implement `From` for `PyErr`
+fn simulation_error_to_py(err: SimulationError) -> PyErr {
+    match err {
+        SimulationError::RecoverableError(reason) => {
+            RecoverableProtocolSimulationError::new_err(reason)
+        }
+        SimulationError::InvalidInput(reason, _) => PyValueError::new_err(reason),
+        SimulationError::FatalError(reason) => ProtocolSimulationError::new_err(reason),
+    }
+}
+
+fn parse_chain(chain: &str) -> PyResult<Chain> {
+    Chain::from_str(chain).map_err(|_| PyValueError::new_err(format!("Unknown chain: {chain}")))
+}
+
+fn parse_address(address: &str) -> PyResult<Bytes> {
+    Bytes::from_str(address)
+        .map_err(|e| PyValueError::new_err(format!("Invalid address {address}: {e}")))
+}
+
+/// An ERC20 token
+///
+/// Attributes
+/// ----------
+/// address: str
+///     Address of the token contract
+/// symbol: str
+///     Token symbol
+/// decimals: int
+///     Number of decimals of the token
+/// chain: str
+///     Name of the chain the token lives on
+/// tax: int
+///     Transfer tax in basis points
+/// quality: int
+///     Token quality as assigned by Tycho. 100 is a normal ERC20 token.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub inner: token::Token,
+}
+
+#[pymethods]
+impl Token {
+    #[new]
+    #[pyo3(signature = (address, symbol, decimals, chain = "ethereum", tax = 0, gas = None, quality = 100))]
+    fn new(
+        address: &str,
+        symbol: &str,
+        decimals: u32,
+        chain: &str,
+        tax: u64,
+        gas: Option<u64>,
+        quality: u32,
+    ) -> PyResult<Self> {
+        let inner = token::Token::new(
+            &parse_address(address)?,
+            symbol,
+            decimals,
+            tax,
+            &[gas],
+            parse_chain(chain)?,
+            quality,
+        );
+        Ok(Self { inner })
+    }
+
+    #[getter]
+    fn address(&self) -> String {
+        self.inner.address.to_string()
+    }
+
+    #[getter]
+    fn symbol(&self) -> String {
+        self.inner.symbol.clone()
+    }
+
+    #[getter]
+    fn decimals(&self) -> u32 {
+        self.inner.decimals
+    }
+
+    #[getter]
+    fn chain(&self) -> String {
+        self.inner.chain.to_string()
+    }
+
+    #[getter]
+    fn tax(&self) -> u64 {
+        self.inner.tax
+    }
+
+    #[getter]
+    fn quality(&self) -> u32 {
+        self.inner.quality
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Token(address={address}, symbol={symbol}, decimals={decimals})",
+            address = self.inner.address,
+            symbol = self.inner.symbol,
+            decimals = self.inner.decimals
+        )
+    }
+}
+
+impl From<token::Token> for Token {
+    fn from(inner: token::Token) -> Self {
+        Self { inner }
+    }
+}
+
+/// Static properties of a protocol component, e.g. a pool
+///
+/// Attributes
+/// ----------
+/// id: str
+///     Unique identifier of the component
+/// protocol_system: str
+///     Name of the protocol, e.g. ``uniswap_v2``
+/// protocol_type_name: str
+///     Type of the component within the protocol
+/// chain: str
+///     Name of the chain the component lives on
+/// tokens: list[Token]
+///     Tokens that can be traded on the component
+/// contract_ids: list[str]
+///     Addresses of the contracts holding the component's state
+/// static_attributes: dict[str, bytearray]
+///     Attributes that never change
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct ProtocolComponent {
+    #[pyo3(get)]
+    pub id: String,
+    #[pyo3(get)]
+    pub protocol_system: String,
+    #[pyo3(get)]
+    pub protocol_type_name: String,
+    #[pyo3(get)]
+    pub chain: String,
+    #[pyo3(get)]
+    pub tokens: Vec<Token>,
+    #[pyo3(get)]
+    pub contract_ids: Vec<String>,
+    #[pyo3(get)]
+    pub static_attributes: HashMap<String, Vec<u8>>,
+}
+
+#[pymethods]
+impl ProtocolComponent {
+    fn __repr__(&self) -> String {
+        format!("{self:#?}")
+    }
+}
+
+impl From<models::ProtocolComponent> for ProtocolComponent {
+    fn from(component: models::ProtocolComponent) -> Self {
+        ProtocolComponent {
+            id: component.id.to_string(),
+            protocol_system: component.protocol_system,
+            protocol_type_name: component.protocol_type_name,
+            chain: component.chain.to_string(),
+            tokens: component
+                .tokens
+                .into_iter()
+                .map(Token::from)
+                .collect(),
+            contract_ids: component
+                .contract_ids
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            static_attributes: component
+                .static_attributes
+                .into_iter()
+                .map(|(key, value)| (key, value.to_vec()))
+                .collect(),
+        }
+    }
+}
+
+/// The state of a protocol component, used to quote swaps
+///
+/// Wraps any Rust-native or VM protocol state. Quoting never mutates the state; `get_amount_out`
+/// returns the state after the swap instead.
+#[pyclass]
+#[derive(Debug)]
+pub struct ProtocolState {
+    inner: Box<dyn ProtocolSim>,
+}
+
+impl Clone for ProtocolState {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone_box() }
+    }
+}
+
+#[pymethods]
+impl ProtocolState {
+    /// Returns the fee of the protocol as a ratio, e.g. 0.003 for 0.3%.
+    ///
+    /// VM pools return 0.0, as their fee can only be observed by simulating swaps. Use
+    /// `swap_fee` to get the fee of a specific swap.
+    fn fee(&self) -> f64 {
+        self.inner.fee()
+    }
+
+    /// Returns the fee charged when selling `token_in` for `token_out` as a ratio.
+    ///
+    /// Parameters
+    /// ----------
+    /// token_in : Token
+    ///     The token being sold
+    /// token_out : Token
+    ///     The token being bought
+    /// amount_in : int, optional
+    ///     Amount of `token_in` to sell, for protocols whose fee depends on the amount
+    #[pyo3(signature = (token_in, token_out, amount_in=None))]
+    fn swap_fee(
+        &self,
+        py: Python<'_>,
+        token_in: Token,
+        token_out: Token,
+        amount_in: Option<BigUint>,
+    ) -> PyResult<f64> {
+        py.allow_threads(|| {
+            fee::swap_fee(
+                self.inner.as_ref(),
+                &token_in.inner,
+                &token_out.inner,
+                amount_in.as_ref(),
+            )
+        })
+        .map_err(simulation_error_to_py)
+    }
+
+    /// Returns the price of `base` in terms of `quote`, including fees.
+    ///
+    /// Parameters
+    /// ----------
+    /// base : Token
+    ///     The token the price is given for
+    /// quote : Token
+    ///     The token the price is denominated in
+    fn spot_price(&self, base: Token, quote: Token) -> PyResult<f64> {
+        self.inner
+            .spot_price(&base.inner, &quote.inner)
+            .map_err(simulation_error_to_py)
+    }
+
+    /// Quotes selling `amount_in` of `token_in` for `token_out`.
+    ///
+    /// Parameters
+    /// ----------
+    /// amount_in : int
+    ///     Amount of `token_in` to sell, in atomic units
+    /// token_in : Token
+    ///     The token being sold
+    /// token_out : Token
+    ///     The token being bought
+    ///
+    /// Returns
+    /// -------
+    /// GetAmountOutResult
+    ///     The amount bought, gas used and the state after the swap.
+    fn get_amount_out(
+        &self,
+        py: Python<'_>,
+        amount_in: BigUint,
+        token_in: Token,
+        token_out: Token,
+    ) -> PyResult<GetAmountOutResult> {
+        let result = py
+            .allow_threads(|| {
+                self.inner
+                    .get_amount_out(amount_in, &token_in.inner, &token_out.inner)
+            })
+            .map_err(simulation_error_to_py)?;
+        Ok(GetAmountOutResult {
+            amount: result.amount,
+            gas: result.gas,
+            new_state: ProtocolState { inner: result.new_state },
+        })
+    }
+
+    /// Returns the maximum amounts that can be traded between two tokens.
+    ///
+    /// Parameters
+    /// ----------
+    /// sell_token : str
+    ///     Address of the token being sold
+    /// buy_token : str
+    ///     Address of the token being bought
+    ///
+    /// Returns
+    /// -------
+    /// tuple[int, int]
+    ///     Maximum amount of `sell_token` that can be sold and the corresponding amount of
+    ///     `buy_token` bought.
+    fn get_limits(&self, sell_token: &str, buy_token: &str) -> PyResult<(BigUint, BigUint)> {
+        self.inner
+            .get_limits(parse_address(sell_token)?, parse_address(buy_token)?)
+            .map_err(simulation_error_to_py)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.inner)
+    }
+}
+
+/// The result of quoting a swap
+///
+/// Attributes
+/// ----------
+/// amount: int
+///     Amount of the bought token, in atomic units
+/// gas: int
+///     Gas used by the swap
+/// new_state: ProtocolState
+///     State of the component after the swap
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct GetAmountOutResult {
+    #[pyo3(get)]
+    pub amount: BigUint,
+    #[pyo3(get)]
+    pub gas: BigUint,
+    #[pyo3(get)]
+    pub new_state: ProtocolState,
+}
+
+#[pymethods]
+impl GetAmountOutResult {
+    fn __repr__(&self) -> String {
+        format!("GetAmountOutResult(amount={}, gas={})", self.amount, self.gas)
+    }
+}
+
+/// Changes to the protocol states in a block
+///
+/// Attributes
+/// ----------
+/// block_number_or_timestamp: int
+///     Block number of the update
+/// sync_states: dict[str, str]
+///     Synchronization state per protocol
+/// states: dict[str, ProtocolState]
+///     New and updated states by component id
+/// new_pairs: dict[str, ProtocolComponent]
+///     Components added in this block by component id
+/// removed_pairs: dict[str, ProtocolComponent]
+///     Components removed in this block by component id
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct Update {
+    #[pyo3(get)]
+    pub block_number_or_timestamp: u64,
+    #[pyo3(get)]
+    pub sync_states: HashMap<String, String>,
+    #[pyo3(get)]
+    pub states: HashMap<String, ProtocolState>,
+    #[pyo3(get)]
+    pub new_pairs: HashMap<String, ProtocolComponent>,
+    #[pyo3(get)]
+    pub removed_pairs: HashMap<String, ProtocolComponent>,
+}
+
+#[pymethods]
+impl Update {
+    fn __repr__(&self) -> String {
+        format!(
+            "Update(block_number_or_timestamp={}, states={}, new_pairs={}, removed_pairs={})",
+            self.block_number_or_timestamp,
+            self.states.len(),
+            self.new_pairs.len(),
+            self.removed_pairs.len()
+        )
+    }
+}
+
+impl From<models::Update> for Update {
+    fn from(update: models::Update) -> Self {
+        Update {
+            block_number_or_timestamp: update.block_number_or_timestamp,
+            sync_states: update
+                .sync_states
+                .into_iter()
+                .map(|(protocol, state)| (protocol, format!("{state:?}")))
+                .collect(),
+            states: update
+                .states
+                .into_iter()
+                .map(|(id, state)| (id, ProtocolState { inner: state }))
+                .collect(),
+            new_pairs: update
+                .new_pairs
+                .into_iter()
+                .map(|(id, component)| (id, component.into()))
+                .collect(),
+            removed_pairs: update
+                .removed_pairs
+                .into_iter()
+                .map(|(id, component)| (id, component.into()))
+                .collect(),
+        }
+    }
+}
+
+type RegisterExchangeFn =
+    fn(stream::ProtocolStreamBuilder, &str, ComponentFilter) -> stream::ProtocolStreamBuilder;
+
+/// Returns the function registering the state type and default filter of an exchange.
+fn exchange_registration(name: &str) -> Option<RegisterExchangeFn> {
+    let register: RegisterExchangeFn = match name {
+        "uniswap_v2" | "sushiswap_v2" => {
+            |builder, name, filter| builder.exchange::<UniswapV2State>(name, filter, None)
+        }
+        "pancakeswap_v2" => {
+            |builder, name, filter| builder.exchange::<PancakeswapV2State>(name, filter, None)
+        }
+        "uniswap_v3" | "pancakeswap_v3" | "sushiswap_v3" => {
+            |builder, name, filter| builder.exchange::<UniswapV3State>(name, filter, None)
+        }
+        "uniswap_v4" => |builder, name, filter| {
+            builder.exchange::<UniswapV4State>(name, filter, Some(uniswap_v4_pool_with_hook_filter))
+        },
+        "ekubo_v2" => |builder, name, filter| builder.exchange::<EkuboState>(name, filter, None),
+        "vm:balancer_v2" => |builder, name, filter| {
+            builder.exchange::<EVMPoolState<PreCachedDB>>(
+                name,
+                filter,
+                Some(balancer_v2_pool_filter),
+            )
+        },
+        "vm:balancer_v3" => |builder, name, filter| {
+            builder.exchange::<EVMPoolState<PreCachedDB>>(
+                name,
+                filter,
+                Some(balancer_v3_pool_filter),
+            )
+        },
+        "vm:curve" => |builder, name, filter| {
+            builder.exchange::<EVMPoolState<PreCachedDB>>(name, filter, Some(curve_pool_filter))
+        },
+        name if name.starts_with("vm:") => |builder, name, filter| {
+            builder.exchange::<EVMPoolState<PreCachedDB>>(name, filter, None)
+        },
+        _ => return None,
+    };
+    Some(register)
+}
+
+/// Builds a stream of protocol state updates from a Tycho Indexer.
+///
+/// Configure the exchanges and tokens to decode, then call `build` to connect. Protocol components
+/// containing tokens which were not set with `set_tokens` or `load_tokens` are not decoded.
+///
+/// Attributes
+/// ----------
+/// tycho_url: str
+///     Host of the Tycho Indexer, e.g. ``tycho-beta.propellerheads.xyz``
+/// chain: str
+///     Name of the chain to stream, e.g. ``ethereum``
+/// auth_key: Optional[str]
+///     API key for authenticating with Tycho
+/// no_tls: bool
+///     If set, connects using http and ws instead of https and wss
+#[pyclass]
+pub struct ProtocolStreamBuilder {
+    // `None` once the stream was built
+    inner: Option<stream::ProtocolStreamBuilder>,
+    tycho_url: String,
+    chain: Chain,
+    auth_key: Option<String>,
+    no_tls: bool,
+    runtime: Arc<Runtime>,
+}
+
+impl ProtocolStreamBuilder {
+    fn take_inner(&mut self) -> PyResult<stream::ProtocolStreamBuilder> {
+        self.inner
+            .take()
+            .ok_or_else(|| PyRuntimeError::new_err("The protocol stream was already built"))
+    }
+
+    fn map_inner(
+        &mut self,
+        f: impl FnOnce(stream::ProtocolStreamBuilder) -> stream::ProtocolStreamBuilder,
+    ) -> PyResult<()> {
+        let builder = self.take_inner()?;
+        self.inner = Some(f(builder));
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl ProtocolStreamBuilder {
+    #[new]
+    #[pyo3(signature = (tycho_url, chain, auth_key = None, no_tls = false))]
+    fn new(
+        tycho_url: String,
+        chain: &str,
+        auth_key: Option<String>,
+        no_tls: bool,
+    ) -> PyResult<Self> {
+        let chain = parse_chain(chain)?;
+        let runtime = Runtime::new()
+            .map_err(|e| PyRuntimeError::new_err(format!("Failed to create runtime: {e}")))?;
+        let inner = stream::ProtocolStreamBuilder::new(&tycho_url, chain)
+            .auth_key(auth_key.clone())
+            .no_tls(no_tls);
+        Ok(Self {
+            inner: Some(inner),
+            tycho_url,
+            chain,
+            auth_key,
+            no_tls,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// Adds an exchange to stream.
+    ///
+    /// Components are added once their TVL exceeds `add_tvl_threshold` and removed once it drops
+    /// below `remove_tvl_threshold`. Exchanges which only support some of their pools get their
+    /// default filter from `tycho_simulation::evm::protocol::filters` applied.
+    ///
+    /// Parameters
+    /// ----------
+    /// name : str
+    ///     Name of the exchange, e.g. ``uniswap_v2`` or ``vm:curve``. See the Readme for the
+    ///     supported exchanges, others raise a ``ValueError``.
+    /// remove_tvl_threshold : float
+    ///     TVL below which components are removed, in the chain's native token
+    /// add_tvl_threshold : Optional[float]
+    ///     TVL above which components are added. Defaults to `remove_tvl_threshold`.
+    #[pyo3(signature = (name, remove_tvl_threshold, add_tvl_threshold = None))]
+    fn exchange<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        name: &str,
+        remove_tvl_threshold: f64,
+        add_tvl_threshold: Option<f64>,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let register = exchange_registration(name)
+            .ok_or_else(|| PyValueError::new_err(format!("Unsupported exchange: {name}")))?;
+        let filter = ComponentFilter::with_tvl_range(
+            remove_tvl_threshold,
+            add_tvl_threshold.unwrap_or(remove_tvl_threshold),
+        );
+        slf.map_inner(|builder| register(builder, name, filter))?;
+        Ok(slf)
+    }
+
+    /// Sets the block time of the chain in seconds.
+    fn block_time(mut slf: PyRefMut<'_, Self>, block_time: u64) -> PyResult<PyRefMut<'_, Self>> {
+        slf.map_inner(|builder| builder.block_time(block_time))?;
+        Ok(slf)
+    }
+
+    /// Sets the timeout of network operations in seconds.
+    fn timeout(mut slf: PyRefMut<'_, Self>, timeout: u64) -> PyResult<PyRefMut<'_, Self>> {
+        slf.map_inner(|builder| builder.timeout(timeout))?;
+        Ok(slf)
+    }
+
+    /// Skips components whose state fails to decode instead of failing the stream.
+    fn skip_state_decode_failures(
+        mut slf: PyRefMut<'_, Self>,
+        skip: bool,
+    ) -> PyResult<PyRefMut<'_, Self>> {
+        slf.map_inner(|builder| builder.skip_state_decode_failures(skip))?;
+        Ok(slf)
+    }
+
+    /// Sets the tokens considered during decoding.
+    ///
+    /// Parameters
+    /// ----------
+    /// tokens : list[Token]
+    ///     The known tokens
+    fn set_tokens<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        py: Python<'_>,
+        tokens: Vec<Token>,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let tokens: HashMap<Bytes, token::Token> = tokens
+            .into_iter()
+            .map(|token| (token.inner.address.clone(), token.inner))
+            .collect();
+        let builder = slf.take_inner()?;
+        let runtime = slf.runtime.clone();
+        slf.inner = Some(py.allow_threads(|| runtime.block_on(builder.set_tokens(tokens))));
+        Ok(slf)
+    }
+
+    /// Loads all tokens of the chain from Tycho and sets them as the tokens considered during
+    /// decoding.
+    ///
+    /// Parameters
+    /// ----------
+    /// min_quality : Optional[int]
+    ///     Minimum token quality. Defaults to 100, i.e. normal ERC20 tokens.
+    /// max_days_since_last_trade : Optional[int]
+    ///     Only load tokens traded within this many days. Defaults to a chain specific value.
+    ///
+    /// Returns
+    /// -------
+    /// list[Token]
+    ///     The loaded tokens
+    #[pyo3(signature = (min_quality = None, max_days_since_last_trade = None))]
+    fn load_tokens(
+        &mut self,
+        py: Python<'_>,
+        min_quality: Option<i32>,
+        max_days_since_last_trade: Option<u64>,
+    ) -> PyResult<Vec<Token>> {
+        let builder = self.take_inner()?;
+        info!(tycho_url = ?self.tycho_url, chain = ?self.chain, "Loading tokens from Tycho");
+        let (builder, tokens) = py.allow_threads(|| {
+            self.runtime.block_on(async {
+                match load_all_tokens(
+                    &self.tycho_url,
+                    self.no_tls,
+                    self.auth_key.as_deref(),
+                    self.chain,
+                    min_quality,
+                    max_days_since_last_trade,
+                )
+                .await
+                {
+                    Ok(tokens) => (builder.set_tokens(tokens.clone()).await, Ok(tokens)),
+                    Err(e) => (builder, Err(e)),
+                }
+            })
+        });
+        self.inner = Some(builder);
+        let tokens = tokens.map_err(simulation_error_to_py)?;
+        Ok(tokens
+            .into_values()
+            .map(Token::from)
+            .collect())
+    }
+
+    /// Connects to Tycho and returns the stream of updates.
+    ///
+    /// The builder can't be used anymore afterwards.
+    ///
+    /// Returns
+    /// -------
+    /// ProtocolStream
+    ///     An iterator over `Update`s, blocking until the next block is received
+    fn build(&mut self, py: Python<'_>) -> PyResult<ProtocolStream> {
+        let builder = self.take_inner()?;
+        let runtime = self.runtime.clone();
+        let stream = py
+            .allow_threads(|| runtime.block_on(builder.build()))
+            .map_err(|e| {
+                PyRuntimeError::new_err(format!("Failed to build protocol stream: {e}"))
+            })?;
+        Ok(ProtocolStream { stream: Box::pin(stream), runtime })
+    }
+}
+
+type UpdateStream = Pin<Box<dyn Stream<Item = Result<models::Update, StreamDecodeError>> + Send>>;
+
+/// An iterator over the `Update`s of a protocol stream
+///
+/// Each iteration blocks until the next block is received. Decoding errors are raised as
+/// `RuntimeError`, after which iteration can continue.
+#[pyclass]
+pub struct ProtocolStream {
+    stream: UpdateStream,
+    // Drives the Tycho client, must outlive the stream
+    runtime: Arc<Runtime>,
+}
+
+#[pymethods]
+impl ProtocolStream {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python<'_>) -> PyResult<Option<Update>> {
+        let ProtocolStream { stream, runtime } = &mut *slf;
+        match py.allow_threads(|| runtime.block_on(stream.next())) {
+            Some(Ok(update)) => Ok(Some(Update::from(update))),
+            Some(Err(e)) => Err(PyRuntimeError::new_err(format!("Failed to decode update: {e}"))),
+            None => Ok(None),
+        }
+    }
+}